@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use proof_experiments::artifacts::ProverArtifacts;
+use semaphore::signal::Signal;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+
+// deserializing arbitrary bytes should fail gracefully, and whatever deserializes
+// should serialize back to bytes which deserialize to the same value
+fuzz_target!(|data: &[u8]| {
+    if let Ok(artifacts) = ProverArtifacts::<F, C, 2>::from_bytes(data) {
+        let bytes = artifacts.to_bytes().unwrap();
+        let round_tripped = ProverArtifacts::<F, C, 2>::from_bytes(&bytes).unwrap();
+        assert_eq!(round_tripped.proof_with_pis, artifacts.proof_with_pis);
+    }
+
+    if let Ok(signal) = Signal::from_bytes(data) {
+        let bytes = signal.to_bytes().unwrap();
+        let round_tripped = Signal::from_bytes(&bytes).unwrap();
+        assert_eq!(round_tripped.nullifier, signal.nullifier);
+        assert_eq!(round_tripped.proof, signal.proof);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use gadgets::fma_gate::FmaGate;
+use libfuzzer_sys::fuzz_target;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use proof_experiments_fuzz::gate::check_evaluations;
+
+fuzz_target!(|data: &[u8]| {
+    let config = CircuitConfig::standard_recursion_config();
+    check_evaluations(FmaGate::new_from_config(&config), config, data);
+});
@@ -0,0 +1,81 @@
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Field;
+use plonky2::gates::gate::Gate;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::vars::{EvaluationTargets, EvaluationVars, EvaluationVarsBaseBatch};
+use proof_experiments::diagnostics::check_witness;
+
+const D: usize = 2;
+type F = GoldilocksField;
+type FE = <F as Extendable<D>>::Extension;
+type C = PoseidonGoldilocksConfig;
+
+/// Evaluates the constraints of `gate` on wires taken from the fuzzer input with the base
+/// field, extension field and recursive evaluations, which should all agree
+pub fn check_evaluations<G: Gate<F, D>>(gate: G, config: CircuitConfig, data: &[u8]) {
+    let num_wires = gate.num_wires();
+
+    let mut bytes = data.chunks(8).map(|chunk| {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        F::from_noncanonical_u64(u64::from_le_bytes(word))
+    });
+    let wires: Vec<F> = (0..num_wires)
+        .map(|_| bytes.next().unwrap_or(F::ZERO))
+        .collect();
+    let wires_ext: Vec<FE> = wires.iter().map(|&w| FE::from_basefield(w)).collect();
+    let public_inputs_hash = HashOut::ZERO;
+
+    let ext = gate.eval_unfiltered(EvaluationVars {
+        local_constants: &[],
+        local_wires: &wires_ext,
+        public_inputs_hash: &public_inputs_hash,
+    });
+    let base = gate.eval_unfiltered_base_batch(EvaluationVarsBaseBatch::new(
+        1,
+        &[],
+        &wires,
+        &public_inputs_hash,
+    ));
+    assert_eq!(ext.len(), base.len());
+    for (e, b) in ext.iter().zip(&base) {
+        assert_eq!(
+            *e,
+            FE::from_basefield(*b),
+            "base and extension evaluations differ"
+        );
+    }
+
+    // the recursive evaluation is checked by connecting it to the extension evaluation,
+    // which conflicts in the witness if they differ
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let wire_targets: Vec<_> = wires_ext
+        .iter()
+        .map(|&w| builder.constant_extension(w))
+        .collect();
+    let public_inputs_hash_target = builder.constant_hash(public_inputs_hash);
+    let circuit = gate.eval_unfiltered_circuit(
+        &mut builder,
+        EvaluationTargets {
+            local_constants: &[],
+            local_wires: &wire_targets,
+            public_inputs_hash: &public_inputs_hash_target,
+        },
+    );
+    for (c, e) in circuit.into_iter().zip(ext) {
+        let expected = builder.constant_extension(e);
+        builder.connect_extension(c, expected);
+    }
+
+    let data = builder.build::<C>();
+    let report = check_witness(&data, &PartialWitness::new(), &Default::default());
+    assert!(
+        report.is_complete(),
+        "recursive evaluation differs: {report}"
+    );
+}
@@ -0,0 +1,85 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gadgets::builder_ext::CircuitBuilderExt;
+use gadgets::poseidon2::Poseidon2Hash;
+use plonky2::field::types::{Field, Sample};
+use plonky2::hash::merkle_proofs::MerkleProofTarget;
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, PoseidonGoldilocksConfig};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+const TREE_HEIGHT: usize = 16;
+
+/// A circuit proving membership of a leaf in a tree of height `TREE_HEIGHT` and computing a
+/// nullifier from it, both with `H`, as in a signal
+fn membership_circuit<H: AlgebraicHasher<F>>(
+    tree: &MerkleTree<F, H>,
+    leaf_index: usize,
+) -> (CircuitData<F, C, D>, PartialWitness<F>) {
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let leaf_data = builder.add_virtual_targets(4);
+    let index = builder.add_virtual_target();
+    let cap = builder.add_virtual_cap(0);
+    let proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(TREE_HEIGHT),
+    };
+    builder.verify_merkle_membership_to_cap::<H>(leaf_data.clone(), index, &cap, &proof);
+    let topic = builder.add_virtual_targets(4);
+    let nullifier = builder.hash_n_to_hash_no_pad::<H>([leaf_data.clone(), topic.clone()].concat());
+    builder.register_public_inputs(&nullifier.elements);
+
+    let mut pw = PartialWitness::new();
+    for (target, value) in leaf_data.iter().zip(&tree.leaves[leaf_index]) {
+        pw.set_target(*target, *value);
+    }
+    for target in topic {
+        pw.set_target(target, F::ZERO);
+    }
+    pw.set_target(index, F::from_canonical_usize(leaf_index));
+    pw.set_cap_target(&cap, &tree.cap);
+    for (target, sibling) in proof.siblings.iter().zip(tree.prove(leaf_index).siblings) {
+        pw.set_hash_target(*target, sibling);
+    }
+
+    (builder.build::<C>(), pw)
+}
+
+fn bench_hasher<H: AlgebraicHasher<F>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+
+    let leaves: Vec<Vec<F>> = (0..1 << TREE_HEIGHT).map(|_| F::rand_vec(4)).collect();
+    group.bench_function(BenchmarkId::new("merkle tree", TREE_HEIGHT), |b| {
+        b.iter(|| MerkleTree::<F, H>::new(leaves.clone(), 0))
+    });
+
+    let inputs = F::rand_vec(8);
+    group.bench_function("nullifier", |b| b.iter(|| H::hash_no_pad(&inputs)));
+
+    let tree = MerkleTree::<F, H>::new(leaves, 0);
+    let (data, pw) = membership_circuit(&tree, 1);
+    println!(
+        "{name}: membership and nullifier in 2^{} gates",
+        data.common.degree_bits()
+    );
+    group.bench_function(BenchmarkId::new("membership proof", TREE_HEIGHT), |b| {
+        b.iter(|| data.prove(pw.clone()).unwrap())
+    });
+
+    group.finish();
+}
+
+// the Merkle membership and nullifier of a signal with Poseidon and Poseidon2
+fn bench_poseidon2(c: &mut Criterion) {
+    bench_hasher::<PoseidonHash>(c, "poseidon");
+    bench_hasher::<Poseidon2Hash>(c, "poseidon2");
+}
+
+criterion_group!(benches, bench_poseidon2);
+criterion_main!(benches);
@@ -0,0 +1,149 @@
+"""Generates the constants of `gadgets::poseidon2` for Goldilocks with a width of 12.
+
+The round constants are drawn from the Grain LFSR of the Poseidon paper, initialized with
+the field, S-box, field size, width and round numbers. The diagonal of the internal matrix
+is the first candidate, in a fixed order of small values, for which the minimal polynomial
+of every power of the matrix up to 2 * WIDTH is irreducible of degree WIDTH, the condition
+of the Poseidon2 paper ruling out invariant subspaces over the partial rounds.
+
+These are not the constants of the reference implementation, so hashes are not
+interoperable with other Poseidon2 instances. Run with `python3 poseidon2_constants.py`.
+"""
+
+import itertools
+
+from sympy import Matrix, Poly, symbols
+
+P = 2**64 - 2**32 + 1
+WIDTH = 12
+ROUNDS_F = 8
+ROUNDS_P = 22
+M4 = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]
+
+
+def grain(field, sbox, n, t, rounds_f, rounds_p):
+    state = [int(b) for b in (
+        format(field, "02b") + format(sbox, "04b") + format(n, "012b") + format(t, "012b")
+        + format(rounds_f, "010b") + format(rounds_p, "010b") + "1" * 30
+    )]
+
+    def step():
+        bit = state[62] ^ state[51] ^ state[38] ^ state[23] ^ state[13] ^ state[0]
+        state.pop(0)
+        state.append(bit)
+        return bit
+
+    for _ in range(160):
+        step()
+    while True:
+        # bits come in pairs, the second one being output only if the first one is set
+        if step() == 1:
+            yield step()
+        else:
+            step()
+
+
+def round_constants():
+    bits = grain(1, 0, 64, WIDTH, ROUNDS_F, ROUNDS_P)
+    constants = []
+    while len(constants) < (ROUNDS_F + ROUNDS_P) * WIDTH:
+        value = int("".join(str(next(bits)) for _ in range(64)), 2)
+        if value < P:
+            constants.append(value)
+    return constants
+
+
+def external_matrix():
+    return [
+        [M4[i % 4][j % 4] * (2 if i // 4 == j // 4 else 1) for j in range(WIDTH)]
+        for i in range(WIDTH)
+    ]
+
+
+def internal_matrix(diagonal):
+    return [[1 + (diagonal[i] if i == j else 0) for j in range(WIDTH)] for i in range(WIDTH)]
+
+
+def mat_mul(a, b):
+    return [
+        [sum(a[i][k] * b[k][j] for k in range(WIDTH)) % P for j in range(WIDTH)]
+        for i in range(WIDTH)
+    ]
+
+
+def is_secure(matrix):
+    x = symbols("x")
+    power = matrix
+    for _ in range(2 * WIDTH):
+        charpoly = Matrix(power).charpoly(x).as_expr()
+        poly = Poly(charpoly, x, modulus=P)
+        if poly.degree() != WIDTH or not poly.is_irreducible:
+            return False
+        power = mat_mul(power, matrix)
+    return True
+
+
+def internal_diagonal():
+    candidates = sorted(set(range(2, 40)) | {2**k for k in range(6, 16)})
+    for start in range(len(candidates) - WIDTH + 1):
+        diagonal = candidates[start:start + WIDTH]
+        if is_secure(internal_matrix(diagonal)):
+            return diagonal
+    for diagonal in itertools.combinations(candidates, WIDTH):
+        if is_secure(internal_matrix(list(diagonal))):
+            return list(diagonal)
+    raise ValueError("no suitable diagonal")
+
+
+def sbox(x):
+    return pow(x, 7, P)
+
+
+def permute(state, constants, diagonal):
+    external = external_matrix()
+
+    def external_layer(s):
+        return [sum(external[i][j] * s[j] for j in range(WIDTH)) % P for i in range(WIDTH)]
+
+    def internal_layer(s):
+        total = sum(s)
+        return [(total + diagonal[i] * s[i]) % P for i in range(WIDTH)]
+
+    state = external_layer(state)
+    rounds = iter(range(ROUNDS_F + ROUNDS_P))
+    for _ in range(ROUNDS_F // 2):
+        r = next(rounds)
+        state = external_layer([sbox((s + c) % P) for s, c in zip(state, constants[r * WIDTH:])])
+    for _ in range(ROUNDS_P):
+        r = next(rounds)
+        state[0] = sbox((state[0] + constants[r * WIDTH]) % P)
+        state = internal_layer(state)
+    for _ in range(ROUNDS_F // 2):
+        r = next(rounds)
+        state = external_layer([sbox((s + c) % P) for s, c in zip(state, constants[r * WIDTH:])])
+    return state
+
+
+def rust_array(name, values, per_line=3):
+    lines = [f"pub(crate) const {name}: [u64; {len(values)}] = ["]
+    for i in range(0, len(values), per_line):
+        lines.append("    " + " ".join(f"0x{v:016x}," for v in values[i:i + per_line]))
+    lines.append("];")
+    return "\n".join(lines)
+
+
+if __name__ == "__main__":
+    constants = round_constants()
+    full = [c for r in range(ROUNDS_F // 2) for c in constants[r * WIDTH:(r + 1) * WIDTH]]
+    partial = [constants[r * WIDTH] for r in range(ROUNDS_F // 2, ROUNDS_F // 2 + ROUNDS_P)]
+    full += [
+        c
+        for r in range(ROUNDS_F // 2 + ROUNDS_P, ROUNDS_F + ROUNDS_P)
+        for c in constants[r * WIDTH:(r + 1) * WIDTH]
+    ]
+    diagonal = internal_diagonal()
+
+    print(rust_array("FULL_ROUND_CONSTANTS", full))
+    print(rust_array("PARTIAL_ROUND_CONSTANTS", partial))
+    print(f"pub(crate) const INTERNAL_DIAGONAL: [u64; {WIDTH}] = {diagonal};")
+    print("test vector:", [f"0x{v:016x}" for v in permute(list(range(WIDTH)), constants, diagonal)])
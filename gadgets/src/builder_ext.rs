@@ -0,0 +1,556 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOutTarget, MerkleCapTarget, RichField};
+use plonky2::hash::hashing::SPONGE_WIDTH;
+use plonky2::hash::merkle_proofs::MerkleProofTarget;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{VerifierCircuitTarget, VerifierOnlyCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+
+use crate::cap_opening::CapOpeningTarget;
+use crate::fma_gate::FmaGate;
+use crate::hint::{Hint, HintGenerator, Inverse, SqrtOrNonResidue};
+use crate::merkle_update::MerkleUpdateTarget;
+use crate::numeric_gate::NumericCustomGate;
+
+/// Extension methods routing through the custom gates of this crate, and gadgets
+pub trait CircuitBuilderExt<F: RichField + Extendable<D>, const D: usize> {
+    /// Computes `accumulator * x^2` with a single `NumericCustomGate` operation
+    fn square_mul(&mut self, accumulator: Target, x: Target) -> Target;
+
+    /// Computes `a * b + c` with a single `FmaGate` operation
+    fn fma(&mut self, a: Target, b: Target, c: Target) -> Target;
+
+    /// Computes `x + y` as the `FmaGate` operation `x * 1 + y`, sharing rows with the other
+    /// operations routed through the gate
+    fn fma_add(&mut self, x: Target, y: Target) -> Target;
+
+    /// The sum of the products of `pairs`, accumulated with one `FmaGate` operation per pair
+    fn fma_inner_product(&mut self, pairs: &[(Target, Target)]) -> Target;
+
+    /// Adds the verifier data of an inner circuit, i.e. both its constants-sigmas cap and its
+    /// circuit digest, to be set together with `WitnessExt::set_verifier_data`
+    fn add_verifier_data_target(&mut self, cap_height: usize) -> VerifierCircuitTarget;
+
+    /// Fixes the verifier data of an inner circuit as constants, so that only proofs of
+    /// that circuit are accepted
+    fn constant_verifier_data<C: GenericConfig<D, F = F>>(
+        &mut self,
+        verifier_data: &VerifierOnlyCircuitData<C, D>,
+    ) -> VerifierCircuitTarget
+    where
+        C::Hasher: AlgebraicHasher<F>;
+
+    /// Checks that `leaf_data` is the leaf at `leaf_index` of a tree committed to by `cap`,
+    /// whose height is that of `proof` plus the height of the cap: the low bits of the
+    /// index walk the path and the high bits select the cap element it ends at
+    fn verify_merkle_membership_to_cap<H: AlgebraicHasher<F>>(
+        &mut self,
+        leaf_data: Vec<Target>,
+        leaf_index: Target,
+        cap: &MerkleCapTarget,
+        proof: &MerkleProofTarget,
+    );
+
+    /// Checks that each of `openings` is the row at `index` of the tree committed to by the
+    /// cap at the same position in `caps`, as FRI checks its initial trees. The trees have
+    /// the same height, so the index is split once for all the paths
+    fn verify_cap_openings<H: AlgebraicHasher<F>>(
+        &mut self,
+        index: Target,
+        caps: &[MerkleCapTarget],
+        openings: &[CapOpeningTarget],
+    );
+
+    /// Checks that replacing the old leaf of `update` by its new leaf takes the tree from
+    /// its old root to its new root. Both paths are hashed along the same siblings and
+    /// index bits, so the leaves they start from are at the same position
+    fn verify_merkle_update<H: AlgebraicHasher<F>>(&mut self, update: &MerkleUpdateTarget);
+
+    /// Checks that `t` is a byte. The plonky2 version used here has no lookup argument, so
+    /// the check decomposes `t` into bits, taking a `BaseSumGate` row per byte; the
+    /// helpers are the place to share a byte table once lookups are available
+    fn assert_byte(&mut self, t: Target);
+
+    /// Checks that each of `targets` is a byte. Each byte is decomposed separately:
+    /// packing several bytes into one decomposition wouldn't bound them individually
+    fn assert_bytes(&mut self, targets: &[Target]);
+
+    /// Adds the outputs of `hint` computed from `inputs`, which are unconstrained until
+    /// the caller constrains them
+    fn add_hint<H: Hint<F>>(&mut self, hint: H, inputs: &[Target]) -> Vec<Target>;
+
+    /// A square root of `x` and whether `x` is a square. For a non-residue, the root is a
+    /// root of `g * x` instead, `g` being a non-residue, which certifies that `x` has none
+    fn sqrt(&mut self, x: Target) -> (Target, BoolTarget);
+
+    /// `base` raised to the exponent of `exponent_bits`, least significant first, by
+    /// square-and-multiply: each bit takes one `square_mul` operation from the most
+    /// significant down, for exponents only known to the prover, unlike `exp_u64`
+    fn pow(&mut self, base: Target, exponent_bits: &[BoolTarget]) -> Target;
+
+    /// The states after hashing each chunk of `chunk_size` elements of `inputs` with the
+    /// state before it, starting from `state`, e.g. `chunked_hash::initial_state` or a state
+    /// exposed by another circuit. The last is the digest, see `chunked_hash::chunk_states`
+    fn hash_chunks<H: AlgebraicHasher<F>>(
+        &mut self,
+        state: HashOutTarget,
+        inputs: &[Target],
+        chunk_size: usize,
+    ) -> Vec<HashOutTarget>;
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderExt<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn square_mul(&mut self, accumulator: Target, x: Target) -> Target {
+        let gate = NumericCustomGate::new_from_config(&self.config);
+        let (row, i) = self.find_slot(gate, &[], &[]);
+
+        self.connect(
+            accumulator,
+            Target::wire(row, NumericCustomGate::wire_ith_accumulator(i)),
+        );
+        self.connect(x, Target::wire(row, NumericCustomGate::wire_ith_input(i)));
+
+        Target::wire(row, NumericCustomGate::wire_ith_output(i))
+    }
+
+    fn fma(&mut self, a: Target, b: Target, c: Target) -> Target {
+        let gate = FmaGate::new_from_config(&self.config);
+        let (row, i) = self.find_slot(gate, &[], &[]);
+
+        self.connect(a, Target::wire(row, FmaGate::wire_ith_multiplicand_0(i)));
+        self.connect(b, Target::wire(row, FmaGate::wire_ith_multiplicand_1(i)));
+        self.connect(c, Target::wire(row, FmaGate::wire_ith_addend(i)));
+
+        Target::wire(row, FmaGate::wire_ith_output(i))
+    }
+
+    fn fma_add(&mut self, x: Target, y: Target) -> Target {
+        let one = self.one();
+        self.fma(x, one, y)
+    }
+
+    fn fma_inner_product(&mut self, pairs: &[(Target, Target)]) -> Target {
+        let mut accumulator = self.zero();
+        for &(a, b) in pairs {
+            accumulator = self.fma(a, b, accumulator);
+        }
+
+        accumulator
+    }
+
+    fn add_verifier_data_target(&mut self, cap_height: usize) -> VerifierCircuitTarget {
+        VerifierCircuitTarget {
+            constants_sigmas_cap: self.add_virtual_cap(cap_height),
+            circuit_digest: self.add_virtual_hash(),
+        }
+    }
+
+    fn constant_verifier_data<C: GenericConfig<D, F = F>>(
+        &mut self,
+        verifier_data: &VerifierOnlyCircuitData<C, D>,
+    ) -> VerifierCircuitTarget
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        VerifierCircuitTarget {
+            constants_sigmas_cap: self.constant_merkle_cap(&verifier_data.constants_sigmas_cap),
+            circuit_digest: self.constant_hash(verifier_data.circuit_digest),
+        }
+    }
+
+    fn verify_merkle_membership_to_cap<H: AlgebraicHasher<F>>(
+        &mut self,
+        leaf_data: Vec<Target>,
+        leaf_index: Target,
+        cap: &MerkleCapTarget,
+        proof: &MerkleProofTarget,
+    ) {
+        let cap_height = cap.0.len().trailing_zeros() as usize;
+        // splitting bounds the index by the number of leaves
+        let leaf_index_bits = self.split_le(leaf_index, proof.siblings.len() + cap_height);
+        self.verify_merkle_proof_to_cap::<H>(leaf_data, &leaf_index_bits, cap, proof);
+    }
+
+    fn verify_cap_openings<H: AlgebraicHasher<F>>(
+        &mut self,
+        index: Target,
+        caps: &[MerkleCapTarget],
+        openings: &[CapOpeningTarget],
+    ) {
+        assert_eq!(caps.len(), openings.len(), "each opening needs a cap");
+        if openings.is_empty() {
+            return;
+        }
+        let cap_height = caps[0].0.len().trailing_zeros() as usize;
+        let height = openings[0].proof.siblings.len() + cap_height;
+        // splitting bounds the index by the number of leaves
+        let index_bits = self.split_le(index, height);
+        for (cap, opening) in caps.iter().zip(openings) {
+            assert_eq!(
+                opening.proof.siblings.len() + cap.0.len().trailing_zeros() as usize,
+                height,
+                "the trees should have the same height"
+            );
+            self.verify_merkle_proof_to_cap::<H>(
+                opening.leaf.clone(),
+                &index_bits,
+                cap,
+                &opening.proof,
+            );
+        }
+    }
+
+    fn verify_merkle_update<H: AlgebraicHasher<F>>(&mut self, update: &MerkleUpdateTarget) {
+        let siblings = &update.proof.siblings;
+        // splitting bounds the index by the number of leaves
+        let leaf_index_bits = self.split_le(update.leaf_index, siblings.len());
+        let zero = self.zero();
+        let mut old = self.hash_or_noop::<H>(update.old_leaf.clone());
+        let mut new = self.hash_or_noop::<H>(update.new_leaf.clone());
+        for (bit, sibling) in leaf_index_bits.into_iter().zip(siblings) {
+            for state in [&mut old, &mut new] {
+                let mut inputs = [zero; SPONGE_WIDTH];
+                inputs[..4].copy_from_slice(&state.elements);
+                inputs[4..8].copy_from_slice(&sibling.elements);
+                let outputs = self.permute_swapped::<H>(inputs, bit);
+                *state = HashOutTarget::from_vec(outputs[..4].to_vec());
+            }
+        }
+
+        self.connect_hashes(old, update.old_root);
+        self.connect_hashes(new, update.new_root);
+    }
+
+    fn assert_byte(&mut self, t: Target) {
+        self.range_check(t, 8);
+    }
+
+    fn assert_bytes(&mut self, targets: &[Target]) {
+        for t in targets {
+            self.assert_byte(*t);
+        }
+    }
+
+    fn add_hint<H: Hint<F>>(&mut self, hint: H, inputs: &[Target]) -> Vec<Target> {
+        let outputs = self.add_virtual_targets(hint.num_outputs());
+        self.add_simple_generator(HintGenerator::new(hint, inputs.to_vec(), outputs.clone()));
+        outputs
+    }
+
+    fn sqrt(&mut self, x: Target) -> (Target, BoolTarget) {
+        let outputs = self.add_hint(SqrtOrNonResidue, &[x]);
+        let (root, is_square) = (outputs[0], BoolTarget::new_unsafe(outputs[1]));
+        self.assert_bool(is_square);
+
+        let non_residue = self.constant(F::MULTIPLICATIVE_GROUP_GENERATOR);
+        let shifted = self.mul(non_residue, x);
+        let radicand = self.select(is_square, x, shifted);
+        let root_squared = self.square(root);
+        self.connect(root_squared, radicand);
+
+        // g * 0 is a square too, so a non-residue has to be nonzero
+        let inverse = self.add_hint(Inverse, &[x])[0];
+        let one = self.one();
+        let not_inverted = self.mul_sub(x, inverse, one);
+        let not_square = self.not(is_square);
+        let unchecked = self.mul(not_square.target, not_inverted);
+        self.assert_zero(unchecked);
+
+        (root, is_square)
+    }
+
+    fn pow(&mut self, base: Target, exponent_bits: &[BoolTarget]) -> Target {
+        let one = self.one();
+        exponent_bits.iter().rev().fold(one, |accumulator, &bit| {
+            // accumulator^2 * base^bit
+            let factor = self.select(bit, base, one);
+            self.square_mul(factor, accumulator)
+        })
+    }
+
+    fn hash_chunks<H: AlgebraicHasher<F>>(
+        &mut self,
+        state: HashOutTarget,
+        inputs: &[Target],
+        chunk_size: usize,
+    ) -> Vec<HashOutTarget> {
+        assert!(chunk_size > 0, "chunks can't be empty");
+        inputs
+            .chunks(chunk_size)
+            .scan(state, |state, chunk| {
+                let inputs = [&state.elements[..], chunk].concat();
+                *state = self.hash_n_to_hash_no_pad::<H>(inputs);
+                Some(*state)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::{Field, Field64, Sample};
+    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::chunked_hash::{chunk_states, initial_state};
+
+    #[test]
+    fn test_square_mul() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let accumulator = builder.add_virtual_target();
+        let x = builder.add_virtual_target();
+        let output = builder.square_mul(accumulator, x);
+        builder.register_public_input(output);
+
+        let mut pw = PartialWitness::new();
+        pw.set_target(accumulator, F::from_canonical_u64(5));
+        pw.set_target(x, F::from_canonical_u64(3));
+
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+        assert_eq!(proof.public_inputs[0], F::from_canonical_u64(45));
+
+        data.verify(proof)
+    }
+
+    #[test]
+    fn test_fma() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let inputs = builder.add_virtual_targets(6);
+        let pairs: Vec<_> = (0..3).map(|i| (inputs[i], inputs[i + 3])).collect();
+        let dot = builder.fma_inner_product(&pairs);
+        let sum = builder.fma_add(inputs[0], inputs[5]);
+        builder.register_public_inputs(&[dot, sum]);
+        // the products and the sum share a row
+        assert_eq!(builder.num_gates(), 1);
+
+        let mut pw = PartialWitness::new();
+        for (i, &input) in inputs.iter().enumerate() {
+            pw.set_target(input, F::from_canonical_usize(i + 1));
+        }
+
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+        // 1 * 4 + 2 * 5 + 3 * 6 and 1 + 6
+        assert_eq!(proof.public_inputs, [32, 7].map(F::from_canonical_u64));
+
+        data.verify(proof)
+    }
+
+    #[test]
+    fn test_assert_bytes() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let bytes = builder.add_virtual_targets(4);
+        builder.assert_bytes(&bytes);
+        let data = builder.build::<C>();
+
+        let prove = |values: [u64; 4]| {
+            let mut pw = PartialWitness::new();
+            for (t, value) in bytes.iter().zip(values) {
+                pw.set_target(*t, F::from_canonical_u64(value));
+            }
+            panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw)))
+        };
+        let proof = prove([0, 1, 128, 255]).unwrap()?;
+        data.verify(proof)?;
+        assert!(!matches!(prove([0, 1, 256, 255]), Ok(Ok(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_merkle_membership_to_cap() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let (tree_height, cap_height) = (4, 2);
+        let leaves: Vec<Vec<F>> = (0..1 << tree_height).map(|_| F::rand_vec(4)).collect();
+        let tree = MerkleTree::<F, PoseidonHash>::new(leaves.clone(), cap_height);
+
+        // leaves under different elements of the cap
+        for leaf_index in [2, 7, 13] {
+            let mut builder =
+                CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+            let leaf_data = builder.add_virtual_targets(4);
+            let leaf_index_target = builder.add_virtual_target();
+            let cap = builder.add_virtual_cap(cap_height);
+            let proof = MerkleProofTarget {
+                siblings: builder.add_virtual_hashes(tree_height - cap_height),
+            };
+            builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+                leaf_data.clone(),
+                leaf_index_target,
+                &cap,
+                &proof,
+            );
+
+            let mut pw = PartialWitness::new();
+            for (target, value) in leaf_data.into_iter().zip(&leaves[leaf_index]) {
+                pw.set_target(target, *value);
+            }
+            pw.set_target(leaf_index_target, F::from_canonical_usize(leaf_index));
+            pw.set_cap_target(&cap, &tree.cap);
+            for (target, sibling) in proof.siblings.iter().zip(&tree.prove(leaf_index).siblings) {
+                pw.set_hash_target(*target, *sibling);
+            }
+
+            let data = builder.build::<C>();
+            data.verify(data.prove(pw)?)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqrt() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x = builder.add_virtual_target();
+        let (root, is_square) = builder.sqrt(x);
+        builder.register_public_inputs(&[root, is_square.target]);
+        let data = builder.build::<C>();
+
+        let non_residue = F::MULTIPLICATIVE_GROUP_GENERATOR;
+        for (value, square) in [
+            (F::ZERO, true),
+            (F::from_canonical_u64(49), true),
+            (non_residue, false),
+            (non_residue * F::from_canonical_u64(9), false),
+        ] {
+            let mut pw = PartialWitness::new();
+            pw.set_target(x, value);
+            let proof = data.prove(pw)?;
+            let (root, is_square) = (proof.public_inputs[0], proof.public_inputs[1]);
+            assert_eq!(is_square, F::from_bool(square));
+            if square {
+                assert_eq!(root * root, value);
+            }
+            data.verify(proof)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pow() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let base = builder.add_virtual_target();
+        let exponent = builder.add_virtual_target();
+        let exponent_bits = builder.split_le(exponent, 16);
+        let power = builder.pow(base, &exponent_bits);
+        builder.register_public_input(power);
+        let data = builder.build::<C>();
+
+        let base_value = F::rand();
+        for exponent_value in [0, 1, 2, 0b1011_0110, u16::MAX as u64] {
+            let mut pw = PartialWitness::new();
+            pw.set_target(base, base_value);
+            pw.set_target(exponent, F::from_canonical_u64(exponent_value));
+            let proof = data.prove(pw)?;
+            assert_eq!(proof.public_inputs[0], base_value.exp_u64(exponent_value));
+            data.verify(proof)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_chunks() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let values = F::rand_vec(300);
+        let states = chunk_states::<F, PoseidonHash>(initial_state(), &values, 64);
+        assert_eq!(states.len(), 5);
+
+        // the last two chunks are hashed by a circuit starting from the state of the third
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let state = builder.add_virtual_hash();
+        builder.register_public_inputs(&state.elements);
+        let inputs = builder.add_virtual_targets(300 - 3 * 64);
+        let chunk_targets = builder.hash_chunks::<PoseidonHash>(state, &inputs, 64);
+        assert_eq!(chunk_targets.len(), 2);
+        builder.register_public_inputs(&chunk_targets[1].elements);
+
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(state, states[2]);
+        for (&target, &value) in inputs.iter().zip(&values[3 * 64..]) {
+            pw.set_target(target, value);
+        }
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+        assert_eq!(proof.public_inputs[4..], states[4].elements);
+
+        data.verify(proof)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+
+        #[test]
+        fn square_mul_proofs_are_sound(
+            accumulator in any::<u64>(),
+            x in any::<u64>(),
+            corrupted in 0..3usize,
+            delta in 1..GoldilocksField::ORDER,
+        ) {
+            const D: usize = 2;
+            type C = PoseidonGoldilocksConfig;
+            type F = <C as GenericConfig<D>>::F;
+
+            let mut builder =
+                CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+            let accumulator_target = builder.add_virtual_target();
+            let x_target = builder.add_virtual_target();
+            let output = builder.square_mul(accumulator_target, x_target);
+            builder.register_public_inputs(&[accumulator_target, x_target, output]);
+
+            let (accumulator, x) =
+                (F::from_noncanonical_u64(accumulator), F::from_noncanonical_u64(x));
+            let mut pw = PartialWitness::new();
+            pw.set_target(accumulator_target, accumulator);
+            pw.set_target(x_target, x);
+
+            let data = builder.build::<C>();
+            let mut proof = data.prove(pw).unwrap();
+            prop_assert_eq!(proof.public_inputs[2], accumulator * x * x);
+            prop_assert!(data.verify(proof.clone()).is_ok());
+
+            proof.public_inputs[corrupted] += F::from_canonical_u64(delta);
+            prop_assert!(data.verify(proof).is_err());
+        }
+    }
+}
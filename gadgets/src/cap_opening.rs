@@ -0,0 +1,143 @@
+//! Openings of rows of Merkle trees committed to by caps, as the FRI verifier checks the
+//! initial trees at each query: the trees have the same height and are opened at the same
+//! index, whose bits are shared by all paths. This lets protocols committing to columns of
+//! external data check openings of them without recursively verifying a proof
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+
+/// The targets of a row of a tree opened by `CircuitBuilderExt::verify_cap_openings`
+#[derive(Clone, Debug)]
+pub struct CapOpeningTarget {
+    pub leaf: Vec<Target>,
+    /// The path from the leaf up to the cap
+    pub proof: MerkleProofTarget,
+}
+
+impl CapOpeningTarget {
+    /// Adds the targets of an opening of a tree of `2^height` leaves of `leaf_len` elements,
+    /// committed to by a cap of `2^cap_height` hashes
+    pub fn new<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        height: usize,
+        cap_height: usize,
+        leaf_len: usize,
+    ) -> Self {
+        Self {
+            leaf: builder.add_virtual_targets(leaf_len),
+            proof: MerkleProofTarget {
+                siblings: builder.add_virtual_hashes(height - cap_height),
+            },
+        }
+    }
+}
+
+/// A row of a tree and its path up to the cap, computed natively
+#[derive(Clone, Debug)]
+pub struct CapOpening<F: RichField, H: Hasher<F>> {
+    pub leaf: Vec<F>,
+    pub proof: MerkleProof<F, H>,
+}
+
+impl<F: RichField, H: Hasher<F>> CapOpening<F, H> {
+    pub fn new(tree: &MerkleTree<F, H>, index: usize) -> Self {
+        Self {
+            leaf: tree.leaves[index].clone(),
+            proof: tree.prove(index),
+        }
+    }
+}
+
+/// The openings of the rows at `index` of each of `trees`, which have the same height
+pub fn open_trees<F: RichField, H: Hasher<F>>(
+    trees: &[&MerkleTree<F, H>],
+    index: usize,
+) -> Vec<CapOpening<F, H>> {
+    trees
+        .iter()
+        .map(|tree| {
+            assert_eq!(
+                tree.leaves.len(),
+                trees[0].leaves.len(),
+                "the trees should have the same height"
+            );
+            CapOpening::new(tree, index)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+    use crate::builder_ext::CircuitBuilderExt;
+    use crate::witness_ext::WitnessExt;
+
+    #[test]
+    fn test_verify_cap_openings() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // two columns of rows of different widths, the narrow one hashed as a noop
+        let (height, cap_height) = (5, 2);
+        let trees: Vec<MerkleTree<F, PoseidonHash>> = [3, 7]
+            .into_iter()
+            .map(|width| {
+                let leaves = (0..1 << height).map(|_| F::rand_vec(width)).collect();
+                MerkleTree::new(leaves, cap_height)
+            })
+            .collect();
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let index = builder.add_virtual_target();
+        let caps: Vec<_> = trees
+            .iter()
+            .map(|_| builder.add_virtual_cap(cap_height))
+            .collect();
+        let openings: Vec<_> = trees
+            .iter()
+            .map(|tree| {
+                CapOpeningTarget::new(&mut builder, height, cap_height, tree.leaves[0].len())
+            })
+            .collect();
+        builder.verify_cap_openings::<PoseidonHash>(index, &caps, &openings);
+        let data = builder.build::<C>();
+
+        let prove = |row: usize, corrupted: bool| {
+            let mut values = open_trees(&trees.iter().collect::<Vec<_>>(), row);
+            if corrupted {
+                values[1].leaf[0] += F::ONE;
+            }
+            let mut pw = PartialWitness::new();
+            pw.set_target(index, F::from_canonical_usize(row));
+            for ((cap, tree), (target, opening)) in
+                caps.iter().zip(&trees).zip(openings.iter().zip(&values))
+            {
+                pw.set_cap_target(cap, &tree.cap);
+                pw.set_cap_opening(target, opening);
+            }
+            panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw)))
+        };
+        // rows under different elements of the cap
+        for row in [0, 13, 31] {
+            data.verify(prove(row, false).unwrap()?)?;
+        }
+        assert!(!matches!(prove(13, true), Ok(Ok(_))));
+
+        Ok(())
+    }
+}
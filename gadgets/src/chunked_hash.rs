@@ -0,0 +1,32 @@
+//! Hashes of long vectors, e.g. the public inputs of proofs with hundreds of them, in
+//! chunks: each chunk is hashed together with the state the previous chunks left, so the
+//! intermediate states can be exposed and the hash split across circuits, each starting
+//! from the state the one before it exposes, instead of one sponge over the whole vector
+
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::plonk::config::{AlgebraicHasher, Hasher};
+
+/// The state hashing starts from
+pub fn initial_state<F: Field>() -> HashOut<F> {
+    HashOut {
+        elements: [F::ZERO; 4],
+    }
+}
+
+/// The states after each chunk of `chunk_size` elements of `inputs`, starting from
+/// `state`, as computed by `CircuitBuilderExt::hash_chunks`. The last is the digest
+pub fn chunk_states<F: RichField, H: AlgebraicHasher<F>>(
+    state: HashOut<F>,
+    inputs: &[F],
+    chunk_size: usize,
+) -> Vec<HashOut<F>> {
+    assert!(chunk_size > 0, "chunks can't be empty");
+    inputs
+        .chunks(chunk_size)
+        .scan(state, |state, chunk| {
+            *state = H::hash_no_pad(&[&state.elements[..], chunk].concat());
+            Some(*state)
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+//! Hints: computations outside of the circuit of values that the circuit then constrains,
+//! such as an inverse or a decomposition. A hint only maps the values of its inputs to
+//! those of its outputs, and `HintGenerator` turns it into a generator for any targets,
+//! virtual or wires of a gate, instead of a generator struct per gadget
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use plonky2::field::types::{Field, PrimeField};
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::{GeneratedValues, SimpleGenerator};
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartitionWitness, Witness, WitnessWrite};
+
+pub trait Hint<F: Field>: 'static + Debug + Send + Sync {
+    /// The number of values `compute` returns
+    fn num_outputs(&self) -> usize;
+
+    fn compute(&self, inputs: &[F]) -> Vec<F>;
+}
+
+/// Sets `outputs` to the values `hint` computes from those of `inputs`
+#[derive(Debug)]
+pub struct HintGenerator<F, H> {
+    hint: H,
+    inputs: Vec<Target>,
+    outputs: Vec<Target>,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField, H: Hint<F>> HintGenerator<F, H> {
+    pub fn new(hint: H, inputs: Vec<Target>, outputs: Vec<Target>) -> Self {
+        assert_eq!(
+            outputs.len(),
+            hint.num_outputs(),
+            "{hint:?} has {} outputs",
+            hint.num_outputs()
+        );
+        Self {
+            hint,
+            inputs,
+            outputs,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: RichField, H: Hint<F>> SimpleGenerator<F> for HintGenerator<F, H> {
+    fn dependencies(&self) -> Vec<Target> {
+        self.inputs.clone()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let inputs = witness.get_targets(&self.inputs);
+        for (&target, value) in self.outputs.iter().zip(self.hint.compute(&inputs)) {
+            out_buffer.set_target(target, value);
+        }
+    }
+}
+
+/// The inverse of the input, or zero for zero, e.g. for `is_nonzero` checks
+#[derive(Clone, Copy, Debug)]
+pub struct Inverse;
+
+impl<F: Field> Hint<F> for Inverse {
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        vec![inputs[0].try_inverse().unwrap_or(F::ZERO)]
+    }
+}
+
+/// A square root of the input, or zero if it has none
+#[derive(Clone, Copy, Debug)]
+pub struct Sqrt;
+
+impl<F: PrimeField> Hint<F> for Sqrt {
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        vec![inputs[0].sqrt().unwrap_or(F::ZERO)]
+    }
+}
+
+/// A square root of the input and 1 if it's a square, else a square root of the input
+/// times the generator of the multiplicative group, which is a non-residue, and 0
+#[derive(Clone, Copy, Debug)]
+pub struct SqrtOrNonResidue;
+
+impl<F: PrimeField> Hint<F> for SqrtOrNonResidue {
+    fn num_outputs(&self) -> usize {
+        2
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        match inputs[0].sqrt() {
+            Some(root) => vec![root, F::ONE],
+            None => {
+                let shifted = F::MULTIPLICATIVE_GROUP_GENERATOR * inputs[0];
+                vec![
+                    shifted
+                        .sqrt()
+                        .expect("a non-residue times a non-residue is a square"),
+                    F::ZERO,
+                ]
+            }
+        }
+    }
+}
+
+/// The little-endian limbs of `limb_bits` bits of the input, the last one holding the
+/// bits above the others
+#[derive(Clone, Copy, Debug)]
+pub struct Decomposition {
+    pub num_limbs: usize,
+    pub limb_bits: usize,
+}
+
+impl<F: RichField> Hint<F> for Decomposition {
+    fn num_outputs(&self) -> usize {
+        self.num_limbs
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        let mut value = inputs[0].to_canonical_u64();
+        (0..self.num_limbs)
+            .map(|i| {
+                let limb = if i + 1 == self.num_limbs || self.limb_bits >= 64 {
+                    value
+                } else {
+                    value & ((1 << self.limb_bits) - 1)
+                };
+                value = value.checked_shr(self.limb_bits as u32).unwrap_or(0);
+                F::from_canonical_u64(limb)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::builder_ext::CircuitBuilderExt;
+
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_hints() -> Result<()> {
+        let x = F::from_canonical_u64(0x1234_5678_9abc);
+        assert_eq!(Hint::<F>::compute(&Inverse, &[x])[0] * x, F::ONE);
+        assert_eq!(Hint::<F>::compute(&Inverse, &[F::ZERO]), [F::ZERO]);
+        let limbs = Decomposition {
+            num_limbs: 3,
+            limb_bits: 16,
+        }
+        .compute(&[x]);
+        assert_eq!(limbs, [0x9abc, 0x5678, 0x1234].map(F::from_canonical_u64));
+
+        // the outputs are generated from the inputs and constrained by the circuit
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let inverse = builder.add_hint(Inverse, &[input])[0];
+        let product = builder.mul(input, inverse);
+        builder.assert_one(product);
+        let square = builder.square(input);
+        let root = builder.add_hint(Sqrt, &[square])[0];
+        let root_squared = builder.square(root);
+        builder.connect(root_squared, square);
+
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, x);
+        let data = builder.build::<PoseidonGoldilocksConfig>();
+        data.verify(data.prove(pw)?)
+    }
+}
@@ -0,0 +1,249 @@
+//! Keccak256 in a circuit, e.g. of the Ethereum addresses in the leaves of an access set
+//! sourced from an on-chain list. The state is kept as bits, on which xor is two arithmetic
+//! operations and rotations are free, so a permutation costs a few thousand gates. Digests
+//! are returned as the little-endian 32-bit limbs of their bytes, which fit in a field
+//! element, unlike 64-bit lanes
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use tiny_keccak::{Hasher, Keccak};
+
+/// The number of 32-bit limbs of a digest
+pub const NUM_DIGEST_LIMBS: usize = 8;
+
+/// The number of bits absorbed per permutation
+const RATE_BITS: usize = 1088;
+
+/// The rotation of each lane, indexed by `x + 5 * y`
+const ROTATIONS: [usize; 25] = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808A,
+    0x8000000080008000,
+    0x000000000000808B,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008A,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000A,
+    0x000000008000808B,
+    0x800000000000008B,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800A,
+    0x800000008000000A,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+type Lane = [BoolTarget; 64];
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut digest = [0; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(bytes);
+    keccak.finalize(&mut digest);
+    digest
+}
+
+/// The limbs of a digest, as output by `CircuitBuilderKeccak::keccak256`
+pub fn digest_limbs<F: Field>(digest: &[u8; 32]) -> [F; NUM_DIGEST_LIMBS] {
+    let mut limbs = [F::ZERO; NUM_DIGEST_LIMBS];
+    for (limb, bytes) in limbs.iter_mut().zip(digest.chunks(4)) {
+        *limb = F::from_canonical_u32(u32::from_le_bytes(bytes.try_into().unwrap()));
+    }
+    limbs
+}
+
+pub trait CircuitBuilderKeccak<F: RichField + Extendable<D>, const D: usize> {
+    /// The keccak256 digest of `bytes`, each checked to be a byte, as `digest_limbs`
+    fn keccak256(&mut self, bytes: &[Target]) -> [Target; NUM_DIGEST_LIMBS];
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderKeccak<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn keccak256(&mut self, bytes: &[Target]) -> [Target; NUM_DIGEST_LIMBS] {
+        let mut bits: Vec<BoolTarget> = bytes
+            .iter()
+            .flat_map(|&byte| self.split_le(byte, 8))
+            .collect();
+        // pad10*1, with the domain bit of keccak
+        bits.push(self._true());
+        while bits.len() % RATE_BITS != RATE_BITS - 1 {
+            bits.push(self._false());
+        }
+        bits.push(self._true());
+
+        let mut state = [[self._false(); 64]; 25];
+        for block in bits.chunks(RATE_BITS) {
+            for (lane, block_lane) in state.iter_mut().zip(block.chunks(64)) {
+                for (bit, &block_bit) in lane.iter_mut().zip(block_lane) {
+                    *bit = xor(self, *bit, block_bit);
+                }
+            }
+            keccak_f(self, &mut state);
+        }
+
+        let mut limbs = [self.zero(); NUM_DIGEST_LIMBS];
+        for (limb, bits) in limbs
+            .iter_mut()
+            .zip(state.iter().flat_map(|lane| lane.chunks(32)))
+        {
+            *limb = self.le_sum(bits.iter());
+        }
+        limbs
+    }
+}
+
+/// Xor of two bits, folded when either is a constant
+fn xor<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: BoolTarget,
+    b: BoolTarget,
+) -> BoolTarget {
+    match (
+        builder.target_as_constant(a.target),
+        builder.target_as_constant(b.target),
+    ) {
+        (Some(a), _) if a.is_zero() => b,
+        (Some(_), _) => builder.not(b),
+        (_, Some(b)) if b.is_zero() => a,
+        (_, Some(_)) => builder.not(a),
+        // a + b - 2ab
+        _ => {
+            let a_minus_2ab = builder.arithmetic(-F::TWO, F::ONE, a.target, b.target, a.target);
+            BoolTarget::new_unsafe(builder.add(a_minus_2ab, b.target))
+        }
+    }
+}
+
+/// `!a & b`
+fn and_not<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: BoolTarget,
+    b: BoolTarget,
+) -> BoolTarget {
+    BoolTarget::new_unsafe(builder.arithmetic(F::NEG_ONE, F::ONE, a.target, b.target, b.target))
+}
+
+fn xor_lanes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: &Lane,
+    b: &Lane,
+) -> Lane {
+    std::array::from_fn(|i| xor(builder, a[i], b[i]))
+}
+
+fn rotate_left(lane: &Lane, n: usize) -> Lane {
+    std::array::from_fn(|i| lane[(i + 64 - n) % 64])
+}
+
+/// The keccak-f[1600] permutation of a state of lanes indexed by `x + 5 * y`
+fn keccak_f<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    state: &mut [Lane; 25],
+) {
+    for round_constant in ROUND_CONSTANTS {
+        // theta
+        let columns: Vec<Lane> = (0..5)
+            .map(|x| {
+                (1..5).fold(state[x], |column, y| {
+                    xor_lanes(builder, &column, &state[x + 5 * y])
+                })
+            })
+            .collect();
+        for x in 0..5 {
+            let rotated = rotate_left(&columns[(x + 1) % 5], 1);
+            let d = xor_lanes(builder, &columns[(x + 4) % 5], &rotated);
+            for y in 0..5 {
+                state[x + 5 * y] = xor_lanes(builder, &state[x + 5 * y], &d);
+            }
+        }
+
+        // rho and pi
+        let mut b = *state;
+        for x in 0..5 {
+            for y in 0..5 {
+                b[y + 5 * ((2 * x + 3 * y) % 5)] =
+                    rotate_left(&state[x + 5 * y], ROTATIONS[x + 5 * y]);
+            }
+        }
+
+        // chi
+        for x in 0..5 {
+            for y in 0..5 {
+                let (next, after) = (&b[(x + 1) % 5 + 5 * y], &b[(x + 2) % 5 + 5 * y]);
+                state[x + 5 * y] = std::array::from_fn(|i| {
+                    let t = and_not(builder, next[i], after[i]);
+                    xor(builder, b[x + 5 * y][i], t)
+                });
+            }
+        }
+
+        // iota
+        for (i, bit) in state[0].iter_mut().enumerate() {
+            if (round_constant >> i) & 1 == 1 {
+                *bit = builder.not(*bit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_keccak256() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // c5d24601...
+        assert_eq!(keccak256(b"")[..4], [0xc5, 0xd2, 0x46, 0x01]);
+
+        // a block with both padding bits in its last byte, and two blocks
+        let messages: Vec<Vec<u8>> = [135usize, 136]
+            .into_iter()
+            .map(|len| (0..len).map(|i| (i * 37 + len) as u8).collect())
+            .collect();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut pw = PartialWitness::new();
+        for message in &messages {
+            let bytes = builder.add_virtual_targets(message.len());
+            let digest = builder.keccak256(&bytes);
+            builder.register_public_inputs(&digest);
+            for (&target, &byte) in bytes.iter().zip(message) {
+                pw.set_target(target, F::from_canonical_u8(byte));
+            }
+        }
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+
+        let expected: Vec<F> = messages
+            .iter()
+            .flat_map(|message| digest_limbs(&keccak256(message)))
+            .collect();
+        assert_eq!(proof.public_inputs, expected);
+        data.verify(proof)
+    }
+}
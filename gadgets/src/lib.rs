@@ -0,0 +1,16 @@
+pub mod builder_ext;
+pub mod cap_opening;
+pub mod chunked_hash;
+pub mod fma_gate;
+pub mod hint;
+#[cfg(feature = "keccak")]
+pub mod keccak;
+pub mod merkle_update;
+#[cfg(feature = "nonnative")]
+pub mod modular;
+pub mod numeric_gate;
+#[cfg(feature = "secp256k1")]
+pub mod point_compression;
+#[cfg(feature = "poseidon2")]
+pub mod poseidon2;
+pub mod witness_ext;
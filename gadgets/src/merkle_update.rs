@@ -0,0 +1,147 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOutTarget, RichField};
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+
+/// The targets of `CircuitBuilderExt::verify_merkle_update`: the leaf at `leaf_index` is
+/// replaced by `new_leaf`, taking the tree from `old_root` to `new_root`
+#[derive(Clone, Debug)]
+pub struct MerkleUpdateTarget {
+    pub leaf_index: Target,
+    pub old_leaf: Vec<Target>,
+    pub new_leaf: Vec<Target>,
+    pub old_root: HashOutTarget,
+    pub new_root: HashOutTarget,
+    /// The siblings of the path, shared by the old and the new leaf
+    pub proof: MerkleProofTarget,
+}
+
+impl MerkleUpdateTarget {
+    /// Adds the targets of an update of a tree of `2^height` leaves of `leaf_len` elements
+    pub fn new<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        height: usize,
+        leaf_len: usize,
+    ) -> Self {
+        Self {
+            leaf_index: builder.add_virtual_target(),
+            old_leaf: builder.add_virtual_targets(leaf_len),
+            new_leaf: builder.add_virtual_targets(leaf_len),
+            old_root: builder.add_virtual_hash(),
+            new_root: builder.add_virtual_hash(),
+            proof: MerkleProofTarget {
+                siblings: builder.add_virtual_hashes(height),
+            },
+        }
+    }
+}
+
+/// The witness of replacing a leaf of a tree, computed natively
+#[derive(Clone, Debug)]
+pub struct MerkleUpdate<F: RichField, H: Hasher<F>> {
+    pub leaf_index: usize,
+    pub old_leaf: Vec<F>,
+    pub new_leaf: Vec<F>,
+    pub old_root: H::Hash,
+    pub new_root: H::Hash,
+    pub proof: MerkleProof<F, H>,
+}
+
+impl<F: RichField, H: Hasher<F>> MerkleUpdate<F, H> {
+    /// The update replacing the leaf at `leaf_index` of `tree` by `new_leaf`. The tree
+    /// should have a cap of a single hash, its root
+    pub fn new(tree: &MerkleTree<F, H>, leaf_index: usize, new_leaf: Vec<F>) -> Self {
+        assert_eq!(
+            tree.cap.0.len(),
+            1,
+            "the tree should have a cap height of 0"
+        );
+        let proof = tree.prove(leaf_index);
+        let new_root = root_of(&new_leaf, leaf_index, &proof);
+
+        Self {
+            leaf_index,
+            old_leaf: tree.leaves[leaf_index].clone(),
+            new_leaf,
+            old_root: tree.cap.0[0],
+            new_root,
+            proof,
+        }
+    }
+}
+
+/// The root of the tree with `leaf` at `leaf_index` and the siblings of `proof`
+pub fn root_of<F: RichField, H: Hasher<F>>(
+    leaf: &[F],
+    leaf_index: usize,
+    proof: &MerkleProof<F, H>,
+) -> H::Hash {
+    proof
+        .siblings
+        .iter()
+        .enumerate()
+        .fold(H::hash_or_noop(leaf), |current, (i, sibling)| {
+            if (leaf_index >> i) & 1 == 1 {
+                H::two_to_one(*sibling, current)
+            } else {
+                H::two_to_one(current, *sibling)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+    use crate::builder_ext::CircuitBuilderExt;
+    use crate::witness_ext::WitnessExt;
+
+    #[test]
+    fn test_merkle_update() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let height = 3;
+        let mut leaves: Vec<Vec<F>> = (0..1 << height).map(|_| F::rand_vec(4)).collect();
+        let tree = MerkleTree::<F, PoseidonHash>::new(leaves.clone(), 0);
+        let new_leaf = F::rand_vec(4);
+        let update = MerkleUpdate::new(&tree, 5, new_leaf.clone());
+        leaves[5] = new_leaf;
+        let new_tree = MerkleTree::<F, PoseidonHash>::new(leaves, 0);
+        assert_eq!(update.new_root, new_tree.cap.0[0]);
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let target = MerkleUpdateTarget::new(&mut builder, height, 4);
+        builder.verify_merkle_update::<PoseidonHash>(&target);
+        builder.register_public_inputs(&target.new_root.elements);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        pw.set_merkle_update(&target, &update);
+        let proof = data.prove(pw)?;
+        assert_eq!(proof.public_inputs, new_tree.cap.0[0].elements);
+        data.verify(proof)?;
+
+        // the new root of an update of another leaf
+        let mut wrong = update.clone();
+        wrong.new_root = MerkleUpdate::new(&tree, 4, F::rand_vec(4)).new_root;
+        let mut pw = PartialWitness::new();
+        pw.set_merkle_update(&target, &wrong);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw)));
+        assert!(!matches!(result, Ok(Ok(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+//! Inverses modulo a non-native modulus, e.g. the order of secp256k1 for the scalar
+//! arithmetic of ECDSA. The inverse is computed by a hint and checked with
+//! `a * inverse == quotient * modulus + 1` over the integers
+
+use num::{BigInt, BigUint, Integer, One};
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2_ecdsa::gadgets::biguint::{BigUintTarget, CircuitBuilderBiguint};
+use plonky2_u32::gadgets::arithmetic_u32::U32Target;
+
+use crate::builder_ext::CircuitBuilderExt;
+use crate::hint::Hint;
+
+/// The inverse of `a` modulo `modulus`, if they are coprime
+pub fn inverse_mod(a: &BigUint, modulus: &BigUint) -> Option<BigUint> {
+    let modulus = BigInt::from(modulus.clone());
+    let gcd = BigInt::from(a.clone()).extended_gcd(&modulus);
+    if !gcd.gcd.is_one() {
+        return None;
+    }
+    gcd.x.mod_floor(&modulus).to_biguint()
+}
+
+fn to_limbs<F: Field>(x: &BigUint, num_limbs: usize) -> Vec<F> {
+    let digits = x.to_u32_digits();
+    (0..num_limbs)
+        .map(|i| F::from_canonical_u32(digits.get(i).copied().unwrap_or(0)))
+        .collect()
+}
+
+fn num_limbs(x: &BigUint) -> usize {
+    x.to_u32_digits().len()
+}
+
+/// Computes the limbs of the inverse of the input modulo `modulus`, then those of the
+/// quotient of the input times the inverse minus one by the modulus
+#[derive(Clone, Debug)]
+struct ModInverse {
+    modulus: BigUint,
+    num_input_limbs: usize,
+}
+
+impl<F: RichField> Hint<F> for ModInverse {
+    fn num_outputs(&self) -> usize {
+        num_limbs(&self.modulus) + self.num_input_limbs
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        let limbs: Vec<u32> = inputs
+            .iter()
+            .map(|limb| limb.to_canonical_u64() as u32)
+            .collect();
+        let a = BigUint::from_slice(&limbs);
+        // without an inverse, the circuit fails on any output
+        let (inverse, quotient) = match inverse_mod(&a, &self.modulus) {
+            Some(inverse) => {
+                let quotient = (&a * &inverse - 1u32) / &self.modulus;
+                (inverse, quotient)
+            }
+            None => Default::default(),
+        };
+        [
+            to_limbs(&inverse, num_limbs(&self.modulus)),
+            to_limbs(&quotient, self.num_input_limbs),
+        ]
+        .concat()
+    }
+}
+
+pub trait CircuitBuilderModular<F: RichField + Extendable<D>, const D: usize> {
+    /// The inverse of `a` modulo `modulus`, reduced below the modulus. The product check
+    /// has no solution for an `a` that is zero modulo `modulus`, nor a zero inverse, so
+    /// proving fails for them
+    fn mod_inverse(&mut self, a: &BigUintTarget, modulus: &BigUint) -> BigUintTarget;
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderModular<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn mod_inverse(&mut self, a: &BigUintTarget, modulus: &BigUint) -> BigUintTarget {
+        assert!(*modulus > BigUint::one(), "no inverses modulo {modulus}");
+        let inputs: Vec<Target> = a.limbs.iter().map(|limb| limb.0).collect();
+        let outputs = self.add_hint(
+            ModInverse {
+                modulus: modulus.clone(),
+                num_input_limbs: a.num_limbs(),
+            },
+            &inputs,
+        );
+        for &limb in &outputs {
+            self.range_check(limb, 32);
+        }
+        let (inverse, quotient) = outputs.split_at(num_limbs(modulus));
+        let to_biguint = |limbs: &[Target]| BigUintTarget {
+            limbs: limbs.iter().copied().map(U32Target).collect(),
+        };
+        let (inverse, quotient) = (to_biguint(inverse), to_biguint(quotient));
+
+        let max = self.constant_biguint(&(modulus - 1u32));
+        let reduced = self.cmp_biguint(&inverse, &max);
+        self.assert_one(reduced.target);
+
+        let product = self.mul_biguint(a, &inverse);
+        let modulus = self.constant_biguint(modulus);
+        let multiple = self.mul_biguint(&quotient, &modulus);
+        let one = self.constant_biguint(&BigUint::one());
+        let multiple_plus_one = self.add_biguint(&multiple, &one);
+        self.connect_biguint(&product, &multiple_plus_one);
+
+        inverse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+    use plonky2::field::types::{PrimeField, Sample};
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_mod_inverse() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        assert_eq!(
+            inverse_mod(&BigUint::from(3u32), &BigUint::from(10u32)),
+            Some(BigUint::from(7u32))
+        );
+        assert_eq!(
+            inverse_mod(&BigUint::from(4u32), &BigUint::from(10u32)),
+            None
+        );
+
+        let order = Secp256K1Scalar::order();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_ecc_config());
+        let a = builder.add_virtual_biguint_target(8);
+        let inverse = builder.mod_inverse(&a, &order);
+        for limb in &inverse.limbs {
+            builder.register_public_input(limb.0);
+        }
+        let data = builder.build::<C>();
+        let prove = |value: &BigUint| {
+            let mut pw = PartialWitness::new();
+            for (limb, limb_value) in a.limbs.iter().zip(to_limbs::<F>(value, 8)) {
+                pw.set_target(limb.0, limb_value);
+            }
+            panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw)))
+        };
+
+        let value = Secp256K1Scalar::rand().to_canonical_biguint();
+        let proof = prove(&value).unwrap()?;
+        let expected = inverse_mod(&value, &order).unwrap();
+        assert_eq!(proof.public_inputs, to_limbs::<F>(&expected, 8));
+        assert!((value * expected % &order).is_one());
+        data.verify(proof)?;
+
+        // zero and multiples of the modulus have no inverse
+        assert!(!matches!(prove(&BigUint::default()), Ok(Ok(_))));
+        assert!(!matches!(prove(&order), Ok(Ok(_))));
+
+        Ok(())
+    }
+}
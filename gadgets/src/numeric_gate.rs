@@ -0,0 +1,224 @@
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::gates::gate::Gate;
+use plonky2::gates::util::StridedConstraintConsumer;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::ext_target::ExtensionTarget;
+use plonky2::iop::generator::{SimpleGenerator, WitnessGenerator};
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::vars::{EvaluationTargets, EvaluationVars, EvaluationVarsBase};
+
+use crate::hint::{Hint, HintGenerator};
+
+/// A gate computing `output = accumulator * input^2` for as many operations as the
+/// routed wires of a row allow, so that a product of squares takes one operation per factor
+#[derive(Copy, Clone, Debug)]
+pub struct NumericCustomGate {
+    pub num_ops: usize,
+}
+
+impl NumericCustomGate {
+    pub fn new_from_config(config: &CircuitConfig) -> Self {
+        Self {
+            num_ops: Self::num_ops(config),
+        }
+    }
+
+    pub(crate) fn num_ops(config: &CircuitConfig) -> usize {
+        config.num_routed_wires / 3
+    }
+
+    pub fn wire_ith_accumulator(i: usize) -> usize {
+        3 * i
+    }
+
+    pub fn wire_ith_input(i: usize) -> usize {
+        3 * i + 1
+    }
+
+    pub fn wire_ith_output(i: usize) -> usize {
+        3 * i + 2
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for NumericCustomGate {
+    fn id(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        (0..self.num_ops)
+            .map(|i| {
+                let accumulator = vars.local_wires[Self::wire_ith_accumulator(i)];
+                let input = vars.local_wires[Self::wire_ith_input(i)];
+                let output = vars.local_wires[Self::wire_ith_output(i)];
+
+                output - accumulator * input * input
+            })
+            .collect()
+    }
+
+    fn eval_unfiltered_base_one(
+        &self,
+        vars: EvaluationVarsBase<F>,
+        mut yield_constr: StridedConstraintConsumer<F>,
+    ) {
+        for i in 0..self.num_ops {
+            let accumulator = vars.local_wires[Self::wire_ith_accumulator(i)];
+            let input = vars.local_wires[Self::wire_ith_input(i)];
+            let output = vars.local_wires[Self::wire_ith_output(i)];
+
+            yield_constr.one(output - accumulator * input * input);
+        }
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        (0..self.num_ops)
+            .map(|i| {
+                let accumulator = vars.local_wires[Self::wire_ith_accumulator(i)];
+                let input = vars.local_wires[Self::wire_ith_input(i)];
+                let output = vars.local_wires[Self::wire_ith_output(i)];
+
+                let input_squared = builder.mul_extension(input, input);
+                let computed_output = builder.mul_extension(accumulator, input_squared);
+                builder.sub_extension(output, computed_output)
+            })
+            .collect()
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<Box<dyn WitnessGenerator<F>>> {
+        (0..self.num_ops)
+            .map(|i| {
+                let wire = |column| Target::wire(row, column);
+                let generator: Box<dyn WitnessGenerator<F>> = Box::new(
+                    HintGenerator::new(
+                        SquareMul,
+                        vec![
+                            wire(Self::wire_ith_accumulator(i)),
+                            wire(Self::wire_ith_input(i)),
+                        ],
+                        vec![wire(Self::wire_ith_output(i))],
+                    )
+                    .adapter(),
+                );
+                generator
+            })
+            .collect()
+    }
+
+    fn num_wires(&self) -> usize {
+        self.num_ops * 3
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        3
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.num_ops
+    }
+}
+
+/// Computes `accumulator * input^2`, the output of an operation of a `NumericCustomGate`
+#[derive(Clone, Copy, Debug)]
+pub struct SquareMul;
+
+impl<F: Field> Hint<F> for SquareMul {
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        vec![inputs[0] * inputs[1] * inputs[1]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::extension::FieldExtension;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::{Field, Field64};
+    use plonky2::gates::gate_testing::{test_eval_fns, test_low_degree};
+    use plonky2::hash::hash_types::HashOut;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const D: usize = 2;
+    type F = GoldilocksField;
+    type FE = <F as Extendable<D>>::Extension;
+
+    /// Wires of a row where every output is `accumulator * input^2`
+    fn valid_wires(gate: &NumericCustomGate, values: &[u64]) -> Vec<FE> {
+        let mut wires = vec![FE::ZERO; 3 * gate.num_ops];
+        for i in 0..gate.num_ops {
+            let accumulator = F::from_noncanonical_u64(values[2 * i]);
+            let input = F::from_noncanonical_u64(values[2 * i + 1]);
+            wires[NumericCustomGate::wire_ith_accumulator(i)] = FE::from_basefield(accumulator);
+            wires[NumericCustomGate::wire_ith_input(i)] = FE::from_basefield(input);
+            wires[NumericCustomGate::wire_ith_output(i)] =
+                FE::from_basefield(accumulator * input * input);
+        }
+        wires
+    }
+
+    fn eval(gate: &NumericCustomGate, wires: &[FE]) -> Vec<FE> {
+        gate.eval_unfiltered(EvaluationVars {
+            local_constants: &[],
+            local_wires: wires,
+            public_inputs_hash: &HashOut::ZERO,
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn valid_wires_satisfy_constraints(values in prop::collection::vec(any::<u64>(), 52)) {
+            let config = CircuitConfig::standard_recursion_config();
+            let gate = NumericCustomGate::new_from_config(&config);
+            let wires = valid_wires(&gate, &values);
+
+            prop_assert!(eval(&gate, &wires).iter().all(|c| *c == FE::ZERO));
+        }
+
+        #[test]
+        fn corrupted_outputs_violate_constraints(
+            values in prop::collection::vec(any::<u64>(), 52),
+            i in 0..26usize,
+            delta in 1..GoldilocksField::ORDER,
+        ) {
+            let config = CircuitConfig::standard_recursion_config();
+            let gate = NumericCustomGate::new_from_config(&config);
+            let mut wires = valid_wires(&gate, &values);
+            wires[NumericCustomGate::wire_ith_output(i)] += FE::from_canonical_u64(delta);
+
+            let constraints = eval(&gate, &wires);
+            prop_assert_ne!(constraints[i], FE::ZERO);
+        }
+    }
+
+    #[test]
+    fn low_degree() {
+        let gate = NumericCustomGate::new_from_config(&CircuitConfig::standard_recursion_config());
+        test_low_degree::<GoldilocksField, _, 4>(gate);
+    }
+
+    #[test]
+    fn eval_fns() -> Result<()> {
+        type C = PoseidonGoldilocksConfig;
+
+        let gate = NumericCustomGate::new_from_config(&CircuitConfig::standard_recursion_config());
+        test_eval_fns::<F, C, _, D>(gate)
+    }
+}
@@ -0,0 +1,234 @@
+//! Compressed secp256k1 points: the x coordinate and the parity of y, i.e. 9 public inputs
+//! instead of 16 for the limbs of both coordinates. Decompression recomputes y with a hint
+//! and checks that the point is on the curve and that y has the given parity
+
+use num::BigUint;
+use plonky2::field::extension::Extendable;
+use plonky2::field::secp256k1_base::Secp256K1Base;
+use plonky2::field::types::{Field, PrimeField};
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2_ecdsa::curve::curve_types::{AffinePoint, Curve};
+use plonky2_ecdsa::curve::secp256k1::Secp256K1;
+use plonky2_ecdsa::gadgets::biguint::{BigUintTarget, CircuitBuilderBiguint};
+use plonky2_ecdsa::gadgets::curve::{AffinePointTarget, CircuitBuilderCurve};
+use plonky2_ecdsa::gadgets::nonnative::CircuitBuilderNonNative;
+use plonky2_u32::gadgets::arithmetic_u32::U32Target;
+
+use crate::builder_ext::CircuitBuilderExt;
+use crate::hint::Hint;
+
+/// The number of 32-bit limbs of a coordinate
+const NUM_LIMBS: usize = 8;
+
+/// A point other than the point at infinity, by its x coordinate and the parity of its y
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressedPoint {
+    pub x: Secp256K1Base,
+    pub odd: bool,
+}
+
+fn is_odd(y: Secp256K1Base) -> bool {
+    y.to_canonical_biguint().bit(0)
+}
+
+/// The y coordinate of the point at `x` of parity `odd`, if `x` is on the curve
+fn recover_y(x: Secp256K1Base, odd: bool) -> Option<Secp256K1Base> {
+    let y = (x.cube() + Secp256K1::B).sqrt()?;
+    Some(if is_odd(y) == odd { y } else { -y })
+}
+
+impl CompressedPoint {
+    pub fn compress(point: &AffinePoint<Secp256K1>) -> Self {
+        Self {
+            x: point.x,
+            odd: is_odd(point.y),
+        }
+    }
+
+    /// The point, if `x` is the x coordinate of one
+    pub fn decompress(&self) -> Option<AffinePoint<Secp256K1>> {
+        recover_y(self.x, self.odd).map(|y| AffinePoint::nonzero(self.x, y))
+    }
+
+    /// The public inputs of `add_compressed_point_public_input`: the limbs of x, then the parity
+    pub fn public_inputs<F: Field>(&self) -> Vec<F> {
+        let digits = self.x.to_canonical_biguint().to_u32_digits();
+        (0..NUM_LIMBS)
+            .map(|i| F::from_canonical_u32(digits.get(i).copied().unwrap_or(0)))
+            .chain([F::from_bool(self.odd)])
+            .collect()
+    }
+}
+
+/// A compressed point in a circuit, `x` being the canonical limbs of the x coordinate
+#[derive(Clone, Debug)]
+pub struct CompressedPointTarget {
+    pub x: BigUintTarget,
+    pub odd: BoolTarget,
+}
+
+/// Sets the targets of a compressed point, e.g. of `add_compressed_point_public_input`
+pub fn set_compressed_point_target<F: RichField>(
+    pw: &mut PartialWitness<F>,
+    target: &CompressedPointTarget,
+    point: &CompressedPoint,
+) {
+    let values = point.public_inputs::<F>();
+    for (limb, &value) in target.x.limbs.iter().zip(&values) {
+        pw.set_target(limb.0, value);
+    }
+    pw.set_bool_target(target.odd, point.odd);
+}
+
+/// Computes the limbs of y from the limbs of x and the parity of y
+#[derive(Clone, Copy, Debug)]
+struct RecoverY;
+
+impl<F: RichField> Hint<F> for RecoverY {
+    fn num_outputs(&self) -> usize {
+        NUM_LIMBS
+    }
+
+    fn compute(&self, inputs: &[F]) -> Vec<F> {
+        let limbs: Vec<u32> = inputs[..NUM_LIMBS]
+            .iter()
+            .map(|limb| limb.to_canonical_u64() as u32)
+            .collect();
+        let x = Secp256K1Base::from_noncanonical_biguint(BigUint::from_slice(&limbs));
+        // off the curve, the circuit fails on any y
+        let y = recover_y(x, inputs[NUM_LIMBS].is_one()).unwrap_or(Secp256K1Base::ZERO);
+        let digits = y.to_canonical_biguint().to_u32_digits();
+        (0..NUM_LIMBS)
+            .map(|i| F::from_canonical_u32(digits.get(i).copied().unwrap_or(0)))
+            .collect()
+    }
+}
+
+pub trait CircuitBuilderCompression<F: RichField + Extendable<D>, const D: usize> {
+    fn compress_point(&mut self, point: &AffinePointTarget<Secp256K1>) -> CompressedPointTarget;
+
+    /// The point of a compressed point, checked to be on the curve
+    fn decompress_point(&mut self, point: &CompressedPointTarget) -> AffinePointTarget<Secp256K1>;
+
+    /// Adds a compressed point whose limbs and parity are public inputs, as laid out by
+    /// `CompressedPoint::public_inputs`
+    fn add_compressed_point_public_input(&mut self) -> CompressedPointTarget;
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderCompression<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn compress_point(&mut self, point: &AffinePointTarget<Secp256K1>) -> CompressedPointTarget {
+        let y = self.nonnative_to_canonical_biguint(&point.y);
+        CompressedPointTarget {
+            x: self.nonnative_to_canonical_biguint(&point.x),
+            odd: lowest_bit(self, &y),
+        }
+    }
+
+    fn decompress_point(&mut self, point: &CompressedPointTarget) -> AffinePointTarget<Secp256K1> {
+        let inputs: Vec<Target> = point
+            .x
+            .limbs
+            .iter()
+            .map(|limb| limb.0)
+            .chain([point.odd.target])
+            .collect();
+        let limbs = self.add_hint(RecoverY, &inputs);
+        for &limb in &limbs {
+            self.range_check(limb, 32);
+        }
+        let y = self.biguint_to_nonnative(&BigUintTarget {
+            limbs: limbs.into_iter().map(U32Target).collect(),
+        });
+
+        let affine = AffinePointTarget {
+            x: self.biguint_to_nonnative(&point.x),
+            y,
+        };
+        self.curve_assert_valid(&affine);
+        let canonical_y = self.nonnative_to_canonical_biguint(&affine.y);
+        let odd = lowest_bit(self, &canonical_y);
+        self.connect(odd.target, point.odd.target);
+
+        affine
+    }
+
+    fn add_compressed_point_public_input(&mut self) -> CompressedPointTarget {
+        let limbs: Vec<Target> = (0..NUM_LIMBS)
+            .map(|_| {
+                let limb = self.add_virtual_public_input();
+                self.range_check(limb, 32);
+                limb
+            })
+            .collect();
+        let odd = self.add_virtual_bool_target_safe();
+        self.register_public_input(odd.target);
+
+        // x + p fits in the limbs for small x, so the limbs are checked to be canonical
+        let x = BigUintTarget {
+            limbs: limbs.into_iter().map(U32Target).collect(),
+        };
+        let nonnative = self.biguint_to_nonnative::<Secp256K1Base>(&x);
+        let canonical = self.nonnative_to_canonical_biguint(&nonnative);
+        self.connect_biguint(&x, &canonical);
+
+        CompressedPointTarget { x, odd }
+    }
+}
+
+fn lowest_bit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: &BigUintTarget,
+) -> BoolTarget {
+    builder.split_le(x.limbs[0].0, 32)[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+    use plonky2::field::types::Sample;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use plonky2_ecdsa::curve::curve_types::CurveScalar;
+
+    use super::*;
+
+    #[test]
+    fn test_point_compression() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let point =
+            (CurveScalar(Secp256K1Scalar::rand()) * Secp256K1::GENERATOR_PROJECTIVE).to_affine();
+        let compressed = CompressedPoint::compress(&point);
+        assert_eq!(compressed.decompress(), Some(point));
+
+        // the point is passed as a compressed public input and decompressed in the circuit
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_ecc_config());
+        let target = builder.add_compressed_point_public_input();
+        let affine = builder.decompress_point(&target);
+        let expected = builder.constant_affine_point(point);
+        builder.connect_affine_point(&affine, &expected);
+        let recompressed = builder.compress_point(&affine);
+        builder.connect(recompressed.odd.target, target.odd.target);
+
+        let mut pw = PartialWitness::new();
+        set_compressed_point_target(&mut pw, &target, &compressed);
+        let data = builder.build::<C>();
+        let mut proof = data.prove(pw)?;
+        assert_eq!(proof.public_inputs, compressed.public_inputs::<F>());
+        data.verify(proof.clone())?;
+
+        // the other point at the same x
+        proof.public_inputs[NUM_LIMBS] = F::from_bool(!compressed.odd);
+        assert!(data.verify(proof).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,672 @@
+//! Poseidon2 over Goldilocks with a width of 12: the permutation, a plonky2 `Hasher` built
+//! on it, and a gate checking one permutation per row like plonky2's `PoseidonGate`. The
+//! round constants are generated by `scripts/poseidon2_constants.py` and aren't those of
+//! the reference implementation, so hashes don't match other Poseidon2 instances
+
+use std::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::gates::gate::Gate;
+use plonky2::gates::util::StridedConstraintConsumer;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::hash::hashing::{compress, hash_n_to_hash_no_pad, PlonkyPermutation, SPONGE_WIDTH};
+use plonky2::iop::ext_target::ExtensionTarget;
+use plonky2::iop::generator::{GeneratedValues, SimpleGenerator, WitnessGenerator};
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartitionWitness, Witness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::{AlgebraicHasher, Hasher};
+use plonky2::plonk::vars::{EvaluationTargets, EvaluationVars, EvaluationVarsBase};
+
+pub const WIDTH: usize = SPONGE_WIDTH;
+const HALF_ROUNDS_F: usize = 4;
+const ROUNDS_P: usize = 22;
+
+/// The 4x4 block of the external matrix, which is `circ(2 * M4, M4, M4)`
+const M4: [[u64; 4]; 4] = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]];
+
+/// The constants of the full rounds, those before the partial rounds first
+const FULL_ROUND_CONSTANTS: [u64; 96] = [
+    0x13dcf33aba214f46,
+    0x30b3b654a1da6d83,
+    0x1fc634ada6159b56,
+    0x937459964dc03466,
+    0xedd2ef2ca7949924,
+    0xede9affde0e22f68,
+    0x8515b9d6bac9282d,
+    0x6b5c07b4e9e900d8,
+    0x1ec66368838c8a08,
+    0x9042367d80d1fbab,
+    0x400283564a3c3799,
+    0x4a00be0466bca75e,
+    0x7913beee58e3817f,
+    0xf545e88532237d90,
+    0x22f8cb8736042005,
+    0x6f04990e247a2623,
+    0xfe22e87ba37c38cd,
+    0xd20e32c85ffe2815,
+    0x117227674048fe73,
+    0x4e9fb7ea98a6b145,
+    0xe0866c232b8af08b,
+    0x00bbc77916884964,
+    0x7031c0fb990d7116,
+    0x240a9e87cf35108f,
+    0x2e6363a5a12244b3,
+    0x5e1c3787d1b5011c,
+    0x4132660e2a196e8b,
+    0x3a013b648d3d4327,
+    0xf79839f49888ea43,
+    0xfe85658ebafe1439,
+    0xb6889825a14240bd,
+    0x578453605541382b,
+    0x4508cda8f6b63ce9,
+    0x9c3ef35848684c91,
+    0x0812bde23c87178c,
+    0xfe49638f7f722c14,
+    0x8e3f688ce885cbf5,
+    0xb8e110acf746a87d,
+    0xb4b2e8973a6dabef,
+    0x9e714c5da3d462ec,
+    0x6438f9033d3d0c15,
+    0x24312f7cf1a27199,
+    0x23f843bb47acbf71,
+    0x9183f11a34be9f01,
+    0x839062fbb9d45dbf,
+    0x24b56e7e6c2e43fa,
+    0xe1683da61c962a72,
+    0xa95c63971a19bfa7,
+    0x9271d450fc9b4117,
+    0xcffeea06b6e3aac1,
+    0xfa4a44c748d1cd8e,
+    0xe64db01ba569b469,
+    0xd31005160e4045fe,
+    0x39e0fa013e025f79,
+    0xe243be574196a956,
+    0x205b2a681e3d2642,
+    0x79cae5ad93486bab,
+    0xfdf567844e32c295,
+    0x331679589bfb7189,
+    0xaf06ee32297b89c2,
+    0xa6bcae311e498491,
+    0x9d16f52c96ac8b3e,
+    0x48a674b59393fa35,
+    0x0f9e65da3fde3796,
+    0x1e098310fc84578c,
+    0x559ae5fab1ae8dad,
+    0x56bd4d624078881d,
+    0xfd8bbbf8fbe817b5,
+    0x82d30695c44df534,
+    0x3ec0a97bc41127c5,
+    0x1eb8b64adaa22078,
+    0x82c45e418d60c983,
+    0xb092280f484d55bf,
+    0xcd317c9537697939,
+    0xd3be2e352feb79f3,
+    0xca6d866539a390e5,
+    0xb5efb1a494e55ee6,
+    0xfa9013ac89756e9e,
+    0xaeb88efd1e981242,
+    0x13ee477cdab6e0dc,
+    0xce7df902c40da2d3,
+    0xf3fbaf0d4e6f5f34,
+    0xf96354ada6785f38,
+    0x13b5692812406886,
+    0xf03cae030a0f4418,
+    0x7d3172887aa98e1a,
+    0x8a2c2644f2faf7b9,
+    0x80d721abee696d00,
+    0x27c8b903a4d68267,
+    0xaf0b7b12f90291b8,
+    0x00acd08cfdff3817,
+    0x4659ee496c634328,
+    0xf5b25c10730dbff1,
+    0xdde3a153297329c2,
+    0x50c0b70d6910a44b,
+    0x23c7426af725a6a0,
+];
+/// The constants added to the first element in the partial rounds
+const PARTIAL_ROUND_CONSTANTS: [u64; 22] = [
+    0x4adf842aa75d4316,
+    0x3f36b9fe72ad4e5f,
+    0x9717f025e7daf6a5,
+    0xac4bb7c627cf7c13,
+    0x047d766678f13875,
+    0xbfce13201f3f7e6b,
+    0x70971fc4e6f85305,
+    0xe2a6e06e61fcec9c,
+    0xdf58134c134491c2,
+    0x1c4bd1e816050a7e,
+    0xf8a6cd02e92cdb0b,
+    0x4c0f5fc6c0dda3d1,
+    0x0a4a11d794be40a2,
+    0x6d3fbd3b4a9f1de6,
+    0x0d0c371c5b35b850,
+    0x2cff3000be1fcd0a,
+    0xd5ef60d6f76a42fa,
+    0x942069f5d6eece7e,
+    0x8b62a5551e9a9797,
+    0x4f88cdcdfb791921,
+    0xab21b42e0f642307,
+    0x587fa39990b62800,
+];
+/// The internal matrix is the all-ones matrix plus this diagonal
+const INTERNAL_DIAGONAL: [u64; 12] = [34, 35, 36, 37, 38, 39, 64, 128, 256, 512, 1024, 2048];
+
+fn sbox<T: Field>(x: T) -> T {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    let x4 = x2 * x2;
+    x3 * x4
+}
+
+fn external_layer<T: Field>(state: &mut [T; WIDTH]) {
+    let mut blocks = [[T::ZERO; 4]; WIDTH / 4];
+    for (b, block) in blocks.iter_mut().enumerate() {
+        for (out, row) in block.iter_mut().zip(M4) {
+            for (j, coefficient) in row.into_iter().enumerate() {
+                *out += T::from_canonical_u64(coefficient) * state[4 * b + j];
+            }
+        }
+    }
+
+    // each block gets twice its own product with M4, and once that of every other block
+    let mut sums = [T::ZERO; 4];
+    for block in &blocks {
+        for (sum, x) in sums.iter_mut().zip(block) {
+            *sum += *x;
+        }
+    }
+    for (b, block) in blocks.iter().enumerate() {
+        for (i, (x, sum)) in block.iter().zip(sums).enumerate() {
+            state[4 * b + i] = *x + sum;
+        }
+    }
+}
+
+fn internal_layer<T: Field>(state: &mut [T; WIDTH]) {
+    let sum = state.iter().fold(T::ZERO, |acc, x| acc + *x);
+    for (x, diagonal) in state.iter_mut().zip(INTERNAL_DIAGONAL) {
+        *x = sum + T::from_canonical_u64(diagonal) * *x;
+    }
+}
+
+fn full_round_constants(round: usize) -> &'static [u64] {
+    &FULL_ROUND_CONSTANTS[WIDTH * round..WIDTH * (round + 1)]
+}
+
+/// Applies the permutation, passing the input of each S-box which has a wire in
+/// `Poseidon2Gate` to `sbox_input` together with the wire, and continuing with the value it
+/// returns
+fn permute_with<T: Field>(
+    mut state: [T; WIDTH],
+    mut sbox_input: impl FnMut(usize, T) -> T,
+) -> [T; WIDTH] {
+    external_layer(&mut state);
+    for round in 0..HALF_ROUNDS_F {
+        for (i, (x, constant)) in state
+            .iter_mut()
+            .zip(full_round_constants(round))
+            .enumerate()
+        {
+            *x += T::from_canonical_u64(*constant);
+            if round != 0 {
+                *x = sbox_input(Poseidon2Gate::wire_first_full_sbox(round, i), *x);
+            }
+            *x = sbox(*x);
+        }
+        external_layer(&mut state);
+    }
+
+    for (round, constant) in PARTIAL_ROUND_CONSTANTS.into_iter().enumerate() {
+        state[0] += T::from_canonical_u64(constant);
+        state[0] = sbox_input(Poseidon2Gate::wire_partial_sbox(round), state[0]);
+        state[0] = sbox(state[0]);
+        internal_layer(&mut state);
+    }
+
+    for round in 0..HALF_ROUNDS_F {
+        for (i, (x, constant)) in state
+            .iter_mut()
+            .zip(full_round_constants(HALF_ROUNDS_F + round))
+            .enumerate()
+        {
+            *x += T::from_canonical_u64(*constant);
+            *x = sbox_input(Poseidon2Gate::wire_second_full_sbox(round, i), *x);
+            *x = sbox(*x);
+        }
+        external_layer(&mut state);
+    }
+
+    state
+}
+
+pub fn permute<T: Field>(state: [T; WIDTH]) -> [T; WIDTH] {
+    permute_with(state, |_, x| x)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Poseidon2Permutation;
+
+impl<F: RichField> PlonkyPermutation<F> for Poseidon2Permutation {
+    fn permute(input: [F; SPONGE_WIDTH]) -> [F; SPONGE_WIDTH] {
+        permute(input)
+    }
+}
+
+/// The sponge of plonky2's `PoseidonHash` over the Poseidon2 permutation, which can replace
+/// it wherever plonky2 takes an `AlgebraicHasher`, e.g. in `MerkleTree` or
+/// `CircuitBuilder::hash_n_to_hash_no_pad`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Poseidon2Hash;
+
+impl<F: RichField> Hasher<F> for Poseidon2Hash {
+    const HASH_SIZE: usize = 4 * 8;
+    type Hash = HashOut<F>;
+    type Permutation = Poseidon2Permutation;
+
+    fn hash_no_pad(input: &[F]) -> Self::Hash {
+        hash_n_to_hash_no_pad::<F, Self::Permutation>(input)
+    }
+
+    fn two_to_one(left: Self::Hash, right: Self::Hash) -> Self::Hash {
+        compress::<F, Self::Permutation>(left, right)
+    }
+}
+
+impl<F: RichField> AlgebraicHasher<F> for Poseidon2Hash {
+    fn permute_swapped<const D: usize>(
+        inputs: [Target; SPONGE_WIDTH],
+        swap: BoolTarget,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> [Target; SPONGE_WIDTH]
+    where
+        F: RichField + Extendable<D>,
+    {
+        let row = builder.add_gate(Poseidon2Gate, vec![]);
+        builder.connect(swap.target, Target::wire(row, Poseidon2Gate::WIRE_SWAP));
+        for (i, input) in inputs.into_iter().enumerate() {
+            builder.connect(input, Target::wire(row, Poseidon2Gate::wire_input(i)));
+        }
+
+        core::array::from_fn(|i| Target::wire(row, Poseidon2Gate::wire_output(i)))
+    }
+}
+
+/// A gate checking one permutation of its inputs, after swapping their first two chunks of
+/// 4 elements if the swap wire is set, as Merkle proofs need. Each S-box input has its own
+/// wire except in the first round, where it is linear in the inputs, so that the
+/// constraints are of degree 7 and the gate takes the 135 wires of a standard row. As with
+/// plonky2's `PoseidonGate`, a permutation, partial rounds included, is a single row, so
+/// there are no rows to save by batching partial rounds
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Poseidon2Gate;
+
+impl Poseidon2Gate {
+    pub const WIRE_SWAP: usize = 2 * WIDTH;
+    const START_DELTA: usize = 2 * WIDTH + 1;
+    const START_FIRST_FULL: usize = Self::START_DELTA + 4;
+    const START_PARTIAL: usize = Self::START_FIRST_FULL + WIDTH * (HALF_ROUNDS_F - 1);
+    const START_SECOND_FULL: usize = Self::START_PARTIAL + ROUNDS_P;
+    const END: usize = Self::START_SECOND_FULL + WIDTH * HALF_ROUNDS_F;
+
+    pub fn wire_input(i: usize) -> usize {
+        i
+    }
+
+    pub fn wire_output(i: usize) -> usize {
+        WIDTH + i
+    }
+
+    /// `swap * (input[i + 4] - input[i])`, which moves between the swapped chunks
+    fn wire_delta(i: usize) -> usize {
+        Self::START_DELTA + i
+    }
+
+    fn wire_first_full_sbox(round: usize, i: usize) -> usize {
+        debug_assert!(round != 0);
+        Self::START_FIRST_FULL + WIDTH * (round - 1) + i
+    }
+
+    fn wire_partial_sbox(round: usize) -> usize {
+        Self::START_PARTIAL + round
+    }
+
+    fn wire_second_full_sbox(round: usize, i: usize) -> usize {
+        Self::START_SECOND_FULL + WIDTH * round + i
+    }
+
+    /// The constraints over the values of the wires, in either field
+    fn eval<T: Field>(wire: impl Fn(usize) -> T, mut constrain: impl FnMut(T)) {
+        let swap = wire(Self::WIRE_SWAP);
+        constrain(swap * swap - swap);
+
+        let mut state: [T; WIDTH] = core::array::from_fn(|i| wire(Self::wire_input(i)));
+        for i in 0..4 {
+            let delta = wire(Self::wire_delta(i));
+            constrain(swap * (state[i + 4] - state[i]) - delta);
+            state[i] += delta;
+            state[i + 4] -= delta;
+        }
+
+        let output = permute_with(state, |column, x| {
+            let value = wire(column);
+            constrain(x - value);
+            value
+        });
+        for (i, x) in output.into_iter().enumerate() {
+            constrain(x - wire(Self::wire_output(i)));
+        }
+    }
+}
+
+fn sbox_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: ExtensionTarget<D>,
+) -> ExtensionTarget<D> {
+    let x2 = builder.mul_extension(x, x);
+    let x3 = builder.mul_extension(x2, x);
+    let x4 = builder.mul_extension(x2, x2);
+    builder.mul_extension(x3, x4)
+}
+
+fn external_layer_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    state: &mut [ExtensionTarget<D>; WIDTH],
+) {
+    let zero = builder.zero_extension();
+    let mut blocks = [[zero; 4]; WIDTH / 4];
+    for (b, block) in blocks.iter_mut().enumerate() {
+        for (out, row) in block.iter_mut().zip(M4) {
+            for (j, coefficient) in row.into_iter().enumerate() {
+                *out = builder.mul_const_add_extension(
+                    F::from_canonical_u64(coefficient),
+                    state[4 * b + j],
+                    *out,
+                );
+            }
+        }
+    }
+
+    let mut sums = [zero; 4];
+    for block in &blocks {
+        for (sum, x) in sums.iter_mut().zip(block) {
+            *sum = builder.add_extension(*sum, *x);
+        }
+    }
+    for (b, block) in blocks.iter().enumerate() {
+        for (i, (x, sum)) in block.iter().zip(sums).enumerate() {
+            state[4 * b + i] = builder.add_extension(*x, sum);
+        }
+    }
+}
+
+fn internal_layer_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    state: &mut [ExtensionTarget<D>; WIDTH],
+) {
+    let sum = builder.add_many_extension(state.iter());
+    for (x, diagonal) in state.iter_mut().zip(INTERNAL_DIAGONAL) {
+        *x = builder.mul_const_add_extension(F::from_canonical_u64(diagonal), *x, sum);
+    }
+}
+
+fn add_constant_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: ExtensionTarget<D>,
+    constant: u64,
+) -> ExtensionTarget<D> {
+    let constant = builder.constant_extension(F::Extension::from_canonical_u64(constant));
+    builder.add_extension(x, constant)
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for Poseidon2Gate {
+    fn id(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        let mut constraints = Vec::with_capacity(<Self as Gate<F, D>>::num_constraints(self));
+        Self::eval(|column| vars.local_wires[column], |c| constraints.push(c));
+        constraints
+    }
+
+    fn eval_unfiltered_base_one(
+        &self,
+        vars: EvaluationVarsBase<F>,
+        mut yield_constr: StridedConstraintConsumer<F>,
+    ) {
+        Self::eval(|column| vars.local_wires[column], |c| yield_constr.one(c));
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        let wire = |column: usize| vars.local_wires[column];
+        let mut constraints = Vec::with_capacity(<Self as Gate<F, D>>::num_constraints(self));
+
+        let swap = wire(Self::WIRE_SWAP);
+        constraints.push(builder.mul_sub_extension(swap, swap, swap));
+
+        let mut state: [ExtensionTarget<D>; WIDTH] =
+            core::array::from_fn(|i| wire(Self::wire_input(i)));
+        for i in 0..4 {
+            let delta = wire(Self::wire_delta(i));
+            let diff = builder.sub_extension(state[i + 4], state[i]);
+            constraints.push(builder.mul_sub_extension(swap, diff, delta));
+            state[i] = builder.add_extension(state[i], delta);
+            state[i + 4] = builder.sub_extension(state[i + 4], delta);
+        }
+
+        // mirrors `permute_with`, substituting the wires for the S-box inputs
+        let mut sbox_input =
+            |builder: &mut CircuitBuilder<F, D>, column: usize, x: ExtensionTarget<D>| {
+                let value = wire(column);
+                constraints.push(builder.sub_extension(x, value));
+                value
+            };
+        external_layer_circuit(builder, &mut state);
+        for round in 0..HALF_ROUNDS_F {
+            for (i, constant) in full_round_constants(round).iter().enumerate() {
+                let mut x = add_constant_circuit(builder, state[i], *constant);
+                if round != 0 {
+                    x = sbox_input(builder, Self::wire_first_full_sbox(round, i), x);
+                }
+                state[i] = sbox_circuit(builder, x);
+            }
+            external_layer_circuit(builder, &mut state);
+        }
+
+        for (round, constant) in PARTIAL_ROUND_CONSTANTS.into_iter().enumerate() {
+            let x = add_constant_circuit(builder, state[0], constant);
+            let x = sbox_input(builder, Self::wire_partial_sbox(round), x);
+            state[0] = sbox_circuit(builder, x);
+            internal_layer_circuit(builder, &mut state);
+        }
+
+        for round in 0..HALF_ROUNDS_F {
+            for (i, constant) in full_round_constants(HALF_ROUNDS_F + round)
+                .iter()
+                .enumerate()
+            {
+                let x = add_constant_circuit(builder, state[i], *constant);
+                let x = sbox_input(builder, Self::wire_second_full_sbox(round, i), x);
+                state[i] = sbox_circuit(builder, x);
+            }
+            external_layer_circuit(builder, &mut state);
+        }
+
+        for (i, x) in state.into_iter().enumerate() {
+            constraints.push(builder.sub_extension(x, wire(Self::wire_output(i))));
+        }
+        constraints
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<Box<dyn WitnessGenerator<F>>> {
+        let generator = Poseidon2Generator::<F, D> {
+            row,
+            _phantom: PhantomData,
+        };
+        vec![Box::new(generator.adapter())]
+    }
+
+    fn num_wires(&self) -> usize {
+        Self::END
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        7
+    }
+
+    fn num_constraints(&self) -> usize {
+        // the swap flag, the deltas, every S-box input with a wire and the outputs
+        1 + 4 + (Self::END - Self::START_FIRST_FULL) + WIDTH
+    }
+}
+
+/// Computes the deltas, S-box inputs and outputs of a `Poseidon2Gate` row
+#[derive(Clone, Debug)]
+pub struct Poseidon2Generator<F: RichField + Extendable<D>, const D: usize> {
+    row: usize,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F> for Poseidon2Generator<F, D> {
+    fn dependencies(&self) -> Vec<Target> {
+        (0..WIDTH)
+            .map(Poseidon2Gate::wire_input)
+            .chain([Poseidon2Gate::WIRE_SWAP])
+            .map(|column| Target::wire(self.row, column))
+            .collect()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let get_wire = |column: usize| witness.get_target(Target::wire(self.row, column));
+
+        let swap = get_wire(Poseidon2Gate::WIRE_SWAP);
+        assert!(
+            swap == F::ZERO || swap == F::ONE,
+            "the swap flag isn't a bit"
+        );
+        let mut state: [F; WIDTH] =
+            core::array::from_fn(|i| get_wire(Poseidon2Gate::wire_input(i)));
+        for i in 0..4 {
+            let delta = swap * (state[i + 4] - state[i]);
+            out_buffer.set_target(Target::wire(self.row, Poseidon2Gate::wire_delta(i)), delta);
+            state[i] += delta;
+            state[i + 4] -= delta;
+        }
+
+        let output = permute_with(state, |column, x| {
+            out_buffer.set_target(Target::wire(self.row, column), x);
+            x
+        });
+        for (i, x) in output.into_iter().enumerate() {
+            out_buffer.set_target(Target::wire(self.row, Poseidon2Gate::wire_output(i)), x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Sample;
+    use plonky2::gates::gate_testing::{test_eval_fns, test_low_degree};
+    use plonky2::hash::merkle_proofs::MerkleProofTarget;
+    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+    use crate::builder_ext::CircuitBuilderExt;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_permutation() {
+        let input = core::array::from_fn(|i| F::from_canonical_usize(i));
+        let expected = [
+            0x7bec3e3c1e5da186,
+            0x55d7fd522cc0ecfd,
+            0xbef667690b000b45,
+            0xac22bcec8779d726,
+            0xb08d370156b2ef24,
+            0xd89afbf582ee6d60,
+            0xd00bed5bcc9c1e18,
+            0xa53774ad0e5ec323,
+            0x35571966c6e00cdf,
+            0x0c02fbd7364f3e6c,
+            0xd2152d7ea3f24700,
+            0x581fc27be4383008,
+        ];
+        assert_eq!(permute(input), expected.map(F::from_canonical_u64));
+
+        let input = F::rand_array();
+        assert_ne!(
+            Poseidon2Hash::hash_no_pad(&input),
+            PoseidonHash::hash_no_pad(&input)
+        );
+    }
+
+    #[test]
+    fn test_merkle_membership_circuit() -> Result<()> {
+        let leaves: Vec<Vec<F>> = (0..16).map(|_| F::rand_vec(4)).collect();
+        let tree = MerkleTree::<F, Poseidon2Hash>::new(leaves.clone(), 1);
+        let leaf_index = 13;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let leaf_data = builder.add_virtual_targets(4);
+        let index = builder.add_virtual_target();
+        let cap = builder.add_virtual_cap(1);
+        let proof = MerkleProofTarget {
+            siblings: builder.add_virtual_hashes(3),
+        };
+        builder.verify_merkle_membership_to_cap::<Poseidon2Hash>(
+            leaf_data.clone(),
+            index,
+            &cap,
+            &proof,
+        );
+        let nullifier = builder.hash_n_to_hash_no_pad::<Poseidon2Hash>(leaf_data.clone());
+        builder.register_public_inputs(&nullifier.elements);
+
+        let mut pw = PartialWitness::new();
+        for (target, value) in leaf_data.iter().zip(&leaves[leaf_index]) {
+            pw.set_target(*target, *value);
+        }
+        pw.set_target(index, F::from_canonical_usize(leaf_index));
+        pw.set_cap_target(&cap, &tree.cap);
+        for (target, sibling) in proof.siblings.iter().zip(tree.prove(leaf_index).siblings) {
+            pw.set_hash_target(*target, sibling);
+        }
+
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+        assert_eq!(
+            proof.public_inputs,
+            Poseidon2Hash::hash_no_pad(&leaves[leaf_index]).elements
+        );
+        data.verify(proof)
+    }
+
+    #[test]
+    fn low_degree() {
+        test_low_degree::<GoldilocksField, _, 4>(Poseidon2Gate);
+    }
+
+    #[test]
+    fn eval_fns() -> Result<()> {
+        test_eval_fns::<F, C, _, D>(Poseidon2Gate)
+    }
+}
@@ -0,0 +1,137 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_data::{VerifierCircuitTarget, VerifierOnlyCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+
+use crate::cap_opening::{CapOpening, CapOpeningTarget};
+use crate::merkle_update::{MerkleUpdate, MerkleUpdateTarget};
+
+/// Extension methods setting the targets added by `CircuitBuilderExt`
+pub trait WitnessExt<F: RichField> {
+    /// Sets both the constants-sigmas cap and the circuit digest of `target`
+    fn set_verifier_data<C: GenericConfig<D, F = F>, const D: usize>(
+        &mut self,
+        target: &VerifierCircuitTarget,
+        verifier_data: &VerifierOnlyCircuitData<C, D>,
+    ) where
+        F: Extendable<D>,
+        C::Hasher: AlgebraicHasher<F>;
+
+    /// Sets the targets of an update to the values computed by `MerkleUpdate::new`
+    fn set_merkle_update<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        target: &MerkleUpdateTarget,
+        update: &MerkleUpdate<F, H>,
+    );
+
+    /// Sets the row and path of an opening to those computed by `CapOpening::new`
+    fn set_cap_opening<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        target: &CapOpeningTarget,
+        opening: &CapOpening<F, H>,
+    );
+}
+
+impl<F: RichField, W: WitnessWrite<F>> WitnessExt<F> for W {
+    fn set_verifier_data<C: GenericConfig<D, F = F>, const D: usize>(
+        &mut self,
+        target: &VerifierCircuitTarget,
+        verifier_data: &VerifierOnlyCircuitData<C, D>,
+    ) where
+        F: Extendable<D>,
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        self.set_cap_target(
+            &target.constants_sigmas_cap,
+            &verifier_data.constants_sigmas_cap,
+        );
+        self.set_hash_target(target.circuit_digest, verifier_data.circuit_digest);
+    }
+
+    fn set_merkle_update<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        target: &MerkleUpdateTarget,
+        update: &MerkleUpdate<F, H>,
+    ) {
+        self.set_target(
+            target.leaf_index,
+            F::from_canonical_usize(update.leaf_index),
+        );
+        for (t, value) in target.old_leaf.iter().zip(&update.old_leaf) {
+            self.set_target(*t, *value);
+        }
+        for (t, value) in target.new_leaf.iter().zip(&update.new_leaf) {
+            self.set_target(*t, *value);
+        }
+        self.set_hash_target(target.old_root, update.old_root);
+        self.set_hash_target(target.new_root, update.new_root);
+        for (t, sibling) in target.proof.siblings.iter().zip(&update.proof.siblings) {
+            self.set_hash_target(*t, *sibling);
+        }
+    }
+    fn set_cap_opening<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        target: &CapOpeningTarget,
+        opening: &CapOpening<F, H>,
+    ) {
+        for (t, value) in target.leaf.iter().zip(&opening.leaf) {
+            self.set_target(*t, *value);
+        }
+        for (t, sibling) in target.proof.siblings.iter().zip(&opening.proof.siblings) {
+            self.set_hash_target(*t, *sibling);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, Witness};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::builder_ext::CircuitBuilderExt;
+
+    #[test]
+    fn test_set_verifier_data() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+        let config = CircuitConfig::standard_recursion_config();
+
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let x = builder.add_virtual_target();
+        let x_squared = builder.square(x);
+        builder.register_public_input(x_squared);
+        let inner_data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::from_canonical_u64(3));
+        let inner_proof = inner_data.prove(pw)?;
+
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let proof_target = builder.add_virtual_proof_with_pis::<C>(&inner_data.common);
+        let verifier_target =
+            builder.add_verifier_data_target(inner_data.common.config.fri_config.cap_height);
+        builder.verify_proof::<C>(&proof_target, &verifier_target, &inner_data.common);
+
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&proof_target, &inner_proof);
+        pw.set_verifier_data(&verifier_target, &inner_data.verifier_only);
+        // the digest used to be left unset, leaving the generators depending on it unrun
+        assert_eq!(
+            verifier_target
+                .circuit_digest
+                .elements
+                .map(|t| pw.try_get_target(t)),
+            inner_data.verifier_only.circuit_digest.elements.map(Some)
+        );
+
+        let data = builder.build::<C>();
+        let proof = data.prove(pw)?;
+        data.verify(proof)
+    }
+}
@@ -0,0 +1,196 @@
+use thiserror::Error;
+
+/// Errors surfaced by the circuit wrappers and semaphore APIs of this workspace
+#[derive(Debug, Error)]
+pub enum ProofError {
+    #[error("the user should provide {expected} values, not {actual}")]
+    WitnessCount { expected: usize, actual: usize },
+    #[error("{name} has {actual} elements, its targets {expected}")]
+    WitnessShape {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("{name} = {value} doesn't fit in {num_bits} bits")]
+    WitnessRange {
+        name: String,
+        value: u64,
+        num_bits: usize,
+    },
+    #[error("{name} is set twice with different values")]
+    WitnessConflict { name: String },
+    #[error("input {index} is out of range for {num_inputs} inputs")]
+    InputOutOfRange { index: usize, num_inputs: usize },
+    #[error("the circuit has no targets to combine into an output")]
+    EmptyCircuit,
+    #[error("cannot pair {lhs} targets with {rhs} targets")]
+    LengthMismatch { lhs: usize, rhs: usize },
+    #[error("the circuit output {actual} does not match the expected value {expected}")]
+    OutputMismatch { expected: u64, actual: u64 },
+    #[error("public key index {index} is out of range for an access set of {size} members")]
+    MemberIndexOutOfRange { index: usize, size: usize },
+    #[error("leaf index {index} is out of range for {num_leaves} leaves")]
+    LeafIndexOutOfRange { index: usize, num_leaves: usize },
+    #[error("the accumulator is full, with {capacity} members")]
+    AccumulatorFull { capacity: usize },
+    #[error("the commitment is not a member of the accumulator")]
+    NotAMember,
+    #[error("the address is not in the address set")]
+    UnknownAddress,
+    #[error("unsatisfied circuit ({message}), involving {labels:?}")]
+    Unsatisfied {
+        message: String,
+        labels: Vec<String>,
+    },
+    #[error("expected {expected} public inputs, got {actual}")]
+    PublicInputCount { expected: usize, actual: usize },
+    #[error("incompatible proof: {0}")]
+    Incompatible(#[from] CompatError),
+    #[error("proof {index} of the batch is invalid: {source}")]
+    InvalidBatchProof {
+        index: usize,
+        source: Box<ProofError>,
+    },
+    #[error("the proof targets circuit {actual}, not {expected}")]
+    CircuitMismatch { expected: String, actual: String },
+    #[error("circuit {0} is not registered")]
+    UnknownCircuit(String),
+    #[error("invalid circuit id {0}, expected 64 hexadecimal digits")]
+    InvalidCircuitId(String),
+    #[error("invalid protocol message: {0}")]
+    Protocol(#[from] ProtocolError),
+    #[error("the chain has no steps to prove")]
+    EmptyChain,
+    #[error("the number of segments {0} should be a non-zero power of two")]
+    SegmentCount(usize),
+    #[error("a signal can prove a power of two leaves of its access set at most, not {0}")]
+    LeafCount(usize),
+    #[error("leaf {index} doesn't start an aligned block of {num_leaves} leaves")]
+    UnalignedLeaves { index: usize, num_leaves: usize },
+    #[error("signal {index} repeats the nullifier of an earlier signal")]
+    DuplicateSignal { index: usize },
+    #[error("a proof of work of {0} bits can't be met, the work has 64 bits")]
+    Difficulty(usize),
+    #[error("the number of members {0} of an access set should be a power of two above 1")]
+    AccessSetSize(usize),
+    #[error("the FRI parameters give {actual} bits of conjectured security, below {required}")]
+    InsecureFri { required: usize, actual: usize },
+    #[error("invalid FRI parameters: {0}")]
+    InvalidFriProfile(String),
+    #[error("the transcript diverges at element {index} of the {label}")]
+    TranscriptMismatch { label: String, index: usize },
+    #[error("application {0} is already registered, or its id is taken")]
+    DuplicateApp(String),
+    #[error("attribute {index} = {value} is outside of {min}..={max}")]
+    AttributeRange {
+        index: usize,
+        value: u64,
+        min: u64,
+        max: u64,
+    },
+    #[error("the credential is not signed by the issuer for this member")]
+    InvalidCredential,
+    #[error("invalid circuit description: {0}")]
+    InvalidDescription(String),
+    #[error("at least 2 proofs of each witness are needed to compare them, not {0}")]
+    LeakageSamples(usize),
+    #[error("constraints can only be split down to degree 2, not {0}")]
+    MaxDegree(usize),
+    #[error(
+        "child access set {index} has {actual} members and a cap height of {cap_height}, \
+         instead of {expected} members and a single root"
+    )]
+    ChildAccessSet {
+        index: usize,
+        expected: usize,
+        actual: usize,
+        cap_height: usize,
+    },
+    #[error("the payload doesn't decrypt to a message of its hash")]
+    InvalidPayload,
+    #[error("deterministic proofs cannot be zero-knowledge, whose blinding is random")]
+    RandomizedProof,
+    #[error("cannot commit to an input without rows")]
+    EmptyCommitment,
+    #[error("the aggregate doesn't follow a previous aggregate, its layout isn't chained")]
+    UnchainedAggregate,
+    #[error(
+        "gadget `{gadget}` takes the circuit to {actual} {resource}, over the budget of {max}"
+    )]
+    OverBudget {
+        gadget: String,
+        resource: &'static str,
+        max: usize,
+        actual: usize,
+    },
+    #[error("serialization failed: {0}")]
+    Serialization(String),
+    #[error("proving failed: {0}")]
+    Proving(anyhow::Error),
+    #[error("proving failed: {0}")]
+    Diagnosed(#[from] ProverDiagnostic),
+    #[error("verification failed: {0}")]
+    Verification(anyhow::Error),
+}
+
+/// Why a proof couldn't be produced, for the failures plonky2 reports by panicking or that
+/// would make it panic, so that callers such as a relayer recover from them
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum ProverDiagnostic {
+    #[error("{generators} generators weren't run, the targets they wait on are unfilled")]
+    UnfilledTargets { generators: usize },
+    #[error("the verifier data is missing {}", .fields.join(", "))]
+    MissingVerifierData { fields: Vec<String> },
+    #[error("proof {index} has {actual} public inputs, the inner circuit expects {expected}")]
+    PublicInputCount {
+        index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("assertion `{label}` failed: {message}")]
+    Assertion { label: String, message: String },
+    #[error("the prover panicked: {0}")]
+    Panic(String),
+}
+
+/// Mismatches between a proof and the common circuit data it is about to be verified with
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CompatError {
+    #[error("the proof has {actual} public inputs, the circuit expects {expected}")]
+    PublicInputCount { expected: usize, actual: usize },
+    #[error("the proof is of a circuit of degree 2^{actual}, not 2^{expected}")]
+    DegreeBits { expected: usize, actual: usize },
+    #[error("the degree of the proof can't be read from its {0}")]
+    UnreadableDegree(&'static str),
+    #[error("the proof has {actual} {field}, the circuit config expects {expected}")]
+    Config {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("the proof opens {actual} {field}, the gates of the circuit require {expected}")]
+    GateSet {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Messages of the signal protocol that don't follow it, e.g. replayed from another session
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProtocolError {
+    #[error("protocol version {actual} is not supported, expected {supported}")]
+    UnsupportedVersion { supported: u16, actual: u16 },
+    #[error("the message belongs to another session")]
+    SessionMismatch,
+    #[error("the message is for epoch {actual}, not {expected}")]
+    EpochMismatch { expected: u64, actual: u64 },
+    #[error("the request expired at {expires_at}, it is now {now}")]
+    Expired { expires_at: u64, now: u64 },
+    #[error(
+        "the topic counts add up to {counted} signals, but {nullifiers} nullifiers are announced"
+    )]
+    CountMismatch { counted: usize, nullifiers: usize },
+    #[error("nullifier {index} is announced more than once")]
+    DuplicateNullifier { index: usize },
+}
@@ -0,0 +1,47 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Sample;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use proof_experiments::halo2_example::Circuit;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+
+// compare proving the product of squares with the custom gate against
+// the chain of square and multiplication gates
+fn bench_square_mul(c: &mut Criterion) {
+    let mut group = c.benchmark_group("product of squares");
+    group.sample_size(10);
+
+    for num_inputs in [1 << 6, 1 << 10, 1 << 14] {
+        let witnesses = F::rand_vec(num_inputs);
+
+        group.bench_with_input(
+            BenchmarkId::new("square + mul", num_inputs),
+            &witnesses,
+            |b, witnesses| {
+                b.iter(|| {
+                    Circuit::<F, 2>::new()
+                        .build_circuit::<C>(witnesses.clone())
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("square_mul gate", num_inputs),
+            &witnesses,
+            |b, witnesses| {
+                b.iter(|| {
+                    Circuit::<F, 2>::new()
+                        .build_square_mul_circuit::<C>(witnesses.clone())
+                        .unwrap()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_square_mul);
+criterion_main!(benches);
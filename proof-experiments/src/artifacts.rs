@@ -0,0 +1,124 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::plonk::circuit_data::{
+    CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::{GenericConfig, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::codec::{Bincode, ProofCodec};
+
+/// What the prover hands out: the proof together with its public inputs
+#[derive(Clone, Debug)]
+pub struct ProverArtifacts<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    pub proof_with_pis: ProofWithPublicInputs<F, C, D>,
+}
+
+/// What a verifier needs to check proofs of a circuit
+pub struct VerifierArtifacts<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub verifier_data: VerifierCircuitData<F, C, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    ProverArtifacts<F, C, D>
+{
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        self.to_bytes_with(&Bincode)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        Self::from_bytes_with(&Bincode, bytes)
+    }
+
+    /// Like `to_bytes`, in the encoding of `codec`
+    pub fn to_bytes_with(&self, codec: &impl ProofCodec) -> Result<Vec<u8>, ProofError> {
+        codec.encode(&self.proof_with_pis)
+    }
+
+    pub fn from_bytes_with(codec: &impl ProofCodec, bytes: &[u8]) -> Result<Self, ProofError> {
+        let proof_with_pis = codec.decode(bytes)?;
+
+        Ok(Self { proof_with_pis })
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    VerifierArtifacts<F, C, D>
+{
+    /// Serializes the verifier-only data, i.e. the constants-sigmas cap and the circuit digest.
+    /// The gates of `CommonCircuitData` can't be serialized with this plonky2 revision, so the
+    /// common data is obtained by rebuilding the circuit instead.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        let VerifierOnlyCircuitData {
+            constants_sigmas_cap,
+            circuit_digest,
+        } = &self.verifier_data.verifier_only;
+
+        bincode::serialize(&(constants_sigmas_cap, circuit_digest))
+            .map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8], common: CommonCircuitData<F, D>) -> Result<Self, ProofError> {
+        let (constants_sigmas_cap, circuit_digest): (
+            MerkleCap<F, C::Hasher>,
+            <C::Hasher as Hasher<F>>::Hash,
+        ) = bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))?;
+
+        Ok(Self {
+            verifier_data: VerifierCircuitData {
+                verifier_only: VerifierOnlyCircuitData {
+                    constants_sigmas_cap,
+                    circuit_digest,
+                },
+                common,
+            },
+        })
+    }
+
+    pub fn verify(&self, prover_artifacts: &ProverArtifacts<F, C, D>) -> Result<(), ProofError> {
+        self.verifier_data
+            .verify(prover_artifacts.proof_with_pis.clone())
+            .map_err(ProofError::Verification)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use prover_utils::codec::Json;
+
+    use super::*;
+    use crate::halo2_example::Circuit;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn it_round_trips_artifacts() -> Result<(), ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+        let outputs = circuit.build_circuit::<C>(vec![F::TWO, F::TWO])?;
+        let (prover_artifacts, verifier_artifacts) = outputs.into_artifacts();
+
+        let prover_bytes = prover_artifacts.to_bytes()?;
+        let verifier_bytes = verifier_artifacts.to_bytes()?;
+        let common = verifier_artifacts.verifier_data.common.clone();
+
+        let prover_artifacts = ProverArtifacts::<F, C, 2>::from_bytes(&prover_bytes)?;
+        let json = prover_artifacts.to_bytes_with(&Json)?;
+        let prover_artifacts = ProverArtifacts::<F, C, 2>::from_bytes_with(&Json, &json)?;
+        let verifier_artifacts = VerifierArtifacts::<F, C, 2>::from_bytes(&verifier_bytes, common)?;
+        assert_eq!(
+            prover_artifacts.proof_with_pis.public_inputs,
+            [2, 2, 16].map(F::from_canonical_u64)
+        );
+
+        verifier_artifacts.verify(&prover_artifacts)
+    }
+}
@@ -0,0 +1,186 @@
+//! The standard scenarios of each suite, timed and measured for tracking over time
+
+use std::time::Duration;
+
+use clap::ValueEnum;
+use plonky2::field::types::Sample;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::WitnessBuilder;
+use prover_utils::TimedProof;
+use semaphore::access_set::AccessSet;
+use semaphore::domain::DomainTag;
+use semaphore::recursion::AggregationOptions;
+use semaphore::signal::{PrivateKey, Signal, C, F, NULLIFIER_VERSION};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Suite {
+    /// Access sets, signals and their aggregation, sized by the number of members
+    Semaphore,
+}
+
+/// The measurements of a scenario at one size. Times are in milliseconds, and the steps a
+/// scenario doesn't have are left out
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ScenarioResult {
+    pub scenario: &'static str,
+    pub size: usize,
+    pub total_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prove_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof_bytes: Option<usize>,
+}
+
+impl ScenarioResult {
+    fn new(scenario: &'static str, size: usize, total: Duration) -> Self {
+        Self {
+            scenario,
+            size,
+            total_ms: millis(total),
+            build_ms: None,
+            prove_ms: None,
+            verify_ms: None,
+            proof_bytes: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchReport {
+    pub suite: Suite,
+    pub fri: FriProfile,
+    pub results: Vec<ScenarioResult>,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Parses a size given either as a number or as a power of two, e.g. `2^14`
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let size = match s.trim().strip_prefix("2^") {
+        Some(exponent) => {
+            let exponent: u32 = exponent.parse().map_err(|e| format!("{s}: {e}"))?;
+            1usize
+                .checked_shl(exponent)
+                .ok_or_else(|| format!("{s} doesn't fit in a usize"))?
+        }
+        None => s.trim().parse().map_err(|e| format!("{s}: {e}"))?,
+    };
+
+    Ok(size)
+}
+
+/// Runs every scenario of `suite` at each of `sizes`, proving with the FRI parameters of
+/// `fri`
+pub fn run(suite: Suite, sizes: &[usize], fri: &FriProfile) -> Result<BenchReport, ProofError> {
+    let mut results = Vec::new();
+    for &size in sizes {
+        match suite {
+            Suite::Semaphore => results.extend(semaphore_scenarios(size, fri)?),
+        }
+    }
+
+    Ok(BenchReport {
+        suite,
+        fri: *fri,
+        results,
+    })
+}
+
+/// Builds an access set of `num_members`, proves a signal and aggregates two signals
+fn semaphore_scenarios(
+    num_members: usize,
+    fri: &FriProfile,
+) -> Result<Vec<ScenarioResult>, ProofError> {
+    if !num_members.is_power_of_two() || num_members < 2 {
+        return Err(ProofError::AccessSetSize(num_members));
+    }
+
+    let private_keys: Vec<PrivateKey> = (0..num_members).map(|_| F::rand_array()).collect();
+    let (access_set, elapsed) = prover_utils::timed(|| AccessSet::from_private_keys(&private_keys));
+    let mut results = vec![ScenarioResult::new("access set", num_members, elapsed)];
+
+    let topic = F::rand_array();
+    let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+    let mut witness = WitnessBuilder::new();
+    let targets = access_set.semaphore_circuit(&mut builder);
+    access_set.fill_semaphore_targets(&mut witness, private_keys[0], topic, 0, targets)?;
+    let TimedProof {
+        circuit_data,
+        proof_with_pis,
+        timings,
+        ..
+    } = prover_utils::prove_and_verify::<F, C, 2>(builder, witness.build())?;
+    let signal = Signal {
+        version: NULLIFIER_VERSION,
+        nullifier: DomainTag::default().nullifier(private_keys[0], topic),
+        nonce: 0,
+        proof: proof_with_pis.proof,
+        payload: None,
+        disclosed: Vec::new(),
+    };
+    results.push(ScenarioResult {
+        build_ms: Some(millis(timings.build)),
+        prove_ms: Some(millis(timings.prove)),
+        verify_ms: timings.verify.map(millis),
+        proof_bytes: Some(signal.to_bytes()?.len()),
+        ..ScenarioResult::new("signal", num_members, timings.total())
+    });
+
+    let verifier_data = circuit_data.verifier_data();
+    let other_topic = F::rand_array();
+    let (other_signal, _) =
+        access_set.make_signal_with_profile(private_keys[1], other_topic, 1, fri)?;
+    let (aggregation, elapsed) = prover_utils::timed(|| {
+        access_set.aggregate_signal_batch_with_options(
+            &[(topic, signal), (other_topic, other_signal)],
+            &verifier_data,
+            &AggregationOptions {
+                fri: *fri,
+                ..Default::default()
+            },
+        )
+    });
+    let (aggregate_proof, _) = aggregation?;
+    let aggregate_bytes = bincode::serialize(&aggregate_proof.proof)
+        .map_err(|e| ProofError::Serialization(e.to_string()))?;
+    results.push(ScenarioResult {
+        proof_bytes: Some(aggregate_bytes.len()),
+        ..ScenarioResult::new("aggregate", num_members, elapsed)
+    });
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("2^14"), Ok(1 << 14));
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert!(parse_size("2^x").is_err());
+        assert!(parse_size("2^64").is_err());
+    }
+
+    #[test]
+    fn test_semaphore_suite() -> Result<(), ProofError> {
+        let report = run(Suite::Semaphore, &[1 << 4], &FriProfile::default())?;
+        let scenarios: Vec<&str> = report.results.iter().map(|r| r.scenario).collect();
+        assert_eq!(scenarios, ["access set", "signal", "aggregate"]);
+        assert!(report.results[1].proof_bytes.is_some());
+        assert!(run(Suite::Semaphore, &[12], &FriProfile::default()).is_err());
+
+        Ok(())
+    }
+}
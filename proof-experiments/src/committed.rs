@@ -0,0 +1,249 @@
+//! Commit-and-prove: large inputs are committed to off-circuit by the root of a Poseidon
+//! Merkle tree of their rows, and circuits take the root as a public input and open only
+//! the rows they use, instead of taking every value as a target
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use gadgets::cap_opening::{CapOpening, CapOpeningTarget};
+use gadgets::witness_ext::WitnessExt;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, MerkleCapTarget, RichField};
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+
+/// Rows of `row_len` elements committed to by the root of their tree, padded with rows of
+/// zeros to a power of two
+#[derive(Clone, Debug)]
+pub struct CommittedInput<F: RichField> {
+    tree: MerkleTree<F, PoseidonHash>,
+    num_rows: usize,
+}
+
+impl<F: RichField> CommittedInput<F> {
+    pub fn new(rows: Vec<Vec<F>>) -> Result<Self, ProofError> {
+        let row_len = rows.first().ok_or(ProofError::EmptyCommitment)?.len();
+        if let Some((i, row)) = rows
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != row_len)
+        {
+            return Err(ProofError::WitnessShape {
+                name: format!("row {i}"),
+                expected: row_len,
+                actual: row.len(),
+            });
+        }
+
+        let num_rows = rows.len();
+        let mut leaves = rows;
+        leaves.resize(num_rows.next_power_of_two(), vec![F::ZERO; row_len]);
+        Ok(Self {
+            tree: MerkleTree::new(leaves, 0),
+            num_rows,
+        })
+    }
+
+    /// Commits to `values` in rows of `row_len` elements, the last one padded with zeros
+    pub fn from_values(values: &[F], row_len: usize) -> Result<Self, ProofError> {
+        Self::new(
+            values
+                .chunks(row_len.max(1))
+                .map(|chunk| {
+                    let mut row = chunk.to_vec();
+                    row.resize(row_len, F::ZERO);
+                    row
+                })
+                .collect(),
+        )
+    }
+
+    pub fn root(&self) -> HashOut<F> {
+        self.tree.cap.0[0]
+    }
+
+    /// The public inputs of `CommittedInputTarget::new`
+    pub fn public_inputs(&self) -> Vec<F> {
+        self.root().elements.to_vec()
+    }
+
+    /// The number of rows committed to, before padding
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.tree.leaves[0].len()
+    }
+
+    /// The height of the tree, which circuits opening the input are built for
+    pub fn height(&self) -> usize {
+        self.tree.leaves.len().trailing_zeros() as usize
+    }
+
+    pub fn row(&self, index: usize) -> Option<&[F]> {
+        self.tree.leaves[..self.num_rows]
+            .get(index)
+            .map(|row| row.as_slice())
+    }
+
+    /// The witness of opening the row at `index`
+    pub fn open(&self, index: usize) -> Result<CapOpening<F, PoseidonHash>, ProofError> {
+        if index >= self.num_rows {
+            return Err(ProofError::LeafIndexOutOfRange {
+                index,
+                num_leaves: self.num_rows,
+            });
+        }
+
+        Ok(CapOpening::new(&self.tree, index))
+    }
+}
+
+/// A committed input in a circuit, of which only the root is known
+#[derive(Clone, Debug)]
+pub struct CommittedInputTarget {
+    pub root: HashOutTarget,
+    pub height: usize,
+    pub row_len: usize,
+}
+
+/// A row opened by `CommittedInputTarget::open`
+#[derive(Clone, Debug)]
+pub struct OpenedRowTarget {
+    pub index: Target,
+    pub opening: CapOpeningTarget,
+}
+
+impl OpenedRowTarget {
+    pub fn row(&self) -> &[Target] {
+        &self.opening.leaf
+    }
+}
+
+impl CommittedInputTarget {
+    /// Adds a committed input of `2^height` rows of `row_len` elements, whose root is
+    /// registered as public inputs
+    pub fn new<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        height: usize,
+        row_len: usize,
+    ) -> Self {
+        let root = builder.add_virtual_hash();
+        builder.register_public_inputs(&root.elements);
+        Self {
+            root,
+            height,
+            row_len,
+        }
+    }
+
+    /// Opens the row at `index`, which may be a constant or computed by the circuit
+    pub fn open<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        index: Target,
+    ) -> OpenedRowTarget {
+        let opening = CapOpeningTarget::new(builder, self.height, 0, self.row_len);
+        builder.verify_cap_openings::<PoseidonHash>(
+            index,
+            &[MerkleCapTarget(vec![self.root])],
+            &[opening.clone()],
+        );
+        OpenedRowTarget { index, opening }
+    }
+
+    /// Sets the root of the input, which the circuit has to be built for
+    pub fn set_input<F: RichField>(
+        &self,
+        pw: &mut impl WitnessWrite<F>,
+        input: &CommittedInput<F>,
+    ) -> Result<(), ProofError> {
+        if input.height() != self.height || input.row_len() != self.row_len {
+            return Err(ProofError::WitnessShape {
+                name: "committed input".to_string(),
+                expected: self.row_len << self.height,
+                actual: input.row_len() << input.height(),
+            });
+        }
+
+        pw.set_hash_target(self.root, input.root());
+        Ok(())
+    }
+}
+
+/// Sets the row at `index` opened by `opened`. The index target is the caller's to set,
+/// unless it's a constant
+pub fn set_opened_row<F: RichField>(
+    pw: &mut impl WitnessWrite<F>,
+    opened: &OpenedRowTarget,
+    input: &CommittedInput<F>,
+    index: usize,
+) -> Result<(), ProofError> {
+    pw.set_cap_opening(&opened.opening, &input.open(index)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_committed_input() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let values = F::rand_vec(100);
+        let input = CommittedInput::from_values(&values, 8)?;
+        assert_eq!((input.num_rows(), input.height()), (13, 4));
+        assert_eq!(input.row(12).unwrap()[..4], values[96..]);
+        assert!(matches!(
+            input.open(13),
+            Err(ProofError::LeafIndexOutOfRange { index: 13, .. })
+        ));
+
+        // the circuit sums the first elements of two rows
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let target = CommittedInputTarget::new(&mut builder, input.height(), input.row_len());
+        let index = builder.add_virtual_target();
+        let first = target.open(&mut builder, index);
+        let last_index = builder.constant(F::from_canonical_usize(12));
+        let last = target.open(&mut builder, last_index);
+        let sum = builder.add(first.row()[0], last.row()[0]);
+        builder.register_public_input(sum);
+        let data = builder.build::<C>();
+
+        let prove = |input: &CommittedInput<F>| -> Result<_, ProofError> {
+            let mut pw = PartialWitness::new();
+            target.set_input(&mut pw, input)?;
+            pw.set_target(index, F::from_canonical_usize(3));
+            // the rows of the honest input, whatever the root
+            let honest = CommittedInput::from_values(&values, 8)?;
+            set_opened_row(&mut pw, &first, &honest, 3)?;
+            set_opened_row(&mut pw, &last, &honest, 12)?;
+            Ok(panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw))))
+        };
+        let proof = prove(&input)?.unwrap().map_err(ProofError::Proving)?;
+        assert_eq!(proof.public_inputs[..4], input.public_inputs());
+        assert_eq!(proof.public_inputs[4], values[24] + values[96]);
+        data.verify(proof).map_err(ProofError::Verification)?;
+
+        // the rows don't open a commitment to other data
+        let mut other = values.clone();
+        other[0] += F::ONE;
+        let other = CommittedInput::from_values(&other, 8)?;
+        assert!(!matches!(prove(&other)?, Ok(Ok(_))));
+
+        Ok(())
+    }
+}
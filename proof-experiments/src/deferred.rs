@@ -0,0 +1,295 @@
+//! Deferred verification: a circuit takes a proof as an opaque handle, witness elements
+//! bound by their Poseidon digest as public inputs, without verifying it, and a follow-up
+//! circuit verifies the proof and exposes the digest of the same elements, so that the
+//! pair attests to the proof once their digests match. Handles of foreign proofs, from
+//! their serialized bytes, can only be bound, the follow-up verifies plonky2 proofs
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, Witness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CommonCircuitData, VerifierCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use proof_error::ProofError;
+use prover_utils::compat::check_compatibility;
+
+/// The number of bytes packed into each element of a handle, below the field size
+const BYTES_PER_ELEMENT: usize = 7;
+
+/// The elements a proof is embedded as, and their digest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofHandle<F: RichField> {
+    elements: Vec<F>,
+    digest: HashOut<F>,
+}
+
+impl<F: RichField> ProofHandle<F> {
+    fn new(elements: Vec<F>) -> Self {
+        let digest = PoseidonHash::hash_no_pad(&elements);
+        Self { elements, digest }
+    }
+
+    /// The handle of an opaque proof: its length, then its bytes packed little-endian
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let elements = std::iter::once(F::from_canonical_usize(bytes.len()))
+            .chain(bytes.chunks(BYTES_PER_ELEMENT).map(|chunk| {
+                let mut limb = [0; 8];
+                limb[..chunk.len()].copy_from_slice(chunk);
+                F::from_canonical_u64(u64::from_le_bytes(limb))
+            }))
+            .collect();
+        Self::new(elements)
+    }
+
+    /// The handle of a plonky2 proof of a circuit of shape `common`: the elements of the
+    /// proof, in the order the follow-up circuit of `add_committed_proof` hashes them
+    pub fn from_proof<C: GenericConfig<D, F = F>, const D: usize>(
+        proof: &ProofWithPublicInputs<F, C, D>,
+        common: &CommonCircuitData<F, D>,
+    ) -> Result<Self, ProofError>
+    where
+        F: Extendable<D>,
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        check_compatibility(proof, common)?;
+        // the elements are read back from targets of the proof, so that they follow the
+        // order of `proof_targets`
+        let mut builder = CircuitBuilder::<F, D>::new(common.config.clone());
+        let proof_target = builder.add_virtual_proof_with_pis::<C>(common);
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&proof_target, proof);
+        Ok(Self::new(pw.get_targets(&proof_targets(&proof_target))))
+    }
+
+    /// Like `from_proof`, for a proof serialized with bincode
+    pub fn from_proof_bytes<C: GenericConfig<D, F = F>, const D: usize>(
+        bytes: &[u8],
+        common: &CommonCircuitData<F, D>,
+    ) -> Result<Self, ProofError>
+    where
+        F: Extendable<D>,
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let proof: ProofWithPublicInputs<F, C, D> =
+            bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))?;
+        Self::from_proof(&proof, common)
+    }
+
+    pub fn elements(&self) -> &[F] {
+        &self.elements
+    }
+
+    /// The public inputs of the circuits of `add_proof_handle` and `add_committed_proof`
+    pub fn digest(&self) -> HashOut<F> {
+        self.digest
+    }
+}
+
+/// The elements of an embedded proof and their digest, registered as public inputs
+#[derive(Clone, Debug)]
+pub struct ProofHandleTarget {
+    pub elements: Vec<Target>,
+    pub digest: HashOutTarget,
+}
+
+/// A proof verified in a follow-up circuit and the digest of its elements, registered as
+/// public inputs
+pub struct CommittedProofTarget<const D: usize> {
+    pub proof: ProofWithPublicInputsTarget<D>,
+    pub digest: HashOutTarget,
+}
+
+/// The targets of a proof, in the order its handle hashes their values
+fn proof_targets<const D: usize>(proof_with_pis: &ProofWithPublicInputsTarget<D>) -> Vec<Target> {
+    let proof = &proof_with_pis.proof;
+    let opening_proof = &proof.opening_proof;
+    let openings = &proof.openings;
+    let mut targets = proof_with_pis.public_inputs.clone();
+
+    let caps = [
+        &proof.wires_cap,
+        &proof.plonk_zs_partial_products_cap,
+        &proof.quotient_polys_cap,
+    ];
+    for cap in caps
+        .into_iter()
+        .chain(&opening_proof.commit_phase_merkle_caps)
+    {
+        targets.extend(cap.0.iter().flat_map(|hash| hash.elements));
+    }
+    let opened_values = [
+        &openings.constants,
+        &openings.plonk_sigmas,
+        &openings.wires,
+        &openings.plonk_zs,
+        &openings.plonk_zs_next,
+        &openings.partial_products,
+        &openings.quotient_polys,
+    ];
+    for value in opened_values
+        .into_iter()
+        .flatten()
+        .chain(&opening_proof.final_poly.0)
+    {
+        targets.extend(value.0);
+    }
+    for round in &opening_proof.query_round_proofs {
+        for (evals, merkle_proof) in &round.initial_trees_proof.evals_proofs {
+            targets.extend(evals);
+            targets.extend(merkle_proof.siblings.iter().flat_map(|hash| hash.elements));
+        }
+        for step in &round.steps {
+            targets.extend(step.evals.iter().flat_map(|value| value.0));
+            targets.extend(
+                step.merkle_proof
+                    .siblings
+                    .iter()
+                    .flat_map(|hash| hash.elements),
+            );
+        }
+    }
+    targets.push(opening_proof.pow_witness);
+
+    targets
+}
+
+/// Embeds a proof as `num_elements` witness elements, the length of the handles it takes,
+/// registering their digest as public inputs without verifying the proof
+pub fn add_proof_handle<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    num_elements: usize,
+) -> ProofHandleTarget {
+    let elements = builder.add_virtual_targets(num_elements);
+    let digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(elements.clone());
+    builder.register_public_inputs(&digest.elements);
+    ProofHandleTarget { elements, digest }
+}
+
+pub fn set_proof_handle<F: RichField>(
+    pw: &mut PartialWitness<F>,
+    target: &ProofHandleTarget,
+    handle: &ProofHandle<F>,
+) -> Result<(), ProofError> {
+    if handle.elements.len() != target.elements.len() {
+        return Err(ProofError::WitnessShape {
+            name: "proof handle".to_string(),
+            expected: target.elements.len(),
+            actual: handle.elements.len(),
+        });
+    }
+    for (&target, &value) in target.elements.iter().zip(&handle.elements) {
+        pw.set_target(target, value);
+    }
+    Ok(())
+}
+
+/// The follow-up of `add_proof_handle`: verifies a proof of the circuit of
+/// `verifier_data` and registers the digest of its handle as public inputs, to be matched
+/// with those of the circuit it was embedded in
+pub fn add_committed_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+) -> CommittedProofTarget<D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let proof = builder.add_virtual_proof_with_pis::<C>(&verifier_data.common);
+    let verifier_data_target = builder.constant_verifier_data(&verifier_data.verifier_only);
+    builder.verify_proof::<C>(&proof, &verifier_data_target, &verifier_data.common);
+    let digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(proof_targets(&proof));
+    builder.register_public_inputs(&digest.elements);
+    CommittedProofTarget { proof, digest }
+}
+
+pub fn set_committed_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    pw: &mut PartialWitness<F>,
+    target: &CommittedProofTarget<D>,
+    proof: &ProofWithPublicInputs<F, C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> Result<(), ProofError>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    check_compatibility(proof, common)?;
+    pw.set_proof_with_pis_target(&target.proof, proof);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use prover_utils::options::ProverOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_deferred_verification() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(prover_utils::standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let inner = builder.build::<C>();
+        let prove_inner = |value: u64| {
+            let mut pw = PartialWitness::new();
+            pw.set_target(x, F::from_canonical_u64(value));
+            prover_utils::prove_with_options(&inner, pw, &ProverOptions::default())
+        };
+        let proof = prove_inner(2)?;
+        let bytes =
+            bincode::serialize(&proof).map_err(|e| ProofError::Serialization(e.to_string()))?;
+        let handle = ProofHandle::from_proof_bytes::<C, D>(&bytes, &inner.common)?;
+        assert_eq!(handle, ProofHandle::from_proof(&proof, &inner.common)?);
+
+        // the proof is embedded without being verified
+        let mut builder = CircuitBuilder::<F, D>::new(prover_utils::standard_config(false));
+        let handle_target = add_proof_handle(&mut builder, handle.elements().len());
+        let deferring = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        set_proof_handle(&mut pw, &handle_target, &handle)?;
+        let deferred = prover_utils::prove_with_options(&deferring, pw, &ProverOptions::default())?;
+        assert_eq!(deferred.public_inputs, handle.digest().elements);
+
+        // then verified by the follow-up, which exposes the same digest
+        let mut builder = CircuitBuilder::<F, D>::new(prover_utils::standard_config(false));
+        let committed = add_committed_proof(&mut builder, &inner.verifier_data());
+        let follow_up = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        set_committed_proof(&mut pw, &committed, &proof, &inner.common)?;
+        let verified = prover_utils::prove_with_options(&follow_up, pw, &ProverOptions::default())?;
+        follow_up
+            .verify(verified.clone())
+            .map_err(ProofError::Verification)?;
+        assert_eq!(verified.public_inputs, deferred.public_inputs);
+
+        let other = ProofHandle::from_proof(&prove_inner(3)?, &inner.common)?;
+        assert_ne!(other.digest(), handle.digest());
+
+        // opaque bytes are bound as their length and 7 bytes per element
+        let opaque = ProofHandle::<F>::from_bytes(&bytes);
+        assert_eq!(opaque.elements().len(), 1 + bytes.chunks(7).count());
+        assert_eq!(opaque.elements()[0], F::from_canonical_usize(bytes.len()));
+        assert!(matches!(
+            set_proof_handle(&mut PartialWitness::new(), &handle_target, &opaque),
+            Err(ProofError::WitnessShape { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use plonky2::field::types::Field64;
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::expr::Expr;
+
+/// A declarative description of a circuit: named inputs, operations over
+/// previously defined names, and the names registered as public outputs
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CircuitDescription {
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Operation {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: OperationKind,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum OperationKind {
+    Const { value: u64 },
+    Add { args: Vec<String> },
+    Mul { args: Vec<String> },
+    Square { arg: String },
+    Pow { arg: String, exponent: u64 },
+    Neg { arg: String },
+}
+
+impl CircuitDescription {
+    pub fn from_toml_str(s: &str) -> Result<Self, ProofError> {
+        toml::from_str(s).map_err(|e| ProofError::InvalidDescription(e.to_string()))
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self, ProofError> {
+        serde_json::from_str(s).map_err(|e| ProofError::InvalidDescription(e.to_string()))
+    }
+
+    /// Reads a description from a `.toml` or `.json` file
+    pub fn from_path(path: &Path) -> Result<Self, ProofError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ProofError::InvalidDescription(format!("{}: {e}", path.display())))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&contents),
+            Some("json") => Self::from_json_str(&contents),
+            _ => Err(ProofError::InvalidDescription(format!(
+                "{} should have a .toml or .json extension",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Lowers the operations into one expression per output
+    pub fn to_exprs<F: Field64>(&self) -> Result<Vec<Expr<F>>, ProofError> {
+        let mut values: HashMap<&str, Expr<F>> = HashMap::new();
+        for (i, name) in self.inputs.iter().enumerate() {
+            if values.insert(name, Expr::Input(i)).is_some() {
+                return Err(ProofError::InvalidDescription(format!(
+                    "{name} is defined twice"
+                )));
+            }
+        }
+
+        for Operation { name, kind } in &self.operations {
+            let get = |arg: &String| {
+                values.get(arg.as_str()).cloned().ok_or_else(|| {
+                    ProofError::InvalidDescription(format!("{name} refers to undefined {arg}"))
+                })
+            };
+            let expr = match kind {
+                OperationKind::Const { value } => Expr::Const(canonical(name, *value)?),
+                OperationKind::Add { args } => {
+                    Expr::sum(args.iter().map(get).collect::<Result<Vec<_>, _>>()?)
+                }
+                OperationKind::Mul { args } => {
+                    Expr::product(args.iter().map(get).collect::<Result<Vec<_>, _>>()?)
+                }
+                OperationKind::Square { arg } => get(arg)?.pow(2),
+                OperationKind::Pow { arg, exponent } => get(arg)?.pow(*exponent),
+                OperationKind::Neg { arg } => -get(arg)?,
+            };
+
+            if values.insert(name, expr).is_some() {
+                return Err(ProofError::InvalidDescription(format!(
+                    "{name} is defined twice"
+                )));
+            }
+        }
+
+        self.outputs
+            .iter()
+            .map(|name| {
+                values.get(name.as_str()).cloned().ok_or_else(|| {
+                    ProofError::InvalidDescription(format!("output {name} is undefined"))
+                })
+            })
+            .collect()
+    }
+
+    /// Orders a JSON object of `input name -> value` by the inputs of the description
+    pub fn witnesses_from_json<F: Field64>(&self, s: &str) -> Result<Vec<F>, ProofError> {
+        let values: HashMap<String, u64> =
+            serde_json::from_str(s).map_err(|e| ProofError::InvalidDescription(e.to_string()))?;
+
+        self.inputs
+            .iter()
+            .map(|name| {
+                let value = values.get(name).ok_or_else(|| {
+                    ProofError::InvalidDescription(format!("no witness value for {name}"))
+                })?;
+                canonical(name, *value)
+            })
+            .collect()
+    }
+}
+
+/// `value` as an element of `F`, rejecting values at or above the field order rather than
+/// reducing them
+fn canonical<F: Field64>(name: &str, value: u64) -> Result<F, ProofError> {
+    if value >= F::ORDER {
+        return Err(ProofError::InvalidDescription(format!(
+            "{name} = {value} is not below the field order"
+        )));
+    }
+
+    Ok(F::from_canonical_u64(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::halo2_example::Circuit;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    const DESCRIPTION: &str = r#"
+        inputs = ["a", "b"]
+        outputs = ["result"]
+
+        [[operations]]
+        name = "a_squared"
+        op = "square"
+        arg = "a"
+
+        [[operations]]
+        name = "b_squared"
+        op = "square"
+        arg = "b"
+
+        [[operations]]
+        name = "result"
+        op = "mul"
+        args = ["a_squared", "b_squared"]
+    "#;
+
+    #[test]
+    fn it_proves_a_toml_description() -> Result<(), ProofError> {
+        let description = CircuitDescription::from_toml_str(DESCRIPTION)?;
+        let exprs = description.to_exprs::<F>()?;
+        let witnesses = description.witnesses_from_json::<F>(r#"{ "a": 2, "b": 3 }"#)?;
+
+        let mut circuit = Circuit::<F, 2>::new();
+        let outputs = circuit.build_exprs_circuit::<C>(&exprs, witnesses)?;
+        assert_eq!(
+            outputs.public_inputs(),
+            [2, 3, 36].map(F::from_canonical_u64)
+        );
+
+        outputs.verify()
+    }
+
+    #[test]
+    fn it_rejects_undefined_names() {
+        let description = CircuitDescription::from_json_str(
+            r#"{
+                "inputs": ["a"],
+                "operations": [{ "name": "c", "op": "add", "args": ["a", "b"] }],
+                "outputs": ["c"]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            description.to_exprs::<F>(),
+            Err(ProofError::InvalidDescription(_))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_values_above_the_field_order() -> Result<(), ProofError> {
+        let description = CircuitDescription::from_json_str(&format!(
+            r#"{{
+                "inputs": ["a"],
+                "operations": [{{ "name": "c", "op": "const", "value": {} }}],
+                "outputs": ["c"]
+            }}"#,
+            F::ORDER
+        ))?;
+        assert!(matches!(
+            description.to_exprs::<F>(),
+            Err(ProofError::InvalidDescription(_))
+        ));
+
+        let witnesses = format!(r#"{{ "a": {} }}"#, F::ORDER + 1);
+        assert!(matches!(
+            description.witnesses_from_json::<F>(&witnesses),
+            Err(ProofError::InvalidDescription(_))
+        ));
+        assert_eq!(
+            description.witnesses_from_json::<F>(&format!(r#"{{ "a": {} }}"#, F::ORDER - 1))?,
+            [F::NEG_ONE]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::types::Field;
+use plonky2::gates::gate::Gate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::GeneratedValues;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, PartitionWitness, Witness, WitnessWrite};
+use plonky2::plonk::circuit_data::{CircuitData, CommonCircuitData};
+use plonky2::plonk::config::{GenericConfig, Hasher};
+use plonky2::plonk::vars::EvaluationVars;
+
+/// A target which no generator and no value of the partial witness could resolve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnresolvedTarget {
+    pub target: Target,
+    pub label: Option<String>,
+    /// The row of the gate the target is a wire of, `None` for virtual targets
+    pub row: Option<usize>,
+}
+
+/// A generator which never ran, e.g. the generator of a gate, whose debug representation
+/// names the gate and its row, with the watched targets it was waiting for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnresolvedGenerator {
+    pub generator: String,
+    pub waiting_on: Vec<UnresolvedTarget>,
+}
+
+/// Outcome of running the witness generators of a circuit without proving
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WitnessReport {
+    pub num_generators: usize,
+    pub unresolved: Vec<UnresolvedGenerator>,
+    /// Targets the generators tried to set to a value other than the one they already had
+    pub conflicts: Vec<UnresolvedTarget>,
+}
+
+impl WitnessReport {
+    pub fn is_complete(&self) -> bool {
+        self.unresolved.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// Labels of the unresolved and conflicting targets, or their debug representation
+    /// for targets without a label
+    pub fn target_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .unresolved
+            .iter()
+            .flat_map(|generator| &generator.waiting_on)
+            .chain(&self.conflicts)
+            .map(|t| t.label.clone().unwrap_or_else(|| format!("{:?}", t.target)))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl fmt::Display for WitnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} generators weren't run",
+            self.unresolved.len(),
+            self.num_generators
+        )?;
+        for generator in &self.unresolved {
+            write!(f, "\n  {} waiting on", generator.generator)?;
+            for t in &generator.waiting_on {
+                match (&t.label, t.row) {
+                    (Some(label), _) => write!(f, " {label}")?,
+                    (None, Some(row)) => write!(f, " {:?} (row {row})", t.target)?,
+                    (None, None) => write!(f, " {:?}", t.target)?,
+                }
+            }
+        }
+        if !self.conflicts.is_empty() {
+            write!(f, "\n  {} targets were set twice", self.conflicts.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets `target` unless its partition already has a value, recording it in `conflicts` if
+/// that value differs, where `PartitionWitness::set_target` would panic
+fn set_or_conflict<F: Field>(
+    witness: &mut PartitionWitness<F>,
+    conflicts: &mut Vec<Target>,
+    target: Target,
+    value: F,
+) {
+    match witness.try_get_target(target) {
+        Some(existing) if existing != value => conflicts.push(target),
+        Some(_) => {}
+        None => witness.set_target(target, value),
+    }
+}
+
+/// Copies `partial_witness` into a witness over the targets of `data` and runs the
+/// generators until none of them makes progress, returning which generators ran and the
+/// targets set to another value than their partition had, by the partial witness or by
+/// a generator
+fn run_generators<'a, F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    data: &'a CircuitData<F, C, D>,
+    partial_witness: &PartialWitness<F>,
+) -> (PartitionWitness<'a, F>, Vec<bool>, Vec<Target>) {
+    let num_wires = data.common.config.num_wires;
+    let degree = data.common.degree();
+    let representative_map = &data.prover_only.representative_map;
+
+    // the representative map covers every wire followed by every virtual target
+    let mut witness = PartitionWitness::new(num_wires, degree, representative_map);
+    let mut conflicts = Vec::new();
+    for index in 0..representative_map.len() {
+        let target = if index < num_wires * degree {
+            Target::wire(index / num_wires, index % num_wires)
+        } else {
+            Target::VirtualTarget {
+                index: index - num_wires * degree,
+            }
+        };
+        if let Some(value) = partial_witness.try_get_target(target) {
+            set_or_conflict(&mut witness, &mut conflicts, target, value);
+        }
+    }
+
+    let generators = &data.prover_only.generators;
+    let mut done = vec![false; generators.len()];
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for (generator, done) in generators.iter().zip(done.iter_mut()) {
+            if *done {
+                continue;
+            }
+
+            let mut buffer = GeneratedValues::empty();
+            *done = generator.run(&witness, &mut buffer);
+            progress |= *done;
+            for (target, value) in buffer.target_values {
+                set_or_conflict(&mut witness, &mut conflicts, target, value);
+            }
+        }
+    }
+
+    (witness, done, conflicts)
+}
+
+/// Runs the generators of `data` from `partial_witness` the way the prover does, without
+/// committing to anything, and reports the generators which could not run
+pub fn check_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    data: &CircuitData<F, C, D>,
+    partial_witness: &PartialWitness<F>,
+    labels: &HashMap<Target, String>,
+) -> WitnessReport {
+    let describe = |target: Target| UnresolvedTarget {
+        target,
+        label: labels.get(&target).cloned(),
+        row: match target {
+            Target::Wire(wire) => Some(wire.row),
+            Target::VirtualTarget { .. } => None,
+        },
+    };
+
+    let (witness, done, conflicts) = run_generators(data, partial_witness);
+    let generators = &data.prover_only.generators;
+    let unresolved = generators
+        .iter()
+        .zip(&done)
+        .filter(|(_, done)| !**done)
+        .map(|(generator, _)| UnresolvedGenerator {
+            generator: format!("{generator:?}"),
+            waiting_on: generator
+                .watch_list()
+                .into_iter()
+                .filter(|t| witness.try_get_target(*t).is_none())
+                .map(describe)
+                .collect(),
+        })
+        .collect();
+
+    WitnessReport {
+        num_generators: generators.len(),
+        unresolved,
+        conflicts: conflicts.into_iter().map(describe).collect(),
+    }
+}
+
+/// The values of the constant polynomials of `data` on each row. The selectors come first,
+/// followed by the constants of the gates
+pub(crate) fn constant_values<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    data: &CircuitData<F, C, D>,
+) -> Vec<Vec<F>> {
+    data.prover_only.constants_sigmas_commitment.polynomials[..data.common.num_constants]
+        .iter()
+        .map(|poly| poly.clone().fft().values)
+        .collect()
+}
+
+/// The index in `common.gates` of the gate of `row`, read from the selector of its group
+/// which is the only one holding a gate index
+pub(crate) fn gate_of_row<F: RichField + Extendable<D>, const D: usize>(
+    common: &CommonCircuitData<F, D>,
+    constants: &[Vec<F>],
+    row: usize,
+) -> Option<usize> {
+    constants[..common.selectors_info.num_selectors()]
+        .iter()
+        .map(|selector| selector[row].to_canonical_u64() as usize)
+        .find(|index| *index < common.gates.len())
+}
+
+/// A constraint of a gate which doesn't vanish on the witness
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsatisfiedConstraint<F> {
+    /// The id of the gate, e.g. `ArithmeticGate { num_ops: 20 }`
+    pub gate: String,
+    pub row: usize,
+    /// The index of the constraint among those of the gate
+    pub constraint: usize,
+    /// The wires of the gate in its row, `None` for wires nothing set
+    pub wires: Vec<Option<F>>,
+}
+
+impl<F: Field> fmt::Display for UnsatisfiedConstraint<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "constraint {} of {} at row {}, wires [",
+            self.constraint, self.gate, self.row
+        )?;
+        for (i, wire) in self.wires.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match wire {
+                Some(value) => write!(f, "{value}")?,
+                None => write!(f, "_")?,
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// Runs the generators of `data` from `partial_witness`, then evaluates the constraints of
+/// the gate of each row on the resulting wires and reports the first `limit` which don't
+/// vanish. Unset wires are taken to be zero, as they are by the prover. This evaluates
+/// every gate of the circuit, so it is meant for debugging a proof which failed
+pub fn unsatisfied_constraints<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    data: &CircuitData<F, C, D>,
+    partial_witness: &PartialWitness<F>,
+    limit: usize,
+) -> Vec<UnsatisfiedConstraint<F>> {
+    let common = &data.common;
+    let (witness, _, _) = run_generators(data, partial_witness);
+    let public_inputs: Vec<F> = data
+        .prover_only
+        .public_inputs
+        .iter()
+        .map(|t| witness.try_get_target(*t).unwrap_or(F::ZERO))
+        .collect();
+    let public_inputs_hash = C::InnerHasher::hash_no_pad(&public_inputs);
+
+    let num_selectors = common.selectors_info.num_selectors();
+    let constants = constant_values(data);
+
+    let mut unsatisfied = Vec::new();
+    for row in 0..common.degree() {
+        let gate = match gate_of_row(common, &constants, row) {
+            Some(index) => &common.gates[index].0,
+            None => continue,
+        };
+
+        let wires: Vec<Option<F>> = (0..gate.num_wires())
+            .map(|column| witness.try_get_target(Target::wire(row, column)))
+            .collect();
+        let local_wires: Vec<F::Extension> = wires
+            .iter()
+            .map(|wire| F::Extension::from_basefield(wire.unwrap_or(F::ZERO)))
+            .collect();
+        let local_constants: Vec<F::Extension> = constants[num_selectors..]
+            .iter()
+            .map(|constant| F::Extension::from_basefield(constant[row]))
+            .collect();
+        let vars = EvaluationVars {
+            local_constants: &local_constants,
+            local_wires: &local_wires,
+            public_inputs_hash: &public_inputs_hash,
+        };
+
+        for (constraint, value) in gate.eval_unfiltered(vars).into_iter().enumerate() {
+            if value.is_zero() {
+                continue;
+            }
+            unsatisfied.push(UnsatisfiedConstraint {
+                gate: gate.id(),
+                row,
+                constraint,
+                wires: wires.clone(),
+            });
+            if unsatisfied.len() == limit {
+                return unsatisfied;
+            }
+        }
+    }
+
+    unsatisfied
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use prover_utils::standard_config;
+
+    use super::*;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_unsatisfied_constraints() {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_squared = builder.mul(x, x);
+        builder.register_public_input(x_squared);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::TWO);
+        assert!(unsatisfied_constraints(&data, &pw, 4).is_empty());
+
+        // the square is set before its generator runs, which then conflicts with it
+        let five = F::from_canonical_u64(5);
+        pw.set_target(x_squared, five);
+        let unsatisfied = unsatisfied_constraints(&data, &pw, 4);
+        assert_eq!(unsatisfied.len(), 1);
+        assert!(unsatisfied[0].gate.starts_with("ArithmeticGate"));
+        assert!(unsatisfied[0].wires.contains(&Some(five)));
+        assert!(unsatisfied[0].to_string().contains("5"));
+    }
+
+    #[test]
+    fn test_conflicting_inputs() {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let y = builder.add_virtual_target();
+        builder.connect(x, y);
+        builder.register_public_input(x);
+        let data = builder.build::<C>();
+
+        // copy-constrained targets set to different values by the partial witness
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::ONE);
+        pw.set_target(y, F::TWO);
+        let report = check_witness(&data, &pw, &HashMap::from([(y, "y".to_string())]));
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].label.as_deref(), Some("y"));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::ops::{Add, Mul, Neg};
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+
+/// An arithmetic expression over the inputs of a circuit, which can be lowered
+/// into `CircuitBuilder` targets with an `ExprCompiler`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Expr<F> {
+    Const(F),
+    Input(usize),
+    Add(Box<Expr<F>>, Box<Expr<F>>),
+    Mul(Box<Expr<F>>, Box<Expr<F>>),
+    Pow(Box<Expr<F>>, u64),
+    Neg(Box<Expr<F>>),
+}
+
+impl<F: Field> Expr<F> {
+    pub fn pow(self, exponent: u64) -> Self {
+        Expr::Pow(Box::new(self), exponent)
+    }
+
+    /// The sum of all expressions, or zero if there are none
+    pub fn sum(exprs: impl IntoIterator<Item = Self>) -> Self {
+        Self::balanced(exprs.into_iter().collect(), |a, b| a + b).unwrap_or(Expr::Const(F::ZERO))
+    }
+
+    /// The product of all expressions, or one if there are none
+    pub fn product(exprs: impl IntoIterator<Item = Self>) -> Self {
+        Self::balanced(exprs.into_iter().collect(), |a, b| a * b).unwrap_or(Expr::Const(F::ONE))
+    }
+
+    // combines neighbouring pairs until a single expression remains, so that the depth of
+    // the tree (and of the recursion compiling or dropping it) stays logarithmic
+    fn balanced(mut exprs: Vec<Self>, combine: fn(Self, Self) -> Self) -> Option<Self> {
+        while exprs.len() > 1 {
+            let mut layer = Vec::with_capacity((exprs.len() + 1) / 2);
+            let mut exprs_iter = exprs.into_iter();
+            while let Some(a) = exprs_iter.next() {
+                layer.push(match exprs_iter.next() {
+                    Some(b) => combine(a, b),
+                    None => a,
+                });
+            }
+            exprs = layer;
+        }
+
+        exprs.pop()
+    }
+
+    /// The number of inputs the expression refers to, i.e. the highest input index plus one
+    pub fn num_inputs(&self) -> usize {
+        match self {
+            Expr::Const(_) => 0,
+            Expr::Input(i) => i + 1,
+            Expr::Add(a, b) | Expr::Mul(a, b) => a.num_inputs().max(b.num_inputs()),
+            Expr::Pow(a, _) | Expr::Neg(a) => a.num_inputs(),
+        }
+    }
+
+    /// Evaluates the expression natively, outside the circuit
+    pub fn evaluate(&self, inputs: &[F]) -> Result<F, ProofError> {
+        Ok(match self {
+            Expr::Const(c) => *c,
+            Expr::Input(i) => *inputs.get(*i).ok_or(ProofError::InputOutOfRange {
+                index: *i,
+                num_inputs: inputs.len(),
+            })?,
+            Expr::Add(a, b) => a.evaluate(inputs)? + b.evaluate(inputs)?,
+            Expr::Mul(a, b) => a.evaluate(inputs)? * b.evaluate(inputs)?,
+            Expr::Pow(a, exponent) => a.evaluate(inputs)?.exp_u64(*exponent),
+            Expr::Neg(a) => -a.evaluate(inputs)?,
+        })
+    }
+}
+
+impl<F> Add for Expr<F> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Expr::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl<F> Mul for Expr<F> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Expr::Mul(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl<F> Neg for Expr<F> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Expr::Neg(Box::new(self))
+    }
+}
+
+/// A node of a lowered expression, keyed by the targets of its operands, so
+/// that identical subexpressions are only added to the circuit once
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    Add(Target, Target),
+    Mul(Target, Target),
+    Pow(Target, u64),
+    Neg(Target),
+}
+
+/// Lowers expressions into targets of a `CircuitBuilder`, eliminating common
+/// subexpressions across every expression compiled with the same instance
+pub struct ExprCompiler<'a, F: RichField + Extendable<D>, const D: usize> {
+    builder: &'a mut CircuitBuilder<F, D>,
+    inputs: &'a [Target],
+    nodes: HashMap<Node, Target>,
+}
+
+impl<'a, F: RichField + Extendable<D>, const D: usize> ExprCompiler<'a, F, D> {
+    pub fn new(builder: &'a mut CircuitBuilder<F, D>, inputs: &'a [Target]) -> Self {
+        Self {
+            builder,
+            inputs,
+            nodes: HashMap::new(),
+        }
+    }
+
+    pub fn compile(&mut self, expr: &Expr<F>) -> Result<Target, ProofError> {
+        let node = match expr {
+            // constants are already deduplicated by the builder
+            Expr::Const(c) => return Ok(self.builder.constant(*c)),
+            Expr::Input(i) => {
+                return self
+                    .inputs
+                    .get(*i)
+                    .copied()
+                    .ok_or(ProofError::InputOutOfRange {
+                        index: *i,
+                        num_inputs: self.inputs.len(),
+                    })
+            }
+            Expr::Add(a, b) => Node::Add(self.compile(a)?, self.compile(b)?),
+            Expr::Mul(a, b) => Node::Mul(self.compile(a)?, self.compile(b)?),
+            Expr::Pow(a, exponent) => Node::Pow(self.compile(a)?, *exponent),
+            Expr::Neg(a) => Node::Neg(self.compile(a)?),
+        };
+
+        if let Some(target) = self.nodes.get(&node) {
+            return Ok(*target);
+        }
+
+        let target = match node {
+            Node::Add(a, b) => self.builder.add(a, b),
+            Node::Mul(a, b) => self.builder.mul(a, b),
+            Node::Pow(a, exponent) => self.builder.exp_u64(a, exponent),
+            Node::Neg(a) => self.builder.neg(a),
+        };
+        self.nodes.insert(node, target);
+
+        Ok(target)
+    }
+
+    /// The number of distinct operations added to the circuit so far
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+
+    #[test]
+    fn it_eliminates_common_subexpressions() {
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let inputs = builder.add_virtual_targets(2);
+
+        // (x0 + x1) * (x0 + x1) + (x0 + x1) only needs a single addition for x0 + x1
+        let sum = Expr::Input(0) + Expr::Input(1);
+        let expr = sum.clone() * sum.clone() + sum;
+
+        let mut compiler = ExprCompiler::new(&mut builder, &inputs);
+        let first = compiler.compile(&expr).unwrap();
+        let second = compiler.compile(&expr).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(compiler.num_nodes(), 3);
+    }
+
+    #[test]
+    fn it_rejects_out_of_range_inputs() {
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let inputs = builder.add_virtual_targets(1);
+
+        let expr = Expr::Input(0) * Expr::Input(1);
+        assert_eq!(expr.num_inputs(), 2);
+
+        let mut compiler = ExprCompiler::new(&mut builder, &inputs);
+        assert!(compiler.compile(&expr).is_err());
+    }
+
+    #[test]
+    fn it_evaluates_natively() {
+        // -(2^3) + 3 * 4 = 4
+        let expr = -Expr::Input(0).pow(3) + Expr::Const(F::from_canonical_u64(3)) * Expr::Input(1);
+        let value = expr.evaluate(&[F::TWO, F::from_canonical_u64(4)]).unwrap();
+
+        assert_eq!(value, F::from_canonical_u64(4));
+    }
+}
@@ -0,0 +1,203 @@
+use gadgets::builder_ext::CircuitBuilderExt;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use proof_error::ProofError;
+
+/// A level of the segmented Fibonacci proof: either the circuit of a segment of steps, or
+/// a circuit folding two proofs of the level below. The public inputs of every level are
+/// `[a, b, a', b']`, the pairs of consecutive Fibonacci numbers it starts and ends with
+struct Level<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    data: CircuitData<F, C, D>,
+    targets: LevelTargets<D>,
+}
+
+enum LevelTargets<const D: usize> {
+    Segment {
+        a: Target,
+        b: Target,
+    },
+    Fold {
+        left: ProofWithPublicInputsTarget<D>,
+        right: ProofWithPublicInputsTarget<D>,
+    },
+}
+
+/// Proves Fibonacci steps segment by segment, folding the proofs of the segments pairwise
+/// with recursion, so that the size of the circuits doesn't depend on the number of steps
+pub struct SegmentedFibonacci<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    config: CircuitConfig,
+    levels: Vec<Level<F, C, D>>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    SegmentedFibonacci<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn new(config: CircuitConfig, steps_per_segment: usize) -> Self {
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let a = builder.add_virtual_target();
+        let b = builder.add_virtual_target();
+        let mut prev_target = a;
+        let mut cur_target = b;
+        for _ in 0..steps_per_segment {
+            let temp = builder.add(prev_target, cur_target);
+            prev_target = cur_target;
+            cur_target = temp;
+        }
+        builder.register_public_inputs(&[a, b, prev_target, cur_target]);
+
+        Self {
+            config,
+            levels: vec![Level {
+                data: builder.build::<C>(),
+                targets: LevelTargets::Segment { a, b },
+            }],
+        }
+    }
+
+    /// Builds the circuit folding two proofs of the last level, checking that the first
+    /// ends where the second starts
+    fn add_fold_level(&mut self) {
+        let inner = &self.levels[self.levels.len() - 1].data;
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
+        let verifier = builder.constant_verifier_data::<C>(&inner.verifier_only);
+
+        let left = builder.add_virtual_proof_with_pis::<C>(&inner.common);
+        let right = builder.add_virtual_proof_with_pis::<C>(&inner.common);
+        builder.verify_proof::<C>(&left, &verifier, &inner.common);
+        builder.verify_proof::<C>(&right, &verifier, &inner.common);
+
+        builder.connect(left.public_inputs[2], right.public_inputs[0]);
+        builder.connect(left.public_inputs[3], right.public_inputs[1]);
+        builder.register_public_inputs(&[
+            left.public_inputs[0],
+            left.public_inputs[1],
+            right.public_inputs[2],
+            right.public_inputs[3],
+        ]);
+
+        self.levels.push(Level {
+            data: builder.build::<C>(),
+            targets: LevelTargets::Fold { left, right },
+        });
+    }
+
+    fn prove_level(
+        &self,
+        level: usize,
+        pw: PartialWitness<F>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        self.levels[level]
+            .data
+            .prove(pw)
+            .map_err(ProofError::Proving)
+    }
+
+    /// Proves `num_segments` segments starting from `a` and `b`. Segments are folded as soon
+    /// as two proofs of the same level are available, so that at most one proof per level
+    /// is kept in memory
+    pub fn prove(
+        &mut self,
+        num_segments: usize,
+        a: F,
+        b: F,
+    ) -> Result<SegmentedProof<F, C, D>, ProofError> {
+        if !num_segments.is_power_of_two() {
+            return Err(ProofError::SegmentCount(num_segments));
+        }
+        while self.levels.len() <= num_segments.trailing_zeros() as usize {
+            self.add_fold_level();
+        }
+
+        // pending proofs with their level, like the digits of a binary counter
+        let mut pending: Vec<(usize, ProofWithPublicInputs<F, C, D>)> = Vec::new();
+        let (mut a, mut b) = (a, b);
+        for _ in 0..num_segments {
+            let LevelTargets::Segment { a: a_target, b: b_target } = self.levels[0].targets else {
+                unreachable!("the first level proves segments");
+            };
+            let mut pw = PartialWitness::new();
+            pw.set_target(a_target, a);
+            pw.set_target(b_target, b);
+            let mut proof = self.prove_level(0, pw)?;
+            (a, b) = (proof.public_inputs[2], proof.public_inputs[3]);
+
+            let mut level = 0;
+            while pending.last().map(|(l, _)| *l) == Some(level) {
+                let (_, left) = pending.pop().unwrap();
+                let LevelTargets::Fold { left: left_target, right: right_target } =
+                    &self.levels[level + 1].targets else {
+                    unreachable!("the levels above the first fold proofs");
+                };
+                let mut pw = PartialWitness::new();
+                pw.set_proof_with_pis_target(left_target, &left);
+                pw.set_proof_with_pis_target(right_target, &proof);
+                level += 1;
+                proof = self.prove_level(level, pw)?;
+            }
+            pending.push((level, proof));
+        }
+
+        let (level, proof_with_pis) = pending.pop().unwrap();
+        Ok(SegmentedProof {
+            level,
+            proof_with_pis,
+        })
+    }
+
+    pub fn verify(&self, proof: &SegmentedProof<F, C, D>) -> Result<(), ProofError> {
+        self.levels[proof.level]
+            .data
+            .verify(proof.proof_with_pis.clone())
+            .map_err(ProofError::Verification)
+    }
+}
+
+/// The proof of all the segments, whose public inputs are the first and last two numbers
+pub struct SegmentedProof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    level: usize,
+    pub proof_with_pis: ProofWithPublicInputs<F, C, D>,
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn it_proves_segments() -> Result<(), ProofError> {
+        // 4 segments of 8 steps, i.e. the Fibonacci numbers 32 and 33 starting from 0, 1
+        let mut fibonacci =
+            SegmentedFibonacci::<F, C, 2>::new(CircuitConfig::standard_recursion_config(), 8);
+        let proof = fibonacci.prove(4, F::ZERO, F::ONE)?;
+        assert_eq!(
+            proof.proof_with_pis.public_inputs,
+            [0, 1, 2_178_309, 3_524_578].map(F::from_canonical_u64)
+        );
+        fibonacci.verify(&proof)?;
+
+        assert!(matches!(
+            fibonacci.prove(3, F::ZERO, F::ONE),
+            Err(ProofError::SegmentCount(3))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! A graph of the gates of a built circuit and of the copy constraints between their
+//! wires, exported to Graphviz or JSON to debug small circuits visually
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use plonky2::field::extension::Extendable;
+use plonky2::gates::gate::Gate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::GenericConfig;
+use serde::Serialize;
+
+use crate::diagnostics;
+
+/// A wire of a gate, or a virtual target connected to one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphTarget {
+    Wire { row: usize, column: usize },
+    Virtual { index: usize },
+}
+
+impl From<Target> for GraphTarget {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Wire(wire) => Self::Wire {
+                row: wire.row,
+                column: wire.column,
+            },
+            Target::VirtualTarget { index } => Self::Virtual { index },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GateNode {
+    pub row: usize,
+    /// The id of the gate, e.g. `ArithmeticGate { num_ops: 20 }`
+    pub gate: String,
+    pub num_wires: usize,
+}
+
+/// Targets constrained to be equal. Each target is linked to the representative of its
+/// partition, so a partition of `n` targets gives `n - 1` edges
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CopyEdge {
+    pub from: GraphTarget,
+    pub to: GraphTarget,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CircuitGraph {
+    pub gates: Vec<GateNode>,
+    pub copies: Vec<CopyEdge>,
+    /// The labels of the targets in the graph
+    pub labels: Vec<(GraphTarget, String)>,
+}
+
+impl CircuitGraph {
+    /// The graph of the rows of `data` in `rows`, or of every row. Copy constraints are kept
+    /// when one of their ends is a wire of these rows
+    pub fn of<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        data: &CircuitData<F, C, D>,
+        rows: Option<Range<usize>>,
+        labels: &HashMap<Target, String>,
+    ) -> Self {
+        let common = &data.common;
+        let num_wires = common.config.num_wires;
+        let degree = common.degree();
+        let rows = rows.unwrap_or(0..degree);
+        let in_rows = |target: GraphTarget| match target {
+            GraphTarget::Wire { row, .. } => rows.contains(&row),
+            GraphTarget::Virtual { .. } => false,
+        };
+
+        let constants = diagnostics::constant_values(data);
+        let gates = (rows.start..rows.end.min(degree))
+            .filter_map(|row| {
+                let gate = &common.gates[diagnostics::gate_of_row(common, &constants, row)?].0;
+                Some(GateNode {
+                    row,
+                    gate: gate.id(),
+                    num_wires: gate.num_wires(),
+                })
+            })
+            .collect();
+
+        // the representative map covers every wire followed by every virtual target
+        let target = |index: usize| -> GraphTarget {
+            if index < num_wires * degree {
+                Target::wire(index / num_wires, index % num_wires).into()
+            } else {
+                Target::VirtualTarget {
+                    index: index - num_wires * degree,
+                }
+                .into()
+            }
+        };
+        let copies = data
+            .prover_only
+            .representative_map
+            .iter()
+            .enumerate()
+            .filter(|(index, representative)| index != *representative)
+            .map(|(index, representative)| CopyEdge {
+                from: target(index),
+                to: target(*representative),
+            })
+            .filter(|edge| in_rows(edge.from) || in_rows(edge.to))
+            .collect::<Vec<_>>();
+
+        let mut labels: Vec<(GraphTarget, String)> = labels
+            .iter()
+            .map(|(target, label)| (GraphTarget::from(*target), label.clone()))
+            .filter(|(target, _)| {
+                in_rows(*target) || copies.iter().any(|e| e.from == *target || e.to == *target)
+            })
+            .collect();
+        labels.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Self {
+            gates,
+            copies,
+            labels,
+        }
+    }
+
+    /// The graph in the DOT language, with a record node per gate whose fields are its
+    /// wires, and dashed edges for the copy constraints
+    pub fn to_dot(&self) -> String {
+        let port = |target: &GraphTarget| match target {
+            GraphTarget::Wire { row, column } => format!("row{row}:w{column}"),
+            GraphTarget::Virtual { index } => format!("v{index}"),
+        };
+
+        let mut dot = String::from("digraph circuit {\n  rankdir=LR;\n  node [shape=record];\n");
+        for gate in &self.gates {
+            let wires: Vec<String> = (0..gate.num_wires)
+                .map(|column| format!("<w{column}> {column}"))
+                .collect();
+            let _ = writeln!(
+                dot,
+                "  row{} [label=\"{{{} | {}}}\"];",
+                gate.row,
+                escape(&format!("{}: {}", gate.row, gate.gate)),
+                wires.join(" | ")
+            );
+        }
+        for (target, label) in &self.labels {
+            let _ = writeln!(
+                dot,
+                "  \"label {}\" [shape=plaintext, label=\"{}\"];\n  \"label {}\" -> {} [style=dotted];",
+                escape(label),
+                escape(label),
+                escape(label),
+                port(target)
+            );
+        }
+        for edge in &self.copies {
+            let _ = writeln!(
+                dot,
+                "  {} -> {} [dir=none, style=dashed];",
+                port(&edge.from),
+                port(&edge.to)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Escapes the characters DOT records give a meaning to
+fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' | '{' | '}' | '|' | '<' | '>' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// Writes the graph of `data` to `path`, as JSON if its extension is `json` and in the
+/// DOT language otherwise, e.g. for `dot -Tsvg`
+pub fn export_dot<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    data: &CircuitData<F, C, D>,
+    path: &Path,
+    rows: Option<Range<usize>>,
+    labels: &HashMap<Target, String>,
+) -> io::Result<()> {
+    let graph = CircuitGraph::of(data, rows, labels);
+    let contents = if path.extension().map_or(false, |ext| ext == "json") {
+        serde_json::to_string_pretty(&graph)?
+    } else {
+        graph.to_dot()
+    };
+
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use prover_utils::standard_config;
+
+    use super::*;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_circuit_graph() {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_squared = builder.mul(x, x);
+        builder.register_public_input(x_squared);
+        let data = builder.build::<C>();
+        let labels = HashMap::from([(x, "x".to_string())]);
+
+        let graph = CircuitGraph::of(&data, None, &labels);
+        assert_eq!(graph.gates.len(), data.common.degree());
+        assert!(graph
+            .gates
+            .iter()
+            .any(|gate| gate.gate.starts_with("ArithmeticGate")));
+        // x is copied to both multiplicands
+        assert!(graph.labels.iter().any(|(_, label)| label == "x"));
+        assert!(!graph.copies.is_empty());
+
+        let first_row = CircuitGraph::of(&data, Some(0..1), &labels);
+        assert_eq!(first_row.gates.len(), 1);
+        assert!(first_row.copies.len() <= graph.copies.len());
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains("style=dashed"));
+        let json = serde_json::to_value(&graph).unwrap();
+        assert_eq!(json["gates"].as_array().unwrap().len(), graph.gates.len());
+    }
+}
@@ -1,89 +1,646 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+use gadgets::builder_ext::CircuitBuilderExt;
 use plonky2::field::extension::Extendable;
+#[cfg(feature = "keccak")]
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::PrimeField64;
 use plonky2::hash::hash_types::RichField;
-use plonky2::iop::target::Target;
-use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, Witness};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
-use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+#[cfg(feature = "keccak")]
+use plonky2::plonk::config::{KeccakGoldilocksConfig, PoseidonGoldilocksConfig};
 use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::options::ProverOptions;
+use prover_utils::recursion_utils;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::artifacts::{ProverArtifacts, VerifierArtifacts};
+use crate::diagnostics::{self, WitnessReport};
+use crate::expr::{Expr, ExprCompiler};
+
+/// Number of unsatisfied gate constraints listed when a proof fails in debug mode
+const UNSATISFIED_CONSTRAINTS_REPORTED: usize = 8;
 
 pub trait NumericInstructionsCircuit<F: Extendable<D> + RichField, const D: usize> {
     fn add_target(&mut self, builder: &mut CircuitBuilder<F, D>);
+    /// Squares every input, see `square_all`
     fn square_targets(&mut self, builder: &mut CircuitBuilder<F, D>);
-    fn mul_targets(&mut self, builder: &mut CircuitBuilder<F, D>) -> Option<Target>;
+    /// The product of every input, see `product_all`
+    fn mul_targets(&mut self, builder: &mut CircuitBuilder<F, D>) -> Result<Target, ProofError>;
+    fn square_mul(&mut self, builder: &mut CircuitBuilder<F, D>) -> Result<Target, ProofError>;
+    fn square_all(&mut self, builder: &mut CircuitBuilder<F, D>, targets: &[Target])
+        -> Vec<Target>;
+    fn mul_pairs(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        lhs: &[Target],
+        rhs: &[Target],
+    ) -> Result<Vec<Target>, ProofError>;
+    /// The sum of the pairwise products of `lhs` and `rhs`, taking one `FmaGate` operation
+    /// per pair instead of a multiplication and an addition
+    fn dot_product(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        lhs: &[Target],
+        rhs: &[Target],
+    ) -> Result<Target, ProofError>;
+    fn sum_all(&mut self, builder: &mut CircuitBuilder<F, D>, targets: &[Target]) -> Target;
+    fn product_all(&mut self, builder: &mut CircuitBuilder<F, D>, targets: &[Target]) -> Target;
+    /// Selects the `a_index`-th input if the `cond_index`-th input is one, and the
+    /// `b_index`-th input if it is zero; the condition is constrained to be boolean
+    fn select(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        cond_index: usize,
+        a_index: usize,
+        b_index: usize,
+    ) -> Result<Target, ProofError>;
     fn register_public_inputs(&mut self, builder: &mut CircuitBuilder<F, D>);
-    fn set_partial_witnesses(&mut self, values: Vec<F>) -> Result<(), String>;
+    fn set_partial_witnesses(&mut self, values: Vec<F>) -> Result<(), ProofError>;
     fn register_output(&mut self, target: Target, builder: &mut CircuitBuilder<F, D>);
 }
 
 pub struct Circuit<F: Extendable<D> + RichField, const D: usize> {
     config: CircuitConfig,
+    /// `None` for a configuration passed to `new_with_config`
+    profile: Option<ConfigProfile>,
     targets: Vec<Target>,
-    partial_witness: PartialWitness<F>,
+    witness: WitnessBuilder<F>,
+    // debug labels of targets and of the constraints between them
+    labels: HashMap<Target, String>,
+    constraint_labels: Vec<(String, Target, Target)>,
+    debug_unsatisfied: bool,
+    deterministic: bool,
 }
 
 pub struct CircuitOutputs<F: Extendable<D> + RichField, C: GenericConfig<D, F = F>, const D: usize>
 {
     circuit_data: CircuitData<F, C, D>,
     proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    profile: Option<ConfigProfile>,
+}
+
+/// The circuit configurations provided by plonky2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigProfile {
+    StandardRecursion,
+    StandardRecursionZk,
+    StandardEcc,
+    WideEcc,
+}
+
+impl ConfigProfile {
+    pub fn config(&self) -> CircuitConfig {
+        match self {
+            ConfigProfile::StandardRecursion => CircuitConfig::standard_recursion_config(),
+            ConfigProfile::StandardRecursionZk => CircuitConfig::standard_recursion_zk_config(),
+            ConfigProfile::StandardEcc => CircuitConfig::standard_ecc_config(),
+            ConfigProfile::WideEcc => CircuitConfig::wide_ecc_config(),
+        }
+    }
+}
+
+impl<F: Extendable<D> + RichField, C: GenericConfig<D, F = F>, const D: usize>
+    CircuitOutputs<F, C, D>
+{
+    pub fn public_inputs(&self) -> &[F] {
+        &self.proof_with_pis.public_inputs
+    }
+
+    /// The profile of the configuration the proof was generated with, or `None` if the
+    /// configuration was passed to `Circuit::new_with_config`
+    pub fn profile(&self) -> Option<ConfigProfile> {
+        self.profile
+    }
+
+    pub fn circuit_data(&self) -> &CircuitData<F, C, D> {
+        &self.circuit_data
+    }
+
+    pub fn proof_with_pis(&self) -> &ProofWithPublicInputs<F, C, D> {
+        &self.proof_with_pis
+    }
+
+    pub fn is_zero_knowledge(&self) -> bool {
+        self.circuit_data.common.config.zero_knowledge
+    }
+
+    /// The public inputs parsed as the inputs followed by the output, which is the layout
+    /// of every circuit with a single output
+    pub fn typed_public_inputs(&self) -> Result<PublicInputs<F>, ProofError> {
+        PublicInputs::from_slice(&self.proof_with_pis.public_inputs)
+    }
+
+    pub fn verify(&self) -> Result<(), ProofError> {
+        self.circuit_data
+            .verify(self.proof_with_pis.clone())
+            .map_err(ProofError::Verification)
+    }
+
+    /// Splits the outputs into the proof handed out by the prover and the data needed to
+    /// verify it, dropping the prover-only data
+    pub fn into_artifacts(self) -> (ProverArtifacts<F, C, D>, VerifierArtifacts<F, C, D>) {
+        let CircuitData {
+            verifier_only,
+            common,
+            ..
+        } = self.circuit_data;
+
+        (
+            ProverArtifacts {
+                proof_with_pis: self.proof_with_pis,
+            },
+            VerifierArtifacts {
+                verifier_data: VerifierCircuitData {
+                    verifier_only,
+                    common,
+                },
+            },
+        )
+    }
+}
+
+/// The public inputs of a single-output proof of `Circuit`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicInputs<F> {
+    pub inputs: Vec<F>,
+    pub output: F,
+}
+
+impl<F: RichField> PublicInputs<F> {
+    /// Registers the targets in the layout parsed by `from_slice`
+    pub fn register<const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        inputs: &[Target],
+        output: Target,
+    ) where
+        F: Extendable<D>,
+    {
+        builder.register_public_inputs(inputs);
+        builder.register_public_input(output);
+    }
+
+    pub fn from_slice(public_inputs: &[F]) -> Result<Self, ProofError> {
+        match public_inputs.split_last() {
+            Some((output, inputs)) => Ok(Self {
+                inputs: inputs.to_vec(),
+                output: *output,
+            }),
+            None => Err(ProofError::PublicInputCount {
+                expected: 1,
+                actual: 0,
+            }),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<F> {
+        let mut public_inputs = self.inputs.clone();
+        public_inputs.push(self.output);
+        public_inputs
+    }
+}
+
+impl<F: Extendable<D> + RichField, const D: usize> Default for Circuit<F, D> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<F: Extendable<D> + RichField, const D: usize> Circuit<F, D> {
     pub fn new() -> Self {
-        let config = CircuitConfig::standard_recursion_config();
-        Self::new_with_config(config)
+        Self::with_profile(ConfigProfile::StandardRecursion)
+    }
+
+    pub fn new_zk() -> Self {
+        Self::with_profile(ConfigProfile::StandardRecursionZk)
+    }
+
+    pub fn with_profile(profile: ConfigProfile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::new_with_config(profile.config())
+        }
     }
 
     pub fn new_with_config(config: CircuitConfig) -> Self {
         Self {
             config,
+            profile: None,
             targets: Vec::new(),
-            partial_witness: PartialWitness::new(),
+            witness: WitnessBuilder::new(),
+            labels: HashMap::new(),
+            constraint_labels: Vec::new(),
+            debug_unsatisfied: false,
+            deterministic: false,
+        }
+    }
+
+    /// Reports failing witness generation or labeled constraints as `ProofError::Unsatisfied`,
+    /// naming the labels involved, instead of panicking inside the prover. In debug builds
+    /// the error also lists the first gate constraints the witness doesn't satisfy
+    pub fn debug_unsatisfied(&mut self) -> &mut Self {
+        self.debug_unsatisfied = true;
+        self
+    }
+
+    /// Proves reproducibly, the same inputs giving the same proof bytes on every run, see
+    /// `ProverOptions::deterministic`. The configuration shouldn't be zero-knowledge
+    pub fn deterministic(&mut self) -> &mut Self {
+        self.deterministic = true;
+        self
+    }
+
+    /// The input targets added so far
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    pub fn label(&mut self, target: Target, label: impl Into<String>) {
+        self.labels.insert(target, label.into());
+    }
+
+    /// The debug labels of the targets, e.g. for `graph::export_dot`
+    pub fn labels(&self) -> &HashMap<Target, String> {
+        &self.labels
+    }
+
+    pub fn label_of(&self, target: Target) -> Option<&str> {
+        self.labels.get(&target).map(String::as_str)
+    }
+
+    /// The `i`-th input, named by its label if it has one
+    fn input_handle(&self, i: usize, target: Target) -> Named<Target> {
+        let name = self
+            .label_of(target)
+            .map_or_else(|| format!("input[{i}]"), str::to_string);
+        Named::new(name, target)
+    }
+
+    pub fn add_labeled_target(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        label: impl Into<String>,
+    ) -> Target {
+        self.add_target(builder);
+        let target = self.targets[self.targets.len() - 1];
+        self.label(target, label);
+        target
+    }
+
+    /// Connects `a` and `b`, recording `label` for the equality constraint
+    pub fn connect_labeled(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        a: Target,
+        b: Target,
+        label: impl Into<String>,
+    ) {
+        builder.connect(a, b);
+        self.constraint_labels.push((label.into(), a, b));
+    }
+
+    /// Applies `step` `n` times starting from `init`, threading the state of each iteration
+    /// into the next, e.g. for hash chains or exponent ladders. The step can add targets and
+    /// use the instructions of the circuit, which keep their bookkeeping across iterations
+    pub fn repeat<S>(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        n: usize,
+        init: S,
+        mut step: impl FnMut(&mut Self, &mut CircuitBuilder<F, D>, S) -> Result<S, ProofError>,
+    ) -> Result<S, ProofError> {
+        let mut state = init;
+        for _ in 0..n {
+            state = step(self, builder, state)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Builds the circuit and proves it against the partial witness set so far
+    pub fn prove<C: GenericConfig<D, F = F>>(
+        &mut self,
+        builder: CircuitBuilder<F, D>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        if self.debug_unsatisfied {
+            let labels = self.unsatisfied_labels();
+            if !labels.is_empty() {
+                return Err(ProofError::Unsatisfied {
+                    message: "the partial witness violates labeled constraints".to_string(),
+                    labels,
+                });
+            }
+        }
+
+        let data = builder.build::<C>();
+        if self.debug_unsatisfied {
+            let report =
+                diagnostics::check_witness(&data, self.witness.partial_witness(), &self.labels);
+            if !report.is_complete() {
+                return Err(ProofError::Unsatisfied {
+                    message: report.to_string(),
+                    labels: report.target_names(),
+                });
+            }
         }
+
+        let partial_witness = self.witness.partial_witness().clone();
+        let options = ProverOptions {
+            deterministic: self.deterministic,
+            ..Default::default()
+        };
+        let prove = || prover_utils::prove_with_options(&data, partial_witness, &options);
+        let proof = if self.debug_unsatisfied {
+            panic::catch_unwind(AssertUnwindSafe(prove)).map_err(|payload| {
+                ProofError::Unsatisfied {
+                    message: self.failure_message(
+                        &data,
+                        payload
+                            .downcast_ref::<String>()
+                            .cloned()
+                            .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                            .unwrap_or_else(|| "the prover panicked".to_string()),
+                    ),
+                    labels: self.unsatisfied_labels(),
+                }
+            })?
+        } else {
+            prove()
+        }?;
+
+        Ok(CircuitOutputs {
+            circuit_data: data,
+            proof_with_pis: proof,
+            profile: self.profile,
+        })
+    }
+
+    /// Builds the circuit and runs its generators against the partial witness set so far
+    /// without proving, reporting the targets which could not be resolved
+    pub fn check_witness<C: GenericConfig<D, F = F>>(
+        &self,
+        builder: CircuitBuilder<F, D>,
+    ) -> WitnessReport {
+        let data = builder.build::<C>();
+        diagnostics::check_witness(&data, self.witness.partial_witness(), &self.labels)
+    }
+
+    /// The message of a failed proof, followed in debug builds by the first gate
+    /// constraints the witness doesn't satisfy
+    fn failure_message<C: GenericConfig<D, F = F>>(
+        &self,
+        data: &CircuitData<F, C, D>,
+        message: String,
+    ) -> String {
+        if !cfg!(debug_assertions) {
+            return message;
+        }
+
+        let unsatisfied = diagnostics::unsatisfied_constraints(
+            data,
+            self.witness.partial_witness(),
+            UNSATISFIED_CONSTRAINTS_REPORTED,
+        );
+        unsatisfied.iter().fold(message, |message, constraint| {
+            format!("{message}\n  {constraint}")
+        })
+    }
+
+    /// Labels of the constraints violated by the values set in the partial witness,
+    /// and of the labeled inputs which were never set
+    fn unsatisfied_labels(&self) -> Vec<String> {
+        let violated = self
+            .constraint_labels
+            .iter()
+            .filter(|(_, a, b)| {
+                match (
+                    self.witness.partial_witness().try_get_target(*a),
+                    self.witness.partial_witness().try_get_target(*b),
+                ) {
+                    (Some(a), Some(b)) => a != b,
+                    _ => false,
+                }
+            })
+            .map(|(label, _, _)| label.clone());
+        let unset = self
+            .targets
+            .iter()
+            .filter(|t| self.witness.partial_witness().try_get_target(**t).is_none())
+            .filter_map(|t| self.label_of(*t))
+            .map(|label| format!("{label} (unset)"));
+
+        violated.chain(unset).collect()
     }
 
     pub fn build_circuit<C: GenericConfig<D, F = F>>(
         &mut self,
         witnesses: Vec<F>,
-    ) -> CircuitOutputs<F, C, D> {
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        // the product of the squares of all the inputs
+        let expr = Expr::product((0..witnesses.len()).map(|i| Expr::Input(i).pow(2)));
+        self.build_expr_circuit(&expr, witnesses)
+    }
+
+    pub fn build_expr_circuit<C: GenericConfig<D, F = F>>(
+        &mut self,
+        expr: &Expr<F>,
+        witnesses: Vec<F>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        self.prove_expr(expr, witnesses, None)
+    }
+
+    /// Proves several expressions over the same inputs, registering each output in order
+    pub fn build_exprs_circuit<C: GenericConfig<D, F = F>>(
+        &mut self,
+        exprs: &[Expr<F>],
+        witnesses: Vec<F>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
+        for i in 0..witnesses.len() {
+            self.add_labeled_target(&mut builder, format!("input[{i}]"));
+        }
+        // common subexpressions are shared between the outputs
+        let outputs = {
+            let mut compiler = ExprCompiler::new(&mut builder, &self.targets);
+            exprs
+                .iter()
+                .map(|expr| compiler.compile(expr))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        self.register_public_inputs(&mut builder);
+        for (i, output) in outputs.into_iter().enumerate() {
+            self.label(output, format!("output[{i}]"));
+            self.register_output(output, &mut builder);
+        }
+        self.set_partial_witnesses(witnesses)?;
+
+        self.prove(builder)
+    }
+
+    /// Same as `build_circuit`, but computes the product of squares with `NumericCustomGate`
+    /// operations instead of separate square and multiplication gates
+    pub fn build_square_mul_circuit<C: GenericConfig<D, F = F>>(
+        &mut self,
+        witnesses: Vec<F>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
+        for i in 0..witnesses.len() {
+            self.add_labeled_target(&mut builder, format!("input[{i}]"));
+        }
+        let output = self.square_mul(&mut builder)?;
+        self.label(output, "output");
+        self.register_public_inputs(&mut builder);
+        self.register_output(output, &mut builder);
+        self.set_partial_witnesses(witnesses)?;
+
+        self.prove(builder)
+    }
+
+    /// Proves the product of the squares of the witnesses, registering `expected` as the
+    /// public output and constraining it to equal the computed output
+    pub fn prove_with_expected_output<C: GenericConfig<D, F = F>>(
+        &mut self,
+        witnesses: Vec<F>,
+        expected: F,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        let expr = Expr::product((0..witnesses.len()).map(|i| Expr::Input(i).pow(2)));
+        self.prove_expr_with_expected_output(&expr, witnesses, expected)
+    }
+
+    pub fn prove_expr_with_expected_output<C: GenericConfig<D, F = F>>(
+        &mut self,
+        expr: &Expr<F>,
+        witnesses: Vec<F>,
+        expected: F,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
+        // fail early with a typed error, instead of a conflicting witness during proving
+        let actual = expr.evaluate(&witnesses)?;
+        if actual != expected {
+            return Err(ProofError::OutputMismatch {
+                expected: expected.to_canonical_u64(),
+                actual: actual.to_canonical_u64(),
+            });
+        }
+
+        self.prove_expr(expr, witnesses, Some(expected))
+    }
+
+    fn prove_expr<C: GenericConfig<D, F = F>>(
+        &mut self,
+        expr: &Expr<F>,
+        witnesses: Vec<F>,
+        expected: Option<F>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError> {
         let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
         let num_targets = witnesses.len();
         // add num_targets
-        for _ in 0..num_targets {
-            self.add_target(&mut builder);
+        for i in 0..num_targets {
+            self.add_labeled_target(&mut builder, format!("input[{i}]"));
         }
-        // square the targets
-        self.square_targets(&mut builder);
-        // multiply targets
-        let mult_targets = self.mul_targets(&mut builder);
+        // lower the expression into the circuit
+        let output = ExprCompiler::new(&mut builder, &self.targets).compile(expr)?;
+        self.label(output, "output");
+        // constrain the output against the expected value, which is then the public output
+        let public_output = match expected {
+            Some(expected) => {
+                let expected_target = builder.add_virtual_target();
+                self.label(expected_target, "expected_output");
+                self.connect_labeled(
+                    &mut builder,
+                    output,
+                    expected_target,
+                    "output == expected_output",
+                );
+                self.witness
+                    .set(&Named::new("expected_output", expected_target), expected)?;
+                expected_target
+            }
+            None => output,
+        };
         // register public inputs
         self.register_public_inputs(&mut builder);
-        if let Some(target) = mult_targets {
-            self.register_output(target, &mut builder);
-        }
+        self.register_output(public_output, &mut builder);
         // set partial witnesses
-        self.set_partial_witnesses(witnesses)
-            .expect("Number of values should coincide with targets");
-        // build the underlying circuit
-        let data = builder.build::<C>();
-        // get the proof
-        let proof = data
-            .prove(self.partial_witness.clone())
-            .expect("Unexpected behavior");
-
-        CircuitOutputs {
-            circuit_data: data,
-            proof_with_pis: proof,
-        }
+        self.set_partial_witnesses(witnesses)?;
+        // build the underlying circuit and get the proof
+        self.prove(builder)
     }
 
     pub fn verify_proof<C: GenericConfig<D, F = F>>(
         &self,
         proof_with_pis: ProofWithPublicInputs<F, C, D>,
-        data: CircuitData<F, C, D>,
-    ) -> Result<()> {
+        data: &VerifierCircuitData<F, C, D>,
+    ) -> Result<(), ProofError> {
         data.verify(proof_with_pis)
+            .map_err(ProofError::Verification)
+    }
+
+    /// Builds an outer circuit verifying the proof in `outputs`, forwarding its public inputs
+    pub fn wrap_recursively<C: GenericConfig<D, F = F>>(
+        &self,
+        outputs: CircuitOutputs<F, C, D>,
+    ) -> Result<CircuitOutputs<F, C, D>, ProofError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        self.wrap_with::<C, C>(outputs)
+    }
+
+    /// Like `wrap_recursively`, proving the wrapper with `OuterC`, e.g. a keccak config as the
+    /// final wrap step, whose hasher doesn't need to be verifiable in a circuit
+    pub fn wrap_with<C: GenericConfig<D, F = F>, OuterC: GenericConfig<D, F = F>>(
+        &self,
+        outputs: CircuitOutputs<F, C, D>,
+    ) -> Result<CircuitOutputs<F, OuterC, D>, ProofError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let CircuitOutputs {
+            circuit_data: inner_data,
+            proof_with_pis: inner_proof,
+            ..
+        } = outputs;
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
+        let mut partial_witness = PartialWitness::new();
+
+        let (_, proof_targets) = recursion_utils::verify_proofs_in_circuit(
+            &mut builder,
+            &mut partial_witness,
+            &[inner_proof],
+            &inner_data.verifier_data(),
+        )?;
+        builder.register_public_inputs(&proof_targets[0].public_inputs);
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::timed_prove::<F, OuterC, D>(builder, partial_witness)?;
+
+        Ok(CircuitOutputs {
+            circuit_data,
+            proof_with_pis,
+            profile: self.profile,
+        })
+    }
+}
+
+#[cfg(feature = "keccak")]
+impl Circuit<GoldilocksField, 2> {
+    /// Wraps a Poseidon proof into a proof with a keccak transcript, for verifiers which
+    /// only have keccak256 such as the EVM
+    pub fn wrap_for_evm(
+        &self,
+        outputs: CircuitOutputs<GoldilocksField, PoseidonGoldilocksConfig, 2>,
+    ) -> Result<CircuitOutputs<GoldilocksField, KeccakGoldilocksConfig, 2>, ProofError> {
+        self.wrap_with::<PoseidonGoldilocksConfig, KeccakGoldilocksConfig>(outputs)
     }
 }
 
@@ -95,28 +652,123 @@ impl<F: Extendable<D> + RichField, const D: usize> NumericInstructionsCircuit<F,
     }
 
     fn square_targets(&mut self, builder: &mut CircuitBuilder<F, D>) {
+        let targets = self.targets.clone();
+        self.square_all(builder, &targets);
+    }
+
+    fn mul_targets(&mut self, builder: &mut CircuitBuilder<F, D>) -> Result<Target, ProofError> {
         if self.targets.is_empty() {
-            return;
+            return Err(ProofError::EmptyCircuit);
         }
 
+        let targets = self.targets.clone();
+        Ok(self.product_all(builder, &targets))
+    }
+
+    fn square_mul(&mut self, builder: &mut CircuitBuilder<F, D>) -> Result<Target, ProofError> {
+        if self.targets.is_empty() {
+            return Err(ProofError::EmptyCircuit);
+        }
+
+        let mut accumulator = builder.one();
         for target in &self.targets {
-            builder.square(*target);
+            accumulator = builder.square_mul(accumulator, *target);
         }
+
+        Ok(accumulator)
     }
 
-    fn mul_targets(&mut self, builder: &mut CircuitBuilder<F, D>) -> Option<Target> {
-        if self.targets.len() <= 1 {
-            return self.targets.first().copied();
+    fn square_all(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        targets: &[Target],
+    ) -> Vec<Target> {
+        targets.iter().map(|t| builder.square(*t)).collect()
+    }
+
+    fn mul_pairs(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        lhs: &[Target],
+        rhs: &[Target],
+    ) -> Result<Vec<Target>, ProofError> {
+        if lhs.len() != rhs.len() {
+            return Err(ProofError::LengthMismatch {
+                lhs: lhs.len(),
+                rhs: rhs.len(),
+            });
         }
 
-        let mut prev_target = self.targets[0];
-        let mut temp: Target = Target::VirtualTarget { index: 0 };
-        for cur_target in &self.targets[1..] {
-            temp = builder.mul(prev_target, *cur_target);
-            prev_target = temp;
+        Ok(lhs
+            .iter()
+            .zip(rhs)
+            .map(|(a, b)| builder.mul(*a, *b))
+            .collect())
+    }
+
+    fn dot_product(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        lhs: &[Target],
+        rhs: &[Target],
+    ) -> Result<Target, ProofError> {
+        if lhs.len() != rhs.len() {
+            return Err(ProofError::LengthMismatch {
+                lhs: lhs.len(),
+                rhs: rhs.len(),
+            });
+        }
+
+        let pairs: Vec<_> = lhs.iter().copied().zip(rhs.iter().copied()).collect();
+        Ok(builder.fma_inner_product(&pairs))
+    }
+
+    fn sum_all(&mut self, builder: &mut CircuitBuilder<F, D>, targets: &[Target]) -> Target {
+        builder.add_many(targets)
+    }
+
+    fn product_all(&mut self, builder: &mut CircuitBuilder<F, D>, targets: &[Target]) -> Target {
+        if targets.is_empty() {
+            return builder.one();
+        }
+
+        // multiply neighbouring pairs until a single target remains, keeping the depth logarithmic
+        let mut layer = targets.to_vec();
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => builder.mul(*a, *b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
         }
 
-        Some(temp)
+        layer[0]
+    }
+
+    fn select(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        cond_index: usize,
+        a_index: usize,
+        b_index: usize,
+    ) -> Result<Target, ProofError> {
+        let [cond, a, b] = [cond_index, a_index, b_index].map(|index| {
+            self.targets
+                .get(index)
+                .copied()
+                .ok_or(ProofError::InputOutOfRange {
+                    index,
+                    num_inputs: self.targets.len(),
+                })
+        });
+
+        let (cond, a, b) = (BoolTarget::new_unsafe(cond?), a?, b?);
+        builder.assert_bool(cond);
+
+        Ok(builder.select(cond, a, b))
     }
 
     fn register_public_inputs(&mut self, builder: &mut CircuitBuilder<F, D>) {
@@ -131,20 +783,17 @@ impl<F: Extendable<D> + RichField, const D: usize> NumericInstructionsCircuit<F,
         builder.register_public_input(target);
     }
 
-    fn set_partial_witnesses(&mut self, witnesses: Vec<F>) -> Result<(), String> {
+    fn set_partial_witnesses(&mut self, witnesses: Vec<F>) -> Result<(), ProofError> {
         if witnesses.len() != self.targets.len() {
-            return Err(format!(
-                "The user should provide {} values, not {}",
-                self.targets.len(),
-                witnesses.len(),
-            ));
+            return Err(ProofError::WitnessCount {
+                expected: self.targets.len(),
+                actual: witnesses.len(),
+            });
+        }
+        for (i, value) in witnesses.into_iter().enumerate() {
+            let handle = self.input_handle(i, self.targets[i]);
+            self.witness.set(&handle, value)?;
         }
-        let _ = self
-            .targets
-            .iter()
-            .zip(witnesses)
-            .map(|(target, value)| self.partial_witness.set_target(*target, value))
-            .collect::<Vec<_>>();
 
         Ok(())
     }
@@ -154,14 +803,15 @@ impl<F: Extendable<D> + RichField, const D: usize> NumericInstructionsCircuit<F,
 mod tests {
     use plonky2::field::goldilocks_field::GoldilocksField;
     use plonky2::field::types::Field;
-    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::{Hasher, PoseidonGoldilocksConfig};
 
     use super::*;
     type F = GoldilocksField;
     type C = PoseidonGoldilocksConfig;
 
     #[test]
-    fn it_works_simple_example_build_circuit() {
+    fn it_works_simple_example_build_circuit() -> Result<(), ProofError> {
         // start with a simple example a = 2, b = 2, in which case
         // (a^2) * (b^2) = 4 * 4 = 16
         let mut circuit = Circuit::<F, 2>::new();
@@ -170,14 +820,23 @@ mod tests {
         let CircuitOutputs {
             circuit_data,
             proof_with_pis,
-        } = circuit.build_circuit::<C>(witnesses);
+            ..
+        } = circuit.build_circuit::<C>(witnesses)?;
+        assert_eq!(
+            proof_with_pis.public_inputs.last(),
+            Some(&F::from_canonical_u64(16))
+        );
 
         // verify the proof
-        assert!(circuit.verify_proof(proof_with_pis, circuit_data).is_ok());
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
     }
 
     #[test]
-    fn it_works_involved_example_build_circuit() {
+    fn it_works_involved_example_build_circuit() -> Result<(), ProofError> {
         // let a = 4, b = 2, c = 7, d = 5, in which case
         // (a^2) * (b^2) * (c^2) * (d^2) = 78_400
         let mut circuit = Circuit::<F, 2>::new();
@@ -191,9 +850,433 @@ mod tests {
         let CircuitOutputs {
             circuit_data,
             proof_with_pis,
-        } = circuit.build_circuit::<C>(witnesses);
+            ..
+        } = circuit.build_circuit::<C>(witnesses)?;
+
+        // verify the proof
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_works_expr_build_circuit() -> Result<(), ProofError> {
+        // let a = 3, b = 5, in which case
+        // (a + b)^2 - a * b = 64 - 15 = 49
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let sum = Expr::Input(0) + Expr::Input(1);
+        let expr = sum.pow(2) + -(Expr::Input(0) * Expr::Input(1));
+        let witnesses: Vec<F> = vec![F::from_canonical_u64(3), F::from_canonical_u64(5)];
+        let CircuitOutputs {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = circuit.build_expr_circuit::<C>(&expr, witnesses)?;
+        assert_eq!(
+            proof_with_pis.public_inputs.last(),
+            Some(&F::from_canonical_u64(49))
+        );
+
+        // verify the proof
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_out_of_range_inputs() {
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let expr = Expr::Input(0) * Expr::Input(2);
+        let witnesses: Vec<F> = vec![F::TWO, F::TWO];
+
+        assert!(matches!(
+            circuit.build_expr_circuit::<C>(&expr, witnesses),
+            Err(ProofError::InputOutOfRange {
+                index: 2,
+                num_inputs: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_mismatched_witnesses() {
+        let mut circuit = Circuit::<F, 2>::new();
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        circuit.add_target(&mut builder);
+        circuit.add_target(&mut builder);
+
+        assert!(matches!(
+            circuit.set_partial_witnesses(vec![F::ONE; 3]),
+            Err(ProofError::WitnessCount {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(circuit.mul_targets(&mut builder).is_ok());
+    }
+
+    #[test]
+    fn it_rejects_empty_products() {
+        let mut circuit = Circuit::<F, 2>::new();
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+
+        assert!(matches!(
+            circuit.mul_targets(&mut builder),
+            Err(ProofError::EmptyCircuit)
+        ));
+    }
+
+    #[test]
+    fn it_works_expected_output() -> Result<(), ProofError> {
+        // a = 2, b = 3, in which case (a^2) * (b^2) = 36
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let witnesses: Vec<F> = vec![F::TWO, F::from_canonical_u64(3)];
+        let CircuitOutputs {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = circuit.prove_with_expected_output::<C>(witnesses, F::from_canonical_u64(36))?;
+        assert_eq!(
+            proof_with_pis.public_inputs.last(),
+            Some(&F::from_canonical_u64(36))
+        );
+
+        // verify the proof
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unexpected_output() {
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let witnesses: Vec<F> = vec![F::TWO, F::from_canonical_u64(3)];
+        let result = circuit.prove_with_expected_output::<C>(witnesses, F::from_canonical_u64(35));
+
+        assert!(matches!(
+            result,
+            Err(ProofError::OutputMismatch {
+                expected: 35,
+                actual: 36
+            })
+        ));
+    }
+
+    #[test]
+    fn it_reports_unsatisfied_labels() {
+        let mut circuit = Circuit::<F, 2>::new();
+        circuit.debug_unsatisfied();
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let a = circuit.add_labeled_target(&mut builder, "a");
+        let b = circuit.add_labeled_target(&mut builder, "b");
+        circuit.connect_labeled(&mut builder, a, b, "a == b");
+        assert_eq!(circuit.label_of(a), Some("a"));
+
+        circuit.set_partial_witnesses(vec![F::ONE, F::TWO]).unwrap();
+
+        match circuit.prove::<C>(builder) {
+            Err(ProofError::Unsatisfied { labels, .. }) => assert_eq!(labels, vec!["a == b"]),
+            _ => panic!("expected the labeled constraint to be reported"),
+        }
+    }
+
+    #[test]
+    fn it_works_square_mul_build_circuit() -> Result<(), ProofError> {
+        // same as the involved example, with the custom gate
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let witnesses: Vec<F> = vec![
+            F::TWO + F::TWO,
+            F::TWO,
+            F::TWO + F::TWO + F::TWO + F::ONE,
+            F::TWO + F::TWO + F::ONE,
+        ];
+        let CircuitOutputs {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = circuit.build_square_mul_circuit::<C>(witnesses)?;
+        assert_eq!(
+            proof_with_pis.public_inputs.last(),
+            Some(&F::from_canonical_u64(78_400))
+        );
+
+        // verify the proof
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_works_vector_instructions() -> Result<(), ProofError> {
+        // inputs 1, 2, 3, 4 and 5, in which case
+        // the squares sum up to 55, their product is 14_400,
+        // and the pairwise products of the first and last two inputs sum up to 1 * 4 + 2 * 5 = 14
+        let mut circuit = Circuit::<F, 2>::new();
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        for _ in 0..5 {
+            circuit.add_target(&mut builder);
+        }
+        let inputs = circuit.targets().to_vec();
+
+        let squares = circuit.square_all(&mut builder, &inputs);
+        let sum = circuit.sum_all(&mut builder, &squares);
+        let product = circuit.product_all(&mut builder, &squares);
+        let dot = circuit.dot_product(&mut builder, &inputs[..2], &inputs[3..])?;
+        for output in [sum, product, dot] {
+            circuit.register_output(output, &mut builder);
+        }
+        assert!(matches!(
+            circuit.mul_pairs(&mut builder, &inputs[..2], &inputs[2..]),
+            Err(ProofError::LengthMismatch { lhs: 2, rhs: 3 })
+        ));
+        assert!(matches!(
+            circuit.dot_product(&mut builder, &inputs[..2], &inputs[2..]),
+            Err(ProofError::LengthMismatch { lhs: 2, rhs: 3 })
+        ));
+
+        circuit.set_partial_witnesses((1..=5).map(F::from_canonical_u64).collect())?;
+        let CircuitOutputs {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = circuit.prove::<C>(builder)?;
+        assert_eq!(
+            proof_with_pis.public_inputs,
+            [55, 14_400, 14].map(F::from_canonical_u64)
+        );
 
         // verify the proof
-        assert!(circuit.verify_proof(proof_with_pis, circuit_data).is_ok());
+        assert!(circuit
+            .verify_proof(proof_with_pis, &circuit_data.verifier_data())
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dot_product_halves_rows() -> Result<(), ProofError> {
+        // 20 pairs: a row of multiplications and a row of additions, or a row of FmaGate
+        let rows = |fma: bool| -> Result<usize, ProofError> {
+            let mut circuit = Circuit::<F, 2>::new();
+            let mut builder =
+                CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+            for _ in 0..40 {
+                circuit.add_target(&mut builder);
+            }
+            let inputs = circuit.targets().to_vec();
+            if fma {
+                circuit.dot_product(&mut builder, &inputs[..20], &inputs[20..])?;
+            } else {
+                let pairs = circuit.mul_pairs(&mut builder, &inputs[..20], &inputs[20..])?;
+                circuit.sum_all(&mut builder, &pairs);
+            }
+            Ok(builder.num_gates())
+        };
+        assert_eq!(rows(false)?, 2);
+        assert_eq!(rows(true)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_works_select() -> Result<(), ProofError> {
+        // inputs cond, 3 and 7, selecting 3 when cond = 1 and 7 when cond = 0
+        for (cond, expected) in [(F::ONE, 3), (F::ZERO, 7)] {
+            let mut circuit = Circuit::<F, 2>::new();
+            let mut builder =
+                CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+            for _ in 0..3 {
+                circuit.add_target(&mut builder);
+            }
+            let selected = circuit.select(&mut builder, 0, 1, 2)?;
+            circuit.register_output(selected, &mut builder);
+            assert!(matches!(
+                circuit.select(&mut builder, 0, 1, 3),
+                Err(ProofError::InputOutOfRange {
+                    index: 3,
+                    num_inputs: 3
+                })
+            ));
+
+            circuit.set_partial_witnesses(vec![
+                cond,
+                F::from_canonical_u64(3),
+                F::from_canonical_u64(7),
+            ])?;
+            let outputs = circuit.prove::<C>(builder)?;
+            assert_eq!(outputs.public_inputs(), [F::from_canonical_u64(expected)]);
+
+            outputs.verify()?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_works_repeat() -> Result<(), ProofError> {
+        // an exponent ladder squaring x = 3 four times, i.e. 3^16, multiplied by a fresh
+        // input in each iteration, and a hash chain of length 3 starting from x
+        let mut circuit = Circuit::<F, 2>::new();
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        circuit.add_target(&mut builder);
+        let x = circuit.targets()[0];
+
+        let ladder = circuit.repeat(&mut builder, 4, x, |c, b, acc| {
+            c.add_target(b);
+            let factor = c.targets()[c.targets().len() - 1];
+            let squared = b.square(acc);
+            Ok(b.mul(squared, factor))
+        })?;
+        let chain = circuit.repeat(&mut builder, 3, vec![x], |_, b, elements| {
+            Ok(b.hash_n_to_hash_no_pad::<PoseidonHash>(elements)
+                .elements
+                .to_vec())
+        })?;
+        circuit.register_output(ladder, &mut builder);
+        builder.register_public_inputs(&chain);
+        assert_eq!(circuit.targets().len(), 5);
+
+        let three = F::from_canonical_u64(3);
+        circuit.set_partial_witnesses(vec![three, F::ONE, F::ONE, F::ONE, F::TWO])?;
+        let outputs = circuit.prove::<C>(builder)?;
+
+        let expected_chain = (0..3).fold(vec![three], |elements, _| {
+            PoseidonHash::hash_no_pad(&elements).elements.to_vec()
+        });
+        assert_eq!(outputs.public_inputs()[0], three.exp_u64(16) * F::TWO);
+        assert_eq!(outputs.public_inputs()[1..], expected_chain[..]);
+
+        outputs.verify()
+    }
+
+    #[test]
+    fn it_reports_unresolved_targets() {
+        // only a is set out of the inputs a and b of a * b
+        for set_b in [false, true] {
+            let mut circuit = Circuit::<F, 2>::new();
+            let mut builder =
+                CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+            let a = circuit.add_labeled_target(&mut builder, "a");
+            let b = circuit.add_labeled_target(&mut builder, "b");
+            let product = builder.mul(a, b);
+            circuit.register_output(product, &mut builder);
+            circuit.witness.set(&Named::new("a", a), F::TWO).unwrap();
+            if set_b {
+                circuit.witness.set(&Named::new("b", b), F::TWO).unwrap();
+            }
+
+            let report = circuit.check_witness::<C>(builder);
+            if set_b {
+                assert!(report.is_complete());
+            } else {
+                assert!(!report.is_complete());
+                assert_eq!(report.target_names(), ["b"]);
+                assert!(report.to_string().contains("generators weren't run"));
+            }
+        }
+    }
+
+    #[test]
+    fn it_works_wrap_recursively() -> Result<(), ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let witnesses: Vec<F> = vec![F::TWO, F::TWO];
+        let inner = circuit.build_circuit::<C>(witnesses)?;
+        let inner_public_inputs = inner.public_inputs().to_vec();
+
+        let outer = circuit.wrap_recursively(inner)?;
+        assert_eq!(outer.public_inputs(), inner_public_inputs);
+
+        outer.verify()
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn it_works_wrap_for_evm() -> Result<(), ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+
+        let witnesses: Vec<F> = vec![F::TWO, F::TWO];
+        let inner = circuit.build_circuit::<C>(witnesses)?;
+        let inner_public_inputs = inner.public_inputs().to_vec();
+
+        let outer = circuit.wrap_for_evm(inner)?;
+        assert_eq!(outer.public_inputs(), inner_public_inputs);
+
+        outer.verify()
+    }
+
+    #[test]
+    fn it_parses_typed_public_inputs() -> Result<(), ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+        let outputs = circuit.prove_with_expected_output::<C>(
+            vec![F::TWO, F::from_canonical_u64(3)],
+            F::from_canonical_u64(36),
+        )?;
+
+        let public_inputs = outputs.typed_public_inputs()?;
+        assert_eq!(
+            public_inputs,
+            PublicInputs {
+                inputs: vec![F::TWO, F::from_canonical_u64(3)],
+                output: F::from_canonical_u64(36),
+            }
+        );
+        assert_eq!(public_inputs.to_vec(), outputs.public_inputs());
+        assert!(matches!(
+            PublicInputs::<F>::from_slice(&[]),
+            Err(ProofError::PublicInputCount {
+                expected: 1,
+                actual: 0
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_works_with_each_profile() -> Result<(), ProofError> {
+        for profile in [
+            ConfigProfile::StandardRecursion,
+            ConfigProfile::StandardRecursionZk,
+            ConfigProfile::StandardEcc,
+            ConfigProfile::WideEcc,
+        ] {
+            let mut circuit = Circuit::<F, 2>::with_profile(profile);
+
+            let outputs = circuit.build_circuit::<C>(vec![F::TWO, F::TWO])?;
+            assert_eq!(outputs.profile(), Some(profile));
+            assert_eq!(
+                outputs.is_zero_knowledge(),
+                profile == ConfigProfile::StandardRecursionZk
+            );
+
+            outputs.verify()?;
+        }
+
+        let outputs = Circuit::<F, 2>::new_zk().build_circuit::<C>(vec![F::TWO, F::TWO])?;
+        assert!(outputs.is_zero_knowledge());
+        outputs.verify()?;
+
+        // a configuration passed as is has no profile, even if a profile has the same one
+        let config = CircuitConfig::standard_recursion_config();
+        let outputs =
+            Circuit::<F, 2>::new_with_config(config).build_circuit::<C>(vec![F::TWO, F::TWO])?;
+        assert_eq!(outputs.profile(), None);
+
+        outputs.verify()
     }
 }
@@ -0,0 +1,110 @@
+//! Proofs of sequential work: `N` Poseidon iterations from a seed, each of which needs the
+//! previous one, proven in segments chained with `IvcChain`. The proof is cheap to verify
+//! while computing the chain takes `N` sequential hashes, as in a VDF without its trapdoor
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+
+use crate::ivc::{IvcChain, StepCircuit};
+
+/// Iterates Poseidon `iterations_per_step` times from the state of the last step. The
+/// state is the current hash followed by the number of iterations so far, `N`
+pub struct HashChain<F> {
+    pub seed: [F; 4],
+    pub iterations_per_step: usize,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> StepCircuit<F, D> for HashChain<F> {
+    fn initial_state(&self) -> Vec<F> {
+        self.seed.into_iter().chain([F::ZERO]).collect()
+    }
+
+    fn num_inputs(&self) -> usize {
+        0
+    }
+
+    fn step(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        state: &[Target],
+        _inputs: &[Target],
+    ) -> Vec<Target> {
+        let mut hash = state[..4].to_vec();
+        for _ in 0..self.iterations_per_step {
+            hash = builder
+                .hash_n_to_hash_no_pad::<PoseidonHash>(hash)
+                .elements
+                .to_vec();
+        }
+        let iterations = builder.constant(F::from_canonical_usize(self.iterations_per_step));
+        let count = builder.add(state[4], iterations);
+
+        hash.into_iter().chain([count]).collect()
+    }
+}
+
+/// The `n`-th iterate of Poseidon from `seed`, computed natively
+pub fn iterate_hash<F: RichField>(seed: [F; 4], n: usize) -> [F; 4] {
+    (0..n).fold(seed, |hash, _| PoseidonHash::hash_no_pad(&hash).elements)
+}
+
+/// Proves `segments * iterations_per_segment` iterations from `seed`. The public inputs
+/// start with the seed and a zero count, followed by the last hash and the number of
+/// iterations `N`, see `IvcChain`
+pub fn prove_sequential_work<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    seed: [F; 4],
+    segments: usize,
+    iterations_per_segment: usize,
+    fri: &FriProfile,
+) -> Result<ProofWithPublicInputs<F, C, D>, ProofError>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let step_circuit = HashChain {
+        seed,
+        iterations_per_step: iterations_per_segment,
+    };
+    let mut chain = IvcChain::<F, C, _, D>::new_with_profile(step_circuit, fri)?;
+    for _ in 0..segments {
+        chain.push(&[])?;
+    }
+
+    chain.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn test_sequential_work() -> Result<(), ProofError> {
+        let seed = F::rand_array();
+        let proof = prove_sequential_work::<F, C, 2>(seed, 3, 4, &FriProfile::default())?;
+
+        let public_inputs = &proof.public_inputs;
+        assert_eq!(public_inputs[..4], seed);
+        assert_eq!(public_inputs[5..9], iterate_hash(seed, 12));
+        assert_eq!(public_inputs[9], F::from_canonical_u64(12));
+        // the number of segments
+        assert_eq!(public_inputs[10], F::from_canonical_u64(3));
+
+        Ok(())
+    }
+}
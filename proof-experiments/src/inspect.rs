@@ -0,0 +1,232 @@
+//! A breakdown of a serialized proof: the shape of its FRI proof and openings, its public
+//! inputs in the layout of the circuit that produced it, and where its bytes go
+
+use std::fmt;
+
+use clap::ValueEnum;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use serde::Serialize;
+
+use crate::halo2_example::PublicInputs;
+
+/// How the public inputs of a proof are laid out
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    /// The inputs followed by the output, as registered by `Circuit`
+    Circuit,
+    /// The root, nullifier and topic of each signal, for signals and their aggregates
+    Signals,
+    /// Undecoded field elements
+    Raw,
+}
+
+/// The public inputs of a signal, as laid out by `semaphore::verify::signal_public_inputs`
+/// for access sets with a single-hash cap
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignalPublicInputs<F> {
+    pub root: [F; 4],
+    pub nullifier: [F; 4],
+    pub topic: [F; 4],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodedPublicInputs<F> {
+    Circuit(PublicInputs<F>),
+    Signals(Vec<SignalPublicInputs<F>>),
+    Raw(Vec<F>),
+}
+
+impl<F: RichField> DecodedPublicInputs<F> {
+    pub fn decode(public_inputs: &[F], layout: Layout) -> Result<Self, ProofError> {
+        match layout {
+            Layout::Circuit => PublicInputs::from_slice(public_inputs).map(Self::Circuit),
+            Layout::Signals => {
+                if public_inputs.is_empty() || public_inputs.len() % 12 != 0 {
+                    return Err(ProofError::PublicInputCount {
+                        expected: 12 * (public_inputs.len() / 12).max(1),
+                        actual: public_inputs.len(),
+                    });
+                }
+                let signals = public_inputs
+                    .chunks_exact(12)
+                    .map(|chunk| SignalPublicInputs {
+                        root: chunk[..4].try_into().unwrap(),
+                        nullifier: chunk[4..8].try_into().unwrap(),
+                        topic: chunk[8..].try_into().unwrap(),
+                    })
+                    .collect();
+                Ok(Self::Signals(signals))
+            }
+            Layout::Raw => Ok(Self::Raw(public_inputs.to_vec())),
+        }
+    }
+}
+
+/// The internals of a proof. Openings are counted in extension field elements and sizes
+/// are those of the bincode encoding the artifacts are serialized with
+#[derive(Clone, Debug)]
+pub struct ProofSummary<F> {
+    pub num_query_rounds: usize,
+    pub cap_height: usize,
+    pub num_fri_layers: usize,
+    pub openings: Vec<(&'static str, usize)>,
+    pub public_inputs: DecodedPublicInputs<F>,
+    pub byte_sizes: Vec<(&'static str, usize)>,
+}
+
+impl<F> ProofSummary<F> {
+    pub fn total_bytes(&self) -> usize {
+        self.byte_sizes.iter().map(|(_, size)| size).sum()
+    }
+}
+
+fn encoded_len<T: Serialize + ?Sized>(value: &T) -> Result<usize, ProofError> {
+    bincode::serialized_size(value)
+        .map(|size| size as usize)
+        .map_err(|e| ProofError::Serialization(e.to_string()))
+}
+
+pub fn inspect<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    proof_with_pis: &ProofWithPublicInputs<F, C, D>,
+    layout: Layout,
+) -> Result<ProofSummary<F>, ProofError> {
+    let proof = &proof_with_pis.proof;
+    let openings = &proof.openings;
+    let fri_proof = &proof.opening_proof;
+
+    Ok(ProofSummary {
+        num_query_rounds: fri_proof.query_round_proofs.len(),
+        cap_height: proof.wires_cap.0.len().trailing_zeros() as usize,
+        num_fri_layers: fri_proof.commit_phase_merkle_caps.len(),
+        openings: vec![
+            ("constants", openings.constants.len()),
+            ("plonk sigmas", openings.plonk_sigmas.len()),
+            ("wires", openings.wires.len()),
+            ("plonk zs", openings.plonk_zs.len()),
+            ("plonk zs next", openings.plonk_zs_next.len()),
+            ("partial products", openings.partial_products.len()),
+            ("quotient polys", openings.quotient_polys.len()),
+        ],
+        public_inputs: DecodedPublicInputs::decode(&proof_with_pis.public_inputs, layout)?,
+        byte_sizes: vec![
+            ("public inputs", encoded_len(&proof_with_pis.public_inputs)?),
+            ("wires cap", encoded_len(&proof.wires_cap)?),
+            (
+                "zs and partial products cap",
+                encoded_len(&proof.plonk_zs_partial_products_cap)?,
+            ),
+            (
+                "quotient polys cap",
+                encoded_len(&proof.quotient_polys_cap)?,
+            ),
+            ("openings", encoded_len(openings)?),
+            (
+                "FRI commit phase caps",
+                encoded_len(&fri_proof.commit_phase_merkle_caps)?,
+            ),
+            (
+                "FRI query rounds",
+                encoded_len(&fri_proof.query_round_proofs)?,
+            ),
+            ("FRI final polynomial", encoded_len(&fri_proof.final_poly)?),
+            ("FRI proof of work", encoded_len(&fri_proof.pow_witness)?),
+        ],
+    })
+}
+
+impl<F: fmt::Display> fmt::Display for ProofSummary<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} FRI query rounds, {} FRI layers, cap height {}",
+            self.num_query_rounds, self.num_fri_layers, self.cap_height
+        )?;
+
+        writeln!(f, "openings:")?;
+        for (name, len) in &self.openings {
+            writeln!(f, "  {name}: {len}")?;
+        }
+
+        writeln!(f, "public inputs:")?;
+        match &self.public_inputs {
+            DecodedPublicInputs::Circuit(public_inputs) => {
+                for (i, input) in public_inputs.inputs.iter().enumerate() {
+                    writeln!(f, "  input {i}: {input}")?;
+                }
+                writeln!(f, "  output: {}", public_inputs.output)?;
+            }
+            DecodedPublicInputs::Signals(signals) => {
+                for (i, signal) in signals.iter().enumerate() {
+                    let [root, nullifier, topic] = [&signal.root, &signal.nullifier, &signal.topic]
+                        .map(|digest| {
+                            digest
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        });
+                    writeln!(f, "  signal {i}:")?;
+                    writeln!(f, "    root: [{root}]")?;
+                    writeln!(f, "    nullifier: [{nullifier}]")?;
+                    writeln!(f, "    topic: [{topic}]")?;
+                }
+            }
+            DecodedPublicInputs::Raw(public_inputs) => {
+                for (i, input) in public_inputs.iter().enumerate() {
+                    writeln!(f, "  {i}: {input}")?;
+                }
+            }
+        }
+
+        let total = self.total_bytes();
+        writeln!(f, "bytes:")?;
+        for (name, size) in &self.byte_sizes {
+            let share = 100.0 * *size as f64 / total as f64;
+            writeln!(f, "  {name}: {size} ({share:.1}%)")?;
+        }
+        write!(f, "  total: {total}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::halo2_example::Circuit;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn test_inspect() -> Result<(), ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+        let outputs = circuit.build_circuit::<C>(vec![F::TWO, F::TWO])?;
+        let (prover_artifacts, _) = outputs.into_artifacts();
+        let proof_with_pis = &prover_artifacts.proof_with_pis;
+
+        let summary = inspect(proof_with_pis, Layout::Circuit)?;
+        assert_eq!(
+            summary.public_inputs,
+            DecodedPublicInputs::Circuit(PublicInputs {
+                inputs: vec![F::TWO, F::TWO],
+                output: F::from_canonical_u64(16),
+            })
+        );
+        assert_eq!(
+            summary.num_query_rounds,
+            proof_with_pis.proof.opening_proof.query_round_proofs.len()
+        );
+        // the components make up the whole encoding
+        assert_eq!(summary.total_bytes(), prover_artifacts.to_bytes()?.len());
+
+        assert!(inspect(proof_with_pis, Layout::Signals).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use gadgets::witness_ext::WitnessExt;
+use plonky2::field::extension::Extendable;
+use plonky2::gates::noop::NoopGate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitTarget,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2::recursion::cyclic_recursion::{check_cyclic_proof_verifier_data, cyclic_base_proof};
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+
+/// One step of an incrementally verifiable computation, mapping a state and the inputs
+/// pushed for the step to the next state
+pub trait StepCircuit<F: RichField + Extendable<D>, const D: usize> {
+    fn initial_state(&self) -> Vec<F>;
+    fn num_inputs(&self) -> usize;
+    fn step(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        state: &[Target],
+        inputs: &[Target],
+    ) -> Vec<Target>;
+}
+
+/// Adds `increment` to a counter in each step
+pub struct Counter;
+
+impl<F: RichField + Extendable<D>, const D: usize> StepCircuit<F, D> for Counter {
+    fn initial_state(&self) -> Vec<F> {
+        vec![F::ZERO]
+    }
+
+    fn num_inputs(&self) -> usize {
+        1
+    }
+
+    fn step(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        state: &[Target],
+        inputs: &[Target],
+    ) -> Vec<Target> {
+        vec![builder.add(state[0], inputs[0])]
+    }
+}
+
+struct IvcTargets<const D: usize> {
+    condition: BoolTarget,
+    inner_proof: ProofWithPublicInputsTarget<D>,
+    verifier_data: VerifierCircuitTarget,
+    inputs: Vec<Target>,
+}
+
+/// Chains proofs of a step circuit with cyclic recursion: each proof verifies the previous
+/// one, or a dummy proof for the first step. The public inputs of a proof are the initial
+/// state, the current state, the number of steps and the verifier data of the circuit
+pub struct IvcChain<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: StepCircuit<F, D>,
+    const D: usize,
+> {
+    step_circuit: S,
+    data: CircuitData<F, C, D>,
+    targets: IvcTargets<D>,
+    proof: Option<ProofWithPublicInputs<F, C, D>>,
+}
+
+/// Common data of a circuit verifying `num_proofs` proofs of itself, i.e. of the fixed
+/// point of recursion with `config`. Each verification takes just under `2^12` gates
+pub(crate) fn common_data_for_recursion<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    config: &CircuitConfig,
+    num_proofs: usize,
+) -> CommonCircuitData<F, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let builder = CircuitBuilder::<F, D>::new(config.clone());
+    let mut data = builder.build::<C>();
+    for min_gates in [0, num_proofs << 12] {
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let verifier_data =
+            builder.add_verifier_data_target(data.common.config.fri_config.cap_height);
+        for _ in 0..num_proofs {
+            let proof = builder.add_virtual_proof_with_pis::<C>(&data.common);
+            builder.verify_proof::<C>(&proof, &verifier_data, &data.common);
+        }
+        while builder.num_gates() < min_gates {
+            builder.add_gate(NoopGate, vec![]);
+        }
+        data = builder.build::<C>();
+    }
+
+    data.common
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        S: StepCircuit<F, D>,
+        const D: usize,
+    > IvcChain<F, C, S, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn new(step_circuit: S) -> Result<Self, ProofError> {
+        Self::new_with_profile(step_circuit, &FriProfile::default())
+    }
+
+    /// Like `new`, proving each step with the FRI parameters of `fri`
+    pub fn new_with_profile(step_circuit: S, fri: &FriProfile) -> Result<Self, ProofError> {
+        let config = fri.circuit_config(false)?;
+        let n = step_circuit.initial_state().len();
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let one = builder.one();
+
+        let initial_state = builder.add_virtual_targets(n);
+        builder.register_public_inputs(&initial_state);
+        let state_out = builder.add_virtual_targets(n);
+        builder.register_public_inputs(&state_out);
+        let counter = builder.add_virtual_public_input();
+
+        let mut common_data = common_data_for_recursion::<F, C, D>(&config, 1);
+        let verifier_data = builder.add_verifier_data_public_inputs();
+        common_data.num_public_inputs = builder.num_public_inputs();
+
+        // the previous proof, or a dummy proof for the first step
+        let condition = builder.add_virtual_bool_target_safe();
+        let inner_proof = builder.add_virtual_proof_with_pis::<C>(&common_data);
+        let inner_public_inputs = inner_proof.public_inputs.clone();
+        let state_in: Vec<Target> = (0..n)
+            .map(|i| {
+                builder.connect(initial_state[i], inner_public_inputs[i]);
+                builder.select(condition, inner_public_inputs[n + i], initial_state[i])
+            })
+            .collect();
+
+        let inputs = builder.add_virtual_targets(step_circuit.num_inputs());
+        let next_state = step_circuit.step(&mut builder, &state_in, &inputs);
+        for (out, next) in state_out.iter().zip(next_state) {
+            builder.connect(*out, next);
+        }
+        let next_counter = builder.mul_add(condition.target, inner_public_inputs[2 * n], one);
+        builder.connect(counter, next_counter);
+
+        builder
+            .conditionally_verify_cyclic_proof_or_dummy::<C>(condition, &inner_proof, &common_data)
+            .map_err(ProofError::Proving)?;
+
+        Ok(Self {
+            step_circuit,
+            data: builder.build::<C>(),
+            targets: IvcTargets {
+                condition,
+                inner_proof,
+                verifier_data,
+                inputs,
+            },
+            proof: None,
+        })
+    }
+
+    /// Proves one more step with `inputs`, verifying the proof of the previous steps
+    pub fn push(&mut self, inputs: &[F]) -> Result<(), ProofError> {
+        if inputs.len() != self.targets.inputs.len() {
+            return Err(ProofError::WitnessCount {
+                expected: self.targets.inputs.len(),
+                actual: inputs.len(),
+            });
+        }
+
+        let mut pw = PartialWitness::new();
+        let base_proof;
+        let inner_proof = match &self.proof {
+            Some(proof) => {
+                pw.set_bool_target(self.targets.condition, true);
+                proof
+            }
+            None => {
+                pw.set_bool_target(self.targets.condition, false);
+                let initial_state: HashMap<usize, F> = self
+                    .step_circuit
+                    .initial_state()
+                    .into_iter()
+                    .enumerate()
+                    .collect();
+                base_proof =
+                    cyclic_base_proof(&self.data.common, &self.data.verifier_only, initial_state);
+                &base_proof
+            }
+        };
+        pw.set_proof_with_pis_target(&self.targets.inner_proof, inner_proof);
+        pw.set_verifier_data(&self.targets.verifier_data, &self.data.verifier_only);
+        for (target, value) in self.targets.inputs.iter().zip(inputs) {
+            pw.set_target(*target, *value);
+        }
+
+        self.proof = Some(self.data.prove(pw).map_err(ProofError::Proving)?);
+        Ok(())
+    }
+
+    /// The proof of all the steps pushed so far
+    pub fn finalize(self) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        let proof = self.proof.ok_or(ProofError::EmptyChain)?;
+        check_cyclic_proof_verifier_data(&proof, &self.data.verifier_only, &self.data.common)
+            .map_err(ProofError::Verification)?;
+        self.data
+            .verify(proof.clone())
+            .map_err(ProofError::Verification)?;
+
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn it_chains_counter_steps() -> Result<(), ProofError> {
+        let mut chain = IvcChain::<F, C, _, 2>::new(Counter)?;
+        for increment in [1, 2, 3] {
+            chain.push(&[F::from_canonical_u64(increment)])?;
+        }
+        assert!(matches!(
+            chain.push(&[]),
+            Err(ProofError::WitnessCount {
+                expected: 1,
+                actual: 0
+            })
+        ));
+
+        // the initial state, the current state and the number of steps
+        let proof = chain.finalize()?;
+        assert_eq!(
+            proof.public_inputs[..3],
+            [0, 6, 3].map(F::from_canonical_u64)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_empty_chains() -> Result<(), ProofError> {
+        let chain = IvcChain::<F, C, _, 2>::new(Counter)?;
+        assert!(matches!(chain.finalize(), Err(ProofError::EmptyChain)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+pub mod artifacts;
+pub mod bench;
+pub mod committed;
+pub mod deferred;
+pub mod description;
+pub mod diagnostics;
+pub mod expr;
+pub mod fibonacci;
+pub mod graph;
+pub mod halo2_example;
+pub mod hash_chain;
+pub mod inspect;
+pub mod ivc;
+pub mod pcd;
+#[cfg(test)]
+mod public_input_snapshots;
+pub mod snapshot;
+pub mod split;
@@ -1,21 +1,244 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
 use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
 use plonky2::field::types::Field;
-use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::circuit_data::CircuitConfig;
+#[cfg(feature = "keccak")]
+use plonky2::plonk::config::KeccakGoldilocksConfig;
 use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_experiments::bench::{self, Suite};
+use proof_experiments::description::CircuitDescription;
+use proof_experiments::fibonacci::SegmentedFibonacci;
+use proof_experiments::graph;
+use proof_experiments::halo2_example::Circuit;
+use proof_experiments::hash_chain;
+use proof_experiments::inspect::{self, Layout};
+use proof_experiments::snapshot::CircuitSnapshot;
+use prover_utils::codec;
+use prover_utils::estimate::MachineProfile;
+use prover_utils::fri::FriProfile;
+use prover_utils::sections::profile_sections;
+use prover_utils::transcript::Transcript;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+use semaphore::circuit::semaphore_circuit;
+use semaphore::domain::DomainTag;
 
 pub mod n_th_root;
 
-// replay fibonacci with Plonky2
+const D: usize = 2;
+type F = <PoseidonGoldilocksConfig as GenericConfig<D>>::F;
+
+#[derive(Parser)]
+#[command(about = "Plonky2 proof experiments")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    fri: FriArgs,
+}
+
+/// Overrides of the FRI parameters of the standard config, rejected if unsound
+#[derive(Args)]
+struct FriArgs {
+    #[arg(long, global = true)]
+    rate_bits: Option<usize>,
+    #[arg(long, global = true)]
+    cap_height: Option<usize>,
+    #[arg(long, global = true)]
+    proof_of_work_bits: Option<u32>,
+    #[arg(long, global = true)]
+    num_query_rounds: Option<usize>,
+}
+
+impl FriArgs {
+    fn profile(&self) -> Result<FriProfile> {
+        let mut profile = FriProfile::default();
+        if let Some(rate_bits) = self.rate_bits {
+            profile = profile.rate_bits(rate_bits);
+        }
+        if let Some(cap_height) = self.cap_height {
+            profile = profile.cap_height(cap_height);
+        }
+        if let Some(proof_of_work_bits) = self.proof_of_work_bits {
+            profile = profile.proof_of_work_bits(proof_of_work_bits);
+        }
+        if let Some(num_query_rounds) = self.num_query_rounds {
+            profile = profile.num_query_rounds(num_query_rounds);
+        }
+        profile.check()?;
+
+        Ok(profile)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay fibonacci with Plonky2 (the default command)
+    Fibonacci {
+        /// Prove with a keccak transcript, for EVM verifiers
+        #[cfg(feature = "keccak")]
+        #[arg(long)]
+        keccak: bool,
+    },
+    /// Replay fibonacci in segments folded with recursion, keeping the circuits small
+    FibonacciSegmented {
+        #[arg(long, default_value_t = 3125)]
+        steps_per_segment: usize,
+        /// Number of segments, a power of two
+        #[arg(long, default_value_t = 32)]
+        segments: usize,
+    },
+    /// Prove sequential Poseidon iterations from a seed, chaining segments with IVC
+    HashChain {
+        #[arg(long, default_value_t = 64)]
+        iterations_per_segment: usize,
+        #[arg(long, default_value_t = 16)]
+        segments: usize,
+    },
+    /// Prove a circuit defined outside of the code
+    Prove {
+        #[command(subcommand)]
+        source: ProveSource,
+    },
+    /// Run the standard scenarios of a suite, reporting their timings and proof sizes
+    Bench {
+        #[arg(long, value_enum)]
+        suite: Suite,
+        /// Comma-separated sizes, as numbers or powers of two such as 2^14
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = bench::parse_size,
+            default_value = "2^14"
+        )]
+        sizes: Vec<usize>,
+        /// Write the results as JSON to this file
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Print the internals of a serialized proof and where its bytes go
+    Inspect {
+        proof: PathBuf,
+        /// How the public inputs of the proof are laid out
+        #[arg(long, value_enum, default_value_t = Layout::Circuit)]
+        layout: Layout,
+    },
+    /// Estimate how long a circuit of `2^degree_bits` rows takes to prove on this machine
+    Estimate {
+        #[arg(long)]
+        degree_bits: usize,
+        /// Read the calibration of the machine from this JSON file, or calibrate and write
+        /// it there if it doesn't exist
+        #[arg(long)]
+        profile: Option<PathBuf>,
+    },
+    /// Compare the gates, dimensions and digests of two circuit snapshots
+    CircuitDiff { old: PathBuf, new: PathBuf },
+    /// Print the gates of each section of the signal circuit
+    ProfileSignal {
+        #[arg(long, default_value_t = 20)]
+        tree_height: usize,
+        #[arg(long, default_value_t = 0)]
+        cap_height: usize,
+        /// Bits of proof of work gating the signal
+        #[arg(long, default_value_t = 0)]
+        difficulty: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProveSource {
+    /// Prove a circuit described in a TOML or JSON file
+    FromFile {
+        circuit: PathBuf,
+        /// JSON object mapping every input of the circuit to its value
+        #[arg(long)]
+        witness: PathBuf,
+        /// Write the proof to this file, for `inspect`
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        debug: DebugArgs,
+    },
+}
+
+#[derive(Args)]
+struct DebugArgs {
+    /// Write the graph of the gates and copy constraints of the circuit to this file,
+    /// as JSON for a `.json` extension and in the DOT language otherwise
+    #[arg(long)]
+    graph: Option<PathBuf>,
+    /// Only graph these rows, e.g. `0..16`
+    #[arg(long, value_parser = parse_rows, requires = "graph")]
+    rows: Option<Range<usize>>,
+    /// Write the Fiat-Shamir transcript of the proof to this file
+    #[arg(long)]
+    record_transcript: Option<PathBuf>,
+    /// Check the proof against a transcript recorded by an earlier run, reporting the
+    /// first value on which they diverge
+    #[arg(long)]
+    replay_transcript: Option<PathBuf>,
+    /// Write a snapshot of the gates, dimensions and digests of the circuit to this file,
+    /// for `circuit-diff`
+    #[arg(long)]
+    snapshot: Option<PathBuf>,
+}
+
 fn main() -> Result<()> {
-    println!("Hello, world!");
+    let cli = Cli::parse();
+    let fri = cli.fri.profile()?;
+    let command = cli.command.unwrap_or(Command::Fibonacci {
+        #[cfg(feature = "keccak")]
+        keccak: false,
+    });
+    match command {
+        #[cfg(feature = "keccak")]
+        Command::Fibonacci { keccak: true } => fibonacci::<KeccakGoldilocksConfig>(&fri),
+        Command::Fibonacci { .. } => fibonacci::<PoseidonGoldilocksConfig>(&fri),
+        Command::FibonacciSegmented {
+            steps_per_segment,
+            segments,
+        } => fibonacci_segmented(steps_per_segment, segments, &fri),
+        Command::HashChain {
+            iterations_per_segment,
+            segments,
+        } => hash_chain(iterations_per_segment, segments, &fri),
+        Command::Prove {
+            source:
+                ProveSource::FromFile {
+                    circuit,
+                    witness,
+                    output,
+                    debug,
+                },
+        } => prove_from_file(&circuit, &witness, output.as_deref(), &debug, &fri),
+        Command::Bench { suite, sizes, json } => run_bench(suite, &sizes, json.as_deref(), &fri),
+        Command::Inspect { proof, layout } => inspect_proof(&proof, layout),
+        Command::Estimate {
+            degree_bits,
+            profile,
+        } => estimate(degree_bits, profile.as_deref(), &fri),
+        Command::CircuitDiff { old, new } => circuit_diff(&old, &new),
+        Command::ProfileSignal {
+            tree_height,
+            cap_height,
+            difficulty,
+        } => profile_signal(tree_height, cap_height, difficulty, &fri),
+    }
+}
 
-    const D: usize = 2;
-    type C = PoseidonGoldilocksConfig;
-    type F = <C as GenericConfig<D>>::F;
+// replay fibonacci with Plonky2
+fn fibonacci<C: GenericConfig<D, F = F>>(fri: &FriProfile) -> Result<()> {
+    println!("Hello, world!");
 
-    let config: CircuitConfig = CircuitConfig::standard_recursion_config();
+    let config: CircuitConfig = fri.circuit_config(false)?;
     let mut builder = CircuitBuilder::<F, D>::new(config);
 
     let initial_a = builder.add_virtual_target();
@@ -34,22 +257,222 @@ fn main() -> Result<()> {
     builder.register_public_input(cur_target);
 
     // provide the initial values
-    let mut partial_witness = PartialWitness::new();
-    partial_witness.set_target(initial_a, F::ZERO);
-    partial_witness.set_target(initial_b, F::ONE);
+    let mut witness = WitnessBuilder::new();
+    witness.set(&Named::new("initial_a", initial_a), F::ZERO)?;
+    witness.set(&Named::new("initial_b", initial_b), F::ONE)?;
 
-    use std::time::Instant;
-    let now = Instant::now();
-
-    // build circuit and prove
-    let data = builder.build::<C>();
-    let proof = data.prove(partial_witness)?;
-    println!("done proving, elapsed: {:.2?}", now.elapsed());
+    // build circuit, prove and verify
+    let TimedProof {
+        proof_with_pis: proof,
+        timings,
+        ..
+    } = prover_utils::prove_and_verify::<F, C, D>(builder, witness.build())?;
+    println!("{timings}");
 
     println!(
         "100th Fibonacci number mod |F| (starting with {}, {}) is: {}",
         proof.public_inputs[0], proof.public_inputs[1], proof.public_inputs[2]
     );
 
-    data.verify(proof)
+    Ok(())
+}
+
+fn fibonacci_segmented(steps_per_segment: usize, segments: usize, fri: &FriProfile) -> Result<()> {
+    type C = PoseidonGoldilocksConfig;
+
+    let config = fri.circuit_config(false)?;
+    let (mut fibonacci, elapsed) =
+        prover_utils::timed(|| SegmentedFibonacci::<F, C, D>::new(config, steps_per_segment));
+    println!("built the segment circuit, elapsed: {elapsed:.2?}");
+
+    let (proof, elapsed) = prover_utils::timed(|| fibonacci.prove(segments, F::ZERO, F::ONE));
+    let proof = proof?;
+    println!("done proving, elapsed: {elapsed:.2?}");
+
+    let public_inputs = &proof.proof_with_pis.public_inputs;
+    println!(
+        "{}th Fibonacci number mod |F| (starting with {}, {}) is: {}",
+        steps_per_segment * segments + 1,
+        public_inputs[0],
+        public_inputs[1],
+        public_inputs[3]
+    );
+
+    Ok(fibonacci.verify(&proof)?)
+}
+
+fn hash_chain(iterations_per_segment: usize, segments: usize, fri: &FriProfile) -> Result<()> {
+    let seed = [F::ZERO, F::ONE, F::TWO, F::from_canonical_u64(3)];
+    let (proof, elapsed) = prover_utils::timed(|| {
+        hash_chain::prove_sequential_work::<F, PoseidonGoldilocksConfig, D>(
+            seed,
+            segments,
+            iterations_per_segment,
+            fri,
+        )
+    });
+    let proof = proof?;
+    println!("done proving, elapsed: {elapsed:.2?}");
+    println!(
+        "{} Poseidon iterations from {:?} end at {:?}",
+        proof.public_inputs[9],
+        seed,
+        &proof.public_inputs[5..9]
+    );
+
+    Ok(())
+}
+
+fn parse_rows(s: &str) -> Result<Range<usize>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("{s}: expected a range of rows such as 0..16"))?;
+    let parse = |row: &str| row.trim().parse().map_err(|e| format!("{s}: {e}"));
+    Ok(parse(start)?..parse(end)?)
+}
+
+fn prove_from_file(
+    circuit: &Path,
+    witness: &Path,
+    output: Option<&Path>,
+    debug: &DebugArgs,
+    fri: &FriProfile,
+) -> Result<()> {
+    type C = PoseidonGoldilocksConfig;
+
+    let description = CircuitDescription::from_path(circuit)?;
+    let exprs = description.to_exprs::<F>()?;
+    let witnesses = description.witnesses_from_json::<F>(&fs::read_to_string(witness)?)?;
+
+    let config = fri.circuit_config(false)?;
+    let mut circuit = Circuit::<F, D>::new_with_config(config);
+    let (outputs, elapsed) =
+        prover_utils::timed(|| circuit.build_exprs_circuit::<C>(&exprs, witnesses));
+    let outputs = outputs?;
+    println!("done proving, elapsed: {elapsed:.2?}");
+    if let Some(path) = output {
+        let mut writer = BufWriter::new(File::create(path)?);
+        codec::write_proof(&mut writer, outputs.proof_with_pis())?;
+        writer.flush()?;
+    }
+    let circuit_data = outputs.circuit_data();
+    if let Some(path) = &debug.graph {
+        graph::export_dot(circuit_data, path, debug.rows.clone(), circuit.labels())?;
+    }
+    if let Some(path) = &debug.snapshot {
+        let snapshot = CircuitSnapshot::of(&circuit_data.verifier_only, &circuit_data.common);
+        fs::write(path, snapshot.to_bytes()?)?;
+    }
+    let transcript = Transcript::of(
+        outputs.proof_with_pis(),
+        &circuit_data.verifier_only.circuit_digest,
+        &circuit_data.common,
+    );
+    if let Some(path) = &debug.record_transcript {
+        fs::write(path, bincode::serialize(&transcript)?)?;
+    }
+    if let Some(path) = &debug.replay_transcript {
+        let recorded: Transcript = bincode::deserialize(&fs::read(path)?)?;
+        recorded.replay(
+            outputs.proof_with_pis(),
+            &circuit_data.verifier_only.circuit_digest,
+            &circuit_data.common,
+        )?;
+        println!("the proof replays the transcript of {}", path.display());
+    }
+
+    // the outputs are registered after the inputs
+    let values = &outputs.public_inputs()[description.inputs.len()..];
+    for (name, value) in description.outputs.iter().zip(values) {
+        println!("{name} = {value}");
+    }
+
+    Ok(outputs.verify()?)
+}
+
+fn run_bench(suite: Suite, sizes: &[usize], json: Option<&Path>, fri: &FriProfile) -> Result<()> {
+    let report = bench::run(suite, sizes, fri)?;
+    for result in &report.results {
+        print!(
+            "{} (size {}): {:.2} ms",
+            result.scenario, result.size, result.total_ms
+        );
+        if let Some(proof_bytes) = result.proof_bytes {
+            print!(", {proof_bytes} bytes");
+        }
+        println!();
+    }
+
+    if let Some(json) = json {
+        fs::write(json, serde_json::to_string_pretty(&report)?)?;
+    }
+
+    Ok(())
+}
+
+fn inspect_proof(proof: &Path, layout: Layout) -> Result<()> {
+    let proof_with_pis: ProofWithPublicInputs<F, PoseidonGoldilocksConfig, D> =
+        codec::read_proof(BufReader::new(File::open(proof)?))?;
+    println!("{}", inspect::inspect(&proof_with_pis, layout)?);
+
+    Ok(())
+}
+
+fn estimate(degree_bits: usize, profile: Option<&Path>, fri: &FriProfile) -> Result<()> {
+    let machine = match profile {
+        Some(path) if path.exists() => serde_json::from_slice(&fs::read(path)?)?,
+        _ => {
+            println!("Calibrating...");
+            let machine = MachineProfile::calibrate(&fri.circuit_config(false)?, &[10, 12])?;
+            if let Some(path) = profile {
+                fs::write(path, serde_json::to_string_pretty(&machine)?)?;
+            }
+            machine
+        }
+    };
+
+    let shape = machine.shape_of_degree(degree_bits);
+    println!(
+        "A circuit of 2^{degree_bits} rows and {} polynomials would prove in about {:.2?} on this machine",
+        shape.num_polys,
+        machine.estimate(&shape)
+    );
+
+    Ok(())
+}
+
+fn circuit_diff(old: &Path, new: &Path) -> Result<()> {
+    let old = CircuitSnapshot::from_bytes(&fs::read(old)?)?;
+    let new = CircuitSnapshot::from_bytes(&fs::read(new)?)?;
+    print!("{}", old.diff(&new));
+
+    Ok(())
+}
+
+fn profile_signal(
+    tree_height: usize,
+    cap_height: usize,
+    difficulty: usize,
+    fri: &FriProfile,
+) -> Result<()> {
+    anyhow::ensure!(cap_height <= tree_height, "the cap is higher than the tree");
+    let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+    let (targets, profile) = profile_sections(&mut builder, |builder| {
+        semaphore_circuit(
+            builder,
+            tree_height,
+            cap_height,
+            &DomainTag::default(),
+            difficulty,
+        )
+    });
+    targets?;
+    println!("{profile}");
+    let data = builder.build::<PoseidonGoldilocksConfig>();
+    println!(
+        "2^{} rows once built, with the gates of the constants and public inputs",
+        data.common.degree_bits()
+    );
+
+    Ok(())
 }
@@ -0,0 +1,268 @@
+//! Proof-carrying data: the generalization of `ivc` to proofs verifying two parent proofs
+//! of the same circuit, so that proofs form trees, or DAGs when a proof is the parent of
+//! several nodes, e.g. to map signals to leaves and reduce them pairwise
+
+use std::collections::HashMap;
+
+use gadgets::witness_ext::WitnessExt;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitData, VerifierCircuitTarget};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2::recursion::cyclic_recursion::{check_cyclic_proof_verifier_data, cyclic_base_proof};
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::options::ProverOptions;
+
+use crate::ivc::common_data_for_recursion;
+
+/// The number of parents a node verifies
+pub const NUM_PARENTS: usize = 2;
+
+/// The computation of a node, combining the states of its parents and the inputs of the
+/// node into its state
+pub trait PcdStep<F: RichField + Extendable<D>, const D: usize> {
+    /// The state of a missing parent, e.g. for the leaves of a tree
+    fn leaf_state(&self) -> Vec<F>;
+    fn num_inputs(&self) -> usize;
+    fn combine(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        left: &[Target],
+        right: &[Target],
+        inputs: &[Target],
+    ) -> Vec<Target>;
+}
+
+/// Sums the states of the parents and the input of the node
+pub struct Sum;
+
+impl<F: RichField + Extendable<D>, const D: usize> PcdStep<F, D> for Sum {
+    fn leaf_state(&self) -> Vec<F> {
+        vec![F::ZERO]
+    }
+
+    fn num_inputs(&self) -> usize {
+        1
+    }
+
+    fn combine(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        left: &[Target],
+        right: &[Target],
+        inputs: &[Target],
+    ) -> Vec<Target> {
+        vec![builder.add_many([left[0], right[0], inputs[0]])]
+    }
+}
+
+struct ParentTargets<const D: usize> {
+    condition: BoolTarget,
+    proof: ProofWithPublicInputsTarget<D>,
+}
+
+struct PcdTargets<const D: usize> {
+    parents: Vec<ParentTargets<D>>,
+    verifier_data: VerifierCircuitTarget,
+    inputs: Vec<Target>,
+}
+
+/// The circuit of the nodes of proof-carrying data of a step, with cyclic recursion: each
+/// proof verifies those of up to two parents, the missing ones being replaced by dummy
+/// proofs and their states by the leaf state. The public inputs of a proof are the state,
+/// the number of nodes it covers, counting a node once per path to it, and the verifier
+/// data of the circuit
+pub struct PcdNode<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: PcdStep<F, D>,
+    const D: usize,
+> {
+    step: S,
+    data: CircuitData<F, C, D>,
+    targets: PcdTargets<D>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        S: PcdStep<F, D>,
+        const D: usize,
+    > PcdNode<F, C, S, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn new(step: S) -> Result<Self, ProofError> {
+        Self::new_with_profile(step, &FriProfile::default())
+    }
+
+    /// Like `new`, proving each node with the FRI parameters of `fri`
+    pub fn new_with_profile(step: S, fri: &FriProfile) -> Result<Self, ProofError> {
+        let config = fri.circuit_config(false)?;
+        let leaf_state = step.leaf_state();
+        let n = leaf_state.len();
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let one = builder.one();
+
+        let state = builder.add_virtual_targets(n);
+        builder.register_public_inputs(&state);
+        let count = builder.add_virtual_public_input();
+
+        let mut common_data = common_data_for_recursion::<F, C, D>(&config, NUM_PARENTS);
+        let verifier_data = builder.add_verifier_data_public_inputs();
+        common_data.num_public_inputs = builder.num_public_inputs();
+
+        let leaf_state = builder.constants(&leaf_state);
+        let mut parents = Vec::with_capacity(NUM_PARENTS);
+        let mut parent_states = Vec::with_capacity(NUM_PARENTS);
+        let mut parent_count = builder.zero();
+        for _ in 0..NUM_PARENTS {
+            let condition = builder.add_virtual_bool_target_safe();
+            let proof = builder.add_virtual_proof_with_pis::<C>(&common_data);
+            let public_inputs = &proof.public_inputs;
+            let parent_state: Vec<Target> = (0..n)
+                .map(|i| builder.select(condition, public_inputs[i], leaf_state[i]))
+                .collect();
+            parent_count = builder.mul_add(condition.target, public_inputs[n], parent_count);
+            parent_states.push(parent_state);
+            parents.push(ParentTargets { condition, proof });
+        }
+
+        let inputs = builder.add_virtual_targets(step.num_inputs());
+        let next_state = step.combine(&mut builder, &parent_states[0], &parent_states[1], &inputs);
+        for (out, next) in state.iter().zip(next_state) {
+            builder.connect(*out, next);
+        }
+        let next_count = builder.add(parent_count, one);
+        builder.connect(count, next_count);
+
+        for parent in &parents {
+            builder
+                .conditionally_verify_cyclic_proof_or_dummy::<C>(
+                    parent.condition,
+                    &parent.proof,
+                    &common_data,
+                )
+                .map_err(ProofError::Proving)?;
+        }
+
+        Ok(Self {
+            step,
+            data: builder.build::<C>(),
+            targets: PcdTargets {
+                parents,
+                verifier_data,
+                inputs,
+            },
+        })
+    }
+
+    /// Proves a node of up to two `parents`, proofs of this circuit, with the inputs of
+    /// `local_witness`
+    pub fn prove(
+        &self,
+        parents: &[ProofWithPublicInputs<F, C, D>],
+        local_witness: &[F],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        if parents.len() > NUM_PARENTS {
+            return Err(ProofError::WitnessCount {
+                expected: NUM_PARENTS,
+                actual: parents.len(),
+            });
+        }
+        if local_witness.len() != self.targets.inputs.len() {
+            return Err(ProofError::WitnessCount {
+                expected: self.targets.inputs.len(),
+                actual: local_witness.len(),
+            });
+        }
+
+        let mut pw = PartialWitness::new();
+        let leaf_state: HashMap<usize, F> =
+            self.step.leaf_state().into_iter().enumerate().collect();
+        for (i, targets) in self.targets.parents.iter().enumerate() {
+            let base_proof;
+            let proof = match parents.get(i) {
+                Some(proof) => {
+                    pw.set_bool_target(targets.condition, true);
+                    proof
+                }
+                None => {
+                    pw.set_bool_target(targets.condition, false);
+                    base_proof = cyclic_base_proof(
+                        &self.data.common,
+                        &self.data.verifier_only,
+                        leaf_state.clone(),
+                    );
+                    &base_proof
+                }
+            };
+            pw.set_proof_with_pis_target(&targets.proof, proof);
+        }
+        pw.set_verifier_data(&self.targets.verifier_data, &self.data.verifier_only);
+        for (target, value) in self.targets.inputs.iter().zip(local_witness) {
+            pw.set_target(*target, *value);
+        }
+
+        prover_utils::prove_with_options(&self.data, pw, &ProverOptions::default())
+    }
+
+    /// Checks a proof of a node, including that it was proven with the verifier data of
+    /// this circuit
+    pub fn verify(&self, proof: &ProofWithPublicInputs<F, C, D>) -> Result<(), ProofError> {
+        check_cyclic_proof_verifier_data(proof, &self.data.verifier_only, &self.data.common)
+            .map_err(ProofError::Verification)?;
+        self.data
+            .verify(proof.clone())
+            .map_err(ProofError::Verification)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    #[test]
+    fn it_proves_trees_of_sums() -> Result<(), ProofError> {
+        let node = PcdNode::<F, C, _, 2>::new(Sum)?;
+        let leaf = |value: u64| node.prove(&[], &[F::from_canonical_u64(value)]);
+        let (left, right) = (leaf(3)?, leaf(4)?);
+        node.verify(&left)?;
+
+        // the state and the number of nodes
+        let root = node.prove(&[left.clone(), right], &[F::ONE])?;
+        node.verify(&root)?;
+        assert_eq!(root.public_inputs[..2], [8, 3].map(F::from_canonical_u64));
+
+        // a node with a single parent, as in IVC
+        let chained = node.prove(&[root.clone()], &[F::ZERO])?;
+        assert_eq!(
+            chained.public_inputs[..2],
+            [8, 4].map(F::from_canonical_u64)
+        );
+
+        assert!(matches!(
+            node.prove(&[left.clone(), left.clone(), left], &[F::ZERO]),
+            Err(ProofError::WitnessCount {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        let mut tampered = root;
+        tampered.public_inputs[0] += F::ONE;
+        assert!(node.verify(&tampered).is_err());
+
+        Ok(())
+    }
+}
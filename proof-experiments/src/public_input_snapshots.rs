@@ -0,0 +1,204 @@
+//! Snapshots of the decoded public inputs of the proof of each example, for fixed witnesses,
+//! so that a change of layout reaching downstream consumers, e.g. a public input added by a
+//! feature, fails these tests. The circuit shapes are snapshotted by `snapshot` instead.
+//!
+//! Each snapshot is a text file in `snapshots/`. A missing or changed snapshot fails the
+//! tests, a changed one being written next to it as `<name>.snap.new` for review. Rerun
+//! them with `UPDATE_SNAPSHOTS=1` to record the snapshots, and commit the updated files.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Field;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::WitnessBuilder;
+use semaphore::access_set::AccessSet;
+
+use crate::fibonacci::SegmentedFibonacci;
+use crate::halo2_example::Circuit;
+use crate::hash_chain::prove_sequential_work;
+use crate::inspect::{DecodedPublicInputs, Layout};
+use crate::pcd::{PcdNode, Sum};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+
+/// Public inputs as labelled fields, one per line, taken in turn from those of a proof.
+/// Hashes and the verifier data of recursive circuits are only counted, as their values
+/// can't be checked by hand and the verifier data changes with any change of the circuit
+struct Snapshot<'a> {
+    public_inputs: &'a [F],
+    text: String,
+}
+
+impl<'a> Snapshot<'a> {
+    fn new(public_inputs: &'a [F]) -> Self {
+        Self {
+            public_inputs,
+            text: String::new(),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> &'a [F] {
+        let (values, rest) = self
+            .public_inputs
+            .split_at(len.min(self.public_inputs.len()));
+        self.public_inputs = rest;
+        values
+    }
+
+    fn field(mut self, label: &str, len: usize) -> Self {
+        let values: Vec<String> = self
+            .take(len)
+            .iter()
+            .map(|value| value.to_string())
+            .collect();
+        writeln!(self.text, "{label}: [{}]", values.join(", ")).unwrap();
+        self
+    }
+
+    fn elided(mut self, label: &str, len: usize) -> Self {
+        let values = self.take(len);
+        writeln!(self.text, "{label}: {} elements", values.len()).unwrap();
+        self
+    }
+
+    /// The text of the snapshot, with the public inputs no field took, if any
+    fn finish(self) -> String {
+        let len = self.public_inputs.len();
+        match len {
+            0 => self.text,
+            _ => self.field("unlabelled", len).text,
+        }
+    }
+}
+
+/// Compares `snapshot` with the recorded one, recording it when updating
+fn assert_snapshot(name: &str, snapshot: Snapshot) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+    let path = dir.join(format!("{name}.snap"));
+    let actual = snapshot.finish();
+    let update = env::var("UPDATE_SNAPSHOTS").as_deref() == Ok("1");
+    match fs::read_to_string(&path) {
+        Ok(expected) if expected == actual => {}
+        _ if update => {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(path, actual).unwrap();
+        }
+        Ok(expected) => {
+            fs::write(path.with_extension("snap.new"), &actual).unwrap();
+            panic!(
+                "snapshot {name} changed from\n{expected}to\n{actual}written to {name}.snap.new, \
+                 rerun with UPDATE_SNAPSHOTS=1 if the change is intended"
+            );
+        }
+        Err(_) => panic!(
+            "snapshot {name} is not recorded in {}, rerun with UPDATE_SNAPSHOTS=1 to record it",
+            dir.display()
+        ),
+    }
+}
+
+#[test]
+fn snapshot_product_of_squares() -> Result<(), ProofError> {
+    let mut circuit = Circuit::<F, 2>::new();
+    circuit.deterministic();
+    let outputs =
+        circuit.build_circuit::<C>(vec![F::from_canonical_u64(2), F::from_canonical_u64(3)])?;
+    let DecodedPublicInputs::Circuit(decoded) =
+        DecodedPublicInputs::decode(outputs.public_inputs(), Layout::Circuit)? else {
+        unreachable!("the layout is decoded as asked");
+    };
+    assert_snapshot(
+        "product_of_squares",
+        Snapshot::new(outputs.public_inputs())
+            .field("inputs", decoded.inputs.len())
+            .field("output", 1),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_fibonacci_segmented() -> Result<(), ProofError> {
+    let mut fibonacci = SegmentedFibonacci::<F, C, 2>::new(prover_utils::standard_config(false), 4);
+    // small starting numbers, so that the snapshot can be checked by hand
+    let [a, b] = [5, 8].map(F::from_canonical_u64);
+    let public_inputs = fibonacci.prove(2, a, b)?.proof_with_pis.public_inputs;
+    assert_snapshot(
+        "fibonacci_segmented",
+        Snapshot::new(&public_inputs)
+            .field("first", 2)
+            .field("last", 2),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_hash_chain() -> Result<(), ProofError> {
+    let seed = [1, 2, 3, 4].map(F::from_canonical_u64);
+    let proof = prove_sequential_work::<F, C, 2>(seed, 2, 2, &FriProfile::default())?;
+    assert_snapshot(
+        "hash_chain",
+        Snapshot::new(&proof.public_inputs)
+            .field("initial state", 5)
+            .elided("hash", 4)
+            .field("iterations", 1)
+            .field("steps", 1)
+            .elided("verifier data", proof.public_inputs.len()),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_pcd_sum() -> Result<(), ProofError> {
+    let node = PcdNode::<F, C, _, 2>::new(Sum)?;
+    let [left, right, input] = [3, 4, 5].map(F::from_canonical_u64);
+    let leaves = [node.prove(&[], &[left])?, node.prove(&[], &[right])?];
+    let public_inputs = node.prove(&leaves, &[input])?.public_inputs;
+    assert_snapshot(
+        "pcd_sum",
+        Snapshot::new(&public_inputs)
+            .field("state", 1)
+            .field("nodes", 1)
+            .elided("verifier data", public_inputs.len()),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_signal() -> Result<(), ProofError> {
+    let private_keys: Vec<[F; 4]> = (0..4)
+        .map(|i| [1, 2, 3, 4].map(|j| F::from_canonical_u64(4 * i + j)))
+        .collect();
+    let access_set = AccessSet::from_private_keys(&private_keys);
+    let mut builder = CircuitBuilder::new(prover_utils::standard_config(false));
+    let mut witness = WitnessBuilder::new();
+    let targets = access_set.semaphore_circuit(&mut builder);
+    let topic = [5, 6, 7, 8].map(F::from_canonical_u64);
+    access_set.fill_semaphore_targets(&mut witness, private_keys[1], topic, 1, targets)?;
+    let proof = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?.proof_with_pis;
+    let DecodedPublicInputs::Signals(signals) =
+        DecodedPublicInputs::decode(&proof.public_inputs, Layout::Signals)? else {
+        unreachable!("the layout is decoded as asked");
+    };
+    let mut snapshot = Snapshot::new(&proof.public_inputs);
+    for _ in &signals {
+        snapshot = snapshot
+            .elided("root", 4)
+            .elided("nullifier", 4)
+            .field("topic", 4);
+    }
+    assert_snapshot("signal", snapshot);
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! Snapshots of the shape of a circuit: its gates, degree, public-input count and digests.
+//! Unlike the verifier-only data, a snapshot can be serialized with its gates, so that two
+//! versions of a circuit can be compared after a refactoring, e.g. of a gadget
+
+use std::fmt;
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::PrimeField64;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::{CommonCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::config::{GenericConfig, GenericHashOut};
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitSnapshot {
+    /// The ids of the gates, in the order of their selectors
+    pub gates: Vec<String>,
+    pub degree_bits: usize,
+    pub num_public_inputs: usize,
+    pub num_constants: usize,
+    pub quotient_degree_factor: usize,
+    pub num_partial_products: usize,
+    pub circuit_digest: Vec<u64>,
+    pub constants_sigmas_cap: Vec<Vec<u64>>,
+}
+
+impl CircuitSnapshot {
+    pub fn of<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+        common: &CommonCircuitData<F, D>,
+    ) -> Self {
+        let canonical = |elements: Vec<F>| elements.iter().map(|e| e.to_canonical_u64()).collect();
+
+        Self {
+            gates: common.gates.iter().map(|gate| gate.0.id()).collect(),
+            degree_bits: common.degree_bits(),
+            num_public_inputs: common.num_public_inputs,
+            num_constants: common.num_constants,
+            quotient_degree_factor: common.quotient_degree_factor,
+            num_partial_products: common.num_partial_products,
+            circuit_digest: canonical(verifier_only.circuit_digest.to_vec()),
+            constants_sigmas_cap: verifier_only
+                .constants_sigmas_cap
+                .0
+                .iter()
+                .map(|hash| canonical(hash.to_vec()))
+                .collect(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    /// What changed from this snapshot to `new`
+    pub fn diff(&self, new: &CircuitSnapshot) -> CircuitDiff {
+        let mut changes = Vec::new();
+        let mut compare = |field: &'static str, old: usize, new: usize| {
+            if old != new {
+                changes.push(Change { field, old, new });
+            }
+        };
+        compare("degree_bits", self.degree_bits, new.degree_bits);
+        compare(
+            "num_public_inputs",
+            self.num_public_inputs,
+            new.num_public_inputs,
+        );
+        compare("num_constants", self.num_constants, new.num_constants);
+        compare(
+            "quotient_degree_factor",
+            self.quotient_degree_factor,
+            new.quotient_degree_factor,
+        );
+        compare(
+            "num_partial_products",
+            self.num_partial_products,
+            new.num_partial_products,
+        );
+
+        let missing_from = |gates: &[String], other: &[String]| {
+            gates
+                .iter()
+                .filter(|gate| !other.contains(gate))
+                .cloned()
+                .collect()
+        };
+
+        CircuitDiff {
+            gates_added: missing_from(&new.gates, &self.gates),
+            gates_removed: missing_from(&self.gates, &new.gates),
+            changes,
+            digest_changed: self.circuit_digest != new.circuit_digest,
+            cap_changed: self.constants_sigmas_cap != new.constants_sigmas_cap,
+        }
+    }
+}
+
+/// A changed dimension of a circuit
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub field: &'static str,
+    pub old: usize,
+    pub new: usize,
+}
+
+/// The differences between two snapshots of a circuit. The digests change with any change
+/// to the constraints or the wiring, including those that keep the gates and dimensions
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CircuitDiff {
+    pub gates_added: Vec<String>,
+    pub gates_removed: Vec<String>,
+    pub changes: Vec<Change>,
+    pub digest_changed: bool,
+    pub cap_changed: bool,
+}
+
+impl CircuitDiff {
+    /// Whether the circuits are the same, i.e. prove and verify the same statements
+    pub fn is_empty(&self) -> bool {
+        self.gates_added.is_empty()
+            && self.gates_removed.is_empty()
+            && self.changes.is_empty()
+            && !self.digest_changed
+            && !self.cap_changed
+    }
+}
+
+impl fmt::Display for CircuitDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "the circuits are identical");
+        }
+
+        for gate in &self.gates_removed {
+            writeln!(f, "- gate {gate}")?;
+        }
+        for gate in &self.gates_added {
+            writeln!(f, "+ gate {gate}")?;
+        }
+        for Change { field, old, new } in &self.changes {
+            writeln!(f, "~ {field}: {old} -> {new}")?;
+        }
+        if self.digest_changed {
+            writeln!(f, "~ circuit_digest")?;
+        }
+        if self.cap_changed {
+            writeln!(f, "~ constants_sigmas_cap")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::halo2_example::Circuit;
+
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+
+    fn snapshot(square_mul: bool) -> Result<CircuitSnapshot, ProofError> {
+        let mut circuit = Circuit::<F, 2>::new();
+        let witnesses = vec![F::TWO, F::TWO];
+        let outputs = if square_mul {
+            circuit.build_square_mul_circuit::<C>(witnesses)?
+        } else {
+            circuit.build_circuit::<C>(witnesses)?
+        };
+        let circuit_data = outputs.circuit_data();
+
+        Ok(CircuitSnapshot::of(
+            &circuit_data.verifier_only,
+            &circuit_data.common,
+        ))
+    }
+
+    #[test]
+    fn test_circuit_diff() -> Result<(), ProofError> {
+        let old = snapshot(false)?;
+        let old = CircuitSnapshot::from_bytes(&old.to_bytes()?)?;
+        assert!(old.diff(&snapshot(false)?).is_empty());
+
+        // the square-mul circuit uses a custom gate instead of arithmetic gates
+        let diff = old.diff(&snapshot(true)?);
+        assert!(!diff.is_empty());
+        assert!(diff.digest_changed);
+        assert!(!diff.gates_added.is_empty());
+        assert!(diff.to_string().contains("+ gate"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! Splits constraints of a high degree into constraints of at most a maximum degree, by
+//! introducing auxiliary variables for subexpressions, e.g. `x^5 * y = 0` into `a = x^3`
+//! and `a * x^2 * y = 0` for a maximum degree of 3. Gates evaluate the split constraints
+//! over wires holding the variables, which `SplitGenerator` fills in
+
+use std::collections::HashMap;
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::{GeneratedValues, SimpleGenerator};
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartitionWitness, Witness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+
+use crate::expr::{Expr, ExprCompiler};
+
+impl<F: Field> Expr<F> {
+    /// The degree of the expression as a polynomial in its inputs
+    pub fn degree(&self) -> u64 {
+        match self {
+            Expr::Const(_) => 0,
+            Expr::Input(_) => 1,
+            Expr::Add(a, b) => a.degree().max(b.degree()),
+            Expr::Mul(a, b) => a.degree() + b.degree(),
+            Expr::Pow(a, exponent) => a.degree() * exponent,
+            Expr::Neg(a) => a.degree(),
+        }
+    }
+}
+
+/// A constraint split into constraints of at most a maximum degree over its inputs and
+/// auxiliary variables. `Input(num_inputs + j)` is the `j`-th auxiliary variable
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitConstraint<F> {
+    pub num_inputs: usize,
+    /// The subexpression each auxiliary variable stands for, over the inputs and the
+    /// variables before it
+    pub auxiliaries: Vec<Expr<F>>,
+    /// The split constraint, followed by `aux - definition` for each auxiliary variable
+    pub constraints: Vec<Expr<F>>,
+}
+
+struct Splitter<F> {
+    num_inputs: usize,
+    max_degree: u64,
+    auxiliaries: Vec<Expr<F>>,
+    indices: HashMap<Expr<F>, usize>,
+}
+
+impl<F: Field> Splitter<F> {
+    /// A variable standing for `expr`, which is of at most the maximum degree
+    fn auxiliary(&mut self, expr: Expr<F>) -> Expr<F> {
+        if expr.degree() <= 1 {
+            return expr;
+        }
+        let index = match self.indices.get(&expr) {
+            Some(&index) => index,
+            None => {
+                self.auxiliaries.push(expr.clone());
+                self.indices.insert(expr, self.auxiliaries.len() - 1);
+                self.auxiliaries.len() - 1
+            }
+        };
+
+        Expr::Input(self.num_inputs + index)
+    }
+
+    /// An expression equal to `expr` of at most the maximum degree
+    fn split(&mut self, expr: &Expr<F>) -> Expr<F> {
+        match expr {
+            Expr::Const(_) | Expr::Input(_) => expr.clone(),
+            Expr::Add(a, b) => self.split(a) + self.split(b),
+            Expr::Neg(a) => -self.split(a),
+            Expr::Mul(a, b) => {
+                let (mut a, mut b) = (self.split(a), self.split(b));
+                // replace the factor of the highest degree until the product fits
+                while a.degree() + b.degree() > self.max_degree {
+                    if a.degree() >= b.degree() {
+                        a = self.auxiliary(a);
+                    } else {
+                        b = self.auxiliary(b);
+                    }
+                }
+                a * b
+            }
+            Expr::Pow(_, 0) => Expr::Const(F::ONE),
+            Expr::Pow(a, exponent) => {
+                let mut base = self.split(a);
+                if base.degree() * exponent <= self.max_degree {
+                    return base.pow(*exponent);
+                }
+                base = self.auxiliary(base);
+                if *exponent <= self.max_degree {
+                    return base.pow(*exponent);
+                }
+                // x^e = (x^d)^(e / d) * x^(e % d)
+                let power = self.auxiliary(base.clone().pow(self.max_degree));
+                let quotient = power.pow(exponent / self.max_degree);
+                let split = match exponent % self.max_degree {
+                    0 => quotient,
+                    remainder => quotient * base.pow(remainder),
+                };
+                self.split(&split)
+            }
+        }
+    }
+}
+
+/// Splits the constraint `expr = 0` over `num_inputs` inputs into constraints of at most
+/// `max_degree`, which is at least 2
+pub fn split_constraint<F: Field>(
+    expr: &Expr<F>,
+    num_inputs: usize,
+    max_degree: usize,
+) -> Result<SplitConstraint<F>, ProofError> {
+    if max_degree < 2 {
+        return Err(ProofError::MaxDegree(max_degree));
+    }
+    if expr.num_inputs() > num_inputs {
+        return Err(ProofError::InputOutOfRange {
+            index: expr.num_inputs() - 1,
+            num_inputs,
+        });
+    }
+
+    let mut splitter = Splitter {
+        num_inputs,
+        max_degree: max_degree as u64,
+        auxiliaries: Vec::new(),
+        indices: HashMap::new(),
+    };
+    let split = splitter.split(expr);
+    let constraints = [split]
+        .into_iter()
+        .chain(
+            splitter
+                .auxiliaries
+                .iter()
+                .enumerate()
+                .map(|(j, definition)| Expr::Input(num_inputs + j) + -definition.clone()),
+        )
+        .collect();
+
+    Ok(SplitConstraint {
+        num_inputs,
+        auxiliaries: splitter.auxiliaries,
+        constraints,
+    })
+}
+
+impl<F: Field> SplitConstraint<F> {
+    /// The values of the inputs followed by those of the auxiliary variables
+    pub fn witness(&self, inputs: &[F]) -> Result<Vec<F>, ProofError> {
+        if inputs.len() != self.num_inputs {
+            return Err(ProofError::WitnessCount {
+                expected: self.num_inputs,
+                actual: inputs.len(),
+            });
+        }
+
+        let mut values = inputs.to_vec();
+        for definition in &self.auxiliaries {
+            let value = definition.evaluate(&values)?;
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    /// Adds the split constraints over `inputs` to the circuit, with a generator filling
+    /// the auxiliary variables, which are returned
+    pub fn enforce<const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        inputs: &[Target],
+    ) -> Result<Vec<Target>, ProofError>
+    where
+        F: RichField + Extendable<D>,
+    {
+        if inputs.len() != self.num_inputs {
+            return Err(ProofError::LengthMismatch {
+                lhs: inputs.len(),
+                rhs: self.num_inputs,
+            });
+        }
+
+        let auxiliaries = builder.add_virtual_targets(self.auxiliaries.len());
+        builder.add_simple_generator(SplitGenerator {
+            inputs: inputs.to_vec(),
+            auxiliaries: auxiliaries.clone(),
+            split: self.clone(),
+        });
+        let variables: Vec<Target> = inputs.iter().chain(&auxiliaries).copied().collect();
+        let mut compiler = ExprCompiler::new(builder, &variables);
+        let constraints = self
+            .constraints
+            .iter()
+            .map(|constraint| compiler.compile(constraint))
+            .collect::<Result<Vec<_>, _>>()?;
+        for constraint in constraints {
+            builder.assert_zero(constraint);
+        }
+
+        Ok(auxiliaries)
+    }
+}
+
+/// Sets the auxiliary variables of a split constraint from its inputs, whether they are
+/// virtual targets or wires of a gate
+#[derive(Debug)]
+pub struct SplitGenerator<F> {
+    pub inputs: Vec<Target>,
+    pub auxiliaries: Vec<Target>,
+    pub split: SplitConstraint<F>,
+}
+
+impl<F: RichField> SimpleGenerator<F> for SplitGenerator<F> {
+    fn dependencies(&self) -> Vec<Target> {
+        self.inputs.clone()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let inputs = witness.get_targets(&self.inputs);
+        let values = self
+            .split
+            .witness(&inputs)
+            .expect("the definitions only refer to the inputs and earlier variables");
+        for (&target, &value) in self.auxiliaries.iter().zip(&values[self.inputs.len()..]) {
+            out_buffer.set_target(target, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_split_constraint() -> Result<(), ProofError> {
+        // x^7 * y + x * y - z = 0
+        let (x, y, z) = (Expr::Input(0), Expr::Input(1), Expr::Input(2));
+        let expr = x.clone().pow(7) * y.clone() + x * y + -z;
+        assert_eq!(expr.degree(), 8);
+
+        let split = split_constraint(&expr, 3, 3)?;
+        assert!(!split.auxiliaries.is_empty());
+        assert!(split
+            .constraints
+            .iter()
+            .all(|constraint| constraint.degree() <= 3));
+
+        // the split constraints hold exactly when the constraint does
+        let (x, y) = (F::from_canonical_u64(3), F::from_canonical_u64(5));
+        let z = x.exp_u64(7) * y + x * y;
+        let values = split.witness(&[x, y, z])?;
+        for constraint in &split.constraints {
+            assert_eq!(constraint.evaluate(&values)?, F::ZERO);
+        }
+        let values = split.witness(&[x, y, z + F::ONE])?;
+        assert_ne!(split.constraints[0].evaluate(&values)?, F::ZERO);
+
+        // in a circuit, with the auxiliary variables generated
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let inputs = builder.add_virtual_targets(3);
+        split.enforce(&mut builder, &inputs)?;
+        let mut pw = PartialWitness::new();
+        for (&target, value) in inputs.iter().zip([x, y, z]) {
+            pw.set_target(target, value);
+        }
+        let data = builder.build::<PoseidonGoldilocksConfig>();
+        data.verify(data.prove(pw).map_err(ProofError::Proving)?)
+            .map_err(ProofError::Verification)?;
+
+        assert!(matches!(
+            split_constraint(&expr, 3, 1),
+            Err(ProofError::MaxDegree(1))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use plonky2::field::types::Sample;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use prover_utils::options::ProverOptions;
+use prover_utils::pipeline;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// A chain of Poseidon hashes from 4 inputs, whose witness generation is a sizable part of
+/// proving
+fn hash_chain(length: usize) -> (CircuitData<F, C, D>, Vec<Target>) {
+    let mut builder = CircuitBuilder::<F, D>::new(prover_utils::standard_config(false));
+    let inputs = builder.add_virtual_targets(4);
+    let mut hash = inputs.clone();
+    for _ in 0..length {
+        hash = builder
+            .hash_n_to_hash_no_pad::<PoseidonHash>(hash)
+            .elements
+            .to_vec();
+    }
+    builder.register_public_inputs(&hash);
+    (builder.build::<C>(), inputs)
+}
+
+// proofs per second when each proof generates its witness, and when the witness of a proof
+// is generated while the previous one is proven
+fn main() {
+    const NUM_PROOFS: usize = 8;
+
+    for length in [1 << 10, 1 << 13] {
+        let (data, inputs) = hash_chain(length);
+        let proving_data = pipeline::without_generators(hash_chain(length).0);
+        let witnesses: Vec<PartialWitness<F>> = (0..NUM_PROOFS)
+            .map(|_| {
+                let mut pw = PartialWitness::new();
+                for (target, value) in inputs.iter().zip(F::rand_vec(4)) {
+                    pw.set_target(*target, value);
+                }
+                pw
+            })
+            .collect();
+
+        let start = Instant::now();
+        for pw in witnesses.clone() {
+            data.prove(pw).unwrap();
+        }
+        let sequential = start.elapsed();
+
+        let start = Instant::now();
+        let results = pipeline::pipelined(
+            witnesses,
+            |pw| pipeline::generate_witness(&data, pw),
+            |witness| {
+                pipeline::prove_with_witness(&proving_data, witness, &ProverOptions::default())
+            },
+        );
+        assert!(results.iter().all(|(proof, _)| proof.is_ok()));
+        let pipelined = start.elapsed();
+
+        println!(
+            "{length} hashes: {:.2} proofs/s sequentially, {:.2} proofs/s pipelined",
+            NUM_PROOFS as f64 / sequential.as_secs_f64(),
+            NUM_PROOFS as f64 / pipelined.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Assertions labelled with a message: each adds the constraint of the assertion and a
+//! generator checking it on the witness, which makes the prover fail with
+//! `ProverDiagnostic::Assertion` naming the label instead of an unsatisfied witness.
+//! The generators are added before those of the gates, so they run before plonky2 panics
+//! on the conflicting values of the constraint, which is why the constraint is applied to
+//! a fresh product rather than by connecting the targets themselves
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::panic;
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::{GeneratedValues, SimpleGenerator};
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartitionWitness, Witness};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProverDiagnostic;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Assertion {
+    Eq,
+    Zero,
+    Bool,
+}
+
+/// Panics with `ProverDiagnostic::Assertion` when the values of `targets` fail `assertion`
+#[derive(Debug)]
+struct AssertionGenerator<F> {
+    assertion: Assertion,
+    targets: Vec<Target>,
+    label: String,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField> AssertionGenerator<F> {
+    /// Why the values fail the assertion, if they do
+    fn failure(&self, values: &[F]) -> Option<String> {
+        match self.assertion {
+            Assertion::Eq if values[0] != values[1] => {
+                Some(format!("{} != {}", values[0], values[1]))
+            }
+            Assertion::Zero if values[0] != F::ZERO => Some(format!("{} isn't zero", values[0])),
+            Assertion::Bool if values[0] != F::ZERO && values[0] != F::ONE => {
+                Some(format!("{} isn't a bit", values[0]))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<F: RichField> SimpleGenerator<F> for AssertionGenerator<F> {
+    fn dependencies(&self) -> Vec<Target> {
+        self.targets.clone()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, _out_buffer: &mut GeneratedValues<F>) {
+        if let Some(message) = self.failure(&witness.get_targets(&self.targets)) {
+            panic::panic_any(ProverDiagnostic::Assertion {
+                label: self.label.clone(),
+                message,
+            });
+        }
+    }
+}
+
+fn add_assertion<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    assertion: Assertion,
+    targets: Vec<Target>,
+    label: impl Into<String>,
+) {
+    builder.add_simple_generator(AssertionGenerator {
+        assertion,
+        targets,
+        label: label.into(),
+        _phantom: PhantomData,
+    });
+}
+
+/// Asserts `a == b`, reporting `label` if the witness doesn't satisfy it
+pub fn assert_eq_msg<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: Target,
+    b: Target,
+    label: impl Into<String>,
+) {
+    add_assertion(builder, Assertion::Eq, vec![a, b], label);
+    let difference = builder.sub(a, b);
+    let square = builder.square(difference);
+    builder.assert_zero(square);
+}
+
+/// Asserts `x == 0`, reporting `label` if the witness doesn't satisfy it
+pub fn assert_zero_msg<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: Target,
+    label: impl Into<String>,
+) {
+    add_assertion(builder, Assertion::Zero, vec![x], label);
+    let square = builder.square(x);
+    builder.assert_zero(square);
+}
+
+/// Asserts that `x` is 0 or 1, reporting `label` if the witness doesn't satisfy it
+pub fn assert_bool_msg<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: Target,
+    label: impl Into<String>,
+) -> BoolTarget {
+    add_assertion(builder, Assertion::Bool, vec![x], label);
+    // x^2 - x
+    let product = builder.mul_sub(x, x, x);
+    builder.assert_zero(product);
+    BoolTarget::new_unsafe(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use proof_error::ProofError;
+
+    use super::*;
+
+    #[test]
+    fn test_labelled_assertions() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let prove = |x: u64, cube: u64, bit: u64| {
+            let mut builder = CircuitBuilder::<F, D>::new(crate::standard_config(false));
+            let [x_target, cube_target, bit_target] = [(); 3].map(|_| builder.add_virtual_target());
+            let x_cubed = builder.exp_u64(x_target, 3);
+            assert_eq_msg(&mut builder, x_cubed, cube_target, "x^3 == cube");
+            assert_bool_msg(&mut builder, bit_target, "bit");
+            let mut pw = PartialWitness::new();
+            pw.set_target(x_target, F::from_canonical_u64(x));
+            pw.set_target(cube_target, F::from_canonical_u64(cube));
+            pw.set_target(bit_target, F::from_canonical_u64(bit));
+            crate::prove_and_verify::<F, C, D>(builder, pw)
+        };
+        prove(2, 8, 1)?;
+
+        assert!(matches!(
+            prove(2, 9, 1),
+            Err(ProofError::Diagnosed(ProverDiagnostic::Assertion { label, message }))
+                if label == "x^3 == cube" && message == "8 != 9"
+        ));
+        assert!(matches!(
+            prove(2, 8, 2),
+            Err(ProofError::Diagnosed(ProverDiagnostic::Assertion { label, .. })) if label == "bit"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use rayon::prelude::*;
+
+use crate::compat::check_compatibility;
+
+/// Verifies proofs of the circuit of `data` in parallel, failing with the first invalid
+/// proof. The shapes of all proofs are checked against the common data before anything is
+/// hashed, so that a malformed proof fails the batch early, and the verifier data is shared
+/// by every thread. The challenges of each proof are drawn from a transcript of that proof,
+/// so the transcripts themselves can't be shared
+pub fn verify_batch<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    data: &VerifierCircuitData<F, C, D>,
+) -> Result<(), ProofError> {
+    let invalid = |index: usize, source: ProofError| ProofError::InvalidBatchProof {
+        index,
+        source: Box::new(source),
+    };
+
+    for (index, proof) in proofs.iter().enumerate() {
+        check_compatibility(proof, &data.common).map_err(|e| invalid(index, e.into()))?;
+    }
+
+    proofs
+        .par_iter()
+        .enumerate()
+        .map(|(index, proof)| {
+            data.verify(proof.clone())
+                .map_err(|e| invalid(index, ProofError::Verification(e)))
+        })
+        .find_first(Result::is_err)
+        .unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use proof_error::CompatError;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_verify_batch() -> Result<(), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let data = builder.build::<C>();
+
+        let mut proofs = (1..=4)
+            .map(|value| {
+                let mut pw = PartialWitness::new();
+                pw.set_target(x, F::from_canonical_u64(value));
+                data.prove(pw).map_err(ProofError::Proving)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let verifier_data = data.verifier_data();
+        verify_batch(&proofs, &verifier_data)?;
+        verify_batch(&[], &verifier_data)?;
+
+        proofs[2].public_inputs[0] += F::ONE;
+        assert!(matches!(
+            verify_batch(&proofs, &verifier_data),
+            Err(ProofError::InvalidBatchProof { index: 2, .. })
+        ));
+
+        // shapes are checked before any proof is verified
+        proofs[3].public_inputs.push(F::ONE);
+        match verify_batch(&proofs, &verifier_data) {
+            Err(ProofError::InvalidBatchProof { index: 3, source }) => assert!(matches!(
+                *source,
+                ProofError::Incompatible(CompatError::PublicInputCount { .. })
+            )),
+            other => panic!("unexpected result {other:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Budgets on the size of circuits, checked after each gadget added to a builder, so that
+//! scaling a circuit past what proves in reasonable time, e.g. to 2^24 rows, fails when
+//! the offending gadget is added rather than hours into proving
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::util::log2_ceil;
+use proof_error::ProofError;
+
+/// The largest circuit a `BudgetedBuilder` builds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitBudget {
+    pub max_gates: usize,
+    /// The circuit is padded to a power of two rows, so this bounds the proving time
+    pub max_degree_bits: usize,
+}
+
+impl CircuitBudget {
+    /// Fails with `ProofError::OverBudget`, blaming `gadget`, if a circuit of `num_gates`
+    /// is over the budget
+    pub fn check(&self, num_gates: usize, gadget: &str) -> Result<(), ProofError> {
+        let over_budget = |resource, max, actual| ProofError::OverBudget {
+            gadget: gadget.to_string(),
+            resource,
+            max,
+            actual,
+        };
+        if num_gates > self.max_gates {
+            return Err(over_budget("gates", self.max_gates, num_gates));
+        }
+        let degree_bits = log2_ceil(num_gates);
+        if degree_bits > self.max_degree_bits {
+            return Err(over_budget(
+                "degree bits",
+                self.max_degree_bits,
+                degree_bits,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A builder whose gadgets are added with `gadget`, failing as soon as one takes the
+/// circuit over its budget
+pub struct BudgetedBuilder<F: RichField + Extendable<D>, const D: usize> {
+    builder: CircuitBuilder<F, D>,
+    budget: CircuitBudget,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> BudgetedBuilder<F, D> {
+    pub fn new(builder: CircuitBuilder<F, D>, budget: CircuitBudget) -> Self {
+        Self { builder, budget }
+    }
+
+    pub fn budget(&self) -> CircuitBudget {
+        self.budget
+    }
+
+    pub fn builder(&self) -> &CircuitBuilder<F, D> {
+        &self.builder
+    }
+
+    /// Adds a gadget with `add`, failing with `ProofError::OverBudget` naming it if the
+    /// circuit is then over the budget. The gates of the gadget stay in the builder, which
+    /// should be dropped on failure
+    pub fn gadget<T>(
+        &mut self,
+        name: &str,
+        add: impl FnOnce(&mut CircuitBuilder<F, D>) -> T,
+    ) -> Result<T, ProofError> {
+        let output = add(&mut self.builder);
+        self.budget.check(self.builder.num_gates(), name)?;
+        Ok(output)
+    }
+
+    /// Builds the circuit, checking its degree against the budget once built, with the
+    /// gates of its constants and public inputs on top of those of the gadgets
+    pub fn build<C: GenericConfig<D, F = F>>(self) -> Result<CircuitData<F, C, D>, ProofError> {
+        let max_degree_bits = self.budget.max_degree_bits;
+        let data = self.builder.build::<C>();
+        let degree_bits = data.common.degree_bits();
+        if degree_bits > max_degree_bits {
+            return Err(ProofError::OverBudget {
+                gadget: "build".to_string(),
+                resource: "degree bits",
+                max: max_degree_bits,
+                actual: degree_bits,
+            });
+        }
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> CircuitBuilder<F, D> {
+        self.builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_budget() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let budget = CircuitBudget {
+            max_gates: 1 << 12,
+            max_degree_bits: 10,
+        };
+        let mut builder = BudgetedBuilder::<F, D>::new(
+            CircuitBuilder::new(crate::standard_config(false)),
+            budget,
+        );
+        let x = builder.gadget("input", |builder| builder.add_virtual_target())?;
+        let x_cubed = builder.gadget("cube", |builder| builder.exp_u64(x, 3))?;
+        builder.gadget("output", |builder| builder.register_public_input(x_cubed))?;
+        assert!(builder.build::<C>()?.common.degree_bits() <= 10);
+
+        let mut builder = BudgetedBuilder::<F, D>::new(
+            CircuitBuilder::new(crate::standard_config(false)),
+            budget,
+        );
+        let mut x = builder.gadget("input", |builder| builder.add_virtual_target())?;
+        // a chain of multiplications, one gate per 20 of them
+        let result = builder.gadget("chain", |builder| {
+            for _ in 0..20 << 11 {
+                x = builder.mul(x, x);
+            }
+        });
+        assert!(matches!(
+            result,
+            Err(ProofError::OverBudget { gadget, resource: "degree bits", max: 10, actual })
+                if gadget == "chain" && actual >= 11
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! Encodings of proofs with their public inputs, behind a common `ProofCodec` trait so that
+//! services and clients pick theirs without bespoke glue: bincode, the default of this
+//! workspace, JSON, and with the `cbor` and `protobuf` features CBOR and protobuf. Plonky2
+//! proofs have no protobuf schema, so the protobuf message, `proto/proof.proto`, carries the
+//! public inputs as canonical integers and the proof itself in the bincode encoding
+
+use std::io::{Read, Write};
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+
+pub trait ProofCodec {
+    /// The name of the encoding, e.g. for content types
+    fn name(&self) -> &'static str;
+
+    fn encode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Vec<u8>, ProofError>;
+
+    fn decode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError>;
+}
+
+fn serialization_error(e: impl ToString) -> ProofError {
+    ProofError::Serialization(e.to_string())
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl ProofCodec for Bincode {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn encode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(proof).map_err(serialization_error)
+    }
+
+    fn decode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        bincode::deserialize(bytes).map_err(serialization_error)
+    }
+}
+
+/// Writes `proof` in the encoding of `Bincode` as it is serialized, without buffering the
+/// whole encoding, e.g. to a file or a socket wrapped in a `BufWriter`
+pub fn write_proof<
+    W: Write,
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    writer: W,
+    proof: &ProofWithPublicInputs<F, C, D>,
+) -> Result<(), ProofError> {
+    bincode::serialize_into(writer, proof).map_err(serialization_error)
+}
+
+/// Reads a proof written by `write_proof`, or encoded by `Bincode`, as it is deserialized
+pub fn read_proof<
+    R: Read,
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    reader: R,
+) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+    bincode::deserialize_from(reader).map_err(serialization_error)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl ProofCodec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Vec<u8>, ProofError> {
+        serde_json::to_vec(proof).map_err(serialization_error)
+    }
+
+    fn decode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        serde_json::from_slice(bytes).map_err(serialization_error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl ProofCodec for Cbor {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Vec<u8>, ProofError> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(proof, &mut bytes).map_err(serialization_error)?;
+        Ok(bytes)
+    }
+
+    fn decode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        ciborium::de::from_reader(bytes).map_err(serialization_error)
+    }
+}
+
+/// The message of `proto/proof.proto`
+#[cfg(feature = "protobuf")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProofMessage {
+    #[prost(fixed64, repeated, tag = "1")]
+    public_inputs: Vec<u64>,
+    #[prost(bytes = "vec", tag = "2")]
+    proof: Vec<u8>,
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl ProofCodec for Protobuf {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn encode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Vec<u8>, ProofError> {
+        use plonky2::field::types::PrimeField64;
+        use prost::Message;
+
+        let message = ProofMessage {
+            public_inputs: proof
+                .public_inputs
+                .iter()
+                .map(|input| input.to_canonical_u64())
+                .collect(),
+            proof: bincode::serialize(&proof.proof).map_err(serialization_error)?,
+        };
+        Ok(message.encode_to_vec())
+    }
+
+    fn decode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+        use plonky2::field::types::{Field, Field64};
+        use prost::Message;
+
+        let message = ProofMessage::decode(bytes).map_err(serialization_error)?;
+        let public_inputs = message
+            .public_inputs
+            .into_iter()
+            .map(|input| {
+                if input < F::ORDER {
+                    Ok(F::from_canonical_u64(input))
+                } else {
+                    Err(serialization_error(format!(
+                        "public input {input} isn't below the field order"
+                    )))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ProofWithPublicInputs {
+            proof: bincode::deserialize(&message.proof).map_err(serialization_error)?,
+            public_inputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    fn check_round_trip(
+        codec: &impl ProofCodec,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<(), ProofError> {
+        let decoded: ProofWithPublicInputs<F, C, D> = codec.decode(&codec.encode(proof)?)?;
+        assert_eq!(
+            Bincode.encode(&decoded)?,
+            Bincode.encode(proof)?,
+            "{} changes the proof",
+            codec.name()
+        );
+        assert!(codec.decode::<F, C, D>(&[0xff; 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_codecs() -> Result<(), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_squared = builder.square(x);
+        builder.register_public_inputs(&[x, x_squared]);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::from_canonical_u64(3));
+        let proof = data.prove(pw).map_err(ProofError::Proving)?;
+
+        // streaming matches the bincode codec
+        let mut streamed = Vec::new();
+        write_proof(&mut streamed, &proof)?;
+        assert_eq!(streamed, Bincode.encode(&proof)?);
+        let read: ProofWithPublicInputs<F, C, D> = read_proof(streamed.as_slice())?;
+        assert_eq!(Bincode.encode(&read)?, streamed);
+        assert!(read_proof::<_, F, C, D>(&streamed[..streamed.len() / 2]).is_err());
+
+        check_round_trip(&Bincode, &proof)?;
+        check_round_trip(&Json, &proof)?;
+        #[cfg(feature = "cbor")]
+        check_round_trip(&Cbor, &proof)?;
+        #[cfg(feature = "protobuf")]
+        check_round_trip(&Protobuf, &proof)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::CommonCircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::CompatError;
+
+fn check_len(
+    field: &'static str,
+    expected: usize,
+    actual: usize,
+    error: fn(&'static str, usize, usize) -> CompatError,
+) -> Result<(), CompatError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(error(field, expected, actual))
+    }
+}
+
+fn config_error(field: &'static str, expected: usize, actual: usize) -> CompatError {
+    CompatError::Config {
+        field,
+        expected,
+        actual,
+    }
+}
+
+fn gate_set_error(field: &'static str, expected: usize, actual: usize) -> CompatError {
+    CompatError::GateSet {
+        field,
+        expected,
+        actual,
+    }
+}
+
+/// Checks that `proof` has the shape of a proof of a circuit with `common_data`, so that a
+/// recursive verifier built from `common_data` fails here rather than with an unsatisfied
+/// witness. The proof doesn't commit to the gates, only to the constants and quotient
+/// polynomials they induce, so two gate sets with the same shape aren't told apart
+pub fn check_compatibility<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    proof: &ProofWithPublicInputs<F, C, D>,
+    common_data: &CommonCircuitData<F, D>,
+) -> Result<(), CompatError> {
+    if proof.public_inputs.len() != common_data.num_public_inputs {
+        return Err(CompatError::PublicInputCount {
+            expected: common_data.num_public_inputs,
+            actual: proof.public_inputs.len(),
+        });
+    }
+
+    let config = &common_data.config;
+    let openings = &proof.proof.openings;
+    let fri_proof = &proof.proof.opening_proof;
+    check_len(
+        "wires",
+        config.num_wires,
+        openings.wires.len(),
+        config_error,
+    )?;
+    check_len(
+        "routed wires",
+        config.num_routed_wires,
+        openings.plonk_sigmas.len(),
+        config_error,
+    )?;
+    check_len(
+        "challenges",
+        config.num_challenges,
+        openings.plonk_zs.len(),
+        config_error,
+    )?;
+    check_len(
+        "cap elements",
+        1 << config.fri_config.cap_height,
+        proof.proof.wires_cap.0.len(),
+        config_error,
+    )?;
+    check_len(
+        "query rounds",
+        config.fri_config.num_query_rounds,
+        fri_proof.query_round_proofs.len(),
+        config_error,
+    )?;
+
+    // the initial Merkle trees have 2^(degree_bits + rate_bits) leaves, of which the cap
+    // holds the top `cap_height` levels
+    let (_, merkle_proof) = fri_proof
+        .query_round_proofs
+        .first()
+        .ok_or(CompatError::UnreadableDegree("query rounds"))?
+        .initial_trees_proof
+        .evals_proofs
+        .first()
+        .ok_or(CompatError::UnreadableDegree("initial trees"))?;
+    let actual_degree_bits = (merkle_proof.siblings.len() + config.fri_config.cap_height)
+        .checked_sub(config.fri_config.rate_bits)
+        .ok_or(CompatError::UnreadableDegree("Merkle proofs"))?;
+    if actual_degree_bits != common_data.degree_bits() {
+        return Err(CompatError::DegreeBits {
+            expected: common_data.degree_bits(),
+            actual: actual_degree_bits,
+        });
+    }
+
+    check_len(
+        "constants",
+        common_data.num_constants,
+        openings.constants.len(),
+        gate_set_error,
+    )?;
+    check_len(
+        "quotient polynomials",
+        config.num_challenges * common_data.quotient_degree_factor,
+        openings.quotient_polys.len(),
+        gate_set_error,
+    )?;
+    check_len(
+        "partial products",
+        config.num_challenges * common_data.num_partial_products,
+        openings.partial_products.len(),
+        gate_set_error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::gates::noop::NoopGate;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitData;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use proof_error::ProofError;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    fn prove_cube(
+        num_public_inputs: usize,
+        min_gates: usize,
+    ) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let y = builder.exp_u64(x, 3);
+        for _ in 0..num_public_inputs {
+            builder.register_public_input(y);
+        }
+        while builder.num_gates() < min_gates {
+            builder.add_gate(NoopGate, vec![]);
+        }
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::TWO);
+
+        let data = builder.build::<C>();
+        let proof = data.prove(pw).map_err(ProofError::Proving)?;
+        Ok((data, proof))
+    }
+
+    #[test]
+    fn test_check_compatibility() -> Result<(), ProofError> {
+        let (data, proof) = prove_cube(1, 0)?;
+        check_compatibility(&proof, &data.common)?;
+
+        let (other_data, _) = prove_cube(2, 0)?;
+        assert_eq!(
+            check_compatibility(&proof, &other_data.common),
+            Err(CompatError::PublicInputCount {
+                expected: 2,
+                actual: 1
+            })
+        );
+
+        let (large_data, _) = prove_cube(1, 1 << 10)?;
+        assert!(matches!(
+            check_compatibility(&proof, &large_data.common),
+            Err(CompatError::DegreeBits { .. })
+        ));
+
+        // a query round without the proofs of the initial trees
+        let mut truncated = proof;
+        truncated.proof.opening_proof.query_round_proofs[0]
+            .initial_trees_proof
+            .evals_proofs
+            .clear();
+        assert_eq!(
+            check_compatibility(&truncated, &data.common),
+            Err(CompatError::UnreadableDegree("initial trees"))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+//! Estimates of proving times from the shape of a circuit, calibrated by proving small
+//! circuits on the host, to tell before building a large circuit whether it proves in
+//! seconds or in hours
+
+use std::time::Duration;
+
+use plonky2::field::extension::Extendable;
+use plonky2::gates::noop::NoopGate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CommonCircuitData};
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::timed;
+
+/// What the proving time of a circuit depends on: the number of polynomials committed to
+/// and the size of their low-degree extensions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitShape {
+    pub degree_bits: usize,
+    pub rate_bits: usize,
+    /// The wires, sigmas, constants, partial products and quotient chunks
+    pub num_polys: usize,
+}
+
+impl CircuitShape {
+    pub fn of<F: RichField + Extendable<D>, const D: usize>(
+        common: &CommonCircuitData<F, D>,
+    ) -> Self {
+        let config = &common.config;
+        Self {
+            degree_bits: common.degree_bits(),
+            rate_bits: config.fri_config.rate_bits,
+            num_polys: config.num_wires
+                + config.num_routed_wires
+                + common.num_constants
+                + config.num_challenges
+                    * (1 + common.num_partial_products + common.quotient_degree_factor),
+        }
+    }
+
+    /// The work of the low-degree extensions, FFTs of `n log n` over `n` points for each
+    /// polynomial, which the Merkle commitments to them are proportional to
+    pub fn work(&self) -> f64 {
+        let lde_bits = self.degree_bits + self.rate_bits;
+        self.num_polys as f64 * (lde_bits as f64) * 2f64.powi(lde_bits as i32)
+    }
+}
+
+/// A circuit proven on the host during calibration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationPoint {
+    pub shape: CircuitShape,
+    pub prove_time: Duration,
+}
+
+/// How fast the host proves, as the proving times of circuits of a few sizes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MachineProfile {
+    pub points: Vec<CalibrationPoint>,
+}
+
+impl MachineProfile {
+    /// Proves circuits of `config` of each of `degree_bits`, e.g. `[10, 12]` for a
+    /// calibration taking a second or so
+    pub fn calibrate(config: &CircuitConfig, degree_bits: &[usize]) -> Result<Self, ProofError> {
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<2>>::F;
+
+        if degree_bits.is_empty() {
+            return Err(ProofError::EmptyCircuit);
+        }
+        let points = degree_bits
+            .iter()
+            .map(|&bits| {
+                // more than 2^(bits - 1) rows, which the circuit pads to 2^bits, or to the
+                // smallest degree plonky2 builds for 0 bits
+                let mut builder = CircuitBuilder::<F, 2>::new(config.clone());
+                for _ in 0..(1 << bits.saturating_sub(1)) + 1 {
+                    builder.add_gate(NoopGate, vec![]);
+                }
+                let circuit_data = builder.build::<C>();
+                let (proof, prove_time) = timed(|| circuit_data.prove(PartialWitness::new()));
+                proof.map_err(ProofError::Proving)?;
+
+                Ok(CalibrationPoint {
+                    shape: CircuitShape::of(&circuit_data.common),
+                    prove_time,
+                })
+            })
+            .collect::<Result<_, ProofError>>()?;
+
+        Ok(Self { points })
+    }
+
+    /// The estimated proving time of a circuit of `shape`, at the speed of the calibration
+    /// point closest to it in work. Estimates far above the calibration don't account for
+    /// the memory of the host running out
+    pub fn estimate(&self, shape: &CircuitShape) -> Duration {
+        let work = shape.work();
+        let closest = self
+            .points
+            .iter()
+            .min_by(|a, b| {
+                let distance = |point: &CalibrationPoint| (point.shape.work() / work).ln().abs();
+                distance(a).total_cmp(&distance(b))
+            })
+            .expect("a profile has calibration points");
+
+        closest.prove_time.mul_f64(work / closest.shape.work())
+    }
+
+    /// The shape of the largest calibration circuit resized to `2^degree_bits` rows, to
+    /// estimate circuits of the same config before building them
+    pub fn shape_of_degree(&self, degree_bits: usize) -> CircuitShape {
+        let largest = self
+            .points
+            .iter()
+            .max_by_key(|point| point.shape.degree_bits)
+            .expect("a profile has calibration points");
+
+        CircuitShape {
+            degree_bits,
+            ..largest.shape
+        }
+    }
+}
+
+/// The estimated proving time of a circuit with data `common` on the host of `profile`
+pub fn estimate_prove_time<F: RichField + Extendable<D>, const D: usize>(
+    common: &CommonCircuitData<F, D>,
+    profile: &MachineProfile,
+) -> Duration {
+    profile.estimate(&CircuitShape::of(common))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::standard_config;
+
+    #[test]
+    fn test_estimate_prove_time() -> Result<(), ProofError> {
+        let profile = MachineProfile::calibrate(&standard_config(false), &[8, 10])?;
+        assert_eq!(profile.points.len(), 2);
+        assert_eq!(profile.points[1].shape.degree_bits, 10);
+
+        // a calibration circuit is estimated at its own proving time
+        let point = &profile.points[1];
+        let estimate = profile.estimate(&point.shape);
+        assert!(
+            estimate.max(point.prove_time) - estimate.min(point.prove_time)
+                < Duration::from_micros(1)
+        );
+
+        // doubling the rows more than doubles the work
+        let shape = profile.shape_of_degree(20);
+        let larger = CircuitShape {
+            degree_bits: 21,
+            ..shape
+        };
+        assert!(profile.estimate(&larger) > profile.estimate(&shape) * 2);
+        assert!(matches!(
+            MachineProfile::calibrate(&standard_config(false), &[]),
+            Err(ProofError::EmptyCircuit)
+        ));
+
+        Ok(())
+    }
+}
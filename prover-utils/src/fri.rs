@@ -0,0 +1,164 @@
+use plonky2::plonk::circuit_data::CircuitConfig;
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::standard_config;
+
+/// The conjectured security of the standard recursion config, below which profiles are
+/// rejected
+pub const MIN_CONJECTURED_SECURITY_BITS: usize = 100;
+
+/// The FRI parameters of a circuit config, set with the builder methods on top of those of
+/// the standard recursion config and checked for soundness when turned into a config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FriProfile {
+    /// The log of the blowup factor of the low-degree extensions
+    pub rate_bits: usize,
+    /// The height of the Merkle caps sent in place of Merkle roots
+    pub cap_height: usize,
+    /// The bits of grinding asked of the prover before the query rounds
+    pub proof_of_work_bits: u32,
+    pub num_query_rounds: usize,
+}
+
+impl Default for FriProfile {
+    fn default() -> Self {
+        Self::of(&standard_config(false))
+    }
+}
+
+impl FriProfile {
+    /// The FRI parameters of `config`
+    pub fn of(config: &CircuitConfig) -> Self {
+        let fri_config = &config.fri_config;
+        Self {
+            rate_bits: fri_config.rate_bits,
+            cap_height: fri_config.cap_height,
+            proof_of_work_bits: fri_config.proof_of_work_bits,
+            num_query_rounds: fri_config.num_query_rounds,
+        }
+    }
+
+    pub fn rate_bits(self, rate_bits: usize) -> Self {
+        Self { rate_bits, ..self }
+    }
+
+    pub fn cap_height(self, cap_height: usize) -> Self {
+        Self { cap_height, ..self }
+    }
+
+    pub fn proof_of_work_bits(self, proof_of_work_bits: u32) -> Self {
+        Self {
+            proof_of_work_bits,
+            ..self
+        }
+    }
+
+    pub fn num_query_rounds(self, num_query_rounds: usize) -> Self {
+        Self {
+            num_query_rounds,
+            ..self
+        }
+    }
+
+    /// The security conjectured by plonky2, where each query round brings `rate_bits` bits
+    /// on top of the grinding
+    pub fn conjectured_security_bits(&self) -> usize {
+        self.rate_bits * self.num_query_rounds + self.proof_of_work_bits as usize
+    }
+
+    /// Checks the profile against `MIN_CONJECTURED_SECURITY_BITS`
+    pub fn check(&self) -> Result<(), ProofError> {
+        if self.rate_bits == 0 {
+            return Err(ProofError::InvalidFriProfile(
+                "a rate of 1 leaves no redundancy to test".into(),
+            ));
+        }
+        let actual = self.conjectured_security_bits();
+        if actual < MIN_CONJECTURED_SECURITY_BITS {
+            return Err(ProofError::InsecureFri {
+                required: MIN_CONJECTURED_SECURITY_BITS,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// `config` with the parameters of the profile, which are checked with `check`. The
+    /// quotient polynomials are committed to in the low-degree extensions, so the blowup
+    /// should also cover the quotient degree factor of the config
+    pub fn apply(&self, mut config: CircuitConfig) -> Result<CircuitConfig, ProofError> {
+        self.check()?;
+        if config.max_quotient_degree_factor > 1 << self.rate_bits {
+            return Err(ProofError::InvalidFriProfile(format!(
+                "a blowup of 2^{} is below the quotient degree factor {}",
+                self.rate_bits, config.max_quotient_degree_factor
+            )));
+        }
+
+        let fri_config = &mut config.fri_config;
+        fri_config.rate_bits = self.rate_bits;
+        fri_config.cap_height = self.cap_height;
+        fri_config.proof_of_work_bits = self.proof_of_work_bits;
+        fri_config.num_query_rounds = self.num_query_rounds;
+        Ok(config)
+    }
+
+    /// The standard config with the parameters of the profile, with zero knowledge if
+    /// requested
+    pub fn circuit_config(&self, zero_knowledge: bool) -> Result<CircuitConfig, ProofError> {
+        self.apply(standard_config(zero_knowledge))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_fri_profile() -> Result<(), ProofError> {
+        let standard = FriProfile::default();
+        assert_eq!(
+            standard.conjectured_security_bits(),
+            MIN_CONJECTURED_SECURITY_BITS
+        );
+        assert_eq!(FriProfile::of(&standard.circuit_config(true)?), standard);
+
+        assert!(matches!(
+            standard.num_query_rounds(20).check(),
+            Err(ProofError::InsecureFri { actual: 76, .. })
+        ));
+        assert!(matches!(
+            standard.rate_bits(0).check(),
+            Err(ProofError::InvalidFriProfile(_))
+        ));
+        // sound on its own, but the standard gates need a blowup of 8
+        let low_rate = standard.rate_bits(2).num_query_rounds(42);
+        low_rate.check()?;
+        assert!(low_rate.circuit_config(false).is_err());
+
+        // fewer queries over a larger blowup, proven with plonky2
+        let profile = standard.rate_bits(4).num_query_rounds(21).cap_height(2);
+        let mut builder = CircuitBuilder::<F, D>::new(profile.circuit_config(false)?);
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::TWO);
+
+        let data = builder.build::<C>();
+        assert_eq!(FriProfile::of(&data.common.config), profile);
+        let proof = data.prove(pw).map_err(ProofError::Proving)?;
+        data.verify(proof).map_err(ProofError::Verification)
+    }
+}
@@ -0,0 +1,173 @@
+//! A statistical check that proofs don't leak their witness: the same statement is proven
+//! with two witnesses, and the proofs of each are compared. Without zero knowledge, the
+//! openings are a deterministic function of the witness, so that two proofs of the same
+//! witness are equal and the proofs of different witnesses tell them apart
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::Proof;
+use proof_error::ProofError;
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LeakageReport {
+    /// The number of proofs of each witness
+    pub samples: usize,
+    /// The total variation distance between the byte distributions of half of the proofs of
+    /// each witness
+    pub distance_between: f64,
+    /// The same distance between both halves of the proofs of the first witness, i.e. the
+    /// noise of the sample
+    pub distance_within: f64,
+    /// The pairs of proofs of the same witness with equal openings, which are blinded with
+    /// zero knowledge
+    pub equal_openings: usize,
+    /// From 0 when the proofs of both witnesses look alike to 1 when they tell the
+    /// witnesses apart: 1 if any openings repeat, else how much of the distance between
+    /// the witnesses exceeds the noise
+    pub score: f64,
+}
+
+/// Proves the circuit `samples` times with each of `witnesses`, which should set the
+/// targets of the same statement, and compares the serialized proofs. The public inputs
+/// are left out, as they are the statement
+pub fn leakage_report<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    circuit_data: &CircuitData<F, C, D>,
+    witnesses: [&PartialWitness<F>; 2],
+    samples: usize,
+) -> Result<LeakageReport, ProofError> {
+    if samples < 2 {
+        return Err(ProofError::LeakageSamples(samples));
+    }
+
+    let proofs = witnesses
+        .iter()
+        .map(|&witness| {
+            (0..samples)
+                .map(|_| {
+                    let proof_with_pis = circuit_data
+                        .prove(witness.clone())
+                        .map_err(ProofError::Proving)?;
+                    Ok(proof_with_pis.proof)
+                })
+                .collect::<Result<Vec<_>, ProofError>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let half = samples / 2;
+    let distance_between = distance(&proofs[0][..half], &proofs[1][..half])?;
+    let distance_within = distance(&proofs[0][..half], &proofs[0][half..2 * half])?;
+    let mut equal_openings = 0;
+    for proofs in &proofs {
+        let openings = proofs
+            .iter()
+            .map(|proof| serialize(&proof.openings))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, opening) in openings.iter().enumerate() {
+            equal_openings += openings[..i].iter().filter(|o| *o == opening).count();
+        }
+    }
+
+    let score = if equal_openings > 0 {
+        1.0
+    } else if distance_between > 0.0 {
+        ((distance_between - distance_within) / distance_between).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    Ok(LeakageReport {
+        samples,
+        distance_between,
+        distance_within,
+        equal_openings,
+        score,
+    })
+}
+
+fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, ProofError> {
+    bincode::serialize(value).map_err(|e| ProofError::Serialization(e.to_string()))
+}
+
+/// The frequency of each byte value in the serialized proofs
+fn byte_distribution<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    proofs: &[Proof<F, C, D>],
+) -> Result<[f64; 256], ProofError> {
+    let mut counts = [0usize; 256];
+    let mut total = 0;
+    for proof in proofs {
+        for byte in serialize(proof)? {
+            counts[byte as usize] += 1;
+            total += 1;
+        }
+    }
+
+    Ok(counts.map(|count| count as f64 / total as f64))
+}
+
+/// The total variation distance between the byte distributions of two sets of proofs
+fn distance<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    lhs: &[Proof<F, C, D>],
+    rhs: &[Proof<F, C, D>],
+) -> Result<f64, ProofError> {
+    let (lhs, rhs) = (byte_distribution(lhs)?, byte_distribution(rhs)?);
+    Ok(lhs.iter().zip(rhs).map(|(p, q)| (p - q).abs()).sum::<f64>() / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_leakage_report() -> Result<(), ProofError> {
+        for zero_knowledge in [true, false] {
+            // knowledge of two numbers adding up to 10
+            let mut builder = CircuitBuilder::<F, D>::new(standard_config(zero_knowledge));
+            let (a, b) = (builder.add_virtual_target(), builder.add_virtual_target());
+            let sum = builder.add(a, b);
+            builder.register_public_input(sum);
+            let data = builder.build::<C>();
+            let witnesses = [(3, 7), (4, 6)].map(|(x, y)| {
+                let mut pw = PartialWitness::new();
+                pw.set_target(a, F::from_canonical_u64(x));
+                pw.set_target(b, F::from_canonical_u64(y));
+                pw
+            });
+
+            let report = leakage_report(&data, [&witnesses[0], &witnesses[1]], 4)?;
+            if zero_knowledge {
+                assert_eq!(report.equal_openings, 0);
+                assert!(report.score < 0.5, "{report:?}");
+            } else {
+                // every proof of a witness is the same
+                assert_eq!(report.equal_openings, 2 * 6);
+                assert_eq!(report.distance_within, 0.0);
+                assert_eq!(report.score, 1.0);
+            }
+        }
+
+        assert!(matches!(
+            leakage_report::<F, C, D>(
+                &CircuitBuilder::new(standard_config(true)).build(),
+                [&PartialWitness::new(), &PartialWitness::new()],
+                1
+            ),
+            Err(ProofError::LeakageSamples(1))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+pub mod assertions;
+pub mod batch;
+pub mod budget;
+pub mod codec;
+pub mod compat;
+pub mod estimate;
+pub mod fri;
+pub mod leakage;
+pub mod options;
+pub mod pipeline;
+pub mod recursion_utils;
+pub mod registry;
+pub mod sections;
+pub mod transcript;
+pub mod witness;
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::{ProofError, ProverDiagnostic};
+use rayon::ThreadPoolBuilder;
+
+use crate::options::ProverOptions;
+
+/// The configuration used throughout the experiments, with zero knowledge if requested.
+/// `fri::FriProfile` derives configs with other FRI parameters from it
+pub fn standard_config(zero_knowledge: bool) -> CircuitConfig {
+    if zero_knowledge {
+        CircuitConfig::standard_recursion_zk_config()
+    } else {
+        CircuitConfig::standard_recursion_config()
+    }
+}
+
+/// Runs `f`, returning its result together with the time it took
+pub fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let now = Instant::now();
+    let result = f();
+    (result, now.elapsed())
+}
+
+/// Time spent in each step of producing a proof
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub build: Duration,
+    pub prove: Duration,
+    /// `None` when the proof has not been verified
+    pub verify: Option<Duration>,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.build + self.prove + self.verify.unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "built in {:.2?}, proved in {:.2?}",
+            self.build, self.prove
+        )?;
+        if let Some(verify) = self.verify {
+            write!(f, ", verified in {verify:.2?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A proof together with the data of its circuit and the time it took to produce
+pub struct TimedProof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub circuit_data: CircuitData<F, C, D>,
+    pub proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    pub timings: Timings,
+}
+
+/// Builds the circuit and proves it against `partial_witness`, timing both steps
+pub fn timed_prove<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    builder: CircuitBuilder<F, D>,
+    partial_witness: PartialWitness<F>,
+) -> Result<TimedProof<F, C, D>, ProofError> {
+    timed_prove_with_options(builder, partial_witness, &ProverOptions::default())
+}
+
+/// Like `timed_prove`, with `options`
+pub fn timed_prove_with_options<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: CircuitBuilder<F, D>,
+    partial_witness: PartialWitness<F>,
+    options: &ProverOptions,
+) -> Result<TimedProof<F, C, D>, ProofError> {
+    let (circuit_data, build) = timed(|| builder.build::<C>());
+    let (proof_with_pis, prove) =
+        timed(|| prove_with_options(&circuit_data, partial_witness, options));
+
+    Ok(TimedProof {
+        proof_with_pis: proof_with_pis?,
+        circuit_data,
+        timings: Timings {
+            build,
+            prove,
+            verify: None,
+        },
+    })
+}
+
+/// Proves `circuit_data` against `partial_witness`, deterministically if `options` ask for it
+pub fn prove_with_options<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    circuit_data: &CircuitData<F, C, D>,
+    partial_witness: PartialWitness<F>,
+    options: &ProverOptions,
+) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+    if !options.deterministic {
+        return catch_prover_panics(|| {
+            circuit_data
+                .prove(partial_witness)
+                .map_err(ProofError::Proving)
+        });
+    }
+    if circuit_data.common.config.zero_knowledge {
+        return Err(ProofError::RandomizedProof);
+    }
+
+    // a parallel search of the FRI proof of work returns whichever valid nonce a thread
+    // finds first, a single thread the first one
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .map_err(|e| ProofError::Proving(e.into()))?;
+    pool.install(|| {
+        catch_prover_panics(|| {
+            circuit_data
+                .prove(partial_witness)
+                .map_err(ProofError::Proving)
+        })
+    })
+}
+
+/// Runs `prove`, turning the panics plonky2 reports some failures with, e.g. generators
+/// whose inputs were never set, into `ProofError::Diagnosed` errors the caller recovers from
+pub fn catch_prover_panics<T>(
+    prove: impl FnOnce() -> Result<T, ProofError>,
+) -> Result<T, ProofError> {
+    panic::catch_unwind(AssertUnwindSafe(prove))
+        .unwrap_or_else(|payload| Err(diagnose_panic(payload.as_ref()).into()))
+}
+
+/// The diagnostic of a panic of the prover: the one it was raised with, e.g. by a labelled
+/// assertion of `assertions`, or one parsed from its message
+pub fn diagnose_panic(payload: &(dyn Any + Send)) -> ProverDiagnostic {
+    if let Some(diagnostic) = payload.downcast_ref::<ProverDiagnostic>() {
+        return diagnostic.clone();
+    }
+    let message = payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_default();
+    // plonky2 asserts that every generator ran, e.g. "3 generators weren't run"
+    let generators = message
+        .split_once(" generators weren't run")
+        .and_then(|(before, _)| before.rsplit(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|count| count.parse().ok());
+    match generators {
+        Some(generators) => ProverDiagnostic::UnfilledTargets { generators },
+        None => ProverDiagnostic::Panic(message),
+    }
+}
+
+/// Like `timed_prove`, additionally verifying the proof
+pub fn prove_and_verify<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: CircuitBuilder<F, D>,
+    partial_witness: PartialWitness<F>,
+) -> Result<TimedProof<F, C, D>, ProofError> {
+    let mut timed_proof = timed_prove(builder, partial_witness)?;
+    let (verified, verify) = timed(|| {
+        timed_proof
+            .circuit_data
+            .verify(timed_proof.proof_with_pis.clone())
+    });
+    verified.map_err(ProofError::Verification)?;
+    timed_proof.timings.verify = Some(verify);
+
+    Ok(timed_proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_prove_and_verify() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::TWO);
+
+        let TimedProof {
+            proof_with_pis,
+            timings,
+            ..
+        } = prove_and_verify::<F, C, D>(builder, pw)?;
+        assert_eq!(proof_with_pis.public_inputs, [F::from_canonical_u64(8)]);
+        assert!(timings.verify.is_some());
+        assert!(timings.total() >= timings.prove);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unfilled_targets() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // `x` is never set, so the generators computing its powers can't run
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let result = timed_prove::<F, C, D>(builder, PartialWitness::new());
+        assert!(matches!(
+            result,
+            Err(ProofError::Diagnosed(ProverDiagnostic::UnfilledTargets { generators }))
+                if generators > 0
+        ));
+
+        assert_eq!(
+            diagnose_panic(&"assertion failed: left: `2`, right: `0`: 2 generators weren't run"),
+            ProverDiagnostic::UnfilledTargets { generators: 2 }
+        );
+        assert_eq!(
+            diagnose_panic(&String::from("out of memory")),
+            ProverDiagnostic::Panic("out of memory".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deterministic_proofs() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let options = ProverOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let prove = |num_threads: usize| -> Result<Vec<u8>, ProofError> {
+            let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+            let x = builder.add_virtual_target();
+            let x_cubed = builder.exp_u64(x, 3);
+            builder.register_public_input(x_cubed);
+            let mut pw = PartialWitness::new();
+            pw.set_target(x, F::TWO);
+
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            let timed_proof =
+                pool.install(|| timed_prove_with_options::<F, C, D>(builder, pw, &options))?;
+            bincode::serialize(&timed_proof.proof_with_pis)
+                .map_err(|e| ProofError::Serialization(e.to_string()))
+        };
+        let bytes = prove(4)?;
+        assert_eq!(prove(4)?, bytes);
+        assert_eq!(prove(1)?, bytes);
+
+        // zero knowledge blinds proofs randomly
+        let builder = CircuitBuilder::<F, D>::new(standard_config(true));
+        assert!(matches!(
+            timed_prove_with_options::<F, C, D>(builder, PartialWitness::new(), &options),
+            Err(ProofError::RandomizedProof)
+        ));
+
+        Ok(())
+    }
+}
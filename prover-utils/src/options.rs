@@ -0,0 +1,35 @@
+/// Options of the prover wrappers, `ProverOptions::default()` proving without proof of work
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProverOptions {
+    /// The leading zero bits of work required by circuits gated by a proof of work, such as
+    /// the signal circuit of `semaphore::pow`, 0 disabling the gate
+    pub difficulty: usize,
+    /// Whether proofs are reproducible bit for bit, across runs and thread counts. Witness
+    /// generation already runs the generators in a fixed order; the FRI proof of work is
+    /// ground on a single thread, which tries the nonces in order, and circuits are built
+    /// without zero knowledge, whose blinding is random
+    pub deterministic: bool,
+}
+
+impl ProverOptions {
+    /// Whether circuits proven with these options are built with zero knowledge, when it
+    /// is `requested`
+    pub fn zero_knowledge(&self, requested: bool) -> bool {
+        requested && !self.deterministic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_knowledge() {
+        assert!(ProverOptions::default().zero_knowledge(true));
+        let deterministic = ProverOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        assert!(!deterministic.zero_knowledge(true));
+    }
+}
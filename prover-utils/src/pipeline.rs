@@ -0,0 +1,242 @@
+//! Witness generation split from proving, so that a pipeline generates the witness of a
+//! proof while the previous one is proven. The prover of plonky2 always runs the
+//! generators of the circuit it is given, so proving from a `FullWitness` takes a copy of
+//! the circuit without generators, see `without_generators`: the full witness sets every
+//! target, which the copy only commits to
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::GeneratedValues;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, PartitionWitness, Witness, WitnessWrite};
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::{ProofError, ProverDiagnostic};
+
+use crate::options::ProverOptions;
+
+/// The values of every target of a circuit, as its generators computed them
+#[derive(Clone, Debug)]
+pub struct FullWitness<F: RichField> {
+    partial_witness: PartialWitness<F>,
+    public_inputs: Vec<F>,
+}
+
+impl<F: RichField> FullWitness<F> {
+    /// The public inputs the proof will have
+    pub fn public_inputs(&self) -> &[F] {
+        &self.public_inputs
+    }
+
+    pub fn partial_witness(&self) -> &PartialWitness<F> {
+        &self.partial_witness
+    }
+}
+
+/// Runs the generators of `data` from `partial_witness` as the prover does, failing with
+/// the diagnostic the prover would panic with if some can't run or set conflicting values
+pub fn generate_witness<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    data: &CircuitData<F, C, D>,
+    partial_witness: PartialWitness<F>,
+) -> Result<FullWitness<F>, ProofError> {
+    crate::catch_prover_panics(|| {
+        let num_wires = data.common.config.num_wires;
+        let degree = data.common.degree();
+        let representative_map = &data.prover_only.representative_map;
+        // the representative map covers every wire followed by every virtual target
+        let targets: Vec<Target> = (0..representative_map.len())
+            .map(|index| {
+                if index < num_wires * degree {
+                    Target::wire(index / num_wires, index % num_wires)
+                } else {
+                    Target::VirtualTarget {
+                        index: index - num_wires * degree,
+                    }
+                }
+            })
+            .collect();
+
+        let mut witness = PartitionWitness::new(num_wires, degree, representative_map);
+        for &target in &targets {
+            if let Some(value) = partial_witness.try_get_target(target) {
+                witness.set_target(target, value);
+            }
+        }
+
+        let generators = &data.prover_only.generators;
+        let mut done = vec![false; generators.len()];
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for (generator, done) in generators.iter().zip(done.iter_mut()) {
+                if *done {
+                    continue;
+                }
+
+                let mut buffer = GeneratedValues::empty();
+                *done = generator.run(&witness, &mut buffer);
+                progress |= *done;
+                for (target, value) in buffer.target_values {
+                    match witness.try_get_target(target) {
+                        Some(existing) if existing != value => {
+                            return Err(ProofError::WitnessConflict {
+                                name: format!("{target:?}"),
+                            });
+                        }
+                        Some(_) => {}
+                        None => witness.set_target(target, value),
+                    }
+                }
+            }
+        }
+        let unrun = done.iter().filter(|done| !**done).count();
+        if unrun > 0 {
+            return Err(ProverDiagnostic::UnfilledTargets { generators: unrun }.into());
+        }
+
+        let mut full_witness = PartialWitness::new();
+        for &target in &targets {
+            if let Some(value) = witness.try_get_target(target) {
+                full_witness.set_target(target, value);
+            }
+        }
+        Ok(FullWitness {
+            public_inputs: witness.get_targets(&data.prover_only.public_inputs),
+            partial_witness: full_witness,
+        })
+    })
+}
+
+/// A copy of `data` without its generators, for `prove_with_witness`. The circuit is the
+/// same, so its proofs verify against the verifier data of `data`
+pub fn without_generators<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    mut data: CircuitData<F, C, D>,
+) -> CircuitData<F, C, D> {
+    data.prover_only.generators.clear();
+    data.prover_only.generator_indices_by_watches.clear();
+    data
+}
+
+/// Proves `data`, usually from `without_generators`, against a witness of the same circuit
+/// generated by `generate_witness`
+pub fn prove_with_witness<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    data: &CircuitData<F, C, D>,
+    witness: FullWitness<F>,
+    options: &ProverOptions,
+) -> Result<ProofWithPublicInputs<F, C, D>, ProofError> {
+    crate::prove_with_options(data, witness.partial_witness, options)
+}
+
+/// Proves `jobs` in order, `generate` computing the witness of a job on another thread
+/// while `prove` proves the previous one. `generate` sees the jobs in order, so it can
+/// carry state from one to the next. Each result comes with the time its job took to
+/// generate and prove, and the results stop at the first failure
+pub fn pipelined<T: Send, W: Send, P>(
+    jobs: Vec<T>,
+    mut generate: impl FnMut(T) -> Result<W, ProofError> + Send,
+    mut prove: impl FnMut(W) -> Result<P, ProofError>,
+) -> Vec<(Result<P, ProofError>, Duration)> {
+    // a single witness waits for the prover, bounding the memory of the pipeline
+    let (sender, receiver) = mpsc::sync_channel(1);
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for job in jobs {
+                let (witness, generation) = crate::timed(|| generate(job));
+                let failed = witness.is_err();
+                if sender.send((witness, generation)).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let mut results = Vec::new();
+        for (witness, generation) in receiver {
+            let (proof, proving) = crate::timed(|| witness.and_then(&mut prove));
+            let failed = proof.is_err();
+            results.push((proof, generation + proving));
+            if failed {
+                break;
+            }
+        }
+        results
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_pipelined_proofs() -> Result<(), ProofError> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let build = || {
+            let mut builder = CircuitBuilder::<F, D>::new(crate::standard_config(false));
+            let x = builder.add_virtual_target();
+            let x_cubed = builder.exp_u64(x, 3);
+            builder.register_public_input(x_cubed);
+            (builder.build::<C>(), x)
+        };
+        let (data, x) = build();
+        let proving_data = without_generators(build().0);
+        let partial_witness = |value: u64| {
+            let mut pw = PartialWitness::new();
+            pw.set_target(x, F::from_canonical_u64(value));
+            pw
+        };
+
+        let witness = generate_witness(&data, partial_witness(2))?;
+        assert_eq!(witness.public_inputs(), [F::from_canonical_u64(8)]);
+        let proof = prove_with_witness(&proving_data, witness, &ProverOptions::default())?;
+        data.verify(proof).map_err(ProofError::Verification)?;
+        assert!(matches!(
+            generate_witness(&data, PartialWitness::new()),
+            Err(ProofError::Diagnosed(
+                ProverDiagnostic::UnfilledTargets { .. }
+            ))
+        ));
+
+        // the third job has no witness, so the pipeline stops there
+        let results = pipelined(
+            vec![Some(1), Some(3), None, Some(4)],
+            |value| match value {
+                Some(value) => generate_witness(&data, partial_witness(value)),
+                None => generate_witness(&data, PartialWitness::new()),
+            },
+            |witness| prove_with_witness(&proving_data, witness, &ProverOptions::default()),
+        );
+        assert_eq!(results.len(), 3);
+        for ((result, _), expected) in results[..2].iter().zip([1, 27]) {
+            let proof = result.as_ref().expect("the first jobs are proven");
+            assert_eq!(proof.public_inputs, [F::from_canonical_u64(expected)]);
+            data.verify(proof.clone())
+                .map_err(ProofError::Verification)?;
+        }
+        assert!(results[2].0.is_err());
+
+        Ok(())
+    }
+}
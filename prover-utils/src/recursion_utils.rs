@@ -0,0 +1,207 @@
+//! Verifying proofs of an inner circuit in an outer circuit, shared by the aggregations,
+//! the rollups and the wrappers built on recursion
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CommonCircuitData, VerifierCircuitData, VerifierCircuitTarget};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use proof_error::{ProofError, ProverDiagnostic};
+
+use crate::compat::check_compatibility;
+
+/// The targets of the verifier data of the inner circuit and of each proof verified
+/// against it
+pub type ProofTargets<const D: usize> =
+    (VerifierCircuitTarget, Vec<ProofWithPublicInputsTarget<D>>);
+
+/// Adds the verification of `num_proofs` proofs of a circuit of shape `common`. The
+/// verifier data is a witness, to be constrained by the caller if the proofs should be of
+/// a given circuit, e.g. with `CircuitBuilderExt::constant_verifier_data`
+pub fn add_proof_targets<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    num_proofs: usize,
+    common: &CommonCircuitData<F, D>,
+) -> Result<ProofTargets<D>, ProofError>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    if num_proofs == 0 {
+        return Err(ProofError::EmptyCircuit);
+    }
+
+    let vd_target = builder.add_verifier_data_target(common.config.fri_config.cap_height);
+    let proof_targets = (0..num_proofs)
+        .map(|_| {
+            let proof_target = builder.add_virtual_proof_with_pis::<C>(common);
+            builder.verify_proof::<C>(&proof_target, &vd_target, common);
+            proof_target
+        })
+        .collect();
+
+    Ok((vd_target, proof_targets))
+}
+
+/// Sets the targets of `add_proof_targets` to `proofs` and their verifier data, checking
+/// that each proof has the shape the circuit expects and that the verifier data fills the
+/// targets, which plonky2 would otherwise panic on or leave to unrun generators
+pub fn set_proof_targets<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    pw: &mut PartialWitness<F>,
+    (vd_target, proof_targets): &ProofTargets<D>,
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    verifier_data: &VerifierCircuitData<F, C, D>,
+) -> Result<(), ProofError>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    if proofs.len() != proof_targets.len() {
+        return Err(ProofError::WitnessCount {
+            expected: proof_targets.len(),
+            actual: proofs.len(),
+        });
+    }
+
+    let cap_targets = &vd_target.constants_sigmas_cap.0;
+    let cap = &verifier_data.verifier_only.constants_sigmas_cap.0;
+    if cap.len() < cap_targets.len() {
+        return Err(ProverDiagnostic::MissingVerifierData {
+            fields: (cap.len()..cap_targets.len())
+                .map(|i| format!("constants_sigmas_cap[{i}]"))
+                .collect(),
+        }
+        .into());
+    }
+    if cap.len() > cap_targets.len() {
+        return Err(ProofError::LengthMismatch {
+            lhs: cap.len(),
+            rhs: cap_targets.len(),
+        });
+    }
+    for (index, (proof_target, proof)) in proof_targets.iter().zip(proofs).enumerate() {
+        if proof.public_inputs.len() != proof_target.public_inputs.len() {
+            return Err(ProverDiagnostic::PublicInputCount {
+                index,
+                expected: proof_target.public_inputs.len(),
+                actual: proof.public_inputs.len(),
+            }
+            .into());
+        }
+    }
+
+    pw.set_cap_target(
+        &vd_target.constants_sigmas_cap,
+        &verifier_data.verifier_only.constants_sigmas_cap,
+    );
+    pw.set_hash_target(
+        vd_target.circuit_digest,
+        verifier_data.verifier_only.circuit_digest,
+    );
+    for (proof_target, proof) in proof_targets.iter().zip(proofs) {
+        check_compatibility(proof, &verifier_data.common)?;
+        pw.set_proof_with_pis_target(proof_target, proof);
+    }
+
+    Ok(())
+}
+
+/// Verifies `proofs` in the circuit of `builder`, setting their targets in `pw`. The
+/// public inputs of the proofs are left to the caller to connect or register
+pub fn verify_proofs_in_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    pw: &mut PartialWitness<F>,
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    verifier_data: &VerifierCircuitData<F, C, D>,
+) -> Result<ProofTargets<D>, ProofError>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let targets = add_proof_targets::<F, C, D>(builder, proofs.len(), &verifier_data.common)?;
+    set_proof_targets(pw, &targets, proofs, verifier_data)?;
+
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_verify_proofs_in_circuit() -> Result<(), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let inner = builder.build::<C>();
+        let proofs = [2, 3]
+            .into_iter()
+            .map(|value| {
+                let mut pw = PartialWitness::new();
+                pw.set_target(x, F::from_canonical_u64(value));
+                inner.prove(pw).map_err(ProofError::Proving)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let verifier_data = inner.verifier_data();
+
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let mut pw = PartialWitness::new();
+        assert!(matches!(
+            verify_proofs_in_circuit(&mut builder, &mut pw, &[], &verifier_data),
+            Err(ProofError::EmptyCircuit)
+        ));
+        let (_, proof_targets) =
+            verify_proofs_in_circuit(&mut builder, &mut pw, &proofs, &verifier_data)?;
+        for proof_target in &proof_targets {
+            builder.register_public_inputs(&proof_target.public_inputs);
+        }
+        let outer = builder.build::<C>();
+
+        let proof = outer.prove(pw).map_err(ProofError::Proving)?;
+        assert_eq!(proof.public_inputs, [8, 27].map(F::from_canonical_u64));
+        outer.verify(proof).map_err(ProofError::Verification)?;
+
+        // the failures plonky2 panics on are diagnosed before setting the targets
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let targets = add_proof_targets::<F, C, D>(&mut builder, 2, &verifier_data.common)?;
+        let mut truncated = proofs[1].clone();
+        truncated.public_inputs.clear();
+        assert!(matches!(
+            set_proof_targets(
+                &mut PartialWitness::new(),
+                &targets,
+                &[proofs[0].clone(), truncated],
+                &verifier_data
+            ),
+            Err(ProofError::Diagnosed(ProverDiagnostic::PublicInputCount {
+                index: 1,
+                expected: 1,
+                actual: 0
+            }))
+        ));
+
+        let mut missing = inner.verifier_data();
+        let cap_len = missing.verifier_only.constants_sigmas_cap.0.len();
+        missing.verifier_only.constants_sigmas_cap.0.pop();
+        match set_proof_targets(&mut PartialWitness::new(), &targets, &proofs, &missing) {
+            Err(ProofError::Diagnosed(ProverDiagnostic::MissingVerifierData { fields })) => {
+                assert_eq!(fields, [format!("constants_sigmas_cap[{}]", cap_len - 1)])
+            }
+            _ => panic!("the missing cap element isn't diagnosed"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{GenericConfig, GenericHashOut, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::compat::check_compatibility;
+
+/// A stable identifier of a circuit: a hash of its verifier-only data together with its
+/// config, shape and gates, so that circuits differing in any of them get different ids
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CircuitId(pub [u64; 4]);
+
+impl CircuitId {
+    pub fn of<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> Self {
+        let verifier_only = &verifier_data.verifier_only;
+        let common = &verifier_data.common;
+        let config = &common.config;
+        let fri_config = &config.fri_config;
+
+        let mut inputs = verifier_only.circuit_digest.to_vec();
+        inputs.extend(verifier_only.constants_sigmas_cap.flatten());
+        inputs.extend(
+            [
+                config.num_wires,
+                config.num_routed_wires,
+                config.num_constants,
+                usize::from(config.use_base_arithmetic_gate),
+                config.security_bits,
+                config.num_challenges,
+                usize::from(config.zero_knowledge),
+                config.max_quotient_degree_factor,
+                fri_config.rate_bits,
+                fri_config.cap_height,
+                fri_config.proof_of_work_bits as usize,
+                fri_config.num_query_rounds,
+                common.degree_bits(),
+                common.num_public_inputs,
+                common.num_constants,
+                common.quotient_degree_factor,
+                common.num_partial_products,
+                common.gates.len(),
+            ]
+            .map(F::from_canonical_usize),
+        );
+        for gate in &common.gates {
+            let id = gate.0.id();
+            inputs.push(F::from_canonical_usize(id.len()));
+            inputs.extend(id.bytes().map(F::from_canonical_u8));
+        }
+
+        let hash = PoseidonHash::hash_no_pad(&inputs);
+        Self(hash.elements.map(|x| x.to_canonical_u64()))
+    }
+}
+
+impl fmt::Display for CircuitId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for limb in self.0 {
+            write!(f, "{limb:016x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for CircuitId {
+    type Err = ProofError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProofError::InvalidCircuitId(s.to_string());
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_str_radix(&s[16 * i..16 * (i + 1)], 16).map_err(|_| invalid())?;
+        }
+
+        Ok(Self(limbs))
+    }
+}
+
+/// The circuits a verifier accepts proofs of, by id, so that a proof is only checked
+/// against the circuit it claims to target and only if that is the expected one
+pub struct CircuitRegistry<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    circuits: HashMap<CircuitId, (String, VerifierCircuitData<F, C, D>)>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    CircuitRegistry<F, C, D>
+{
+    pub fn new() -> Self {
+        Self {
+            circuits: HashMap::new(),
+        }
+    }
+
+    /// Registers a circuit under `name`, returning its id
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        verifier_data: VerifierCircuitData<F, C, D>,
+    ) -> CircuitId {
+        let id = CircuitId::of(&verifier_data);
+        self.circuits.insert(id, (name.into(), verifier_data));
+        id
+    }
+
+    pub fn get(&self, id: CircuitId) -> Option<&VerifierCircuitData<F, C, D>> {
+        self.circuits
+            .get(&id)
+            .map(|(_, verifier_data)| verifier_data)
+    }
+
+    pub fn name(&self, id: CircuitId) -> Option<&str> {
+        self.circuits.get(&id).map(|(name, _)| name.as_str())
+    }
+
+    /// The verifier data of `claimed`, the circuit a proof says it targets, checking that
+    /// it is the `expected` circuit and that it is registered
+    pub fn check(
+        &self,
+        expected: CircuitId,
+        claimed: CircuitId,
+    ) -> Result<&VerifierCircuitData<F, C, D>, ProofError> {
+        if claimed != expected {
+            return Err(ProofError::CircuitMismatch {
+                expected: expected.to_string(),
+                actual: claimed.to_string(),
+            });
+        }
+
+        self.get(claimed)
+            .ok_or_else(|| ProofError::UnknownCircuit(claimed.to_string()))
+    }
+
+    /// Verifies a proof claiming to target `claimed` against the `expected` circuit
+    pub fn verify(
+        &self,
+        expected: CircuitId,
+        claimed: CircuitId,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> Result<(), ProofError> {
+        let verifier_data = self.check(expected, claimed)?;
+        check_compatibility(&proof_with_pis, &verifier_data.common)?;
+        verifier_data
+            .verify(proof_with_pis)
+            .map_err(ProofError::Verification)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> Default
+    for CircuitRegistry<F, C, D>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitData;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    fn build_power(exponent: u64) -> (CircuitData<F, C, D>, PartialWitness<F>) {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let y = builder.exp_u64(x, exponent);
+        builder.register_public_input(y);
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::TWO);
+
+        (builder.build::<C>(), pw)
+    }
+
+    #[test]
+    fn test_circuit_registry() -> Result<(), ProofError> {
+        let (cube, pw) = build_power(3);
+        let proof = cube.prove(pw).map_err(ProofError::Proving)?;
+        let (fifth_power, _) = build_power(5);
+
+        // ids are deterministic, and differ between circuits
+        let cube_id = CircuitId::of(&build_power(3).0.verifier_data());
+        let mut registry = CircuitRegistry::new();
+        assert_eq!(registry.register("cube", cube.verifier_data()), cube_id);
+        let fifth_power_id = registry.register("fifth power", fifth_power.verifier_data());
+        assert_ne!(cube_id, fifth_power_id);
+        assert_eq!(cube_id.to_string().parse::<CircuitId>()?, cube_id);
+
+        registry.verify(cube_id, cube_id, proof.clone())?;
+        assert!(matches!(
+            registry.verify(fifth_power_id, cube_id, proof.clone()),
+            Err(ProofError::CircuitMismatch { .. })
+        ));
+        assert!(registry
+            .verify(fifth_power_id, fifth_power_id, proof)
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Gates attributed to the logical sections of a circuit, e.g. the Merkle path of a
+//! signal, to tell which component an optimization should target. Gadgets mark where a
+//! section starts with `builder_section!`, which does nothing unless the circuit is built
+//! within `profile_sections`: each section then gets the gates added until the next
+//! marker. Plonky2 fills arithmetic gates lazily, so a gate started in one section may
+//! also hold operations of the next
+
+use std::cell::RefCell;
+use std::fmt;
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+/// The name of the gates added before the first marker
+pub const UNMARKED: &str = "(unmarked)";
+
+/// Marks the start of a section of the circuit of a reference to a builder, see `sections`
+#[macro_export]
+macro_rules! builder_section {
+    ($builder:expr, $name:expr) => {
+        $crate::sections::mark(&*$builder, $name)
+    };
+}
+
+struct Recording {
+    /// The address of the builder being profiled, so that the markers of circuits built
+    /// meanwhile, e.g. inner circuits, are ignored
+    builder: usize,
+    markers: Vec<(String, usize)>,
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+fn address<F: RichField + Extendable<D>, const D: usize>(builder: &CircuitBuilder<F, D>) -> usize {
+    builder as *const CircuitBuilder<F, D> as usize
+}
+
+/// Starts section `name` at the current gate of `builder`, if it is being profiled
+pub fn mark<F: RichField + Extendable<D>, const D: usize>(
+    builder: &CircuitBuilder<F, D>,
+    name: &str,
+) {
+    RECORDING.with(|recording| {
+        if let Some(recording) = recording.borrow_mut().as_mut() {
+            if recording.builder == address(builder) {
+                recording
+                    .markers
+                    .push((name.to_string(), builder.num_gates()));
+            }
+        }
+    });
+}
+
+/// The gates of each section of a circuit, in the order the sections were first marked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectionProfile {
+    pub sections: Vec<(String, usize)>,
+}
+
+impl SectionProfile {
+    pub fn num_gates(&self) -> usize {
+        self.sections.iter().map(|(_, gates)| gates).sum()
+    }
+
+    pub fn gates_of(&self, name: &str) -> usize {
+        self.sections
+            .iter()
+            .find(|(section, _)| section == name)
+            .map_or(0, |(_, gates)| *gates)
+    }
+}
+
+impl fmt::Display for SectionProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.num_gates().max(1);
+        let width = self
+            .sections
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, gates) in &self.sections {
+            let share = 100.0 * *gates as f64 / total as f64;
+            writeln!(f, "{name:<width$}  {gates:>8} gates  {share:>5.1}%")?;
+        }
+        write!(f, "{:<width$}  {:>8} gates", "total", self.num_gates())
+    }
+}
+
+/// Runs `f` on `builder`, attributing the gates it adds to the sections it marks. Sections
+/// marked several times, e.g. in a loop, add up
+pub fn profile_sections<F: RichField + Extendable<D>, const D: usize, T>(
+    builder: &mut CircuitBuilder<F, D>,
+    f: impl FnOnce(&mut CircuitBuilder<F, D>) -> T,
+) -> (T, SectionProfile) {
+    let start = builder.num_gates();
+    let previous = RECORDING.with(|recording| {
+        recording.replace(Some(Recording {
+            builder: address(builder),
+            markers: vec![(UNMARKED.to_string(), start)],
+        }))
+    });
+    let output = f(builder);
+    let recording = RECORDING.with(|recording| recording.replace(previous));
+    let mut markers = recording.expect("the recording is restored last").markers;
+    markers.push((String::new(), builder.num_gates()));
+
+    let mut profile = SectionProfile::default();
+    for window in markers.windows(2) {
+        let (name, start) = &window[0];
+        let gates = window[1].1 - start;
+        match profile
+            .sections
+            .iter_mut()
+            .find(|(section, _)| section == name)
+        {
+            Some((_, total)) => *total += gates,
+            None if gates > 0 || name != UNMARKED => profile.sections.push((name.clone(), gates)),
+            None => {}
+        }
+    }
+    (output, profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_profile_sections() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(crate::standard_config(false));
+        let ((), profile) = profile_sections(&mut builder, |builder| {
+            let x = builder.add_virtual_target();
+            builder_section!(builder, "hash");
+            let hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(vec![x]);
+            builder_section!(builder, "checks");
+            builder.range_check(hash.elements[0], 32);
+            // markers of another builder are ignored
+            let mut inner = CircuitBuilder::<F, D>::new(crate::standard_config(false));
+            builder_section!(&mut inner, "inner");
+            builder_section!(builder, "hash");
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(vec![x, x]);
+        });
+
+        let names: Vec<&str> = profile
+            .sections
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["hash", "checks"]);
+        // a Poseidon gate per hash
+        assert_eq!(profile.gates_of("hash"), 2);
+        assert!(profile.gates_of("checks") > 0);
+        assert_eq!(profile.num_gates(), builder.num_gates());
+        assert!(profile
+            .to_string()
+            .ends_with(&format!("{} gates", builder.num_gates())));
+
+        // without profiling, markers do nothing
+        builder_section!(&mut builder, "ignored");
+    }
+}
@@ -0,0 +1,238 @@
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::iop::challenger::Challenger;
+use plonky2::plonk::circuit_data::CommonCircuitData;
+use plonky2::plonk::config::{GenericConfig, GenericHashOut, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+/// Values observed or sampled by the challenger, in canonical form
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub label: String,
+    pub values: Vec<u64>,
+}
+
+/// The Fiat-Shamir transcript of a proof, in the order the challenger of plonky2 goes
+/// through it: what is observed, followed by the challenges sampled from it. Recorded
+/// after a proving run and replayed against a proof, it locates the first value on which
+/// two runs or a prover and a verifier disagree
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    fn push(&mut self, label: &str, values: impl IntoIterator<Item = u64>) {
+        self.entries.push(TranscriptEntry {
+            label: label.to_string(),
+            values: values.into_iter().collect(),
+        });
+    }
+
+    fn push_cap<F: RichField, H: Hasher<F>>(&mut self, label: &str, cap: &MerkleCap<F, H>) {
+        let values = cap
+            .0
+            .iter()
+            .flat_map(|hash| hash.to_vec())
+            .map(|x| x.to_canonical_u64());
+        self.push(label, values);
+    }
+
+    fn push_extension<F: RichField + Extendable<D>, const D: usize>(
+        &mut self,
+        label: &str,
+        values: &[F::Extension],
+    ) {
+        let values = values
+            .iter()
+            .flat_map(|x| x.to_basefield_array())
+            .map(|x| x.to_canonical_u64());
+        self.push(label, values);
+    }
+
+    /// Re-derives the transcript of `proof_with_pis` the way the verifier does, from the
+    /// circuit digest and the common data of its circuit
+    pub fn of<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        proof_with_pis: &ProofWithPublicInputs<F, C, D>,
+        circuit_digest: &<C::Hasher as Hasher<F>>::Hash,
+        common: &CommonCircuitData<F, D>,
+    ) -> Self {
+        let proof = &proof_with_pis.proof;
+        let num_challenges = common.config.num_challenges;
+        let public_inputs_hash = C::InnerHasher::hash_no_pad(&proof_with_pis.public_inputs);
+        let mut transcript = Self::default();
+        let mut challenger = Challenger::<F, C::Hasher>::new();
+
+        challenger.observe_hash::<C::Hasher>(*circuit_digest);
+        challenger.observe_hash::<C::InnerHasher>(public_inputs_hash);
+        transcript.push(
+            "circuit digest",
+            circuit_digest.to_vec().iter().map(|x| x.to_canonical_u64()),
+        );
+        transcript.push(
+            "public inputs hash",
+            public_inputs_hash
+                .elements
+                .iter()
+                .map(|x| x.to_canonical_u64()),
+        );
+
+        challenger.observe_cap(&proof.wires_cap);
+        transcript.push_cap("wires cap", &proof.wires_cap);
+        let plonk_betas = challenger.get_n_challenges(num_challenges);
+        let plonk_gammas = challenger.get_n_challenges(num_challenges);
+        transcript.push(
+            "plonk betas",
+            plonk_betas.iter().map(|x| x.to_canonical_u64()),
+        );
+        transcript.push(
+            "plonk gammas",
+            plonk_gammas.iter().map(|x| x.to_canonical_u64()),
+        );
+
+        challenger.observe_cap(&proof.plonk_zs_partial_products_cap);
+        transcript.push_cap(
+            "zs and partial products cap",
+            &proof.plonk_zs_partial_products_cap,
+        );
+        let plonk_alphas = challenger.get_n_challenges(num_challenges);
+        transcript.push(
+            "plonk alphas",
+            plonk_alphas.iter().map(|x| x.to_canonical_u64()),
+        );
+
+        challenger.observe_cap(&proof.quotient_polys_cap);
+        transcript.push_cap("quotient polys cap", &proof.quotient_polys_cap);
+        let plonk_zeta = challenger.get_extension_challenge::<D>();
+        transcript.push_extension::<F, D>("plonk zeta", &[plonk_zeta]);
+
+        let openings = &proof.openings;
+        challenger.observe_openings(&openings.to_fri_openings());
+        for (label, values) in [
+            ("constants openings", &openings.constants),
+            ("plonk sigmas openings", &openings.plonk_sigmas),
+            ("wires openings", &openings.wires),
+            ("plonk zs openings", &openings.plonk_zs),
+            ("plonk zs next openings", &openings.plonk_zs_next),
+            ("partial products openings", &openings.partial_products),
+            ("quotient polys openings", &openings.quotient_polys),
+        ] {
+            transcript.push_extension::<F, D>(label, values);
+        }
+
+        let fri_proof = &proof.opening_proof;
+        let fri_challenges = challenger.fri_challenges::<C, D>(
+            &fri_proof.commit_phase_merkle_caps,
+            &fri_proof.final_poly,
+            fri_proof.pow_witness,
+            common.degree_bits(),
+            &common.config.fri_config,
+        );
+        transcript.push_extension::<F, D>("fri alpha", &[fri_challenges.fri_alpha]);
+        for (i, cap) in fri_proof.commit_phase_merkle_caps.iter().enumerate() {
+            transcript.push_cap(&format!("fri commit phase cap {i}"), cap);
+        }
+        transcript.push_extension::<F, D>("fri betas", &fri_challenges.fri_betas);
+        transcript.push_extension::<F, D>("fri final poly", &fri_proof.final_poly.coeffs);
+        transcript.push(
+            "fri proof of work",
+            [fri_proof.pow_witness.to_canonical_u64()],
+        );
+        transcript.push(
+            "fri pow response",
+            [fri_challenges.fri_pow_response.to_canonical_u64()],
+        );
+        transcript.push(
+            "fri query indices",
+            fri_challenges.fri_query_indices.iter().map(|i| *i as u64),
+        );
+
+        transcript
+    }
+
+    /// Checks that `proof_with_pis` re-derives this transcript, reporting the first entry
+    /// and element on which they differ
+    pub fn replay<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof_with_pis: &ProofWithPublicInputs<F, C, D>,
+        circuit_digest: &<C::Hasher as Hasher<F>>::Hash,
+        common: &CommonCircuitData<F, D>,
+    ) -> Result<(), ProofError> {
+        let replayed = Self::of(proof_with_pis, circuit_digest, common);
+        let len = self.entries.len().max(replayed.entries.len());
+        for i in 0..len {
+            let (recorded, replayed) = match (self.entries.get(i), replayed.entries.get(i)) {
+                (Some(recorded), Some(replayed)) => (recorded, replayed),
+                (Some(entry), None) | (None, Some(entry)) => {
+                    return Err(ProofError::TranscriptMismatch {
+                        label: entry.label.clone(),
+                        index: 0,
+                    })
+                }
+                (None, None) => unreachable!(),
+            };
+            if recorded == replayed {
+                continue;
+            }
+
+            let index = recorded
+                .values
+                .iter()
+                .zip(&replayed.values)
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| recorded.values.len().min(replayed.values.len()));
+            return Err(ProofError::TranscriptMismatch {
+                label: recorded.label.clone(),
+                index,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_transcript_replay() -> Result<(), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let x = builder.add_virtual_target();
+        let x_cubed = builder.exp_u64(x, 3);
+        builder.register_public_input(x_cubed);
+        let data = builder.build::<C>();
+
+        let prove = |value: u64| {
+            let mut pw = PartialWitness::new();
+            pw.set_target(x, F::from_canonical_u64(value));
+            data.prove(pw).map_err(ProofError::Proving)
+        };
+        let proof = prove(2)?;
+        let digest = &data.verifier_only.circuit_digest;
+        let transcript = Transcript::of(&proof, digest, &data.common);
+        transcript.replay(&proof, digest, &data.common)?;
+
+        // another statement diverges from its public inputs on
+        let other = prove(3)?;
+        assert!(matches!(
+            transcript.replay(&other, digest, &data.common),
+            Err(ProofError::TranscriptMismatch { label, .. }) if label == "public inputs hash"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, MerkleCapTarget, RichField};
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, Witness, WitnessWrite};
+use plonky2::plonk::config::Hasher;
+use proof_error::ProofError;
+
+/// Targets paired with the name the errors of `WitnessBuilder` refer to them by
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Named<T> {
+    pub name: String,
+    pub targets: T,
+}
+
+impl<T> Named<T> {
+    pub fn new(name: impl Into<String>, targets: T) -> Self {
+        Self {
+            name: name.into(),
+            targets,
+        }
+    }
+}
+
+/// A partial witness whose setters check values against the shape of their targets, so
+/// that a path of the wrong height or a value set twice fails before proving rather than
+/// with an unsatisfied witness or a panic inside plonky2
+#[derive(Clone, Debug)]
+pub struct WitnessBuilder<F: Field> {
+    partial_witness: PartialWitness<F>,
+}
+
+impl<F: RichField> Default for WitnessBuilder<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: RichField> WitnessBuilder<F> {
+    pub fn new() -> Self {
+        Self {
+            partial_witness: PartialWitness::new(),
+        }
+    }
+
+    /// The values set so far
+    pub fn partial_witness(&self) -> &PartialWitness<F> {
+        &self.partial_witness
+    }
+
+    pub fn build(self) -> PartialWitness<F> {
+        self.partial_witness
+    }
+
+    fn set_target(&mut self, name: &str, target: Target, value: F) -> Result<(), ProofError> {
+        match self.partial_witness.try_get_target(target) {
+            Some(previous) if previous != value => Err(ProofError::WitnessConflict {
+                name: name.to_string(),
+            }),
+            _ => {
+                self.partial_witness.set_target(target, value);
+                Ok(())
+            }
+        }
+    }
+
+    fn set_targets(
+        &mut self,
+        name: &str,
+        targets: &[Target],
+        values: &[F],
+    ) -> Result<(), ProofError> {
+        Self::check_len(name, targets.len(), values.len())?;
+        for (target, value) in targets.iter().zip(values) {
+            self.set_target(name, *target, *value)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_len(name: &str, expected: usize, actual: usize) -> Result<(), ProofError> {
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(ProofError::WitnessShape {
+                name: name.to_string(),
+                expected,
+                actual,
+            })
+        }
+    }
+
+    pub fn set(&mut self, handle: &Named<Target>, value: F) -> Result<(), ProofError> {
+        self.set_target(&handle.name, handle.targets, value)
+    }
+
+    pub fn set_bool(&mut self, handle: &Named<BoolTarget>, value: bool) -> Result<(), ProofError> {
+        self.set_target(&handle.name, handle.targets.target, F::from_bool(value))
+    }
+
+    /// Sets a value which should fit in `num_bits` bits, e.g. an index into `2^num_bits`
+    /// leaves. From 64 bits on, the value should be below the field order instead
+    pub fn set_bounded(
+        &mut self,
+        handle: &Named<Target>,
+        value: u64,
+        num_bits: usize,
+    ) -> Result<(), ProofError> {
+        let in_range = if num_bits < 64 {
+            value >> num_bits == 0
+        } else {
+            value < F::ORDER
+        };
+        if !in_range {
+            return Err(ProofError::WitnessRange {
+                name: handle.name.clone(),
+                value,
+                num_bits,
+            });
+        }
+        self.set(handle, F::from_canonical_u64(value))
+    }
+
+    /// Sets each of the targets, e.g. the 4 elements of a digest
+    pub fn set_elements<T: AsRef<[Target]>>(
+        &mut self,
+        handle: &Named<T>,
+        values: &[F],
+    ) -> Result<(), ProofError> {
+        self.set_targets(&handle.name, handle.targets.as_ref(), values)
+    }
+
+    pub fn set_hash(
+        &mut self,
+        handle: &Named<HashOutTarget>,
+        value: HashOut<F>,
+    ) -> Result<(), ProofError> {
+        self.set_targets(&handle.name, &handle.targets.elements, &value.elements)
+    }
+
+    pub fn set_cap<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        handle: &Named<MerkleCapTarget>,
+        cap: &MerkleCap<F, H>,
+    ) -> Result<(), ProofError> {
+        Self::check_len(&handle.name, 4 * handle.targets.0.len(), 4 * cap.0.len())?;
+        for (target, hash) in handle.targets.0.iter().zip(&cap.0) {
+            self.set_targets(&handle.name, &target.elements, &hash.elements)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the siblings of a Merkle path, which should be as long as the path checked in
+    /// the circuit
+    pub fn set_merkle_proof<H: Hasher<F, Hash = HashOut<F>>>(
+        &mut self,
+        handle: &Named<MerkleProofTarget>,
+        proof: &MerkleProof<F, H>,
+    ) -> Result<(), ProofError> {
+        let siblings = &handle.targets.siblings;
+        Self::check_len(&handle.name, 4 * siblings.len(), 4 * proof.siblings.len())?;
+        for (target, sibling) in siblings.iter().zip(&proof.siblings) {
+            self.set_targets(&handle.name, &target.elements, &sibling.elements)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field64;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use super::*;
+    use crate::standard_config;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_witness_builder() -> Result<(), ProofError> {
+        let mut builder = CircuitBuilder::<F, D>::new(standard_config(false));
+        let index = Named::new("index", builder.add_virtual_target());
+        let digest = Named::new("digest", builder.add_virtual_hash());
+        let elements = Named::new("elements", builder.add_virtual_targets(4));
+        let sum = builder.add_many(&elements.targets);
+        builder.connect(sum, digest.targets.elements[0]);
+        builder.range_check(index.targets, 3);
+        builder.register_public_input(index.targets);
+
+        let mut witness = WitnessBuilder::new();
+        assert!(matches!(
+            witness.set_elements(&elements, &[F::ONE; 3]),
+            Err(ProofError::WitnessShape {
+                expected: 4,
+                actual: 3,
+                ..
+            })
+        ));
+        witness.set_elements(&elements, &[F::ONE; 4])?;
+        assert!(matches!(
+            witness.set_bounded(&index, 8, 3),
+            Err(ProofError::WitnessRange { value: 8, .. })
+        ));
+        witness.set_bounded(&index, 7, 3)?;
+        // a value reduced modulo the order would be another one
+        assert!(matches!(
+            WitnessBuilder::<F>::new().set_bounded(&index, F::ORDER, 64),
+            Err(ProofError::WitnessRange { num_bits: 64, .. })
+        ));
+        assert!(matches!(
+            witness.set(&index, F::ONE),
+            Err(ProofError::WitnessConflict { .. })
+        ));
+        witness.set(&index, F::from_canonical_u64(7))?;
+        witness.set_hash(
+            &digest,
+            HashOut {
+                elements: [F::from_canonical_u64(4), F::ZERO, F::ZERO, F::ZERO],
+            },
+        )?;
+
+        let data = builder.build::<C>();
+        let proof = data.prove(witness.build()).map_err(ProofError::Proving)?;
+        assert_eq!(proof.public_inputs, [F::from_canonical_u64(7)]);
+        data.verify(proof).map_err(ProofError::Verification)
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use plonky2::field::types::{Field, Sample};
+use prover_utils::registry::CircuitId;
+use relayer::envelope::SignalEnvelope;
+use relayer::epoch::EpochManager;
+use relayer::pipeline::{EpochMetadata, Relayer};
+use semaphore::access_set::AccessSet;
+use semaphore::signal::{Digest, F};
+
+#[derive(Parser)]
+#[command(
+    about = "Feeds a relayer with the signals of simulated members and reports its latency and throughput"
+)]
+struct Cli {
+    /// The number of members, a power of two
+    #[arg(long, default_value_t = 16)]
+    members: usize,
+    /// The number of signals sent, each member signaling once per epoch in turn
+    #[arg(long, default_value_t = 64)]
+    signals: usize,
+    /// The signals sent per second
+    #[arg(long, default_value_t = 4.0)]
+    rate: f64,
+    /// The threads proving signals, to keep up with the rate
+    #[arg(long, default_value_t = 4)]
+    threads: usize,
+    /// Aggregate the signals of an epoch in batches of this size before it closes
+    #[arg(long)]
+    batch_size: Option<usize>,
+}
+
+/// Signal `index`, proven by a member, with the time it was due to be sent
+struct Sent {
+    index: usize,
+    envelope: SignalEnvelope,
+    due: Instant,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if !cli.members.is_power_of_two() {
+        bail!("the number of members should be a power of two");
+    }
+    if cli.signals == 0 || cli.rate <= 0.0 || cli.threads == 0 {
+        bail!("the number of signals, the rate and the number of threads should be positive");
+    }
+
+    let private_keys: Vec<Digest> = (0..cli.members).map(|_| F::rand_array()).collect();
+    let access_set = Arc::new(AccessSet::from_private_keys(&private_keys));
+    let circuit_id = CircuitId::of(&access_set.signal_verifier_data());
+    let output = env::temp_dir().join(format!("loadgen-{}", std::process::id()));
+    let relayer = Relayer::new(AccessSet(access_set.0.clone()), output.clone())?;
+    let mut manager = EpochManager::new(relayer, None);
+    if let Some(batch_size) = cli.batch_size {
+        manager = manager.with_batch_size(batch_size);
+    }
+
+    // signal `i` is due `i / rate` seconds after the start, sent by member `i % members`
+    // in epoch `i / members`, on the topic of its epoch
+    let start = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..cli.threads)
+        .map(|worker| {
+            let (access_set, private_keys, sender) =
+                (access_set.clone(), private_keys.clone(), sender.clone());
+            let (members, signals, rate, threads) =
+                (cli.members, cli.signals, cli.rate, cli.threads);
+            thread::spawn(move || -> Result<()> {
+                for i in (worker..signals).step_by(threads) {
+                    let due = start + Duration::from_secs_f64(i as f64 / rate);
+                    thread::sleep(due.saturating_duration_since(Instant::now()));
+                    let (member, epoch) = (i % members, (i / members) as u64);
+                    let topic = [F::from_canonical_u64(epoch), F::ZERO, F::ZERO, F::ZERO];
+                    let (signal, _) =
+                        access_set.make_signal(private_keys[member], topic, member)?;
+                    let envelope = SignalEnvelope {
+                        circuit_id,
+                        epoch,
+                        topic,
+                        signal,
+                    };
+                    if sender
+                        .send(Sent {
+                            index: i,
+                            envelope,
+                            due,
+                        })
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut due: HashMap<Digest, Instant> = HashMap::new();
+    let mut latencies = Vec::new();
+    let mut aggregation_times = Vec::new();
+    let mut record = |published: Vec<EpochMetadata>, due: &HashMap<Digest, Instant>| {
+        let now = Instant::now();
+        for metadata in published {
+            latencies.extend(
+                metadata
+                    .signals
+                    .iter()
+                    .map(|(_, nullifier)| now - due[nullifier]),
+            );
+            aggregation_times.push(metadata.aggregation_time);
+        }
+    };
+    // the workers finish their signals out of order, and the relayer rejects the late
+    // signals of a closed epoch, so the signals are fed in the order they were due
+    let mut proven = BTreeMap::new();
+    let mut next = 0;
+    for sent in receiver {
+        proven.insert(sent.index, sent);
+        while let Some(Sent {
+            envelope,
+            due: sent,
+            ..
+        }) = proven.remove(&next)
+        {
+            due.insert(envelope.signal.nullifier, sent);
+            next += 1;
+            let mut published = manager.accept(envelope, 0)?;
+            published.extend(manager.tick(0)?);
+            record(published, &due);
+        }
+    }
+    let published = manager.flush()?;
+    record(published, &due);
+    let elapsed = start.elapsed();
+    for worker in workers {
+        worker.join().expect("a worker panicked")?;
+    }
+    fs::remove_dir_all(output)?;
+
+    latencies.sort();
+    let aggregation_time: Duration = aggregation_times.iter().sum();
+    println!(
+        "{} signals of {} members at {} per second, in {:.2?}",
+        cli.signals, cli.members, cli.rate, elapsed
+    );
+    for p in [50, 90, 99] {
+        println!(
+            "p{p} latency: {:.2?}",
+            latencies[(latencies.len() * p / 100).min(latencies.len() - 1)]
+        );
+    }
+    println!(
+        "{} aggregates of {:.1} signals on average, {:.2} signals per second of aggregation",
+        aggregation_times.len(),
+        cli.signals as f64 / aggregation_times.len() as f64,
+        cli.signals as f64 / aggregation_time.as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use proof_error::ProofError;
+use prover_utils::registry::CircuitId;
+use semaphore::signal::{Digest, Signal};
+use serde::{Deserialize, Serialize};
+
+/// A signal as handed to the relayer: the epoch it is signaled in, its topic and the circuit
+/// it was proven with, which the signal itself doesn't carry
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignalEnvelope {
+    pub circuit_id: CircuitId,
+    pub epoch: u64,
+    pub topic: Digest,
+    pub signal: Signal,
+}
+
+impl SignalEnvelope {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+}
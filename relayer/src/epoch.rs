@@ -0,0 +1,207 @@
+use anyhow::{bail, Result};
+
+use crate::envelope::SignalEnvelope;
+use crate::pipeline::{EpochMetadata, Relayer};
+
+/// Epochs of `duration` seconds from `start`, in seconds since the Unix epoch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpochSchedule {
+    pub start: u64,
+    pub duration: u64,
+}
+
+impl EpochSchedule {
+    /// The epoch open at `timestamp`, or `None` before the first one
+    pub fn epoch_of(&self, timestamp: u64) -> Option<u64> {
+        timestamp
+            .checked_sub(self.start)
+            .map(|elapsed| elapsed / self.duration)
+    }
+
+    /// The timestamp at which `epoch` closes
+    pub fn end_of(&self, epoch: u64) -> u64 {
+        self.start + (epoch + 1) * self.duration
+    }
+}
+
+/// Called with the metadata of each aggregate published by an `EpochManager`
+pub type PublishHook = Box<dyn FnMut(&EpochMetadata) -> Result<()>>;
+
+/// Schedules the aggregations of a relayer: an epoch is published when it closes, and in
+/// batches before that if it gets `batch_size` signals. Without a schedule, the epoch of a
+/// signal is the one of its envelope and closes once a signal of a later epoch is accepted,
+/// like `Relayer::publish_ready`
+pub struct EpochManager {
+    relayer: Relayer,
+    schedule: Option<EpochSchedule>,
+    batch_size: Option<usize>,
+    hooks: Vec<PublishHook>,
+}
+
+impl EpochManager {
+    pub fn new(relayer: Relayer, schedule: Option<EpochSchedule>) -> Self {
+        Self {
+            relayer,
+            schedule,
+            batch_size: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Publishes an epoch in batches of `batch_size` signals, bounding the size of the
+    /// aggregations and the latency of busy epochs
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Calls `hook` with each aggregate once it's written, e.g. to post it on chain
+    pub fn on_publish(&mut self, hook: impl FnMut(&EpochMetadata) -> Result<()> + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn relayer(&self) -> &Relayer {
+        &self.relayer
+    }
+
+    /// Accepts a signal received at `timestamp`, publishing a batch of its epoch if it's
+    /// full. With a schedule, the envelope should be for the epoch open at `timestamp`
+    pub fn accept(
+        &mut self,
+        envelope: SignalEnvelope,
+        timestamp: u64,
+    ) -> Result<Vec<EpochMetadata>> {
+        let epoch = envelope.epoch;
+        if let Some(schedule) = &self.schedule {
+            match schedule.epoch_of(timestamp) {
+                Some(open) if open == epoch => {}
+                Some(open) => bail!("the signal is for epoch {epoch}, epoch {open} is open"),
+                None => bail!("no epoch is open before {}", schedule.start),
+            }
+        }
+        self.relayer.accept(envelope)?;
+
+        let mut published = Vec::new();
+        if self
+            .batch_size
+            .map_or(false, |size| self.relayer.num_pending(epoch) >= size)
+        {
+            let metadata = self.relayer.publish_batch(epoch)?;
+            published.push(self.run_hooks(metadata)?);
+        }
+
+        Ok(published)
+    }
+
+    /// Publishes the epochs closed at `now`
+    pub fn tick(&mut self, now: u64) -> Result<Vec<EpochMetadata>> {
+        let pending = self.relayer.pending_epochs();
+        let closed: Vec<u64> = match &self.schedule {
+            Some(schedule) => pending
+                .into_iter()
+                .filter(|epoch| schedule.end_of(*epoch) <= now)
+                .collect(),
+            None => pending[..pending.len().saturating_sub(1)].to_vec(),
+        };
+        self.close(closed)
+    }
+
+    /// Publishes every epoch with pending signals, e.g. before shutting down
+    pub fn flush(&mut self) -> Result<Vec<EpochMetadata>> {
+        let pending = self.relayer.pending_epochs();
+        self.close(pending)
+    }
+
+    fn close(&mut self, epochs: Vec<u64>) -> Result<Vec<EpochMetadata>> {
+        let mut published = Vec::new();
+        for epoch in epochs {
+            if let Some(metadata) = self.relayer.close(epoch)? {
+                published.push(self.run_hooks(metadata)?);
+            }
+        }
+
+        Ok(published)
+    }
+
+    fn run_hooks(&mut self, metadata: EpochMetadata) -> Result<EpochMetadata> {
+        for hook in &mut self.hooks {
+            hook(&metadata)?;
+        }
+
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::rc::Rc;
+
+    use plonky2::field::types::Sample;
+    use prover_utils::registry::CircuitId;
+    use semaphore::access_set::AccessSet;
+    use semaphore::fixtures;
+    use semaphore::signal::F;
+
+    use super::*;
+
+    #[test]
+    fn test_epoch_manager() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let topic = F::rand_array();
+        let envelope = |epoch, i: usize| -> Result<SignalEnvelope> {
+            let (signal, verifier_data) =
+                access_set.make_signal(fixtures::private_key(i), topic, i)?;
+            Ok(SignalEnvelope {
+                circuit_id: CircuitId::of(&verifier_data),
+                epoch,
+                topic,
+                signal,
+            })
+        };
+
+        let schedule = EpochSchedule {
+            start: 1000,
+            duration: 60,
+        };
+        assert_eq!(schedule.epoch_of(999), None);
+        assert_eq!(schedule.epoch_of(1059), Some(0));
+        assert_eq!(schedule.epoch_of(1060), Some(1));
+        assert_eq!(schedule.end_of(1), 1120);
+
+        let output = env::temp_dir().join(format!("relayer-epoch-{}", std::process::id()));
+        let relayer = Relayer::new(AccessSet(access_set.0.clone()), output.clone())?;
+        let mut manager = EpochManager::new(relayer, Some(schedule)).with_batch_size(2);
+        let published = Rc::new(RefCell::new(Vec::new()));
+        let hook_published = published.clone();
+        manager.on_publish(move |metadata| {
+            hook_published
+                .borrow_mut()
+                .push((metadata.epoch, metadata.batch));
+            Ok(())
+        });
+
+        // the envelope of a signal received in epoch 0 should be for epoch 0
+        assert!(manager.accept(envelope(1, 0)?, 1010).is_err());
+        assert!(manager.accept(envelope(0, 0)?, 1010)?.is_empty());
+        // the second signal fills a batch
+        assert_eq!(manager.accept(envelope(0, 1)?, 1020)?.len(), 1);
+        manager.accept(envelope(0, 2)?, 1030)?;
+        assert!(manager.tick(1059)?.is_empty());
+
+        let closed = manager.tick(1060)?;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].history_indices, [2]);
+        assert!(output.join("epoch-0-batch-1.proof").exists());
+        assert_eq!(*published.borrow(), [(0, 0), (0, 1)]);
+        assert_eq!(manager.relayer().history().len(), 3);
+
+        // a late signal of epoch 0, e.g. received by a lagging source, once it's closed
+        assert!(manager.accept(envelope(0, 3)?, 1059).is_err());
+
+        fs::remove_dir_all(output)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,6 @@
+pub mod envelope;
+pub mod epoch;
+pub mod pipeline;
+pub mod source;
+pub mod storage;
+pub mod transport;
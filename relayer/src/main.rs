@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use plonky2::hash::merkle_tree::MerkleTree;
+use relayer::epoch::{EpochManager, EpochSchedule};
+use relayer::pipeline::{EpochMetadata, Relayer};
+#[cfg(feature = "redis")]
+use relayer::source::RedisSource;
+use relayer::source::{DirectorySource, Received, SignalSource};
+#[cfg(feature = "sled")]
+use relayer::storage::SledStorage;
+use relayer::storage::{MemoryStorage, Storage};
+use semaphore::access_set::AccessSet;
+use semaphore::signal::Digest;
+
+#[derive(Parser)]
+#[command(
+    about = "Verifies semaphore signals, aggregates them per epoch and publishes the aggregates"
+)]
+struct Cli {
+    /// JSON array of the public keys of the access set, each made of four field elements
+    #[arg(long)]
+    access_set: PathBuf,
+    /// Directory of `.signal` files, renamed to `.done` once consumed
+    #[arg(long)]
+    input: Option<PathBuf>,
+    /// Redis server to pop signals from instead of a directory
+    #[cfg(feature = "redis")]
+    #[arg(long, conflicts_with = "input")]
+    redis_url: Option<String>,
+    #[cfg(feature = "redis")]
+    #[arg(long, default_value = "signals")]
+    redis_list: String,
+    /// Where the aggregate of each epoch and the history of accepted signals are written
+    #[arg(long)]
+    output: PathBuf,
+    /// sled database keeping the nullifiers, aggregates and checkpoints across restarts
+    #[cfg(feature = "sled")]
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Publish every pending epoch and exit once the input is drained
+    #[arg(long)]
+    once: bool,
+    #[arg(long, default_value_t = 1000)]
+    poll_interval_ms: u64,
+    /// Derive the epoch of each signal from the time it is received, in epochs of this many
+    /// seconds, instead of taking it from its envelope
+    #[arg(long)]
+    epoch_duration: Option<u64>,
+    /// The Unix timestamp at which the first epoch opens
+    #[arg(long, default_value_t = 0, requires = "epoch_duration")]
+    epoch_start: u64,
+    /// Aggregate the signals of an epoch in batches of this size before it closes
+    #[arg(long)]
+    batch_size: Option<usize>,
+    /// Chain each aggregate to the previous one and write its link proof next to it
+    #[arg(long)]
+    chain: bool,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut source = signal_source(&cli)?;
+    let mut relayer = Relayer::with_storage(
+        load_access_set(&cli.access_set)?,
+        cli.output.clone(),
+        storage(&cli)?,
+    )?;
+    if cli.chain {
+        relayer = relayer.with_chain();
+    }
+    println!("accepting signals of circuit {}", relayer.signal_circuit());
+    let schedule = cli.epoch_duration.map(|duration| EpochSchedule {
+        start: cli.epoch_start,
+        duration,
+    });
+    let mut manager = EpochManager::new(relayer, schedule);
+    if let Some(batch_size) = cli.batch_size {
+        manager = manager.with_batch_size(batch_size);
+    }
+    manager.on_publish(|metadata| {
+        report(metadata);
+        Ok(())
+    });
+
+    loop {
+        let received = source.poll()?;
+        let drained = received.is_empty();
+        for Received { origin, envelope } in received {
+            match envelope
+                .map_err(anyhow::Error::from)
+                .and_then(|envelope| manager.accept(envelope, now()?))
+            {
+                Ok(_) => println!("accepted {origin}"),
+                Err(e) => eprintln!("rejected {origin}: {e}"),
+            }
+        }
+        manager.tick(now()?)?;
+
+        if drained {
+            if cli.once {
+                manager.flush()?;
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(cli.poll_interval_ms));
+        }
+    }
+}
+
+fn signal_source(cli: &Cli) -> Result<Box<dyn SignalSource>> {
+    #[cfg(feature = "redis")]
+    if let Some(url) = &cli.redis_url {
+        return Ok(Box::new(RedisSource::new(url, cli.redis_list.clone())?));
+    }
+
+    match &cli.input {
+        Some(dir) => Ok(Box::new(DirectorySource::new(dir.clone()))),
+        None => bail!("no input to read signals from, see --help"),
+    }
+}
+
+#[cfg_attr(not(feature = "sled"), allow(unused_variables))]
+fn storage(cli: &Cli) -> Result<Box<dyn Storage>> {
+    #[cfg(feature = "sled")]
+    if let Some(path) = &cli.db {
+        return Ok(Box::new(SledStorage::open(path)?));
+    }
+
+    Ok(Box::new(MemoryStorage::new()))
+}
+
+fn load_access_set(path: &Path) -> Result<AccessSet> {
+    let public_keys: Vec<Digest> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if !public_keys.len().is_power_of_two() {
+        bail!(
+            "the access set should have a power of two number of public keys, not {}",
+            public_keys.len()
+        );
+    }
+
+    Ok(AccessSet(MerkleTree::new(
+        public_keys.iter().map(|pk| pk.to_vec()).collect(),
+        0,
+    )))
+}
+
+/// The current time in seconds since the Unix epoch
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+fn report(metadata: &EpochMetadata) {
+    println!(
+        "published batch {} of epoch {} with {} signals, aggregated in {:.2?}",
+        metadata.batch,
+        metadata.epoch,
+        metadata.signals.len(),
+        metadata.aggregation_time
+    );
+    if let Some(link) = &metadata.chain {
+        println!(
+            "  chained after {:?}, digest {:?}",
+            link.previous, link.digest
+        );
+    }
+}
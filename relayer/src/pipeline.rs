@@ -0,0 +1,660 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use plonky2::hash::hash_types::HashOut;
+use prover_utils::registry::{CircuitId, CircuitRegistry};
+use semaphore::access_set::AccessSet;
+use semaphore::chain::{self, ChainLink};
+use semaphore::layout::PublicInputLayout;
+use semaphore::mmr::Mmr;
+use semaphore::recursion::{Aggregation, AggregationOptions, Aggregator};
+use semaphore::signal::{Digest, Signal, C, F};
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::SignalEnvelope;
+use crate::storage::{Checkpoint, MemoryStorage, Storage, StoredAggregate};
+
+/// The accepted nullifiers with their epochs, from which the history is rebuilt on restart
+/// when the storage of the relayer is empty
+const HISTORY_FILE: &str = "history.json";
+
+/// The signals accepted but not aggregated yet, appended to a file per epoch as they are
+/// accepted and queued again on restart
+const PENDING_DIR: &str = "pending";
+
+/// What is written next to the aggregate proof of an epoch
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EpochMetadata {
+    pub epoch: u64,
+    /// The index of the aggregate among those of the epoch, which is aggregated in batches
+    /// when `Relayer::publish_batch` publishes it before it closes
+    #[serde(default)]
+    pub batch: usize,
+    /// The topic and nullifier of each signal, in the order of the public inputs
+    pub signals: Vec<(Digest, Digest)>,
+    /// The leaf of each signal in the history of accepted signals
+    pub history_indices: Vec<usize>,
+    /// The root of the history once the signals of the epoch were added
+    pub history_root: HashOut<F>,
+    pub aggregation_time: Duration,
+    /// The link of the aggregate to the previous one, if the aggregates are chained
+    #[serde(default)]
+    pub chain: Option<ChainLink>,
+}
+
+/// Verifies signals, rejects reused nullifiers and aggregates the signals of each epoch.
+/// Epochs are expected to increase: once an epoch is closed, its late signals are rejected.
+/// The nullifiers, aggregates and checkpoints are committed to a `Storage` as each aggregate
+/// is published, from which the relayer resumes on restart. The signals waiting to be
+/// aggregated are appended to the output as they are accepted, so that they survive it too
+pub struct Relayer {
+    access_set: AccessSet,
+    registry: CircuitRegistry<F, C, 2>,
+    signal_circuit: CircuitId,
+    /// Builds the aggregation circuit of each number of signals once
+    aggregator: Aggregator,
+    /// Whether each aggregate follows the previous one, see `with_chain`
+    chained: bool,
+    output: PathBuf,
+    accepted: Vec<(u64, Digest)>,
+    history: Mmr,
+    nullifiers: HashSet<Digest>,
+    pending: BTreeMap<u64, Vec<(Digest, Signal)>>,
+    checkpoint: Checkpoint,
+    storage: Box<dyn Storage>,
+}
+
+impl Relayer {
+    /// Creates a relayer writing to `output`, resuming from the history found there and
+    /// keeping its state in memory
+    pub fn new(access_set: AccessSet, output: PathBuf) -> Result<Self> {
+        Self::with_storage(access_set, output, Box::new(MemoryStorage::new()))
+    }
+
+    /// Like `new`, resuming from `storage`, or from the history in `output` if `storage`
+    /// is empty. The pending signals are queued again from `output`
+    pub fn with_storage(
+        access_set: AccessSet,
+        output: PathBuf,
+        mut storage: Box<dyn Storage>,
+    ) -> Result<Self> {
+        fs::create_dir_all(output.join(PENDING_DIR))?;
+        let (accepted, checkpoint) = match storage.checkpoint()? {
+            Some(checkpoint) => (storage.nullifiers()?, checkpoint),
+            None => {
+                let accepted: Vec<(u64, Digest)> =
+                    match fs::read_to_string(output.join(HISTORY_FILE)) {
+                        Ok(contents) => serde_json::from_str(&contents)?,
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                        Err(e) => return Err(e.into()),
+                    };
+                // the history doesn't record batches, so the epoch of the last signal is
+                // closed
+                let checkpoint = Checkpoint {
+                    last_closed: accepted.last().map(|&(epoch, _)| epoch),
+                    ..Default::default()
+                };
+                if !accepted.is_empty() {
+                    storage.commit(&accepted, None, &checkpoint)?;
+                }
+                (accepted, checkpoint)
+            }
+        };
+
+        let mut history = Mmr::new();
+        for &(epoch, nullifier) in &accepted {
+            history.push(epoch, nullifier);
+        }
+
+        let mut nullifiers: HashSet<Digest> =
+            accepted.iter().map(|&(_, nullifier)| nullifier).collect();
+        let mut pending = read_pending(&output.join(PENDING_DIR))?;
+        // signals aggregated before their pending file was removed are in the history already
+        for signals in pending.values_mut() {
+            signals.retain(|(_, signal)| !nullifiers.contains(&signal.nullifier));
+        }
+        pending.retain(|_, signals| !signals.is_empty());
+        nullifiers.extend(
+            pending
+                .values()
+                .flatten()
+                .map(|(_, signal)| signal.nullifier),
+        );
+
+        let mut registry = CircuitRegistry::new();
+        let signal_circuit = registry.register("signal", access_set.signal_verifier_data());
+
+        Ok(Self {
+            access_set,
+            registry,
+            signal_circuit,
+            aggregator: Aggregator::default(),
+            chained: false,
+            output,
+            nullifiers,
+            accepted,
+            history,
+            pending,
+            checkpoint,
+            storage,
+        })
+    }
+
+    /// Chains the aggregates, each one taking the digest of the last one published as a
+    /// public input, see `semaphore::chain`, from the chain head of the checkpoint on
+    /// restart. The link proof of each aggregate is written next to it
+    pub fn with_chain(mut self) -> Self {
+        let options = AggregationOptions {
+            chain: true,
+            ..Default::default()
+        };
+        let chain_head = self.checkpoint.chain_head.unwrap_or(chain::GENESIS);
+        self.aggregator = Aggregator::new(options).with_chain_head(chain_head);
+        self.chained = true;
+        self
+    }
+
+    /// The digest of the last aggregate published, if the aggregates are chained
+    pub fn chain_head(&self) -> Option<Digest> {
+        self.checkpoint.chain_head
+    }
+
+    pub fn history(&self) -> &Mmr {
+        &self.history
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// The id of the circuit the signals of the access set are proven with
+    pub fn signal_circuit(&self) -> CircuitId {
+        self.signal_circuit
+    }
+
+    /// The epochs with signals waiting to be aggregated, in increasing order
+    pub fn pending_epochs(&self) -> Vec<u64> {
+        self.pending.keys().copied().collect()
+    }
+
+    /// The number of signals of `epoch` waiting to be aggregated
+    pub fn num_pending(&self, epoch: u64) -> usize {
+        self.pending.get(&epoch).map_or(0, Vec::len)
+    }
+
+    /// Verifies a signal and queues it for the aggregate of its epoch, writing it to the
+    /// pending signals of the output before returning
+    pub fn accept(&mut self, envelope: SignalEnvelope) -> Result<()> {
+        let SignalEnvelope {
+            circuit_id,
+            epoch,
+            topic,
+            signal,
+        } = envelope;
+        if let Some(last) = self.checkpoint.last_closed.filter(|last| epoch <= *last) {
+            bail!("epoch {epoch} is late, epoch {last} has already been published");
+        }
+        if self.nullifiers.contains(&signal.nullifier) {
+            bail!("nullifier {:?} has already been signaled", signal.nullifier);
+        }
+
+        let verifier_data = self.registry.check(self.signal_circuit, circuit_id)?;
+        self.access_set
+            .verify_signal(topic, signal.clone(), verifier_data)?;
+        // the signal isn't accepted unless it would be queued again on restart
+        let signal = (topic, signal);
+        self.append_pending(epoch, &signal)?;
+        self.nullifiers.insert(signal.1.nullifier);
+        self.pending.entry(epoch).or_default().push(signal);
+
+        Ok(())
+    }
+
+    fn pending_path(&self, epoch: u64) -> PathBuf {
+        self.output
+            .join(PENDING_DIR)
+            .join(format!("epoch-{epoch}.bin"))
+    }
+
+    /// Appends a signal to the pending file of `epoch`, cutting off what was written of it
+    /// if that fails, so that the signals appended later can still be read
+    fn append_pending(&self, epoch: u64, signal: &(Digest, Signal)) -> Result<()> {
+        let bytes = bincode::serialize(signal)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.pending_path(epoch))?;
+        let len = file.metadata()?.len();
+        if let Err(e) = file.write_all(&bytes).and_then(|()| file.sync_data()) {
+            file.set_len(len)?;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Drops the pending signals of `epoch` once aggregated
+    fn remove_pending(&mut self, epoch: u64) -> Result<()> {
+        self.pending.remove(&epoch);
+        match fs::remove_file(self.pending_path(epoch)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Publishes the epochs before the latest one a signal was accepted in
+    pub fn publish_ready(&mut self) -> Result<Vec<EpochMetadata>> {
+        let num_ready = self.pending.len().saturating_sub(1);
+        let ready: Vec<u64> = self.pending.keys().take(num_ready).copied().collect();
+        self.publish_all(&ready)
+    }
+
+    /// Publishes every epoch with pending signals
+    pub fn flush(&mut self) -> Result<Vec<EpochMetadata>> {
+        let ready: Vec<u64> = self.pending.keys().copied().collect();
+        self.publish_all(&ready)
+    }
+
+    /// Publishes the signals of `epoch` received so far as a batch, keeping the epoch open
+    pub fn publish_batch(&mut self, epoch: u64) -> Result<EpochMetadata> {
+        self.publish(epoch, false)
+    }
+
+    /// Closes `epoch`, publishing its pending signals if it has any
+    pub fn close(&mut self, epoch: u64) -> Result<Option<EpochMetadata>> {
+        if self.num_pending(epoch) > 0 {
+            return self.publish(epoch, true).map(Some);
+        }
+
+        let mut checkpoint = self.checkpoint.clone();
+        checkpoint.batches.remove(&epoch);
+        checkpoint.last_closed = checkpoint.last_closed.max(Some(epoch));
+        self.storage.commit(&[], None, &checkpoint)?;
+        self.checkpoint = checkpoint;
+        Ok(None)
+    }
+
+    /// Aggregates the signals of `epoch` and records the aggregate, see `record`. The epoch
+    /// is closed if `close` is set. The signals stay pending until the aggregate is
+    /// recorded, and are aggregated again by the next publication if it fails
+    fn publish(&mut self, epoch: u64, close: bool) -> Result<EpochMetadata> {
+        let signals = self.pending.get(&epoch).cloned().unwrap_or_default();
+        let verifier_data = self
+            .registry
+            .get(self.signal_circuit)
+            .expect("the signal circuit is registered on creation");
+        let (aggregation, aggregation_time) = prover_utils::timed(|| {
+            self.aggregator
+                .aggregate(&self.access_set, &signals, verifier_data)
+        });
+        let recorded = aggregation
+            .map_err(anyhow::Error::from)
+            .and_then(|aggregation| {
+                self.record(epoch, close, signals, aggregation, aggregation_time)
+            });
+        if recorded.is_err() {
+            self.rewind_chain();
+        }
+
+        recorded
+    }
+
+    /// Publishes and closes `epochs` in order, generating the witness of the aggregate of
+    /// an epoch while that of the previous one is proven, see
+    /// `Aggregator::aggregate_pipelined`. If an aggregation fails, the epochs before it are
+    /// published and those after it stay pending
+    fn publish_all(&mut self, epochs: &[u64]) -> Result<Vec<EpochMetadata>> {
+        let batches: Vec<Vec<(Digest, Signal)>> = epochs
+            .iter()
+            .map(|epoch| self.pending.get(epoch).cloned().unwrap_or_default())
+            .collect();
+        let verifier_data = self
+            .registry
+            .get(self.signal_circuit)
+            .expect("the signal circuit is registered on creation");
+        let aggregations =
+            self.aggregator
+                .aggregate_pipelined(&self.access_set, &batches, verifier_data);
+
+        let mut batches = batches.into_iter();
+        let mut published = Vec::with_capacity(aggregations.len());
+        for (aggregation, aggregation_time) in aggregations {
+            let epoch = epochs[published.len()];
+            let signals = batches.next().expect("there is a batch per aggregation");
+            let recorded = aggregation
+                .map_err(anyhow::Error::from)
+                .and_then(|aggregation| {
+                    self.record(epoch, true, signals, aggregation, aggregation_time)
+                });
+            match recorded {
+                Ok(metadata) => published.push(metadata),
+                Err(e) => {
+                    // the signals of the failed epoch stay pending, as in `publish`
+                    self.rewind_chain();
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(published)
+    }
+
+    /// Moves the chain head of the aggregator back to that of the last recorded aggregate,
+    /// dropping the aggregates proven since then which weren't recorded
+    fn rewind_chain(&mut self) {
+        if self.chained {
+            let chain_head = self.checkpoint.chain_head.unwrap_or(chain::GENESIS);
+            self.aggregator = mem::take(&mut self.aggregator).with_chain_head(chain_head);
+        }
+    }
+
+    /// Adds the aggregated signals of `epoch` to the history and writes the aggregate
+    /// proof, the verifier-only data of the aggregation with the layout of its public inputs,
+    /// the link proof if the aggregates are chained and the metadata of the epoch, or of its
+    /// batch after the first, before committing them to the storage and dropping the
+    /// signals from the pending ones. The epoch is closed if `close` is set. The history
+    /// and checkpoint of the relayer are only updated once the storage commit succeeds, so
+    /// that a failure leaves them as they were
+    fn record(
+        &mut self,
+        epoch: u64,
+        close: bool,
+        signals: Vec<(Digest, Signal)>,
+        aggregation: Aggregation<C>,
+        aggregation_time: Duration,
+    ) -> Result<EpochMetadata> {
+        let mut layout =
+            PublicInputLayout::aggregate(self.access_set.cap_height(), signals.len(), false);
+        let link = if self.chained {
+            layout = layout.chained();
+            Some(chain::prove_link(&aggregation, &layout)?)
+        } else {
+            None
+        };
+        let (proof_with_pis, aggregate_verifier_data) = aggregation;
+        let chain = link
+            .as_ref()
+            .map(|(link, _)| ChainLink::from_public_inputs(&link.public_inputs))
+            .transpose()?;
+
+        let nullifiers: Vec<(u64, Digest)> = signals
+            .iter()
+            .map(|(_, signal)| (epoch, signal.nullifier))
+            .collect();
+        let mut history = self.history.clone();
+        let history_indices = nullifiers
+            .iter()
+            .map(|&(epoch, nullifier)| history.push(epoch, nullifier))
+            .collect();
+        let accepted = [self.accepted.as_slice(), &nullifiers].concat();
+        let mut checkpoint = self.checkpoint.clone();
+        let batch = if close {
+            checkpoint.batches.remove(&epoch).unwrap_or_default()
+        } else {
+            let batches = checkpoint.batches.entry(epoch).or_default();
+            *batches += 1;
+            *batches - 1
+        };
+        let metadata = EpochMetadata {
+            epoch,
+            batch,
+            signals: signals
+                .iter()
+                .map(|(topic, signal)| (*topic, signal.nullifier))
+                .collect(),
+            history_indices,
+            history_root: history.root(),
+            aggregation_time,
+            chain,
+        };
+
+        let verifier_only = &aggregate_verifier_data.verifier_only;
+        // encoded whole rather than streamed with `codec::write_proof`, as the storage and
+        // the transport take the aggregate as bytes
+        let aggregate = StoredAggregate {
+            metadata,
+            proof: bincode::serialize(&proof_with_pis.proof)?,
+            verifier: bincode::serialize(&(
+                &verifier_only.constants_sigmas_cap,
+                &verifier_only.circuit_digest,
+                &layout,
+            ))?,
+        };
+        let name = match batch {
+            0 => format!("epoch-{epoch}"),
+            batch => format!("epoch-{epoch}-batch-{batch}"),
+        };
+        fs::write(self.output.join(format!("{name}.proof")), &aggregate.proof)?;
+        fs::write(
+            self.output.join(format!("{name}.verifier")),
+            &aggregate.verifier,
+        )?;
+        if let Some((link, link_verifier_data)) = &link {
+            let link_verifier_only = &link_verifier_data.verifier_only;
+            fs::write(
+                self.output.join(format!("{name}.link")),
+                bincode::serialize(&(
+                    &link.proof,
+                    &link_verifier_only.constants_sigmas_cap,
+                    &link_verifier_only.circuit_digest,
+                ))?,
+            )?;
+        }
+        fs::write(
+            self.output.join(format!("{name}.json")),
+            serde_json::to_string_pretty(&aggregate.metadata)?,
+        )?;
+        fs::write(
+            self.output.join(HISTORY_FILE),
+            serde_json::to_string(&accepted)?,
+        )?;
+        if close {
+            checkpoint.last_closed = checkpoint.last_closed.max(Some(epoch));
+        }
+        if let Some(link) = &aggregate.metadata.chain {
+            checkpoint.chain_head = Some(link.digest);
+        }
+        self.storage
+            .commit(&nullifiers, Some(&aggregate), &checkpoint)?;
+
+        self.history = history;
+        self.accepted = accepted;
+        self.checkpoint = checkpoint;
+        self.remove_pending(epoch)?;
+
+        Ok(aggregate.metadata)
+    }
+}
+
+/// Reads the pending files of `dir` by epoch. A crash while a signal was appended leaves it
+/// cut short at the end of its file, before it was accepted: it is ignored and cut off, so
+/// that the signals appended after the restart follow the last complete one
+fn read_pending(dir: &Path) -> Result<BTreeMap<u64, Vec<(Digest, Signal)>>> {
+    let mut pending = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let epoch = path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.strip_prefix("epoch-")?.parse::<u64>().ok());
+        let Some(epoch) = epoch else {
+            continue;
+        };
+
+        let bytes = fs::read(&path)?;
+        let mut reader = bytes.as_slice();
+        let mut signals: Vec<(Digest, Signal)> = Vec::new();
+        while !reader.is_empty() {
+            let complete = bytes.len() - reader.len();
+            match bincode::deserialize_from(&mut reader) {
+                Ok(signal) => signals.push(signal),
+                Err(e) => match *e {
+                    bincode::ErrorKind::Io(ref cause)
+                        if cause.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        OpenOptions::new()
+                            .write(true)
+                            .open(&path)?
+                            .set_len(complete as u64)?;
+                        break;
+                    }
+                    _ => return Err(e.into()),
+                },
+            }
+        }
+        if !signals.is_empty() {
+            pending.insert(epoch, signals);
+        }
+    }
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use plonky2::field::types::Sample;
+    use semaphore::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn test_relayer() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let topic = F::rand_array();
+        let envelope = |epoch, i: usize| -> Result<SignalEnvelope> {
+            let (signal, verifier_data) =
+                access_set.make_signal(fixtures::private_key(i), topic, i)?;
+            Ok(SignalEnvelope {
+                circuit_id: CircuitId::of(&verifier_data),
+                epoch,
+                topic,
+                signal,
+            })
+        };
+        let (first, second, reused, later) = (
+            envelope(0, 0)?,
+            envelope(0, 1)?,
+            envelope(0, 0)?,
+            envelope(1, 2)?,
+        );
+
+        let output = env::temp_dir().join(format!("relayer-pipeline-{}", std::process::id()));
+        let mut relayer = Relayer::new(AccessSet(access_set.0.clone()), output.clone())?;
+        let mut other_circuit = first.clone();
+        other_circuit.circuit_id = CircuitId([0; 4]);
+        assert!(relayer.accept(other_circuit).is_err());
+        relayer.accept(first)?;
+        relayer.accept(second.clone())?;
+        assert!(relayer.accept(reused).is_err());
+        assert!(relayer.publish_ready()?.is_empty());
+
+        relayer.accept(later)?;
+        let published = relayer.publish_ready()?;
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].history_indices, [0, 1]);
+        assert!(output.join("epoch-0.proof").exists());
+        assert!(relayer.accept(second).is_err());
+
+        assert_eq!(relayer.flush()?.len(), 1);
+        let root = relayer.history().root();
+        let pending = envelope(2, 3)?;
+        relayer.accept(pending.clone())?;
+        // a signal cut short by a crash while it was appended
+        let pending_path = relayer.pending_path(2);
+        let complete = fs::metadata(&pending_path)?.len();
+        let cut = bincode::serialize(&(topic, pending.signal.clone()))?;
+        let mut file = OpenOptions::new().append(true).open(&pending_path)?;
+        file.write_all(&cut[..cut.len() / 2])?;
+
+        // the history is rebuilt and the pending signals are queued again from the output on
+        // restart, without the one cut short
+        let mut relayer = Relayer::new(AccessSet(access_set.0.clone()), output.clone())?;
+        assert_eq!(relayer.history().len(), 3);
+        assert_eq!(relayer.history().root(), root);
+        assert_eq!(relayer.pending_epochs(), [2]);
+        assert_eq!(relayer.num_pending(2), 1);
+        assert_eq!(fs::metadata(&pending_path)?.len(), complete);
+        assert!(relayer.accept(pending).is_err());
+        assert_eq!(relayer.flush()?.len(), 1);
+
+        fs::remove_dir_all(output)?;
+        Ok(())
+    }
+
+    /// Fails the first commit of an aggregate
+    #[derive(Default)]
+    struct FlakyStorage {
+        inner: MemoryStorage,
+        failed: bool,
+    }
+
+    impl Storage for FlakyStorage {
+        fn nullifiers(&self) -> Result<Vec<(u64, Digest)>> {
+            self.inner.nullifiers()
+        }
+
+        fn aggregate(&self, epoch: u64, batch: usize) -> Result<Option<StoredAggregate>> {
+            self.inner.aggregate(epoch, batch)
+        }
+
+        fn checkpoint(&self) -> Result<Option<Checkpoint>> {
+            self.inner.checkpoint()
+        }
+
+        fn commit(
+            &mut self,
+            nullifiers: &[(u64, Digest)],
+            aggregate: Option<&StoredAggregate>,
+            checkpoint: &Checkpoint,
+        ) -> Result<()> {
+            if aggregate.is_some() && !self.failed {
+                self.failed = true;
+                bail!("the storage is unavailable");
+            }
+            self.inner.commit(nullifiers, aggregate, checkpoint)
+        }
+    }
+
+    #[test]
+    fn test_failed_publication() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let topic = F::rand_array();
+        let (signal, verifier_data) = access_set.make_signal(fixtures::private_key(0), topic, 0)?;
+        let envelope = SignalEnvelope {
+            circuit_id: CircuitId::of(&verifier_data),
+            epoch: 0,
+            topic,
+            signal,
+        };
+
+        let output = env::temp_dir().join(format!("relayer-flaky-{}", std::process::id()));
+        let mut relayer = Relayer::with_storage(
+            AccessSet(access_set.0.clone()),
+            output.clone(),
+            Box::<FlakyStorage>::default(),
+        )?
+        .with_chain();
+        relayer.accept(envelope.clone())?;
+        assert!(relayer.flush().is_err());
+
+        // the signal stays pending and its nullifier taken, and nothing was recorded
+        assert_eq!(relayer.pending_epochs(), [0]);
+        assert!(relayer.accept(envelope).is_err());
+        assert_eq!(relayer.history().len(), 0);
+        assert_eq!(relayer.chain_head(), None);
+
+        let published = relayer.flush()?;
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].history_indices, [0]);
+        assert!(relayer.pending_epochs().is_empty());
+
+        fs::remove_dir_all(output)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use proof_error::ProofError;
+
+use crate::envelope::SignalEnvelope;
+
+/// A signal read from a source, which may fail to deserialize
+pub struct Received {
+    /// Where the signal was read from, for logging
+    pub origin: String,
+    pub envelope: Result<SignalEnvelope, ProofError>,
+}
+
+pub trait SignalSource {
+    /// The signals received since the last poll, in the order they were received
+    fn poll(&mut self) -> Result<Vec<Received>>;
+}
+
+/// Reads the `.signal` files of a directory in the order of their names, renaming each to
+/// `.done` once read so that it is consumed only once
+pub struct DirectorySource {
+    dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl SignalSource for DirectorySource {
+    fn poll(&mut self) -> Result<Vec<Received>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("signal") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let envelope = SignalEnvelope::from_bytes(&fs::read(&path)?);
+                fs::rename(&path, path.with_extension("done"))?;
+                Ok(Received {
+                    origin: path.display().to_string(),
+                    envelope,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Pops serialized signals from a redis list
+#[cfg(feature = "redis")]
+pub struct RedisSource {
+    connection: redis::Connection,
+    list: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisSource {
+    pub fn new(url: &str, list: String) -> Result<Self> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+        Ok(Self { connection, list })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl SignalSource for RedisSource {
+    fn poll(&mut self) -> Result<Vec<Received>> {
+        let mut received = Vec::new();
+        while let Some(bytes) = redis::cmd("LPOP")
+            .arg(&self.list)
+            .query::<Option<Vec<u8>>>(&mut self.connection)?
+        {
+            received.push(Received {
+                origin: format!("{} item {}", self.list, received.len()),
+                envelope: SignalEnvelope::from_bytes(&bytes),
+            });
+        }
+
+        Ok(received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_directory_source() -> Result<()> {
+        let dir = env::temp_dir().join(format!("relayer-source-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("a.signal"), b"not a signal")?;
+        fs::write(dir.join("b.txt"), b"ignored")?;
+
+        let mut source = DirectorySource::new(dir.clone());
+        let received = source.poll()?;
+        assert_eq!(received.len(), 1);
+        assert!(matches!(
+            received[0].envelope,
+            Err(ProofError::Serialization(_))
+        ));
+        assert!(dir.join("a.done").exists());
+        assert!(source.poll()?.is_empty());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
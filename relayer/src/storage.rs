@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "sled")]
+use std::path::Path;
+
+#[cfg(feature = "sled")]
+use anyhow::anyhow;
+use anyhow::Result;
+use semaphore::signal::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::EpochMetadata;
+
+/// How far a relayer got through its epochs
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// The last epoch closed, whose late signals are rejected
+    pub last_closed: Option<u64>,
+    /// The number of batches published so far of each epoch still open
+    pub batches: BTreeMap<u64, usize>,
+    /// The digest of the last aggregate published, if the aggregates are chained
+    #[serde(default)]
+    pub chain_head: Option<Digest>,
+}
+
+/// A published aggregate: its metadata, which holds the root of the history after it, and
+/// the serialized proof, and the verifier-only data of the aggregation followed by the
+/// `PublicInputLayout` of its public inputs
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredAggregate {
+    pub metadata: EpochMetadata,
+    pub proof: Vec<u8>,
+    pub verifier: Vec<u8>,
+}
+
+/// The state a relayer resumes from after a restart. Signals which are verified but not
+/// aggregated yet aren't stored here, the relayer keeps them in its output
+pub trait Storage {
+    /// The nullifiers accepted so far with their epochs, in the order they were accepted
+    fn nullifiers(&self) -> Result<Vec<(u64, Digest)>>;
+
+    /// The aggregate `batch` of `epoch`, if it was published
+    fn aggregate(&self, epoch: u64, batch: usize) -> Result<Option<StoredAggregate>>;
+
+    /// The checkpoint of the last commit, or `None` if nothing was committed
+    fn checkpoint(&self) -> Result<Option<Checkpoint>>;
+
+    /// Appends the nullifiers and records the aggregate of their signals if any, together
+    /// with the checkpoint after them. Stores which can, commit them atomically
+    fn commit(
+        &mut self,
+        nullifiers: &[(u64, Digest)],
+        aggregate: Option<&StoredAggregate>,
+        checkpoint: &Checkpoint,
+    ) -> Result<()>;
+}
+
+/// Keeps the state in memory, so that it lasts as long as the relayer
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    nullifiers: Vec<(u64, Digest)>,
+    aggregates: BTreeMap<(u64, usize), StoredAggregate>,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn nullifiers(&self) -> Result<Vec<(u64, Digest)>> {
+        Ok(self.nullifiers.clone())
+    }
+
+    fn aggregate(&self, epoch: u64, batch: usize) -> Result<Option<StoredAggregate>> {
+        Ok(self.aggregates.get(&(epoch, batch)).cloned())
+    }
+
+    fn checkpoint(&self) -> Result<Option<Checkpoint>> {
+        Ok(self.checkpoint.clone())
+    }
+
+    fn commit(
+        &mut self,
+        nullifiers: &[(u64, Digest)],
+        aggregate: Option<&StoredAggregate>,
+        checkpoint: &Checkpoint,
+    ) -> Result<()> {
+        self.nullifiers.extend_from_slice(nullifiers);
+        if let Some(aggregate) = aggregate {
+            let key = (aggregate.metadata.epoch, aggregate.metadata.batch);
+            self.aggregates.insert(key, aggregate.clone());
+        }
+        self.checkpoint = Some(checkpoint.clone());
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sled")]
+const CHECKPOINT_KEY: &[u8] = b"checkpoint";
+
+/// Keeps the state in a sled database, committing each aggregate in a transaction
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+    /// The nullifiers by the big-endian index they were accepted at
+    nullifiers: sled::Tree,
+    /// The aggregates by big-endian epoch and batch
+    aggregates: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    /// Opens the database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            nullifiers: db.open_tree("nullifiers")?,
+            aggregates: db.open_tree("aggregates")?,
+            db,
+        })
+    }
+
+    fn aggregate_key(epoch: u64, batch: usize) -> Vec<u8> {
+        epoch
+            .to_be_bytes()
+            .into_iter()
+            .chain((batch as u64).to_be_bytes())
+            .collect()
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    fn nullifiers(&self) -> Result<Vec<(u64, Digest)>> {
+        self.nullifiers
+            .iter()
+            .values()
+            .map(|value| Ok(bincode::deserialize(&value?)?))
+            .collect()
+    }
+
+    fn aggregate(&self, epoch: u64, batch: usize) -> Result<Option<StoredAggregate>> {
+        match self.aggregates.get(Self::aggregate_key(epoch, batch))? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn checkpoint(&self) -> Result<Option<Checkpoint>> {
+        match self.db.get(CHECKPOINT_KEY)? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn commit(
+        &mut self,
+        nullifiers: &[(u64, Digest)],
+        aggregate: Option<&StoredAggregate>,
+        checkpoint: &Checkpoint,
+    ) -> Result<()> {
+        use sled::transaction::ConflictableTransactionError;
+        use sled::Transactional;
+
+        let first = self.nullifiers.len() as u64;
+        let nullifiers = nullifiers
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let key = (first + i as u64).to_be_bytes().to_vec();
+                Ok((key, bincode::serialize(entry)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let aggregate = aggregate
+            .map(|aggregate| -> Result<_> {
+                let metadata = &aggregate.metadata;
+                let key = Self::aggregate_key(metadata.epoch, metadata.batch);
+                Ok((key, bincode::serialize(aggregate)?))
+            })
+            .transpose()?;
+        let checkpoint = bincode::serialize(checkpoint)?;
+
+        let db: &sled::Tree = &self.db;
+        (&self.nullifiers, &self.aggregates, db)
+            .transaction(|(nullifiers_tree, aggregates_tree, db)| {
+                for (key, value) in &nullifiers {
+                    nullifiers_tree.insert(key.as_slice(), value.as_slice())?;
+                }
+                if let Some((key, value)) = &aggregate {
+                    aggregates_tree.insert(key.as_slice(), value.as_slice())?;
+                }
+                db.insert(CHECKPOINT_KEY, checkpoint.as_slice())?;
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| anyhow!("sled transaction failed: {e:?}"))?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use plonky2::field::types::Sample;
+    use plonky2::hash::hash_types::HashOut;
+    use semaphore::signal::F;
+
+    use super::*;
+
+    fn check_storage(storage: &mut impl Storage) -> Result<()> {
+        assert!(storage.checkpoint()?.is_none());
+        let nullifiers: Vec<(u64, Digest)> = (0..3).map(|_| (4, F::rand_array())).collect();
+        let aggregate = StoredAggregate {
+            metadata: EpochMetadata {
+                epoch: 4,
+                batch: 1,
+                signals: nullifiers
+                    .iter()
+                    .map(|&(_, nullifier)| (F::rand_array(), nullifier))
+                    .collect(),
+                history_indices: vec![5, 6, 7],
+                history_root: HashOut {
+                    elements: F::rand_array(),
+                },
+                aggregation_time: Duration::from_secs(1),
+                chain: None,
+            },
+            proof: vec![1, 2, 3],
+            verifier: vec![4, 5],
+        };
+        let checkpoint = Checkpoint {
+            last_closed: Some(3),
+            batches: BTreeMap::from([(4, 2)]),
+            chain_head: Some(F::rand_array()),
+        };
+
+        storage.commit(&nullifiers[..1], None, &Checkpoint::default())?;
+        storage.commit(&nullifiers[1..], Some(&aggregate), &checkpoint)?;
+        assert_eq!(storage.nullifiers()?, nullifiers);
+        assert_eq!(storage.checkpoint()?, Some(checkpoint));
+        let stored = storage.aggregate(4, 1)?.unwrap();
+        assert_eq!(stored.proof, aggregate.proof);
+        assert_eq!(
+            stored.metadata.history_root,
+            aggregate.metadata.history_root
+        );
+        assert!(storage.aggregate(4, 0)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_storage() -> Result<()> {
+        check_storage(&mut MemoryStorage::new())
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_storage() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("relayer-sled-{}", std::process::id()));
+        check_storage(&mut SledStorage::open(&path)?)?;
+
+        // the state survives reopening the database
+        let storage = SledStorage::open(&path)?;
+        assert_eq!(storage.nullifiers()?.len(), 3);
+        assert!(storage.checkpoint()?.is_some());
+        drop(storage);
+
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::SignalEnvelope;
+use crate::source::{Received, SignalSource};
+use crate::storage::StoredAggregate;
+
+/// What relayers share with each other
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Message {
+    Signal(SignalEnvelope),
+    Aggregate(StoredAggregate),
+}
+
+impl Message {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+}
+
+/// Publishes messages to the other relayers subscribed to the same transport and receives
+/// theirs. Messages are serialized, so that in-process relayers go through the same
+/// encoding as remote ones
+pub trait Transport {
+    fn publish(&mut self, message: &Message) -> Result<()>;
+
+    /// The messages received since the last poll, in the order they were received. A
+    /// message which fails to decode is reported in its place, without stopping the others
+    fn poll(&mut self) -> Result<Vec<Result<Message, ProofError>>>;
+}
+
+/// Connects the `ChannelTransport`s of relayers running in the same process
+#[derive(Clone, Default)]
+pub struct ChannelHub {
+    subscribers: Arc<Mutex<Vec<Sender<Vec<u8>>>>>,
+}
+
+impl ChannelHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transport receiving the messages published by every other transport of the hub
+    pub fn connect(&self) -> ChannelTransport {
+        let (sender, receiver) = mpsc::channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.push(sender);
+        ChannelTransport {
+            hub: self.clone(),
+            index: subscribers.len() - 1,
+            receiver,
+        }
+    }
+}
+
+pub struct ChannelTransport {
+    hub: ChannelHub,
+    index: usize,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl Transport for ChannelTransport {
+    fn publish(&mut self, message: &Message) -> Result<()> {
+        let bytes = bincode::serialize(message)?;
+        let subscribers = self.hub.subscribers.lock().unwrap();
+        for (i, subscriber) in subscribers.iter().enumerate() {
+            // a transport which was dropped doesn't receive anymore
+            if i != self.index {
+                let _ = subscriber.send(bytes.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<Result<Message, ProofError>>> {
+        Ok(self
+            .receiver
+            .try_iter()
+            .map(|bytes| Message::from_bytes(&bytes))
+            .collect())
+    }
+}
+
+/// The signals received from a transport, for a relayer to consume them like those of a
+/// directory. Aggregates of other relayers are skipped, and messages which fail to decode
+/// are received as errors
+pub struct TransportSource<T: Transport> {
+    transport: T,
+}
+
+impl<T: Transport> TransportSource<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+}
+
+impl<T: Transport> SignalSource for TransportSource<T> {
+    fn poll(&mut self) -> Result<Vec<Received>> {
+        Ok(self
+            .transport
+            .poll()?
+            .into_iter()
+            .filter_map(|message| {
+                let envelope = match message {
+                    Ok(Message::Signal(envelope)) => Ok(envelope),
+                    Ok(Message::Aggregate(_)) => return None,
+                    Err(e) => Err(e),
+                };
+                Some(Received {
+                    origin: "transport".to_string(),
+                    envelope,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "libp2p")]
+pub use gossip::GossipsubTransport;
+
+#[cfg(feature = "libp2p")]
+mod gossip {
+    use std::thread;
+
+    use futures::channel::mpsc::{unbounded, UnboundedSender};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use libp2p::gossipsub::{
+        Gossipsub, GossipsubConfig, GossipsubEvent, IdentTopic, MessageAuthenticity,
+    };
+    use libp2p::swarm::SwarmEvent;
+    use libp2p::{identity, Multiaddr, PeerId, Swarm};
+
+    use super::*;
+
+    /// Shares messages over libp2p gossipsub on a topic, with a swarm running on its own
+    /// thread. Publishing fails once the swarm stopped, or when gossipsub rejects the
+    /// message, e.g. without peers on the topic
+    pub struct GossipsubTransport {
+        peer_id: PeerId,
+        publish: UnboundedSender<(Vec<u8>, Sender<Result<()>>)>,
+        received: Receiver<Vec<u8>>,
+    }
+
+    impl GossipsubTransport {
+        /// Listens on `listen`, dials `peers` and subscribes to `topic`
+        pub fn new(listen: Multiaddr, peers: &[Multiaddr], topic: &str) -> Result<Self> {
+            let key = identity::Keypair::generate_ed25519();
+            let peer_id = PeerId::from(key.public());
+            let transport = block_on(libp2p::development_transport(key.clone()))?;
+            let mut gossipsub =
+                Gossipsub::new(MessageAuthenticity::Signed(key), GossipsubConfig::default())
+                    .map_err(|e| anyhow!("invalid gossipsub configuration: {e}"))?;
+            let topic = IdentTopic::new(topic);
+            gossipsub.subscribe(&topic)?;
+
+            let mut swarm = Swarm::with_async_std_executor(transport, gossipsub, peer_id);
+            swarm.listen_on(listen)?;
+            for peer in peers {
+                swarm.dial(peer.clone())?;
+            }
+
+            let (publish, mut to_publish) = unbounded::<(Vec<u8>, Sender<Result<()>>)>();
+            let (received_sender, received) = mpsc::channel();
+            thread::spawn(move || {
+                block_on(async move {
+                    loop {
+                        futures::select! {
+                            event = swarm.select_next_some() => {
+                                if let SwarmEvent::Behaviour(GossipsubEvent::Message {
+                                    message, ..
+                                }) = event
+                                {
+                                    if received_sender.send(message.data).is_err() {
+                                        return;
+                                    }
+                                }
+                            }
+                            request = to_publish.next() => match request {
+                                Some((bytes, published)) => {
+                                    let result = swarm
+                                        .behaviour_mut()
+                                        .publish(topic.clone(), bytes)
+                                        .map(|_| ())
+                                        .map_err(|e| anyhow!("gossipsub dropped the message: {e:?}"));
+                                    // the publisher waits for the result, unless it was dropped
+                                    let _ = published.send(result);
+                                }
+                                None => return,
+                            },
+                        }
+                    }
+                })
+            });
+
+            Ok(Self {
+                peer_id,
+                publish,
+                received,
+            })
+        }
+
+        pub fn peer_id(&self) -> PeerId {
+            self.peer_id
+        }
+    }
+
+    impl Transport for GossipsubTransport {
+        fn publish(&mut self, message: &Message) -> Result<()> {
+            let stopped = || anyhow!("the gossipsub swarm stopped");
+            let (published, result) = mpsc::channel();
+            self.publish
+                .unbounded_send((bincode::serialize(message)?, published))
+                .map_err(|_| stopped())?;
+            result.recv().map_err(|_| stopped())?
+        }
+
+        fn poll(&mut self) -> Result<Vec<Result<Message, ProofError>>> {
+            Ok(self
+                .received
+                .try_iter()
+                .map(|bytes| Message::from_bytes(&bytes))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use plonky2::field::types::Sample;
+    use plonky2::hash::hash_types::HashOut;
+    use prover_utils::registry::CircuitId;
+    use semaphore::fixtures;
+    use semaphore::signal::F;
+
+    use super::*;
+    use crate::pipeline::EpochMetadata;
+
+    #[test]
+    fn test_channel_transport() -> Result<()> {
+        let access_set = fixtures::access_set(1);
+        let topic = F::rand_array();
+        let (signal, verifier_data) = access_set.make_signal(fixtures::private_key(1), topic, 1)?;
+        let envelope = SignalEnvelope {
+            circuit_id: CircuitId::of(&verifier_data),
+            epoch: 0,
+            topic,
+            signal,
+        };
+
+        let hub = ChannelHub::new();
+        let (mut alice, bob, mut carol) = (hub.connect(), hub.connect(), hub.connect());
+        // a message which doesn't decode doesn't stop the ones after it
+        hub.subscribers.lock().unwrap()[bob.index]
+            .send(b"not a message".to_vec())
+            .unwrap();
+        alice.publish(&Message::Signal(envelope.clone()))?;
+        carol.publish(&Message::Aggregate(StoredAggregate {
+            metadata: EpochMetadata {
+                epoch: 0,
+                batch: 0,
+                signals: vec![(topic, envelope.signal.nullifier)],
+                history_indices: vec![0],
+                history_root: HashOut {
+                    elements: F::rand_array(),
+                },
+                aggregation_time: Duration::ZERO,
+                chain: None,
+            },
+            proof: Vec::new(),
+            verifier: Vec::new(),
+        }))?;
+
+        // a transport doesn't receive its own messages
+        assert_eq!(alice.poll()?.len(), 1);
+        assert_eq!(carol.poll()?.len(), 1);
+        let received = TransportSource::new(bob).poll()?;
+        assert_eq!(received.len(), 2);
+        assert!(matches!(
+            received[0].envelope,
+            Err(ProofError::Serialization(_))
+        ));
+        let received = received[1].envelope.as_ref().unwrap();
+        assert_eq!(received.signal.nullifier, envelope.signal.nullifier);
+        access_set.verify_signal(topic, received.signal.clone(), &verifier_data)?;
+
+        Ok(())
+    }
+}
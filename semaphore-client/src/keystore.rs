@@ -0,0 +1,128 @@
+use std::array;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use plonky2::field::types::{Field, PrimeField64};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use semaphore::signal::{Digest, F};
+use serde::{Deserialize, Serialize};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// A private key encrypted with XChaCha20-Poly1305, under a key derived from the password
+/// with Argon2
+#[derive(Deserialize, Serialize)]
+struct EncryptedIdentity {
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+/// A directory of named identities, one encrypted file each
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+fn cipher(password: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("key derivation failed: {e}"))?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+impl Keystore {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    pub fn store(&self, name: &str, private_key: Digest, password: &str) -> Result<()> {
+        let path = self.path(name);
+        if path.exists() {
+            bail!("identity {name} already exists");
+        }
+
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        let plaintext: Vec<u8> = private_key
+            .iter()
+            .flat_map(|f| f.to_canonical_u64().to_le_bytes())
+            .collect();
+        let ciphertext = cipher(password, &salt)?
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow!("encryption of identity {name} failed"))?;
+
+        let identity = EncryptedIdentity {
+            salt: salt.to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        };
+        fs::write(path, serde_json::to_string(&identity)?)?;
+        Ok(())
+    }
+
+    pub fn load(&self, name: &str, password: &str) -> Result<Digest> {
+        let contents = fs::read_to_string(self.path(name))
+            .with_context(|| format!("no identity named {name}"))?;
+        let identity: EncryptedIdentity = serde_json::from_str(&contents)?;
+        if identity.nonce.len() != NONCE_LEN {
+            bail!("identity {name} is corrupted");
+        }
+
+        let plaintext = cipher(password, &identity.salt)?
+            .decrypt(
+                XNonce::from_slice(&identity.nonce),
+                identity.ciphertext.as_slice(),
+            )
+            .map_err(|_| anyhow!("wrong password for identity {name}"))?;
+        if plaintext.len() != 32 {
+            bail!("identity {name} is corrupted");
+        }
+
+        Ok(array::from_fn(|i| {
+            F::from_canonical_u64(u64::from_le_bytes(
+                plaintext[8 * i..8 * (i + 1)].try_into().unwrap(),
+            ))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use plonky2::field::types::Sample;
+
+    use super::*;
+
+    #[test]
+    fn test_keystore() -> Result<()> {
+        let dir = env::temp_dir().join(format!("semaphore-keystore-{}", std::process::id()));
+        let keystore = Keystore::open(dir.clone())?;
+        let private_key = F::rand_array();
+
+        keystore.store("alice", private_key, "correct horse")?;
+        assert_eq!(keystore.load("alice", "correct horse")?, private_key);
+        assert!(keystore.load("alice", "battery staple").is_err());
+        assert!(keystore.load("bob", "correct horse").is_err());
+        assert!(keystore
+            .store("alice", F::rand_array(), "correct horse")
+            .is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
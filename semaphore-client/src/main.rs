@@ -0,0 +1,178 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use plonky2::field::types::{PrimeField64, Sample};
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::merkle_proofs::{verify_merkle_proof_to_cap, MerkleProof};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use prover_utils::registry::CircuitId;
+use relayer::envelope::SignalEnvelope;
+use semaphore::access_set::{identity_commitment, make_signal_from_merkle_proof};
+use semaphore::domain::DomainTag;
+use semaphore::signal::{Digest, Signal, F};
+use serde::Deserialize;
+
+use crate::keystore::Keystore;
+
+mod keystore;
+
+/// The environment variable holding the password of the keystore
+const PASSWORD_VAR: &str = "SEMAPHORE_KEYSTORE_PASSWORD";
+
+#[derive(Parser)]
+#[command(about = "Manages semaphore identities and proves signals for a relayer")]
+struct Cli {
+    /// Directory of the encrypted identities
+    #[arg(long, default_value = "keystore")]
+    keystore: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate an identity and store it in the keystore
+    Keygen { name: String },
+    /// Print the identity commitment, i.e. the public key to add to the access set
+    Commitment { name: String },
+    /// Prove a signal on a topic from the Merkle path of the identity commitment
+    Prove {
+        name: String,
+        #[arg(long)]
+        topic: String,
+        /// JSON object with the `root`, the `index` and the `siblings` of the commitment
+        #[arg(long)]
+        merkle_path: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Wrap a proven signal into an envelope for the relayer
+    ExportSignal {
+        signal: PathBuf,
+        /// The id of the circuit the signal was proven with, as printed by `prove`
+        #[arg(long)]
+        circuit_id: CircuitId,
+        #[arg(long)]
+        topic: String,
+        #[arg(long)]
+        epoch: u64,
+        /// The envelope to write, with a `.signal` extension for the relayer to pick it up
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+/// The Merkle path of an identity commitment, as handed out by the access set operator
+#[derive(Deserialize)]
+struct MerklePath {
+    root: Digest,
+    index: usize,
+    siblings: Vec<Digest>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keystore = Keystore::open(cli.keystore)?;
+
+    match cli.command {
+        Command::Keygen { name } => {
+            keystore.store(&name, F::rand_array(), &password()?)?;
+            println!("stored identity {name}");
+            Ok(())
+        }
+        Command::Commitment { name } => {
+            let private_key = keystore.load(&name, &password()?)?;
+            println!("{}", to_json(identity_commitment(private_key))?);
+            Ok(())
+        }
+        Command::Prove {
+            name,
+            topic,
+            merkle_path,
+            output,
+        } => {
+            let private_key = keystore.load(&name, &password()?)?;
+            prove(private_key, &topic, &merkle_path, &output)
+        }
+        Command::ExportSignal {
+            signal,
+            circuit_id,
+            topic,
+            epoch,
+            output,
+        } => {
+            let envelope = SignalEnvelope {
+                circuit_id,
+                epoch,
+                topic: topic_digest(&topic),
+                signal: Signal::from_bytes(&fs::read(signal)?)?,
+            };
+            fs::write(output, envelope.to_bytes()?)?;
+            Ok(())
+        }
+    }
+}
+
+fn password() -> Result<String> {
+    env::var(PASSWORD_VAR).with_context(|| format!("set {PASSWORD_VAR} to the keystore password"))
+}
+
+/// Topics are free text, hashed to a digest the same way by every member
+fn topic_digest(topic: &str) -> Digest {
+    DomainTag::default().message(topic.as_bytes())
+}
+
+/// A digest in the JSON format of the access set file of the relayer
+fn to_json(digest: Digest) -> Result<String> {
+    Ok(serde_json::to_string(
+        &digest.map(|f| f.to_canonical_u64()),
+    )?)
+}
+
+fn prove(private_key: Digest, topic: &str, merkle_path: &Path, output: &Path) -> Result<()> {
+    let MerklePath {
+        root,
+        index,
+        siblings,
+    } = serde_json::from_str(&fs::read_to_string(merkle_path)?)?;
+    let cap = MerkleCap(vec![HashOut { elements: root }]);
+    let merkle_proof = MerkleProof {
+        siblings: siblings
+            .into_iter()
+            .map(|elements| HashOut { elements })
+            .collect(),
+    };
+
+    // fail early rather than with an unsatisfiable circuit
+    verify_merkle_proof_to_cap::<F, PoseidonHash>(
+        identity_commitment(private_key).to_vec(),
+        index,
+        &cap,
+        &merkle_proof,
+    )
+    .context("the Merkle path isn't the one of this identity")?;
+
+    let (signal, elapsed) = prover_utils::timed(|| {
+        make_signal_from_merkle_proof(
+            &DomainTag::default(),
+            &cap,
+            &merkle_proof,
+            private_key,
+            topic_digest(topic),
+            index,
+        )
+    });
+    let (signal, verifier_data) = signal?;
+    fs::write(output, signal.to_bytes()?)?;
+    println!(
+        "proved signal with nullifier {} for circuit {} in {elapsed:.2?}",
+        to_json(signal.nullifier)?,
+        CircuitId::of(&verifier_data)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use plonky2::field::types::Sample;
+use plonky2::hash::merkle_tree::MerkleTree;
+use semaphore::access_set::{identity_commitment, AccessSet};
+use semaphore::signal::{PrivateKey, F};
+
+// building an access set with the commitments hashed serially or in parallel
+fn bench_access_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("access set");
+    group.sample_size(10);
+
+    for num_members in [1 << 14, 1 << 20] {
+        let private_keys: Vec<PrivateKey> = (0..num_members).map(|_| F::rand_array()).collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("serial", num_members),
+            &private_keys,
+            |b, private_keys| {
+                b.iter(|| {
+                    let leaves = private_keys
+                        .iter()
+                        .map(|&sk| identity_commitment(sk).to_vec())
+                        .collect();
+                    AccessSet(MerkleTree::new(leaves, 0))
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("parallel", num_members),
+            &private_keys,
+            |b, private_keys| b.iter(|| AccessSet::from_private_keys(private_keys)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_access_set);
+criterion_main!(benches);
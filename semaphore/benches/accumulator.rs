@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use plonky2::field::types::Sample;
+use plonky2::hash::merkle_tree::MerkleTree;
+use semaphore::access_set::AccessSet;
+use semaphore::accumulator::Accumulator;
+use semaphore::signal::{Digest, F};
+
+// replacing a member of an access set, by rebuilding its tree or updating an accumulator
+fn bench_churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("churn");
+    group.sample_size(10);
+
+    for height in [14, 20] {
+        let num_members = 1 << height;
+        let commitments: Vec<Digest> = (0..num_members).map(|_| F::rand_array()).collect();
+
+        let mut leaves: Vec<Vec<F>> = commitments.iter().map(|c| c.to_vec()).collect();
+        group.bench_function(BenchmarkId::new("merkle rebuild", num_members), |b| {
+            b.iter(|| {
+                leaves[0] = F::rand_array::<4>().to_vec();
+                AccessSet(MerkleTree::new(leaves.clone(), 0))
+            })
+        });
+
+        let mut accumulator = Accumulator::new(height);
+        for commitment in &commitments {
+            accumulator.insert(*commitment).unwrap();
+        }
+        let mut member = commitments[0];
+        group.bench_function(BenchmarkId::new("accumulator", num_members), |b| {
+            b.iter(|| {
+                accumulator.remove(member).unwrap();
+                member = F::rand_array();
+                accumulator.insert(member).unwrap();
+                accumulator.value()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_churn);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use plonky2::field::types::Sample;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use prover_utils::witness::WitnessBuilder;
+use semaphore::access_set::AccessSet;
+use semaphore::signal::{PrivateKey, C, F};
+
+// proving a signal against access sets of a few sizes
+fn bench_signal(c: &mut Criterion) {
+    let mut group = c.benchmark_group("signal");
+    group.sample_size(10);
+
+    for num_members in [1 << 10, 1 << 16] {
+        let private_keys: Vec<PrivateKey> = (0..num_members).map(|_| F::rand_array()).collect();
+        let access_set = AccessSet::from_private_keys(&private_keys);
+        let topic = F::rand_array();
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_members),
+            &num_members,
+            |b, _| {
+                b.iter(|| {
+                    let mut builder = CircuitBuilder::new(prover_utils::standard_config(true));
+                    let mut witness = WitnessBuilder::new();
+                    let targets = access_set.semaphore_circuit(&mut builder);
+                    access_set
+                        .fill_semaphore_targets(&mut witness, private_keys[1], topic, 1, targets)
+                        .unwrap();
+                    prover_utils::timed_prove::<F, C, 2>(builder, witness.build()).unwrap()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_signal);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use plonky2::field::types::Sample;
+use semaphore::access_set::AccessSet;
+use semaphore::signal::{Digest, PrivateKey, Signal, F};
+
+const NUM_SIGNALS: usize = 1000;
+
+// verifying 1000 signals one after the other and as a batch. Verification doesn't depend
+// on the member, so the batch repeats the signals of a few members to keep the setup short
+fn bench_verify_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify 1000 signals");
+    group.sample_size(10);
+
+    let private_keys: Vec<PrivateKey> = (0..1 << 10).map(|_| F::rand_array()).collect();
+    let access_set = AccessSet::from_private_keys(&private_keys);
+    let mut distinct = Vec::new();
+    let mut verifier_data = None;
+    for i in 0..8 {
+        let topic: Digest = F::rand_array();
+        let (signal, data) = access_set.make_signal(private_keys[i], topic, i).unwrap();
+        distinct.push((topic, signal));
+        verifier_data = Some(data);
+    }
+    let verifier_data = verifier_data.unwrap();
+    let signals: Vec<(Digest, Signal)> =
+        distinct.iter().cycle().take(NUM_SIGNALS).cloned().collect();
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            for (topic, signal) in &signals {
+                access_set
+                    .verify_signal(*topic, signal.clone(), &verifier_data)
+                    .unwrap();
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| access_set.verify_signals(&signals, &verifier_data).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_verify_batch);
+criterion_main!(benches);
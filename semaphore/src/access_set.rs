@@ -1,37 +1,83 @@
-use anyhow::Result;
-use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::hash::merkle_proofs::MerkleProof;
+use plonky2::hash::merkle_tree::{MerkleCap, MerkleTree};
 use plonky2::hash::poseidon::PoseidonHash;
-use plonky2::iop::witness::PartialWitness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, VerifierCircuitData};
-use plonky2::plonk::config::Hasher;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
 use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::batch::verify_batch;
+use prover_utils::fri::FriProfile;
+use prover_utils::options::ProverOptions;
+use prover_utils::witness::WitnessBuilder;
+use prover_utils::TimedProof;
+use rayon::prelude::*;
 
-use crate::signal::{Digest, Signal, C, F};
+use crate::circuit;
+use crate::domain::DomainTag;
+use crate::pow;
+use crate::signal::{Digest, PrivateKey, Signal, C, F, NULLIFIER_VERSION};
+use crate::verify;
 
 pub struct AccessSet(pub MerkleTree<F, PoseidonHash>);
 
 impl AccessSet {
+    /// The access set of the given members, computing their commitments in parallel
+    pub fn from_private_keys(private_keys: &[PrivateKey]) -> Self {
+        Self::from_private_keys_with_cap_height(private_keys, 0)
+    }
+
+    /// Like `from_private_keys`, committed to by a cap of `2^cap_height` hashes, which
+    /// shortens the Merkle path verified by the signal circuit
+    pub fn from_private_keys_with_cap_height(
+        private_keys: &[PrivateKey],
+        cap_height: usize,
+    ) -> Self {
+        AccessSet(MerkleTree::new(
+            compute_commitments_par(private_keys),
+            cap_height,
+        ))
+    }
+
     pub fn verify_signal(
         &self,
         topic: Digest,
         signal: Signal,
         verifier_data: &VerifierCircuitData<F, C, 2>,
-    ) -> Result<()> {
-        let public_inputs: Vec<F> = self
-            .0
-            .cap
-            .0
+    ) -> Result<(), ProofError> {
+        verify::verify_signal(&self.0.cap, topic, signal, verifier_data)
+            .map_err(ProofError::Verification)
+    }
+
+    /// Like `verify_signal`, for signals gated by a proof of work of `difficulty` bits,
+    /// whose verifier data is `signal_verifier_data_with_options` of the same difficulty
+    pub fn verify_signal_with_difficulty(
+        &self,
+        topic: Digest,
+        signal: Signal,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+        difficulty: usize,
+    ) -> Result<(), ProofError> {
+        verify::verify_signal_with_difficulty(&self.0.cap, topic, signal, verifier_data, difficulty)
+            .map_err(ProofError::Verification)
+    }
+
+    /// Verifies signals on their topics in parallel, see `prover_utils::batch::verify_batch`
+    pub fn verify_signals(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        for (_, signal) in signals {
+            verify::check_version(signal).map_err(ProofError::Verification)?;
+        }
+        let proofs: Vec<ProofWithPublicInputs<F, C, 2>> = signals
             .iter()
-            .flat_map(|h| h.elements)
-            .chain(signal.nullifier)
-            .chain(topic)
+            .map(|(topic, signal)| ProofWithPublicInputs {
+                public_inputs: verify::signal_public_inputs(&self.0.cap, *topic, signal.nullifier),
+                proof: signal.proof.clone(),
+            })
             .collect();
-
-        verifier_data.verify(ProofWithPublicInputs {
-            proof: signal.proof,
-            public_inputs,
-        })
+        verify_batch(&proofs, verifier_data)
     }
 
     pub fn make_signal(
@@ -39,31 +85,304 @@ impl AccessSet {
         private_key: Digest,
         topic: Digest,
         public_key_index: usize,
-    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>)> {
-        let nullifier = PoseidonHash::hash_no_pad(&[private_key, topic].concat()).elements;
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        self.make_signal_with_profile(private_key, topic, public_key_index, &FriProfile::default())
+    }
 
-        let config = CircuitConfig::standard_recursion_zk_config();
-        let mut builder = CircuitBuilder::new(config);
-        let mut partial_witness = PartialWitness::new();
+    /// Like `make_signal`, proven with the FRI parameters of `fri`
+    pub fn make_signal_with_profile(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        public_key_index: usize,
+        fri: &FriProfile,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        self.make_signal_with_options(
+            private_key,
+            topic,
+            public_key_index,
+            fri,
+            &ProverOptions::default(),
+        )
+    }
 
-        let targets = self.semaphore_circuit(&mut builder);
-        self.fill_semaphore_targets(
-            &mut partial_witness,
+    /// Like `make_signal_with_profile`, proven with `options`, which gate the signal by a
+    /// proof of work of `options.difficulty` bits
+    pub fn make_signal_with_options(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        public_key_index: usize,
+        fri: &FriProfile,
+        options: &ProverOptions,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        if public_key_index >= self.0.leaves.len() {
+            return Err(ProofError::MemberIndexOutOfRange {
+                index: public_key_index,
+                size: self.0.leaves.len(),
+            });
+        }
+
+        make_signal_from_merkle_proof_with_options(
+            &DomainTag::default(),
+            &self.0.cap,
+            &self.0.prove(public_key_index),
             private_key,
             topic,
             public_key_index,
-            targets,
+            fri,
+            options,
+        )
+    }
+
+    /// The verifier data of the signals of this access set, built without proving one
+    pub fn signal_verifier_data(&self) -> VerifierCircuitData<F, C, 2> {
+        self.signal_verifier_data_with_profile(&FriProfile::default())
+            .expect("the standard FRI profile is sound")
+    }
+
+    /// Like `signal_verifier_data`, for signals proven with the FRI parameters of `fri`
+    pub fn signal_verifier_data_with_profile(
+        &self,
+        fri: &FriProfile,
+    ) -> Result<VerifierCircuitData<F, C, 2>, ProofError> {
+        self.signal_verifier_data_with_options(fri, &ProverOptions::default())
+    }
+
+    /// Like `signal_verifier_data_with_profile`, for signals proven with `options`
+    pub fn signal_verifier_data_with_options(
+        &self,
+        fri: &FriProfile,
+        options: &ProverOptions,
+    ) -> Result<VerifierCircuitData<F, C, 2>, ProofError> {
+        let mut builder = CircuitBuilder::new(fri.circuit_config(options.zero_knowledge(true))?);
+        self.semaphore_circuit_with_difficulty(&mut builder, options.difficulty)?;
+        Ok(builder.build::<C>().verifier_data())
+    }
+
+    /// Rehashes an access set of legacy, untagged commitments into commitments tagged with
+    /// `domain`, keeping the members at the same indices
+    pub fn migrate(&self, domain: &DomainTag) -> AccessSet {
+        let leaves = self
+            .0
+            .leaves
+            .iter()
+            .map(|leaf| {
+                let legacy: Digest = leaf[..].try_into().unwrap();
+                domain.migrate_commitment(legacy).to_vec()
+            })
+            .collect();
+        AccessSet(MerkleTree::new(leaves, self.cap_height()))
+    }
+}
+
+/// The public key of a member, i.e. the leaf of the access set committing to its private
+/// key, with the default domain tags
+pub fn identity_commitment(private_key: Digest) -> Digest {
+    DomainTag::default().commitment(private_key)
+}
+
+/// The leaves of an access set of the given members, hashed in parallel
+pub fn compute_commitments_par(private_keys: &[PrivateKey]) -> Vec<Vec<F>> {
+    private_keys
+        .par_iter()
+        .map(|&sk| identity_commitment(sk).to_vec())
+        .collect()
+}
+
+/// Like `AccessSet::make_signal`, from the Merkle path of the public key to the cap of the
+/// access set instead of the whole access set
+pub fn make_signal_from_merkle_proof(
+    domain: &DomainTag,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    private_key: Digest,
+    topic: Digest,
+    public_key_index: usize,
+) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+    make_signal_from_merkle_proof_with_profile(
+        domain,
+        cap,
+        merkle_proof,
+        private_key,
+        topic,
+        public_key_index,
+        &FriProfile::default(),
+    )
+}
+
+/// Like `make_signal_from_merkle_proof`, proven with the FRI parameters of `fri`
+pub fn make_signal_from_merkle_proof_with_profile(
+    domain: &DomainTag,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    private_key: Digest,
+    topic: Digest,
+    public_key_index: usize,
+    fri: &FriProfile,
+) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+    make_signal_from_merkle_proof_with_options(
+        domain,
+        cap,
+        merkle_proof,
+        private_key,
+        topic,
+        public_key_index,
+        fri,
+        &ProverOptions::default(),
+    )
+}
+
+/// Like `make_signal_from_merkle_proof_with_profile`, proven with `options`
+#[allow(clippy::too_many_arguments)]
+pub fn make_signal_from_merkle_proof_with_options(
+    domain: &DomainTag,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    private_key: Digest,
+    topic: Digest,
+    public_key_index: usize,
+    fri: &FriProfile,
+    options: &ProverOptions,
+) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+    let nullifier = domain.nullifier(private_key, topic);
+    let nonce = pow::grind(nullifier, options.difficulty)?;
+
+    let mut builder = CircuitBuilder::new(fri.circuit_config(options.zero_knowledge(true))?);
+    let mut witness = WitnessBuilder::new();
+
+    let cap_height = cap.0.len().trailing_zeros() as usize;
+    let targets = circuit::semaphore_circuit(
+        &mut builder,
+        merkle_proof.siblings.len() + cap_height,
+        cap_height,
+        domain,
+        options.difficulty,
+    )?;
+    circuit::fill_semaphore_targets(
+        &mut witness,
+        cap,
+        merkle_proof,
+        private_key,
+        topic,
+        public_key_index,
+        nonce,
+        targets,
+    )?;
+
+    let TimedProof {
+        circuit_data,
+        proof_with_pis,
+        ..
+    } = prover_utils::timed_prove_with_options::<F, C, 2>(builder, witness.build(), options)?;
+
+    Ok((
+        Signal {
+            version: NULLIFIER_VERSION,
+            nullifier,
+            nonce,
+            proof: proof_with_pis.proof,
+            payload: None,
+            disclosed: Vec::new(),
+        },
+        circuit_data.verifier_data(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::domain::legacy_commitment;
+    use crate::fixtures;
+
+    #[test]
+    fn test_migrate() -> Result<()> {
+        let private_keys: Vec<Digest> = (0..4).map(fixtures::private_key).collect();
+        let leaves = |commitment: fn(Digest) -> Digest| -> Vec<Vec<F>> {
+            private_keys
+                .iter()
+                .map(|&sk| commitment(sk).to_vec())
+                .collect()
+        };
+        let legacy = AccessSet(MerkleTree::new(leaves(legacy_commitment), 0));
+        let tagged = AccessSet(MerkleTree::new(leaves(identity_commitment), 0));
+
+        let migrated = legacy.migrate(&DomainTag::default());
+        assert_eq!(migrated.0.cap, tagged.0.cap);
+
+        let topic = F::rand_array();
+        let (signal, verifier_data) = migrated.make_signal(private_keys[3], topic, 3)?;
+        assert_eq!(
+            signal.nullifier,
+            DomainTag::default().nullifier(private_keys[3], topic)
         );
+        migrated.verify_signal(topic, signal.clone(), &verifier_data)?;
 
-        let data = builder.build();
-        let proof = data.prove(partial_witness).unwrap();
+        // the nullifier of another version of the scheme isn't accepted
+        let mut other_version = signal;
+        other_version.version += 1;
+        assert!(migrated
+            .verify_signal(topic, other_version, &verifier_data)
+            .is_err());
 
-        Ok((
-            Signal {
-                nullifier,
-                proof: proof.proof,
-            },
-            data.verifier_data(),
-        ))
+        Ok(())
+    }
+
+    #[test]
+    fn test_cap_height() -> Result<()> {
+        let private_keys: Vec<Digest> = (0..16).map(fixtures::private_key).collect();
+        let access_set = AccessSet::from_private_keys_with_cap_height(&private_keys, 2);
+        assert_eq!(access_set.cap_height(), 2);
+
+        // members under different elements of the cap
+        for i in [1, 6, 14] {
+            let topic = F::rand_array();
+            let (signal, verifier_data) = access_set.make_signal(private_keys[i], topic, i)?;
+            assert_eq!(verifier_data.common.num_public_inputs, 4 * 4 + 8);
+            access_set.verify_signal(topic, signal, &verifier_data)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pow_difficulty() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let topic = F::rand_array();
+        let fri = FriProfile::default();
+        let options = ProverOptions {
+            difficulty: 6,
+            ..Default::default()
+        };
+
+        let (signal, verifier_data) = access_set.make_signal_with_options(
+            fixtures::private_key(2),
+            topic,
+            2,
+            &fri,
+            &options,
+        )?;
+        assert_eq!(
+            verifier_data.verifier_only.circuit_digest,
+            access_set
+                .signal_verifier_data_with_options(&fri, &options)?
+                .verifier_only
+                .circuit_digest
+        );
+        access_set.verify_signal_with_difficulty(topic, signal.clone(), &verifier_data, 6)?;
+
+        // the difficulty is a constant of the signal circuit, and a signal short of the
+        // difficulty asked by the verifier is rejected before its proof
+        assert!(access_set
+            .verify_signal(topic, signal.clone(), &access_set.signal_verifier_data())
+            .is_err());
+        assert!(access_set
+            .verify_signal_with_difficulty(topic, signal, &verifier_data, 40)
+            .is_err());
+
+        Ok(())
     }
 }
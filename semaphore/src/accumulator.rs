@@ -0,0 +1,359 @@
+//! A dynamic accumulator of identity commitments, for access sets with high churn: members
+//! are inserted and removed in `height` hashes each instead of rebuilding the tree of the
+//! access set. The accumulator is a sparse Poseidon Merkle tree of fixed height, whose
+//! empty leaves are zero digests, so its value is the root of the `MerkleTree` of the same
+//! leaves and its membership proofs are Merkle paths, which the signal circuit verifies
+//! as it does for an `AccessSet` with a single-hash cap
+
+use std::collections::HashMap;
+
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget};
+use plonky2::hash::merkle_proofs::{verify_merkle_proof_to_cap, MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::Hasher;
+use proof_error::ProofError;
+use prover_utils::witness::{Named, WitnessBuilder};
+
+use crate::access_set::{identity_commitment, make_signal_from_merkle_proof};
+use crate::domain::DomainTag;
+use crate::signal::{Digest, PrivateKey, Signal, C, F};
+
+/// Identity commitments in the slots of a sparse Merkle tree of `2^height` leaves. Slots
+/// freed by removals are reused by later insertions
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    height: usize,
+    /// The nodes of non-empty subtrees by height, the leaves first
+    nodes: Vec<HashMap<usize, HashOut<F>>>,
+    /// The roots of empty subtrees by height
+    empty: Vec<HashOut<F>>,
+    indices: HashMap<Digest, usize>,
+    free: Vec<usize>,
+    num_slots: usize,
+}
+
+/// Proof that a commitment is in the slot `index` of an accumulator: the Merkle path from
+/// its leaf to the value
+#[derive(Clone, Debug)]
+pub struct MembershipProof {
+    pub index: usize,
+    pub siblings: Vec<HashOut<F>>,
+}
+
+impl Accumulator {
+    /// An empty accumulator of up to `2^height` members
+    pub fn new(height: usize) -> Self {
+        let mut empty = vec![HashOut {
+            elements: [F::ZERO; 4],
+        }];
+        for h in 0..height {
+            empty.push(PoseidonHash::two_to_one(empty[h], empty[h]));
+        }
+        Self {
+            height,
+            nodes: vec![HashMap::new(); height + 1],
+            empty,
+            indices: HashMap::new(),
+            free: Vec::new(),
+            num_slots: 0,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn capacity(&self) -> usize {
+        1 << self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The root of the tree, which signals are made against
+    pub fn value(&self) -> HashOut<F> {
+        self.node(self.height, 0)
+    }
+
+    /// The value as the cap of an access set, for `verify::verify_signal`
+    pub fn cap(&self) -> MerkleCap<F, PoseidonHash> {
+        MerkleCap(vec![self.value()])
+    }
+
+    fn node(&self, height: usize, index: usize) -> HashOut<F> {
+        self.nodes[height]
+            .get(&index)
+            .copied()
+            .unwrap_or(self.empty[height])
+    }
+
+    /// Sets the leaf of slot `index`, rehashing its path
+    fn set_leaf(&mut self, index: usize, commitment: Digest) {
+        let mut node = PoseidonHash::hash_or_noop(&commitment);
+        let mut index = index;
+        for height in 0..=self.height {
+            if node == self.empty[height] {
+                self.nodes[height].remove(&index);
+            } else {
+                self.nodes[height].insert(index, node);
+            }
+            if height < self.height {
+                let (left, right) = (self.node(height, index & !1), self.node(height, index | 1));
+                node = PoseidonHash::two_to_one(left, right);
+                index >>= 1;
+            }
+        }
+    }
+
+    /// Inserts `commitment`, returning its slot. Inserting a member again returns its
+    /// slot without changing the accumulator
+    pub fn insert(&mut self, commitment: Digest) -> Result<usize, ProofError> {
+        if let Some(&index) = self.indices.get(&commitment) {
+            return Ok(index);
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None if self.num_slots < self.capacity() => {
+                self.num_slots += 1;
+                self.num_slots - 1
+            }
+            None => {
+                return Err(ProofError::AccumulatorFull {
+                    capacity: self.capacity(),
+                })
+            }
+        };
+        self.set_leaf(index, commitment);
+        self.indices.insert(commitment, index);
+        Ok(index)
+    }
+
+    /// Removes `commitment`, returning the slot it freed
+    pub fn remove(&mut self, commitment: Digest) -> Result<usize, ProofError> {
+        let index = self
+            .indices
+            .remove(&commitment)
+            .ok_or(ProofError::NotAMember)?;
+        self.set_leaf(index, [F::ZERO; 4]);
+        self.free.push(index);
+        Ok(index)
+    }
+
+    /// Proves that `commitment` is a member, against the current value
+    pub fn prove(&self, commitment: Digest) -> Result<MembershipProof, ProofError> {
+        let index = *self
+            .indices
+            .get(&commitment)
+            .ok_or(ProofError::NotAMember)?;
+        Ok(MembershipProof {
+            index,
+            siblings: (0..self.height)
+                .map(|h| self.node(h, (index >> h) ^ 1))
+                .collect(),
+        })
+    }
+
+    /// Like `AccessSet::make_signal`, for a member of the accumulator
+    pub fn make_signal(
+        &self,
+        private_key: PrivateKey,
+        topic: Digest,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        let proof = self.prove(identity_commitment(private_key))?;
+        make_signal_from_merkle_proof(
+            &DomainTag::default(),
+            &self.cap(),
+            &proof.merkle_proof(),
+            private_key,
+            topic,
+            proof.index,
+        )
+    }
+}
+
+impl MembershipProof {
+    pub fn merkle_proof(&self) -> MerkleProof<F, PoseidonHash> {
+        MerkleProof {
+            siblings: self.siblings.clone(),
+        }
+    }
+
+    /// Checks that `commitment` is a member of the accumulator of value `value`
+    pub fn verify(&self, commitment: Digest, value: HashOut<F>) -> Result<(), ProofError> {
+        verify_merkle_proof_to_cap::<F, PoseidonHash>(
+            commitment.to_vec(),
+            self.index,
+            &MerkleCap(vec![value]),
+            &self.merkle_proof(),
+        )
+        .map_err(ProofError::Verification)
+    }
+}
+
+/// Targets of a membership proof in an accumulator of a fixed height
+pub struct MembershipTargets {
+    pub commitment: [Target; 4],
+    pub index: Target,
+    pub value: HashOutTarget,
+    siblings: MerkleProofTarget,
+}
+
+/// Checks in the circuit that a commitment is a member of an accumulator of `2^height`
+/// slots. The empty leaves are zero digests, which no private key commits to
+pub fn membership_circuit(builder: &mut CircuitBuilder<F, 2>, height: usize) -> MembershipTargets {
+    let commitment: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let index = builder.add_virtual_target();
+    let value = builder.add_virtual_hash();
+    let siblings = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(height),
+    };
+
+    // splitting bounds the index by the capacity
+    let index_bits = builder.split_le(index, height);
+    builder.verify_merkle_proof::<PoseidonHash>(commitment.to_vec(), &index_bits, value, &siblings);
+
+    MembershipTargets {
+        commitment,
+        index,
+        value,
+        siblings,
+    }
+}
+
+/// Fills the targets of a membership proof, failing if its path isn't of the height of
+/// the circuit
+pub fn fill_membership_targets(
+    witness: &mut WitnessBuilder<F>,
+    targets: &MembershipTargets,
+    commitment: Digest,
+    proof: &MembershipProof,
+    value: HashOut<F>,
+) -> Result<(), ProofError> {
+    witness.set_elements(&Named::new("commitment", targets.commitment), &commitment)?;
+    witness.set(
+        &Named::new("index", targets.index),
+        F::from_canonical_usize(proof.index),
+    )?;
+    witness.set_hash(&Named::new("value", targets.value), value)?;
+    witness.set_merkle_proof(
+        &Named::new("siblings", targets.siblings.clone()),
+        &proof.merkle_proof(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::verify;
+
+    #[test]
+    fn test_accumulator() -> Result<()> {
+        let mut accumulator = Accumulator::new(3);
+        let commitments: Vec<Digest> = (0..8).map(|_| F::rand_array()).collect();
+        for (i, commitment) in commitments.iter().enumerate() {
+            assert_eq!(accumulator.insert(*commitment)?, i);
+        }
+        assert!(matches!(
+            accumulator.insert(F::rand_array()),
+            Err(ProofError::AccumulatorFull { capacity: 8 })
+        ));
+
+        // the value is the root of the tree of the members
+        let leaves = commitments.iter().map(|c| c.to_vec()).collect();
+        assert_eq!(
+            accumulator.value(),
+            MerkleTree::<F, PoseidonHash>::new(leaves, 0).cap.0[0]
+        );
+
+        // a removed member frees its slot and its proofs no longer verify
+        let proof = accumulator.prove(commitments[5])?;
+        proof.verify(commitments[5], accumulator.value())?;
+        assert_eq!(accumulator.remove(commitments[5])?, 5);
+        assert!(proof.verify(commitments[5], accumulator.value()).is_err());
+        assert!(matches!(
+            accumulator.prove(commitments[5]),
+            Err(ProofError::NotAMember)
+        ));
+        let newcomer = F::rand_array();
+        assert_eq!(accumulator.insert(newcomer)?, 5);
+        accumulator
+            .prove(newcomer)?
+            .verify(newcomer, accumulator.value())?;
+        accumulator
+            .prove(commitments[2])?
+            .verify(commitments[2], accumulator.value())?;
+
+        // removing every member empties the tree
+        for commitment in commitments.iter().filter(|&&c| c != commitments[5]) {
+            accumulator.remove(*commitment)?;
+        }
+        accumulator.remove(newcomer)?;
+        assert!(accumulator.is_empty());
+        assert_eq!(accumulator.value(), Accumulator::new(3).value());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_membership_circuit() -> Result<()> {
+        let mut accumulator = Accumulator::new(4);
+        let commitments: Vec<Digest> = (0..5).map(|_| F::rand_array()).collect();
+        for commitment in &commitments {
+            accumulator.insert(*commitment)?;
+        }
+        accumulator.remove(commitments[1])?;
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let targets = membership_circuit(&mut builder, accumulator.height());
+        builder.register_public_inputs(&targets.value.elements);
+        let data = builder.build::<C>();
+
+        let mut witness = WitnessBuilder::new();
+        let proof = accumulator.prove(commitments[3])?;
+        fill_membership_targets(
+            &mut witness,
+            &targets,
+            commitments[3],
+            &proof,
+            accumulator.value(),
+        )?;
+        let proof = data.prove(witness.build())?;
+        assert_eq!(proof.public_inputs, accumulator.value().elements);
+        data.verify(proof)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accumulator_signal() -> Result<()> {
+        let private_keys: Vec<PrivateKey> = (0..3).map(fixtures::private_key).collect();
+        let mut accumulator = Accumulator::new(2);
+        for &private_key in &private_keys {
+            accumulator.insert(identity_commitment(private_key))?;
+        }
+        accumulator.remove(identity_commitment(private_keys[0]))?;
+
+        let topic = F::rand_array();
+        let (signal, verifier_data) = accumulator.make_signal(private_keys[2], topic)?;
+        verify::verify_signal(&accumulator.cap(), topic, signal, &verifier_data)?;
+        assert!(accumulator.make_signal(private_keys[0], topic).is_err());
+
+        Ok(())
+    }
+}
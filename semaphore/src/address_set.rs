@@ -0,0 +1,198 @@
+//! Access sets sourced from on-chain lists of Ethereum addresses, whose leaves are the
+//! keccak256 digests of the addresses while the nodes above them are Poseidon hashes, so
+//! that a contract's list is committed to without the members registering commitments.
+//! The membership circuit hashes the hidden address with the keccak gadget and verifies
+//! its Poseidon path, which proves that the address is listed, not that the prover owns
+//! it: binding it to a key, e.g. with a signature as in `credential`, is up to the caller
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use gadgets::keccak::{self, CircuitBuilderKeccak};
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::MerkleCapTarget;
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::{MerkleCap, MerkleTree};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use rayon::prelude::*;
+
+use crate::signal::{C, F};
+
+pub type Address = [u8; 20];
+
+/// Addresses in the leaves of a Merkle tree, as the limbs of their keccak256 digests, see
+/// `gadgets::keccak::digest_limbs`
+pub struct AddressSet(pub MerkleTree<F, PoseidonHash>);
+
+/// The leaf of `address`
+pub fn address_leaf(address: &Address) -> Vec<F> {
+    keccak::digest_limbs(&keccak::keccak256(address)).to_vec()
+}
+
+impl AddressSet {
+    pub fn from_addresses(addresses: &[Address]) -> Self {
+        Self::from_addresses_with_cap_height(addresses, 0)
+    }
+
+    /// Like `from_addresses`, committed to by a cap of `2^cap_height` hashes
+    pub fn from_addresses_with_cap_height(addresses: &[Address], cap_height: usize) -> Self {
+        AddressSet(MerkleTree::new(
+            addresses.par_iter().map(address_leaf).collect(),
+            cap_height,
+        ))
+    }
+
+    pub fn tree_height(&self) -> usize {
+        self.0.leaves.len().trailing_zeros() as usize
+    }
+
+    pub fn cap_height(&self) -> usize {
+        self.0.cap.0.len().trailing_zeros() as usize
+    }
+
+    pub fn index_of(&self, address: &Address) -> Option<usize> {
+        let leaf = address_leaf(address);
+        self.0.leaves.iter().position(|other| *other == leaf)
+    }
+
+    /// Proves that `address` is in the set, without revealing it. The public inputs of the
+    /// proof are the cap of the set
+    pub fn prove_membership(
+        &self,
+        address: &Address,
+        fri: &FriProfile,
+    ) -> Result<(ProofWithPublicInputs<F, C, 2>, VerifierCircuitData<F, C, 2>), ProofError> {
+        let index = self.index_of(address).ok_or(ProofError::UnknownAddress)?;
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        let mut witness = WitnessBuilder::new();
+        let targets =
+            address_membership_circuit(&mut builder, self.tree_height(), self.cap_height());
+        fill_address_membership_targets(
+            &mut witness,
+            &self.0.cap,
+            &self.0.prove(index),
+            address,
+            index,
+            targets,
+        )?;
+
+        let proof = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+        Ok((proof.proof_with_pis, proof.circuit_data.verifier_data()))
+    }
+
+    /// Verifies a proof of `prove_membership` against the cap of this set
+    pub fn verify_membership(
+        &self,
+        proof: ProofWithPublicInputs<F, C, 2>,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        let public_inputs = self.0.cap.0.iter().flat_map(|hash| hash.elements).collect();
+        verifier_data
+            .verify(ProofWithPublicInputs {
+                proof: proof.proof,
+                public_inputs,
+            })
+            .map_err(ProofError::Verification)
+    }
+}
+
+pub struct AddressMembershipTargets {
+    merkle_cap: Named<MerkleCapTarget>,
+    merkle_proof: Named<MerkleProofTarget>,
+    address: Named<Vec<Target>>,
+    index: Named<Target>,
+    tree_height: usize,
+}
+
+/// The circuit proving that a hidden address is in an address set of height
+/// `tree_height`, committed to by a cap of height `cap_height` as public inputs
+pub fn address_membership_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+) -> AddressMembershipTargets {
+    let merkle_cap = builder.add_virtual_cap(cap_height);
+    for hash in &merkle_cap.0 {
+        builder.register_public_inputs(&hash.elements);
+    }
+    let merkle_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(tree_height - cap_height),
+    };
+
+    // the keccak gadget checks that the address is made of bytes
+    let address = builder.add_virtual_targets(20);
+    let leaf = builder.keccak256(&address);
+    let index = builder.add_virtual_target();
+    builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+        leaf.to_vec(),
+        index,
+        &merkle_cap,
+        &merkle_proof,
+    );
+
+    AddressMembershipTargets {
+        merkle_cap: Named::new("merkle cap", merkle_cap),
+        merkle_proof: Named::new("merkle proof", merkle_proof),
+        address: Named::new("address", address),
+        index: Named::new("address index", index),
+        tree_height,
+    }
+}
+
+/// Fills the targets of `address_membership_circuit` from the Merkle path of the leaf of
+/// `address` to `cap`
+pub fn fill_address_membership_targets(
+    witness: &mut WitnessBuilder<F>,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    address: &Address,
+    index: usize,
+    targets: AddressMembershipTargets,
+) -> Result<(), ProofError> {
+    let address: Vec<F> = address
+        .iter()
+        .map(|&byte| F::from_canonical_u8(byte))
+        .collect();
+    witness.set_cap(&targets.merkle_cap, cap)?;
+    witness.set_elements(&targets.address, &address)?;
+    witness.set_bounded(&targets.index, index as u64, targets.tree_height)?;
+    witness.set_merkle_proof(&targets.merkle_proof, merkle_proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_address_membership() -> Result<()> {
+        let addresses: Vec<Address> = (0..8u8).map(|i| [i; 20]).collect();
+        let address_set = AddressSet::from_addresses(&addresses);
+        let fri = FriProfile::default();
+
+        // the leaves are keccak256 digests, the nodes above them Poseidon hashes
+        assert_eq!(
+            address_set.0.leaves[3],
+            keccak::digest_limbs::<F>(&keccak::keccak256(&[3; 20])).to_vec()
+        );
+
+        let (proof, verifier_data) = address_set.prove_membership(&addresses[5], &fri)?;
+        address_set.verify_membership(proof.clone(), &verifier_data)?;
+
+        // the proof is against the cap of the set
+        let other_set = AddressSet::from_addresses(&addresses[..4]);
+        assert!(other_set.verify_membership(proof, &verifier_data).is_err());
+        assert!(matches!(
+            other_set.prove_membership(&addresses[5], &fri),
+            Err(ProofError::UnknownAddress)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+//! Operator attestations: the relayer aggregating a batch signs its metadata together with
+//! the nullifiers of its signals, and the aggregation circuit verifies the signature, so
+//! that the aggregate proves which operator produced it. The signature is secp256k1 ECDSA,
+//! verified with the non-native gadgets of `plonky2_ecdsa` like credentials are
+
+use plonky2::field::secp256k1_base::Secp256K1Base;
+use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+use plonky2::field::types::{Field, Sample};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{VerifierCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::config::Hasher;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_ecdsa::curve::curve_types::{Curve, CurveScalar};
+use plonky2_ecdsa::curve::ecdsa::{
+    sign_message, verify_message, ECDSAPublicKey, ECDSASecretKey, ECDSASignature,
+};
+use plonky2_ecdsa::curve::secp256k1::Secp256K1;
+use plonky2_ecdsa::gadgets::curve::{AffinePointTarget, CircuitBuilderCurve};
+use plonky2_ecdsa::gadgets::ecdsa::{
+    verify_message_circuit, ECDSAPublicKeyTarget, ECDSASignatureTarget,
+};
+use plonky2_ecdsa::gadgets::nonnative::CircuitBuilderNonNative;
+use proof_error::ProofError;
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::credential::{
+    add_nonnative_limbs, hash_to_scalar, hash_to_scalar_circuit, limb_targets, nonnative_limbs,
+};
+use crate::recursion::{
+    register_aggregate_public_inputs, signal_input_targets, Aggregation, AggregationOptions,
+};
+use crate::signal::{Digest, Signal, C, F};
+use crate::verify;
+
+/// Prefixes the hash signed by an operator, so that it never coincides with a credential
+/// or with the hashes of `DomainTag`
+const ATTESTATION_TAG: u64 = 5;
+
+/// What an operator attests to about a batch besides its signals
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchMetadata {
+    pub epoch: u64,
+    /// The number of the batch in its epoch
+    pub batch: u64,
+}
+
+impl BatchMetadata {
+    fn elements(&self) -> [F; 2] {
+        [self.epoch, self.batch].map(F::from_noncanonical_u64)
+    }
+}
+
+/// A relayer signing the batches it aggregates
+pub struct Operator {
+    secret_key: ECDSASecretKey<Secp256K1>,
+}
+
+impl Operator {
+    pub fn new(secret_key: Secp256K1Scalar) -> Self {
+        Self {
+            secret_key: ECDSASecretKey(secret_key),
+        }
+    }
+
+    pub fn rand() -> Self {
+        Self::new(Secp256K1Scalar::rand())
+    }
+
+    pub fn public_key(&self) -> ECDSAPublicKey<Secp256K1> {
+        ECDSAPublicKey(
+            (CurveScalar(self.secret_key.0) * Secp256K1::GENERATOR_PROJECTIVE).to_affine(),
+        )
+    }
+
+    pub fn attest(
+        &self,
+        metadata: &BatchMetadata,
+        nullifiers: &[Digest],
+    ) -> ECDSASignature<Secp256K1> {
+        sign_message(attestation_message(metadata, nullifiers), self.secret_key)
+    }
+}
+
+/// The message signed by an operator: the hash of the metadata and of the nullifiers of
+/// the batch, see `credential::hash_to_scalar`
+pub fn attestation_message(metadata: &BatchMetadata, nullifiers: &[Digest]) -> Secp256K1Scalar {
+    let inputs: Vec<F> = [F::from_canonical_u64(ATTESTATION_TAG)]
+        .into_iter()
+        .chain(metadata.elements())
+        .chain(nullifiers.iter().flatten().copied())
+        .collect();
+    hash_to_scalar(PoseidonHash::hash_no_pad(&inputs).elements)
+}
+
+/// Whether `signature` is an attestation of `operator` for the batch, checked natively
+pub fn verify_attestation(
+    operator: &ECDSAPublicKey<Secp256K1>,
+    metadata: &BatchMetadata,
+    nullifiers: &[Digest],
+    signature: ECDSASignature<Secp256K1>,
+) -> bool {
+    verify_message(
+        attestation_message(metadata, nullifiers),
+        signature,
+        *operator,
+    )
+}
+
+/// The limbs of the coordinates of a public key, as registered by the circuit
+fn public_key_limbs(public_key: &ECDSAPublicKey<Secp256K1>) -> Vec<F> {
+    let num_limbs = (Secp256K1Base::BITS + 31) / 32;
+    [public_key.0.x, public_key.0.y]
+        .into_iter()
+        .flat_map(|coordinate| nonnative_limbs(coordinate, num_limbs))
+        .collect()
+}
+
+/// The public inputs of `AccessSet::aggregate_attested_batch`: those of the aggregation,
+/// followed by the metadata and the coordinates of the public key of the operator
+pub fn attested_public_inputs(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+    inner_verifier_only: Option<&VerifierOnlyCircuitData<C, 2>>,
+    metadata: &BatchMetadata,
+    operator: &ECDSAPublicKey<Secp256K1>,
+) -> Vec<F> {
+    let mut public_inputs = verify::aggregate_public_inputs(cap, topics_and_nullifiers);
+    public_inputs.extend(
+        inner_verifier_only
+            .map(verify::verifier_data_digest)
+            .into_iter()
+            .flatten(),
+    );
+    public_inputs.extend(metadata.elements());
+    public_inputs.extend(public_key_limbs(operator));
+    public_inputs
+}
+
+/// Checks an aggregate of `AccessSet::aggregate_attested_batch` for `metadata` signed by
+/// `operator`. `inner_verifier_only` is the verifier data of the signal circuit if the
+/// aggregation embeds it
+pub fn verify_attested_batch(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+    inner_verifier_only: Option<&VerifierOnlyCircuitData<C, 2>>,
+    metadata: &BatchMetadata,
+    operator: &ECDSAPublicKey<Secp256K1>,
+    proof_with_pis: &ProofWithPublicInputs<F, C, 2>,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<(), ProofError> {
+    let public_inputs = attested_public_inputs(
+        cap,
+        topics_and_nullifiers,
+        inner_verifier_only,
+        metadata,
+        operator,
+    );
+    verifier_data
+        .verify(ProofWithPublicInputs {
+            public_inputs,
+            proof: proof_with_pis.proof.clone(),
+        })
+        .map_err(ProofError::Verification)
+}
+
+impl AccessSet {
+    /// Like `aggregate_signal_batch_with_options`, also verifying in the circuit the
+    /// signature of `operator` over `metadata` and the nullifiers of the signals. The
+    /// public key of the operator is a public input, so that one circuit serves every
+    /// operator and verifiers tell who aggregated from the public inputs, see
+    /// `attested_public_inputs`. Attested aggregates aren't chained, whatever the options
+    pub fn aggregate_attested_batch(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+        options: &AggregationOptions,
+        operator: &Operator,
+        metadata: &BatchMetadata,
+    ) -> Result<Aggregation<C>, ProofError> {
+        let signals: Vec<(Digest, &Signal)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal))
+            .collect();
+        let nullifiers: Vec<Digest> = signals.iter().map(|(_, signal)| signal.nullifier).collect();
+        let public_key = operator.public_key();
+        let signature = operator.attest(metadata, &nullifiers);
+
+        let mut builder = CircuitBuilder::new(options.fri.circuit_config(true)?);
+        let mut pw = PartialWitness::new();
+        let (vd_target, proof_targets) =
+            self.add_signal_proofs(&mut builder, &mut pw, &signals, verifier_data)?;
+        let options = AggregationOptions {
+            chain: false,
+            ..*options
+        };
+        register_aggregate_public_inputs(&mut builder, &vd_target, &proof_targets, &options);
+
+        // the message signed by the operator, see `attestation_message`
+        let metadata_targets = builder.add_virtual_targets(2);
+        builder.register_public_inputs(&metadata_targets);
+        let tag = builder.constant(F::from_canonical_u64(ATTESTATION_TAG));
+        let inputs = [tag]
+            .into_iter()
+            .chain(metadata_targets.iter().copied())
+            .chain(
+                signal_input_targets(&proof_targets, self.cap_height())
+                    .into_iter()
+                    .flat_map(|(_, nullifier, _)| nullifier.iter().copied()),
+            )
+            .collect();
+        let hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        let message = hash_to_scalar_circuit(&mut builder, hash);
+
+        let x = add_nonnative_limbs::<Secp256K1Base>(&mut builder);
+        let y = add_nonnative_limbs::<Secp256K1Base>(&mut builder);
+        let key_targets: Vec<Target> = limb_targets(&x)
+            .into_iter()
+            .chain(limb_targets(&y))
+            .collect();
+        builder.register_public_inputs(&key_targets);
+        let point = AffinePointTarget::<Secp256K1> {
+            x: builder.biguint_to_nonnative(&x),
+            y: builder.biguint_to_nonnative(&y),
+        };
+        builder.curve_assert_valid(&point);
+
+        let r = add_nonnative_limbs::<Secp256K1Scalar>(&mut builder);
+        let s = add_nonnative_limbs::<Secp256K1Scalar>(&mut builder);
+        let signature_target = ECDSASignatureTarget {
+            r: builder.biguint_to_nonnative(&r),
+            s: builder.biguint_to_nonnative(&s),
+        };
+        verify_message_circuit(
+            &mut builder,
+            message,
+            signature_target,
+            ECDSAPublicKeyTarget(point),
+        );
+
+        let ECDSASignature {
+            r: r_value,
+            s: s_value,
+        } = signature;
+        let assignments = [
+            (metadata_targets, metadata.elements().to_vec()),
+            (key_targets, public_key_limbs(&public_key)),
+            (limb_targets(&r), nonnative_limbs(r_value, r.limbs.len())),
+            (limb_targets(&s), nonnative_limbs(s_value, s.limbs.len())),
+        ];
+        for (targets, values) in assignments {
+            for (target, value) in targets.into_iter().zip(values) {
+                pw.set_target(target, value);
+            }
+        }
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::prove_and_verify::<F, C, 2>(builder, pw)?;
+
+        Ok((proof_with_pis, circuit_data.verifier_data()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_attested_batch() -> Result<()> {
+        let access_set = fixtures::access_set(fixtures::log_size());
+        let topic = F::rand_array();
+        let (signal0, verifier_data) =
+            access_set.make_signal(fixtures::private_key(3), topic, 3)?;
+        let (signal1, _) = access_set.make_signal(fixtures::private_key(7), topic, 7)?;
+        let nullifiers = [signal0.nullifier, signal1.nullifier];
+        let signals = [(topic, signal0), (topic, signal1)];
+
+        let operator = Operator::rand();
+        let metadata = BatchMetadata { epoch: 4, batch: 1 };
+        assert!(verify_attestation(
+            &operator.public_key(),
+            &metadata,
+            &nullifiers,
+            operator.attest(&metadata, &nullifiers)
+        ));
+
+        let (proof, aggregate_verifier_data) = access_set.aggregate_attested_batch(
+            &signals,
+            &verifier_data,
+            &AggregationOptions::default(),
+            &operator,
+            &metadata,
+        )?;
+        let topics_and_nullifiers: Vec<(Digest, Digest)> = nullifiers
+            .iter()
+            .map(|&nullifier| (topic, nullifier))
+            .collect();
+        let verify = |metadata: &BatchMetadata, operator: &ECDSAPublicKey<Secp256K1>| {
+            verify_attested_batch(
+                &access_set.0.cap,
+                &topics_and_nullifiers,
+                None,
+                metadata,
+                operator,
+                &proof,
+                &aggregate_verifier_data,
+            )
+        };
+        verify(&metadata, &operator.public_key())?;
+
+        // the aggregate is bound to the operator and to the metadata
+        assert!(verify(&metadata, &Operator::rand().public_key()).is_err());
+        assert!(verify(
+            &BatchMetadata { epoch: 4, batch: 2 },
+            &operator.public_key()
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! Digests, e.g. nullifiers and roots, as the `bytes32` values of Ethereum contracts. The
+//! encoding is canonical, so that digests compare equal on chain and off chain exactly when
+//! they are equal:
+//! - element `i` of the digest fills bytes `8 * i..8 * (i + 1)`, the first element first;
+//! - each element is its canonical value below the Goldilocks order, big-endian, so the
+//!   encodings order like the digests compared element by element;
+//! - decoding rejects a word whose value is at or above the order, which no digest encodes
+//!   to, instead of reducing it, so each digest has a single encoding.
+
+use anyhow::{ensure, Result};
+use plonky2::field::types::{Field, Field64, PrimeField64};
+
+use crate::signal::{Digest, F};
+
+pub fn digest_to_bytes32(digest: &Digest) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for (word, element) in bytes.chunks_mut(8).zip(digest) {
+        word.copy_from_slice(&element.to_canonical_u64().to_be_bytes());
+    }
+    bytes
+}
+
+/// The digest encoded by `bytes`, failing if a word isn't a canonical element
+pub fn digest_from_bytes32(bytes: &[u8; 32]) -> Result<Digest> {
+    let mut digest = [F::ZERO; 4];
+    for (i, (element, word)) in digest.iter_mut().zip(bytes.chunks(8)).enumerate() {
+        let value = u64::from_be_bytes(word.try_into().unwrap());
+        ensure!(
+            value < F::ORDER,
+            "word {i} of the bytes32, {value:#x}, isn't below the field order"
+        );
+        *element = F::from_canonical_u64(value);
+    }
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Sample;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_bytes32() -> Result<()> {
+        let digest = [1, 2, 3, F::ORDER - 1].map(F::from_canonical_u64);
+        let bytes = digest_to_bytes32(&digest);
+        assert_eq!(bytes[..8], [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bytes[24..], (F::ORDER - 1).to_be_bytes());
+        assert_eq!(digest_from_bytes32(&bytes)?, digest);
+
+        for _ in 0..16 {
+            let digest = F::rand_array();
+            assert_eq!(digest_from_bytes32(&digest_to_bytes32(&digest))?, digest);
+        }
+
+        // the order itself would reduce to zero
+        let mut bytes = [0; 32];
+        bytes[8..16].copy_from_slice(&F::ORDER.to_be_bytes());
+        assert!(digest_from_bytes32(&bytes).is_err());
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn bytes32_are_canonical(bytes in prop::array::uniform32(any::<u8>())) {
+            // either the bytes encode a digest, and only them, or a word is out of range
+            match digest_from_bytes32(&bytes) {
+                Ok(digest) => prop_assert_eq!(digest_to_bytes32(&digest), bytes),
+                Err(_) => prop_assert!(bytes
+                    .chunks(8)
+                    .any(|word| u64::from_be_bytes(word.try_into().unwrap()) >= F::ORDER)),
+            }
+        }
+
+        #[test]
+        fn bytes32_order_like_digests(
+            lhs in prop::array::uniform4(0..F::ORDER),
+            rhs in prop::array::uniform4(0..F::ORDER),
+        ) {
+            let (lhs, rhs) = (lhs.map(F::from_canonical_u64), rhs.map(F::from_canonical_u64));
+            let key = |digest: &Digest| digest.map(|element| element.to_canonical_u64());
+            prop_assert_eq!(
+                digest_to_bytes32(&lhs).cmp(&digest_to_bytes32(&rhs)),
+                key(&lhs).cmp(&key(&rhs))
+            );
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Hash-chained aggregates: an aggregation with `AggregationOptions::chain` takes the digest
+//! of the previous aggregate as a public input, so that the aggregates published by a
+//! relayer form an append-only history, each committing to all those before it. A link
+//! proof verifies an aggregate in a recursion circuit and only exposes the digest it chains
+//! from and its own, so that the history is checked link by link, e.g. to timestamp an
+//! aggregate as published after another
+
+use anyhow::{ensure, Result};
+use plonky2::field::types::Field;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitData, CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::Hasher;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use proof_error::ProofError;
+use prover_utils::options::ProverOptions;
+use prover_utils::recursion_utils::{self, ProofTargets};
+use serde::{Deserialize, Serialize};
+
+use crate::layout::{InputKind, PublicInputLayout};
+use crate::recursion::Aggregation;
+use crate::signal::{Digest, C, F};
+use crate::verify;
+
+/// Prefixes the digests of aggregates, so that they never coincide with payloads,
+/// attestations, credentials or the hashes of `DomainTag`
+const CHAIN_TAG: u64 = 7;
+
+/// The digest the first aggregate of a chain follows
+pub const GENESIS: Digest = [F::ZERO; 4];
+
+/// The digest of an aggregate: the digest of the verifier data of its aggregation circuit,
+/// see `verify::verifier_data_digest`, and its public inputs, which hold the digest of the
+/// previous aggregate
+pub fn aggregate_digest(
+    public_inputs: &[F],
+    verifier_only: &VerifierOnlyCircuitData<C, 2>,
+) -> Digest {
+    let inputs: Vec<F> = [F::from_canonical_u64(CHAIN_TAG)]
+        .into_iter()
+        .chain(verify::verifier_data_digest(verifier_only))
+        .chain(public_inputs.iter().copied())
+        .collect();
+    PoseidonHash::hash_no_pad(&inputs).elements
+}
+
+/// A link of the chain, as exposed by the public inputs of a link proof
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainLink {
+    /// The digest of the previous aggregate
+    pub previous: Digest,
+    /// The digest of the verifier data of the aggregation circuit
+    pub verifier_data_digest: Digest,
+    /// The digest of the aggregate, which the next link follows
+    pub digest: Digest,
+}
+
+impl ChainLink {
+    /// previous ‖ verifier data digest ‖ digest
+    pub fn public_inputs(&self) -> Vec<F> {
+        [self.previous, self.verifier_data_digest, self.digest].concat()
+    }
+
+    pub fn from_public_inputs(public_inputs: &[F]) -> Result<Self> {
+        ensure!(
+            public_inputs.len() == 12,
+            "a link has 12 public inputs, not {}",
+            public_inputs.len()
+        );
+        let digest = |i: usize| -> Digest {
+            public_inputs[4 * i..4 * i + 4]
+                .try_into()
+                .expect("the digests have 4 elements")
+        };
+
+        Ok(Self {
+            previous: digest(0),
+            verifier_data_digest: digest(1),
+            digest: digest(2),
+        })
+    }
+}
+
+/// The circuit proving the links of the aggregates of an aggregation circuit of shape
+/// `common`, whose public inputs follow a chained layout. The verifier data of the
+/// aggregation circuit is a witness, exposed through its digest
+pub struct LinkCircuit {
+    circuit_data: CircuitData<F, C, 2>,
+    targets: ProofTargets<2>,
+}
+
+impl LinkCircuit {
+    pub fn new(
+        common: &CommonCircuitData<F, 2>,
+        layout: &PublicInputLayout,
+    ) -> Result<Self, ProofError> {
+        if common.num_public_inputs != layout.num_inputs() {
+            return Err(ProofError::PublicInputCount {
+                expected: layout.num_inputs(),
+                actual: common.num_public_inputs,
+            });
+        }
+        let previous = layout
+            .range_of(InputKind::PreviousAggregate, 0)
+            .ok_or(ProofError::UnchainedAggregate)?;
+
+        let mut builder = CircuitBuilder::new(prover_utils::standard_config(false));
+        let targets = recursion_utils::add_proof_targets::<F, C, 2>(&mut builder, 1, common)?;
+        let (vd_target, proof_targets) = &targets;
+        let public_inputs = &proof_targets[0].public_inputs;
+        builder.register_public_inputs(&public_inputs[previous]);
+
+        // the same hashes as `verify::verifier_data_digest` and `aggregate_digest`
+        let vd_inputs = vd_target
+            .constants_sigmas_cap
+            .0
+            .iter()
+            .chain([&vd_target.circuit_digest])
+            .flat_map(|hash| hash.elements)
+            .collect();
+        let vd_digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(vd_inputs);
+        builder.register_public_inputs(&vd_digest.elements);
+        let tag = builder.constant(F::from_canonical_u64(CHAIN_TAG));
+        let inputs = [tag]
+            .into_iter()
+            .chain(vd_digest.elements)
+            .chain(public_inputs.iter().copied())
+            .collect();
+        let digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        builder.register_public_inputs(&digest.elements);
+
+        Ok(Self {
+            circuit_data: builder.build::<C>(),
+            targets,
+        })
+    }
+
+    /// Proves the link of `aggregation`, whose public inputs are those of `ChainLink`
+    pub fn prove(
+        &self,
+        (proof_with_pis, verifier_data): &Aggregation<C>,
+    ) -> Result<ProofWithPublicInputs<F, C, 2>, ProofError> {
+        let mut pw = PartialWitness::new();
+        recursion_utils::set_proof_targets(
+            &mut pw,
+            &self.targets,
+            &[proof_with_pis.clone()],
+            verifier_data,
+        )?;
+        prover_utils::prove_with_options(&self.circuit_data, pw, &ProverOptions::default())
+    }
+
+    pub fn verifier_data(&self) -> VerifierCircuitData<F, C, 2> {
+        self.circuit_data.verifier_data()
+    }
+}
+
+/// Builds the link circuit of `aggregation`, laid out as `layout`, and proves its link
+pub fn prove_link(
+    aggregation: &Aggregation<C>,
+    layout: &PublicInputLayout,
+) -> Result<Aggregation<C>, ProofError> {
+    let circuit = LinkCircuit::new(&aggregation.1.common, layout)?;
+    let proof_with_pis = circuit.prove(aggregation)?;
+    Ok((proof_with_pis, circuit.verifier_data()))
+}
+
+/// Checks the link proofs of consecutive aggregates, the first following `previous`, e.g.
+/// `GENESIS`, and returns the digest of the last one, the head of the chain. The verifier
+/// data of the links is expected to be that of `LinkCircuit`, e.g. rebuilt by the caller
+pub fn verify_chain(previous: Digest, links: &[Aggregation<C>]) -> Result<Digest> {
+    let mut head = previous;
+    for (index, (proof_with_pis, verifier_data)) in links.iter().enumerate() {
+        let link = ChainLink::from_public_inputs(&proof_with_pis.public_inputs)?;
+        ensure!(
+            link.previous == head,
+            "link {} doesn't follow the aggregate before it",
+            index
+        );
+        verifier_data.verify(proof_with_pis.clone())?;
+        head = link.digest;
+    }
+
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::recursion::{AggregationOptions, Aggregator};
+
+    #[test]
+    fn test_chained_aggregates() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let verifier_data = access_set.signal_verifier_data();
+        let mut signals = Vec::new();
+        for i in 0..3 {
+            let topic = F::rand_array();
+            let (signal, _) = access_set.make_signal(fixtures::private_key(i), topic, i)?;
+            signals.push((topic, signal));
+        }
+
+        let mut aggregator = Aggregator::new(AggregationOptions {
+            chain: true,
+            ..Default::default()
+        });
+        let layout = PublicInputLayout::aggregate(access_set.cap_height(), 1, false).chained();
+        let mut links = Vec::new();
+        for signal in signals.chunks(1) {
+            let previous = aggregator.chain_head();
+            let aggregation = aggregator.aggregate(access_set, signal, &verifier_data)?;
+            let topics_and_nullifiers = [(signal[0].0, signal[0].1.nullifier)];
+            assert_eq!(
+                aggregation.0.public_inputs,
+                layout.arrange_chained(
+                    &access_set.0.cap,
+                    &topics_and_nullifiers,
+                    None,
+                    previous
+                )?
+            );
+            assert_eq!(
+                aggregator.chain_head(),
+                aggregate_digest(&aggregation.0.public_inputs, &aggregation.1.verifier_only)
+            );
+            links.push(prove_link(&aggregation, &layout)?);
+        }
+        assert_eq!(verify_chain(GENESIS, &links)?, aggregator.chain_head());
+
+        // links can't be dropped or reordered
+        assert!(verify_chain(GENESIS, &links[1..]).is_err());
+        links.swap(1, 2);
+        assert!(verify_chain(GENESIS, &links).is_err());
+
+        assert!(matches!(
+            prove_link(
+                &access_set.aggregate_signal_batch(&signals[..1], &verifier_data)?,
+                &PublicInputLayout::aggregate(access_set.cap_height(), 1, false)
+            ),
+            Err(ProofError::UnchainedAggregate)
+        ));
+
+        Ok(())
+    }
+}
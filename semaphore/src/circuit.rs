@@ -1,20 +1,30 @@
+use gadgets::builder_ext::CircuitBuilderExt;
 use plonky2::field::types::Field;
-use plonky2::hash::hash_types::{HashOutTarget, MerkleCapTarget};
-use plonky2::hash::merkle_proofs::MerkleProofTarget;
+use plonky2::hash::hash_types::MerkleCapTarget;
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleCap;
 use plonky2::hash::poseidon::PoseidonHash;
 use plonky2::iop::target::Target;
-use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+use prover_utils::builder_section;
+use prover_utils::witness::{Named, WitnessBuilder};
 
 use crate::access_set::AccessSet;
+use crate::domain::DomainTag;
+use crate::pow;
 use crate::signal::{Digest, F};
 
 pub struct SemaphoreTargets {
-    merkle_root: HashOutTarget,
-    topic: [Target; 4],
-    merkle_proof: MerkleProofTarget,
-    private_key: [Target; 4],
-    public_key_index: Target,
+    merkle_cap: Named<MerkleCapTarget>,
+    topic: Named<[Target; 4]>,
+    merkle_proof: Named<MerkleProofTarget>,
+    private_key: Named<[Target; 4]>,
+    public_key_index: Named<Target>,
+    /// The nonce of the proof of work, if the circuit is gated by one
+    nonce: Option<Named<Target>>,
+    tree_height: usize,
+    difficulty: usize,
 }
 
 impl AccessSet {
@@ -22,80 +32,172 @@ impl AccessSet {
         self.0.leaves.len().trailing_zeros() as usize
     }
 
+    /// The height of the cap committing to the access set, i.e. the number of levels of the
+    /// tree that are public inputs rather than part of the Merkle path
+    pub fn cap_height(&self) -> usize {
+        self.0.cap.0.len().trailing_zeros() as usize
+    }
+
     pub fn semaphore_circuit(&self, builder: &mut CircuitBuilder<F, 2>) -> SemaphoreTargets {
-        // Register public inputs
-        let merkle_root = builder.add_virtual_hash();
-        builder.register_public_inputs(&merkle_root.elements);
-        let nullifier = builder.add_virtual_hash();
-        builder.register_public_inputs(&nullifier.elements);
-        let topic: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
-        builder.register_public_inputs(&topic);
-
-        // Merkle proof
-        let merkle_proof = MerkleProofTarget {
-            siblings: builder.add_virtual_hashes(self.tree_height()),
-        };
-
-        // Verify public key Merkle proof
-        let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
-        let public_key_index = builder.add_virtual_target();
-        let public_key_index_bits = builder.split_le(public_key_index, self.tree_height());
-        let zero = builder.zero();
-
-        builder.verify_merkle_proof_to_cap::<PoseidonHash>(
-            [private_key, [zero; 4]].concat(),
-            &public_key_index_bits,
-            &MerkleCapTarget(vec![merkle_root]),
-            &merkle_proof,
-        );
-
-        // Check nullifier
-        let should_be_nullifier =
-            builder.hash_n_to_hash_no_pad::<PoseidonHash>([private_key, topic].concat());
-        for i in 0..4 {
-            builder.connect(nullifier.elements[i], should_be_nullifier.elements[i]);
-        }
+        self.semaphore_circuit_with_difficulty(builder, 0)
+            .expect("a signal without proof of work has no difficulty to check")
+    }
 
-        SemaphoreTargets {
-            merkle_root,
-            topic,
-            merkle_proof,
-            private_key,
-            public_key_index,
-        }
+    /// Like `semaphore_circuit`, gated by a proof of work of `difficulty` bits, see
+    /// `pow::pow_circuit`
+    pub fn semaphore_circuit_with_difficulty(
+        &self,
+        builder: &mut CircuitBuilder<F, 2>,
+        difficulty: usize,
+    ) -> Result<SemaphoreTargets, ProofError> {
+        semaphore_circuit(
+            builder,
+            self.tree_height(),
+            self.cap_height(),
+            &DomainTag::default(),
+            difficulty,
+        )
     }
 
     pub fn fill_semaphore_targets(
         &self,
-        pw: &mut PartialWitness<F>,
+        witness: &mut WitnessBuilder<F>,
         private_key: Digest,
         topic: Digest,
         public_key_index: usize,
         targets: SemaphoreTargets,
-    ) {
-        let SemaphoreTargets {
-            merkle_root,
-            topic: topic_target,
-            merkle_proof: merkle_proof_target,
-            private_key: private_key_target,
-            public_key_index: public_key_index_target,
-        } = targets;
-
-        pw.set_hash_target(merkle_root, self.0.cap.0[0]);
-        pw.set_target_arr(private_key_target, private_key);
-        pw.set_target_arr(topic_target, topic);
-        pw.set_target(
-            public_key_index_target,
-            F::from_canonical_usize(public_key_index),
-        );
-
-        let merkle_proof = self.0.prove(public_key_index);
-        for (ht, h) in merkle_proof_target
-            .siblings
-            .into_iter()
-            .zip(merkle_proof.siblings)
-        {
-            pw.set_hash_target(ht, h);
+    ) -> Result<(), ProofError> {
+        if public_key_index >= self.0.leaves.len() {
+            return Err(ProofError::MemberIndexOutOfRange {
+                index: public_key_index,
+                size: self.0.leaves.len(),
+            });
         }
+
+        let nullifier = DomainTag::default().nullifier(private_key, topic);
+        let nonce = pow::grind(nullifier, targets.difficulty)?;
+        fill_semaphore_targets(
+            witness,
+            &self.0.cap,
+            &self.0.prove(public_key_index),
+            private_key,
+            topic,
+            public_key_index,
+            nonce,
+            targets,
+        )
+    }
+}
+
+/// The circuit of a signal for an access set of height `tree_height` committed to by a cap
+/// of height `cap_height`, which only depends on the heights so that members can prove from
+/// their Merkle path alone. A higher cap shortens the path verified in the circuit, at the
+/// cost of `4 * 2^cap_height` public inputs. A `difficulty` above 0 gates the signal by a
+/// proof of work on its nullifier, which fails if it is 64 bits or more
+pub fn semaphore_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+    domain: &DomainTag,
+    difficulty: usize,
+) -> Result<SemaphoreTargets, ProofError> {
+    semaphore_circuit_with_leaf(builder, tree_height, cap_height, domain, difficulty, &[])
+}
+
+/// Like `semaphore_circuit`, for leaves made of the identity commitment followed by
+/// `leaf_suffix`, e.g. the attributes of the member, see `disclosure`
+pub(crate) fn semaphore_circuit_with_leaf(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+    domain: &DomainTag,
+    difficulty: usize,
+    leaf_suffix: &[Target],
+) -> Result<SemaphoreTargets, ProofError> {
+    pow::check_range(difficulty)?;
+
+    // Register public inputs
+    let merkle_cap = builder.add_virtual_cap(cap_height);
+    for hash in &merkle_cap.0 {
+        builder.register_public_inputs(&hash.elements);
+    }
+    let nullifier = builder.add_virtual_hash();
+    builder.register_public_inputs(&nullifier.elements);
+    let topic: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    builder.register_public_inputs(&topic);
+
+    // Merkle proof
+    let merkle_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(tree_height - cap_height),
+    };
+
+    // Verify public key Merkle proof
+    let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let public_key_index = builder.add_virtual_target();
+    builder_section!(builder, "commitment");
+    let commitment = domain.commitment_circuit(builder, private_key);
+
+    builder_section!(builder, "merkle_path");
+    builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+        [&commitment.elements[..], leaf_suffix].concat(),
+        public_key_index,
+        &merkle_cap,
+        &merkle_proof,
+    );
+
+    // Check nullifier
+    builder_section!(builder, "nullifier");
+    let should_be_nullifier = domain.nullifier_circuit(builder, private_key, topic);
+    for i in 0..4 {
+        builder.connect(nullifier.elements[i], should_be_nullifier.elements[i]);
+    }
+
+    // Check proof of work
+    let nonce = if difficulty > 0 {
+        builder_section!(builder, "pow");
+        let nonce = builder.add_virtual_target();
+        pow::pow_circuit(builder, nullifier, nonce, difficulty)?;
+        Some(Named::new("nonce", nonce))
+    } else {
+        None
+    };
+
+    Ok(SemaphoreTargets {
+        merkle_cap: Named::new("merkle cap", merkle_cap),
+        topic: Named::new("topic", topic),
+        merkle_proof: Named::new("merkle proof", merkle_proof),
+        private_key: Named::new("private key", private_key),
+        public_key_index: Named::new("public key index", public_key_index),
+        nonce,
+        tree_height,
+        difficulty,
+    })
+}
+
+/// Fills the targets of a signal from the Merkle path of the public key to `cap`, failing
+/// if the cap or the path doesn't have the heights of the circuit. The nonce is only set
+/// if the circuit is gated by a proof of work, see `pow::grind`
+#[allow(clippy::too_many_arguments)]
+pub fn fill_semaphore_targets(
+    witness: &mut WitnessBuilder<F>,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    private_key: Digest,
+    topic: Digest,
+    public_key_index: usize,
+    nonce: u64,
+    targets: SemaphoreTargets,
+) -> Result<(), ProofError> {
+    if let Some(nonce_target) = &targets.nonce {
+        witness.set(nonce_target, F::from_canonical_u64(nonce))?;
     }
+    witness.set_cap(&targets.merkle_cap, cap)?;
+    witness.set_elements(&targets.private_key, &private_key)?;
+    witness.set_elements(&targets.topic, &topic)?;
+    witness.set_bounded(
+        &targets.public_key_index,
+        public_key_index as u64,
+        targets.tree_height,
+    )?;
+    witness.set_merkle_proof(&targets.merkle_proof, merkle_proof)
 }
@@ -0,0 +1,398 @@
+//! Membership credentials: an issuer signs the identity commitment of a member together with
+//! its attributes with secp256k1 ECDSA, and the member proves in a circuit that it holds a
+//! credential of the issuer, disclosing some attributes and proving others in ranges without
+//! revealing its commitment
+
+use num::BigUint;
+use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+use plonky2::field::types::{Field, PrimeField, PrimeField64, Sample};
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::Hasher;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_ecdsa::curve::curve_types::{Curve, CurveScalar};
+use plonky2_ecdsa::curve::ecdsa::{
+    sign_message, verify_message, ECDSAPublicKey, ECDSASecretKey, ECDSASignature,
+};
+use plonky2_ecdsa::curve::secp256k1::Secp256K1;
+use plonky2_ecdsa::gadgets::biguint::{BigUintTarget, CircuitBuilderBiguint};
+use plonky2_ecdsa::gadgets::curve::CircuitBuilderCurve;
+use plonky2_ecdsa::gadgets::ecdsa::{
+    verify_message_circuit, ECDSAPublicKeyTarget, ECDSASignatureTarget,
+};
+use plonky2_ecdsa::gadgets::nonnative::{CircuitBuilderNonNative, NonNativeTarget};
+use plonky2_u32::gadgets::arithmetic_u32::U32Target;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::domain::DomainTag;
+use crate::signal::{Digest, C, F};
+
+/// The number of attributes of a credential, unused ones being zero
+pub const NUM_ATTRIBUTES: usize = 8;
+
+/// Attributes proven in a range are compared as integers of this many bits
+pub const ATTRIBUTE_BITS: usize = 32;
+
+/// Prefixes the hash signed by the issuer, so that it never coincides with the hashes of
+/// `DomainTag`
+const CREDENTIAL_TAG: u64 = 4;
+
+pub type Attributes = [F; NUM_ATTRIBUTES];
+
+/// The attributes of a member, signed by an issuer together with its identity commitment
+#[derive(Clone, Debug)]
+pub struct Credential {
+    pub commitment: Digest,
+    pub attributes: Attributes,
+    pub signature: ECDSASignature<Secp256K1>,
+}
+
+pub struct Issuer {
+    secret_key: ECDSASecretKey<Secp256K1>,
+}
+
+impl Issuer {
+    pub fn new(secret_key: Secp256K1Scalar) -> Self {
+        Self {
+            secret_key: ECDSASecretKey(secret_key),
+        }
+    }
+
+    pub fn rand() -> Self {
+        Self::new(Secp256K1Scalar::rand())
+    }
+
+    pub fn public_key(&self) -> ECDSAPublicKey<Secp256K1> {
+        ECDSAPublicKey(
+            (CurveScalar(self.secret_key.0) * Secp256K1::GENERATOR_PROJECTIVE).to_affine(),
+        )
+    }
+
+    /// Signs `attributes` for the member committed to by `commitment`, e.g. its leaf in an
+    /// access set
+    pub fn issue(&self, commitment: Digest, attributes: Attributes) -> Credential {
+        Credential {
+            commitment,
+            attributes,
+            signature: sign_message(credential_message(commitment, &attributes), self.secret_key),
+        }
+    }
+}
+
+fn credential_hash(commitment: Digest, attributes: &Attributes) -> Digest {
+    let inputs: Vec<F> = [F::from_canonical_u64(CREDENTIAL_TAG)]
+        .into_iter()
+        .chain(commitment)
+        .chain(*attributes)
+        .collect();
+    PoseidonHash::hash_no_pad(&inputs).elements
+}
+
+/// The message signed by the issuer: the hash of the commitment and attributes, see
+/// `hash_to_scalar`
+pub fn credential_message(commitment: Digest, attributes: &Attributes) -> Secp256K1Scalar {
+    hash_to_scalar(credential_hash(commitment, attributes))
+}
+
+/// A hash read as a little-endian integer of 32-bit limbs and reduced to a secp256k1 scalar,
+/// to be signed with ECDSA
+pub(crate) fn hash_to_scalar(hash: Digest) -> Secp256K1Scalar {
+    let limbs: Vec<u32> = hash
+        .into_iter()
+        .flat_map(|element| {
+            let value = element.to_canonical_u64();
+            [value as u32, (value >> 32) as u32]
+        })
+        .collect();
+    Secp256K1Scalar::from_noncanonical_biguint(BigUint::from_slice(&limbs))
+}
+
+impl Credential {
+    pub fn verify(&self, issuer: &ECDSAPublicKey<Secp256K1>) -> bool {
+        let message = credential_message(self.commitment, &self.attributes);
+        verify_message(message, self.signature, *issuer)
+    }
+}
+
+/// An attribute proven to be in `min..=max` without being disclosed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeRange {
+    pub index: usize,
+    pub min: u32,
+    pub max: u32,
+}
+
+/// What a credential proof reveals: the attributes at `disclosed`, which are its public
+/// inputs in that order, and that each of `ranges` holds
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Disclosure {
+    pub disclosed: Vec<usize>,
+    pub ranges: Vec<AttributeRange>,
+}
+
+impl Disclosure {
+    fn check(&self) -> Result<(), ProofError> {
+        let mut indices = self
+            .disclosed
+            .iter()
+            .chain(self.ranges.iter().map(|range| &range.index));
+        match indices.find(|&&index| index >= NUM_ATTRIBUTES) {
+            Some(&index) => Err(ProofError::InputOutOfRange {
+                index,
+                num_inputs: NUM_ATTRIBUTES,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct CredentialTargets {
+    private_key: Named<[Target; 4]>,
+    attributes: Named<[Target; NUM_ATTRIBUTES]>,
+    signature_r: Named<Vec<Target>>,
+    signature_s: Named<Vec<Target>>,
+    disclosure: Disclosure,
+}
+
+/// The 32-bit limbs of an element of `FF`, range checked
+pub(crate) fn add_nonnative_limbs<FF: Field>(builder: &mut CircuitBuilder<F, 2>) -> BigUintTarget {
+    let num_limbs = (FF::BITS + 31) / 32;
+    let limbs = (0..num_limbs)
+        .map(|_| {
+            let limb = builder.add_virtual_target();
+            builder.range_check(limb, 32);
+            U32Target(limb)
+        })
+        .collect();
+    BigUintTarget { limbs }
+}
+
+/// The circuit of a credential of `issuer` revealing `disclosure`. The member proves that
+/// the issuer signed the commitment of its private key, as computed by `domain`, together
+/// with its attributes. The commitment stays private, so that credential proofs of a member
+/// can't be linked to each other or to its signals
+pub fn credential_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    issuer: &ECDSAPublicKey<Secp256K1>,
+    domain: &DomainTag,
+    disclosure: &Disclosure,
+) -> Result<CredentialTargets, ProofError> {
+    disclosure.check()?;
+
+    let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let attributes: [Target; NUM_ATTRIBUTES] = builder
+        .add_virtual_targets(NUM_ATTRIBUTES)
+        .try_into()
+        .unwrap();
+    for &index in &disclosure.disclosed {
+        builder.register_public_input(attributes[index]);
+    }
+    for range in &disclosure.ranges {
+        let min = builder.constant(F::from_canonical_u32(range.min));
+        let max = builder.constant(F::from_canonical_u32(range.max));
+        let above_min = builder.sub(attributes[range.index], min);
+        let below_max = builder.sub(max, attributes[range.index]);
+        builder.range_check(above_min, ATTRIBUTE_BITS);
+        builder.range_check(below_max, ATTRIBUTE_BITS);
+    }
+
+    // the message signed by the issuer, see `credential_message`
+    let commitment = domain.commitment_circuit(builder, private_key);
+    let tag = builder.constant(F::from_canonical_u64(CREDENTIAL_TAG));
+    let inputs = [tag]
+        .into_iter()
+        .chain(commitment.elements)
+        .chain(attributes)
+        .collect();
+    let hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+    let message = hash_to_scalar_circuit(builder, hash);
+
+    let r = add_nonnative_limbs::<Secp256K1Scalar>(builder);
+    let s = add_nonnative_limbs::<Secp256K1Scalar>(builder);
+    let signature = ECDSASignatureTarget {
+        r: builder.biguint_to_nonnative(&r),
+        s: builder.biguint_to_nonnative(&s),
+    };
+    let issuer = ECDSAPublicKeyTarget(builder.constant_affine_point(issuer.0));
+    verify_message_circuit(builder, message, signature, issuer);
+
+    Ok(CredentialTargets {
+        private_key: Named::new("private key", private_key),
+        attributes: Named::new("attributes", attributes),
+        signature_r: Named::new("signature r", limb_targets(&r)),
+        signature_s: Named::new("signature s", limb_targets(&s)),
+        disclosure: disclosure.clone(),
+    })
+}
+
+/// `hash_to_scalar` in a circuit
+pub(crate) fn hash_to_scalar_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    hash: HashOutTarget,
+) -> NonNativeTarget<Secp256K1Scalar> {
+    let limbs = hash
+        .elements
+        .iter()
+        .flat_map(|&element| {
+            let (low, high) = builder.split_low_high(element, 32, 64);
+            [U32Target(low), U32Target(high)]
+        })
+        .collect();
+    builder.reduce::<Secp256K1Scalar>(&BigUintTarget { limbs })
+}
+
+pub(crate) fn limb_targets(x: &BigUintTarget) -> Vec<Target> {
+    x.limbs.iter().map(|limb| limb.0).collect()
+}
+
+/// The values of the limbs of `add_nonnative_limbs` for `x`
+pub(crate) fn nonnative_limbs<FF: PrimeField>(x: FF, num_limbs: usize) -> Vec<F> {
+    let digits = x.to_canonical_biguint().to_u32_digits();
+    (0..num_limbs)
+        .map(|i| F::from_canonical_u32(digits.get(i).copied().unwrap_or(0)))
+        .collect()
+}
+
+/// Fills the targets of a credential, failing before proving if an attribute isn't in the
+/// range the circuit proves
+pub fn fill_credential_targets(
+    witness: &mut WitnessBuilder<F>,
+    private_key: Digest,
+    credential: &Credential,
+    targets: CredentialTargets,
+) -> Result<(), ProofError> {
+    for range in &targets.disclosure.ranges {
+        let value = credential.attributes[range.index].to_canonical_u64();
+        if value < range.min as u64 || value > range.max as u64 {
+            return Err(ProofError::AttributeRange {
+                index: range.index,
+                value,
+                min: range.min as u64,
+                max: range.max as u64,
+            });
+        }
+    }
+
+    witness.set_elements(&targets.private_key, &private_key)?;
+    witness.set_elements(&targets.attributes, &credential.attributes)?;
+    let ECDSASignature { r, s } = credential.signature;
+    let r_limbs = nonnative_limbs(r, targets.signature_r.targets.len());
+    witness.set_elements(&targets.signature_r, &r_limbs)?;
+    let s_limbs = nonnative_limbs(s, targets.signature_s.targets.len());
+    witness.set_elements(&targets.signature_s, &s_limbs)
+}
+
+/// Proves that the member of `private_key` holds `credential` of `issuer`, revealing
+/// `disclosure`. The credential is checked before proving, as an invalid signature only
+/// shows up as an unsatisfied circuit otherwise
+pub fn prove_credential(
+    issuer: &ECDSAPublicKey<Secp256K1>,
+    disclosure: &Disclosure,
+    private_key: Digest,
+    credential: &Credential,
+    fri: &FriProfile,
+) -> Result<(ProofWithPublicInputs<F, C, 2>, VerifierCircuitData<F, C, 2>), ProofError> {
+    let domain = DomainTag::default();
+    if domain.commitment(private_key) != credential.commitment || !credential.verify(issuer) {
+        return Err(ProofError::InvalidCredential);
+    }
+
+    let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+    let mut witness = WitnessBuilder::new();
+    let targets = credential_circuit(&mut builder, issuer, &domain, disclosure)?;
+    fill_credential_targets(&mut witness, private_key, credential, targets)?;
+
+    let TimedProof {
+        circuit_data,
+        proof_with_pis,
+        ..
+    } = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+
+    Ok((proof_with_pis, circuit_data.verifier_data()))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::access_set::identity_commitment;
+
+    #[test]
+    fn test_credential() -> Result<()> {
+        let issuer = Issuer::rand();
+        let private_key = F::rand_array();
+        let mut attributes = [F::ZERO; NUM_ATTRIBUTES];
+        // e.g. a country code and an age
+        attributes[0] = F::from_canonical_u32(250);
+        attributes[1] = F::from_canonical_u32(34);
+        let credential = issuer.issue(identity_commitment(private_key), attributes);
+        assert!(credential.verify(&issuer.public_key()));
+        assert!(!credential.verify(&Issuer::rand().public_key()));
+
+        let disclosure = Disclosure {
+            disclosed: vec![0],
+            ranges: vec![AttributeRange {
+                index: 1,
+                min: 18,
+                max: 150,
+            }],
+        };
+        let fri = FriProfile::default();
+        let (proof, verifier_data) = prove_credential(
+            &issuer.public_key(),
+            &disclosure,
+            private_key,
+            &credential,
+            &fri,
+        )?;
+        assert_eq!(proof.public_inputs, [attributes[0]]);
+        verifier_data.verify(proof)?;
+
+        // an age out of the range, a credential of another member and an unknown index
+        let minor = Disclosure {
+            disclosed: Vec::new(),
+            ranges: vec![AttributeRange {
+                index: 1,
+                min: 40,
+                max: 150,
+            }],
+        };
+        assert!(matches!(
+            prove_credential(&issuer.public_key(), &minor, private_key, &credential, &fri),
+            Err(ProofError::AttributeRange { index: 1, .. })
+        ));
+        assert!(matches!(
+            prove_credential(
+                &issuer.public_key(),
+                &disclosure,
+                F::rand_array(),
+                &credential,
+                &fri
+            ),
+            Err(ProofError::InvalidCredential)
+        ));
+        let unknown = Disclosure {
+            disclosed: vec![NUM_ATTRIBUTES],
+            ranges: Vec::new(),
+        };
+        assert!(matches!(
+            prove_credential(
+                &issuer.public_key(),
+                &unknown,
+                private_key,
+                &credential,
+                &fri
+            ),
+            Err(ProofError::InputOutOfRange { .. })
+        ));
+
+        Ok(())
+    }
+}
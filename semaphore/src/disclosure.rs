@@ -0,0 +1,310 @@
+//! Selective disclosure of member attributes: the leaf of a member commits to a small
+//! vector of attributes after its identity commitment, and a signal reveals the attributes
+//! a `DisclosurePolicy` picks as public inputs and proves range predicates over others,
+//! e.g. an age of at least 18, keeping the rest hidden. Like the member itself, the hidden
+//! attributes are only hidden among those of the access set, whose leaves are public
+
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::circuit::{self, SemaphoreTargets};
+use crate::domain::DomainTag;
+use crate::signal::{Digest, PrivateKey, Signal, C, F, NULLIFIER_VERSION};
+
+/// The number of attributes committed to by the leaf of a member, unused ones being zero
+pub const NUM_MEMBER_ATTRIBUTES: usize = 4;
+
+/// Attributes under a range predicate are compared as integers of this many bits
+pub const PREDICATE_BITS: usize = 32;
+
+pub type MemberAttributes = [F; NUM_MEMBER_ATTRIBUTES];
+
+/// The leaf of a member with attributes: its identity commitment followed by the attributes
+pub fn attribute_leaf(
+    domain: &DomainTag,
+    private_key: PrivateKey,
+    attributes: &MemberAttributes,
+) -> Vec<F> {
+    [&domain.commitment(private_key)[..], &attributes[..]].concat()
+}
+
+/// An attribute proven to be in `min..=max` without being revealed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangePredicate {
+    pub index: usize,
+    pub min: u32,
+    pub max: u32,
+}
+
+/// What a signal reveals about the attributes of its member: the attributes at `revealed`,
+/// which follow the public inputs of the signal in that order, and that each of `ranges`
+/// holds. The policy is a constant of the signal circuit, so verifiers check it through the
+/// verifier data
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DisclosurePolicy {
+    pub revealed: Vec<usize>,
+    pub ranges: Vec<RangePredicate>,
+}
+
+impl DisclosurePolicy {
+    fn check(&self) -> Result<(), ProofError> {
+        let mut indices = self
+            .revealed
+            .iter()
+            .chain(self.ranges.iter().map(|range| &range.index));
+        match indices.find(|&&index| index >= NUM_MEMBER_ATTRIBUTES) {
+            Some(&index) => Err(ProofError::InputOutOfRange {
+                index,
+                num_inputs: NUM_MEMBER_ATTRIBUTES,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The attributes the policy reveals, in the order of the public inputs
+    pub fn reveal(&self, attributes: &MemberAttributes) -> Vec<F> {
+        self.revealed
+            .iter()
+            .map(|&index| attributes[index])
+            .collect()
+    }
+}
+
+pub struct DisclosureTargets {
+    semaphore: SemaphoreTargets,
+    attributes: Named<[Target; NUM_MEMBER_ATTRIBUTES]>,
+    policy: DisclosurePolicy,
+}
+
+/// The circuit of a signal whose leaves are `attribute_leaf`s, revealing the attributes of
+/// `policy` after the public inputs of the signal and proving its range predicates
+pub fn disclosure_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+    domain: &DomainTag,
+    policy: &DisclosurePolicy,
+) -> Result<DisclosureTargets, ProofError> {
+    policy.check()?;
+
+    let attributes: [Target; NUM_MEMBER_ATTRIBUTES] = builder
+        .add_virtual_targets(NUM_MEMBER_ATTRIBUTES)
+        .try_into()
+        .unwrap();
+    let semaphore = circuit::semaphore_circuit_with_leaf(
+        builder,
+        tree_height,
+        cap_height,
+        domain,
+        0,
+        &attributes,
+    )?;
+    for &index in &policy.revealed {
+        builder.register_public_input(attributes[index]);
+    }
+    for range in &policy.ranges {
+        let min = builder.constant(F::from_canonical_u32(range.min));
+        let max = builder.constant(F::from_canonical_u32(range.max));
+        let above_min = builder.sub(attributes[range.index], min);
+        let below_max = builder.sub(max, attributes[range.index]);
+        builder.range_check(above_min, PREDICATE_BITS);
+        builder.range_check(below_max, PREDICATE_BITS);
+    }
+
+    Ok(DisclosureTargets {
+        semaphore,
+        attributes: Named::new("attributes", attributes),
+        policy: policy.clone(),
+    })
+}
+
+impl AccessSet {
+    /// The access set of members with attributes, whose leaves are `attribute_leaf`s
+    pub fn from_members(members: &[(PrivateKey, MemberAttributes)]) -> Self {
+        let domain = DomainTag::default();
+        AccessSet(MerkleTree::new(
+            members
+                .iter()
+                .map(|(private_key, attributes)| attribute_leaf(&domain, *private_key, attributes))
+                .collect(),
+            0,
+        ))
+    }
+
+    /// Fills the targets of `disclosure_circuit`, failing before proving if an attribute
+    /// doesn't satisfy a range predicate of the policy
+    pub fn fill_disclosure_targets(
+        &self,
+        witness: &mut WitnessBuilder<F>,
+        private_key: Digest,
+        attributes: &MemberAttributes,
+        topic: Digest,
+        public_key_index: usize,
+        targets: DisclosureTargets,
+    ) -> Result<(), ProofError> {
+        for range in &targets.policy.ranges {
+            let value = attributes[range.index].to_canonical_u64();
+            if value < range.min as u64 || value > range.max as u64 {
+                return Err(ProofError::AttributeRange {
+                    index: range.index,
+                    value,
+                    min: range.min as u64,
+                    max: range.max as u64,
+                });
+            }
+        }
+
+        witness.set_elements(&targets.attributes, attributes)?;
+        self.fill_semaphore_targets(
+            witness,
+            private_key,
+            topic,
+            public_key_index,
+            targets.semaphore,
+        )
+    }
+
+    /// Like `make_signal_with_profile`, for a member of an access set of `from_members`
+    /// with `attributes`, revealing what `policy` discloses of them
+    pub fn make_signal_with_policy(
+        &self,
+        private_key: Digest,
+        attributes: &MemberAttributes,
+        topic: Digest,
+        public_key_index: usize,
+        policy: &DisclosurePolicy,
+        fri: &FriProfile,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        let domain = DomainTag::default();
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        let mut witness = WitnessBuilder::new();
+        let targets = disclosure_circuit(
+            &mut builder,
+            self.tree_height(),
+            self.cap_height(),
+            &domain,
+            policy,
+        )?;
+        self.fill_disclosure_targets(
+            &mut witness,
+            private_key,
+            attributes,
+            topic,
+            public_key_index,
+            targets,
+        )?;
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+
+        Ok((
+            Signal {
+                version: NULLIFIER_VERSION,
+                nullifier: domain.nullifier(private_key, topic),
+                nonce: 0,
+                proof: proof_with_pis.proof,
+                payload: None,
+                disclosed: policy.reveal(attributes),
+            },
+            circuit_data.verifier_data(),
+        ))
+    }
+
+    /// The verifier data of the signals of this access set disclosing `policy`
+    pub fn signal_verifier_data_with_policy(
+        &self,
+        policy: &DisclosurePolicy,
+        fri: &FriProfile,
+    ) -> Result<VerifierCircuitData<F, C, 2>, ProofError> {
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        disclosure_circuit(
+            &mut builder,
+            self.tree_height(),
+            self.cap_height(),
+            &DomainTag::default(),
+            policy,
+        )?;
+        Ok(builder.build::<C>().verifier_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::verify;
+
+    #[test]
+    fn test_signal_with_policy() -> Result<()> {
+        // attributes: country, age, then two hidden ones
+        let members: Vec<(PrivateKey, MemberAttributes)> = (0..4)
+            .map(|i| {
+                let attributes = [33, 20 + i, 7, 9].map(F::from_canonical_u64);
+                (F::rand_array(), attributes)
+            })
+            .collect();
+        let access_set = AccessSet::from_members(&members);
+        let topic = F::rand_array();
+        let fri = FriProfile::default();
+        let adult = DisclosurePolicy {
+            revealed: vec![0],
+            ranges: vec![RangePredicate {
+                index: 1,
+                min: 21,
+                max: 200,
+            }],
+        };
+
+        let (private_key, attributes) = members[2];
+        let (signal, verifier_data) =
+            access_set.make_signal_with_policy(private_key, &attributes, topic, 2, &adult, &fri)?;
+        assert_eq!(signal.disclosed, [F::from_canonical_u64(33)]);
+        assert_eq!(
+            verifier_data.verifier_only.circuit_digest,
+            access_set
+                .signal_verifier_data_with_policy(&adult, &fri)?
+                .verifier_only
+                .circuit_digest
+        );
+        let cap = &access_set.0.cap;
+        verify::verify_signal_with_disclosure(cap, topic, signal.clone(), &verifier_data)?;
+
+        // the revealed attributes are bound to the proof
+        let mut tampered = signal;
+        tampered.disclosed[0] += F::ONE;
+        assert!(
+            verify::verify_signal_with_disclosure(cap, topic, tampered, &verifier_data).is_err()
+        );
+
+        // a member outside of the range can't prove the predicate
+        let (private_key, attributes) = members[0];
+        assert!(matches!(
+            access_set.make_signal_with_policy(private_key, &attributes, topic, 0, &adult, &fri),
+            Err(ProofError::AttributeRange { index: 1, .. })
+        ));
+
+        let unknown = DisclosurePolicy {
+            revealed: vec![NUM_MEMBER_ATTRIBUTES],
+            ..Default::default()
+        };
+        assert!(matches!(
+            access_set.signal_verifier_data_with_policy(&unknown, &fri),
+            Err(ProofError::InputOutOfRange { .. })
+        ));
+
+        Ok(())
+    }
+}
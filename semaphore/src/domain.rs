@@ -0,0 +1,184 @@
+//! Domain separation of the Poseidon hashes of the protocol: each kind of hash is prefixed
+//! with its own tag, so that a commitment, a nullifier and a message digest never coincide
+
+use alloc::vec::Vec;
+
+use plonky2::field::types::Field;
+#[cfg(feature = "std")]
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::hash::poseidon::PoseidonHash;
+#[cfg(feature = "std")]
+use plonky2::iop::target::Target;
+#[cfg(feature = "std")]
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::signal::{Digest, F, NULLIFIER_VERSION};
+
+/// The tags of each kind of hash. The default tags are distinct small constants, and
+/// `personalized` derives tags specific to an application
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainTag {
+    pub commitment: F,
+    pub nullifier: F,
+    pub message: F,
+}
+
+impl Default for DomainTag {
+    fn default() -> Self {
+        Self {
+            commitment: F::ONE,
+            nullifier: F::TWO,
+            message: F::from_canonical_u64(3),
+        }
+    }
+}
+
+/// The bytes as field elements, after their number, so that byte strings differing only by
+/// trailing zeros, which the unpadded sponge would absorb into the same state, hash
+/// differently
+fn length_prefixed(bytes: &[u8]) -> Vec<F> {
+    core::iter::once(F::from_canonical_usize(bytes.len()))
+        .chain(bytes.iter().map(|b| F::from_canonical_u8(*b)))
+        .collect()
+}
+
+/// The identity commitment of the first access sets, without a tag
+pub fn legacy_commitment(private_key: Digest) -> Digest {
+    PoseidonHash::hash_no_pad(&[private_key, [F::ZERO; 4]].concat()).elements
+}
+
+impl DomainTag {
+    /// Tags derived from `personalization`, e.g. the name of an application, so that its
+    /// commitments and nullifiers can't be replayed in another application
+    pub fn personalized(personalization: &[u8]) -> Self {
+        let [commitment, nullifier, message, _] =
+            PoseidonHash::hash_no_pad(&length_prefixed(personalization)).elements;
+        Self {
+            commitment,
+            nullifier,
+            message,
+        }
+    }
+
+    fn hash(tag: F, inputs: &[F]) -> Digest {
+        PoseidonHash::hash_no_pad(&[&[tag], inputs].concat()).elements
+    }
+
+    /// The leaf of the access set committing to `private_key`. The tag is applied on top of
+    /// the legacy commitment, so that existing access sets can be migrated without the
+    /// private keys of their members
+    pub fn commitment(&self, private_key: Digest) -> Digest {
+        self.migrate_commitment(legacy_commitment(private_key))
+    }
+
+    pub fn migrate_commitment(&self, legacy: Digest) -> Digest {
+        Self::hash(self.commitment, &legacy)
+    }
+
+    /// The nullifier of `private_key` on `topic` under the current `NULLIFIER_VERSION`
+    pub fn nullifier(&self, private_key: Digest, topic: Digest) -> Digest {
+        self.versioned_nullifier(NULLIFIER_VERSION, private_key, topic)
+    }
+
+    /// The nullifier under the scheme of `version`, which is hashed after the tag
+    pub fn versioned_nullifier(&self, version: u32, private_key: Digest, topic: Digest) -> Digest {
+        let inputs: Vec<F> = [F::from_canonical_u32(version)]
+            .into_iter()
+            .chain(private_key)
+            .chain(topic)
+            .collect();
+        Self::hash(self.nullifier, &inputs)
+    }
+
+    /// The digest of an arbitrary message, e.g. the text of a topic, hashed after its length
+    pub fn message(&self, message: &[u8]) -> Digest {
+        Self::hash(self.message, &length_prefixed(message))
+    }
+}
+
+#[cfg(feature = "std")]
+impl DomainTag {
+    fn hash_circuit(
+        builder: &mut CircuitBuilder<F, 2>,
+        tag: F,
+        inputs: &[Target],
+    ) -> HashOutTarget {
+        let tag = builder.constant(tag);
+        builder.hash_n_to_hash_no_pad::<PoseidonHash>([&[tag], inputs].concat())
+    }
+
+    pub fn commitment_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, 2>,
+        private_key: [Target; 4],
+    ) -> HashOutTarget {
+        let zero = builder.zero();
+        let legacy =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>([private_key, [zero; 4]].concat());
+        Self::hash_circuit(builder, self.commitment, &legacy.elements)
+    }
+
+    /// The nullifier under the current `NULLIFIER_VERSION`, which is a constant of the
+    /// circuit
+    pub fn nullifier_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, 2>,
+        private_key: [Target; 4],
+        topic: [Target; 4],
+    ) -> HashOutTarget {
+        let version = builder.constant(F::from_canonical_u32(NULLIFIER_VERSION));
+        let inputs: Vec<Target> = [version]
+            .into_iter()
+            .chain(private_key)
+            .chain(topic)
+            .collect();
+        Self::hash_circuit(builder, self.nullifier, &inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Sample;
+
+    use super::*;
+
+    #[test]
+    fn test_domain_tags() {
+        let private_key = F::rand_array();
+        let topic = [F::ZERO; 4];
+        let default = DomainTag::default();
+        let personalized = DomainTag::personalized(b"eureka");
+
+        // a nullifier on the zero topic used to be the legacy commitment
+        assert_ne!(
+            default.nullifier(private_key, topic),
+            legacy_commitment(private_key)
+        );
+        assert_ne!(
+            default.commitment(private_key),
+            personalized.commitment(private_key)
+        );
+        assert_ne!(
+            default.nullifier(private_key, topic),
+            personalized.nullifier(private_key, topic)
+        );
+        assert_eq!(
+            default.migrate_commitment(legacy_commitment(private_key)),
+            default.commitment(private_key)
+        );
+        assert_ne!(
+            default.versioned_nullifier(NULLIFIER_VERSION + 1, private_key, topic),
+            default.nullifier(private_key, topic)
+        );
+
+        // trailing zero bytes aren't absorbed away
+        assert_ne!(default.message(b"yes"), default.message(b"yes\0"));
+        assert_ne!(default.message(b""), default.message(b"\0"));
+        assert_ne!(
+            DomainTag::personalized(b"eureka"),
+            DomainTag::personalized(b"eureka\0")
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Access sets shared by the tests, here and in the relayer with the `fixtures` feature,
+//! built once per size and process and cached on disk across runs, as hashing the tree of
+//! a large set takes minutes in debug builds. Their private keys are derived from the
+//! index of the member. The leaves are computed on each run, and the digests of the tree
+//! are cached with the hash of all of them, so that a cache built from other leaves isn't
+//! used. Circuit data isn't serializable with this version of plonky2, so only the tree is
+//! cached. Tests take sets of `log_size()` members, `2^10` unless `FIXTURE_LOG_SIZE` asks
+//! for more, e.g. 20 to prove against a realistic tree
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::merkle_tree::{MerkleCap, MerkleTree};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::Hasher;
+use rayon::prelude::*;
+
+use crate::access_set::{compute_commitments_par, AccessSet};
+use crate::signal::{Digest, F};
+
+const DEFAULT_LOG_SIZE: usize = 10;
+
+static FIXTURES: Mutex<BTreeMap<usize, &'static AccessSet>> = Mutex::new(BTreeMap::new());
+
+/// The log of the number of members of the sets of the tests
+pub fn log_size() -> usize {
+    env::var("FIXTURE_LOG_SIZE")
+        .ok()
+        .and_then(|log_size| log_size.parse().ok())
+        .unwrap_or(DEFAULT_LOG_SIZE)
+}
+
+/// The private key of member `index` of the fixtures
+pub fn private_key(index: usize) -> Digest {
+    PoseidonHash::hash_no_pad(&[F::from_canonical_usize(index)]).elements
+}
+
+/// The access set of the members `0..2^log_size`
+pub fn access_set(log_size: usize) -> &'static AccessSet {
+    // held while building, so that tests wanting the same set wait for it
+    let mut fixtures = FIXTURES.lock().unwrap_or_else(|e| e.into_inner());
+    fixtures.entry(log_size).or_insert_with(|| {
+        let private_keys: Vec<Digest> = (0..1 << log_size)
+            .into_par_iter()
+            .map(private_key)
+            .collect();
+        let leaves = compute_commitments_par(&private_keys);
+        let leaves_hash = PoseidonHash::hash_no_pad(&leaves.concat());
+        let tree = load_tree(log_size, &leaves, leaves_hash)
+            .unwrap_or_else(|| build_tree(log_size, leaves, leaves_hash));
+        Box::leak(Box::new(AccessSet(tree)))
+    })
+}
+
+fn cache_path(log_size: usize) -> PathBuf {
+    let target = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target"));
+    target
+        .join("fixtures")
+        .join(format!("access_set_{log_size}.bin"))
+}
+
+/// The hash of all leaves, and the digests and cap of their tree
+type CachedTree = (HashOut<F>, Vec<HashOut<F>>, MerkleCap<F, PoseidonHash>);
+
+/// The cached tree of `leaves`, unless it is missing or was built from other leaves, e.g.
+/// after a change of the domain tags
+fn load_tree(
+    log_size: usize,
+    leaves: &[Vec<F>],
+    leaves_hash: HashOut<F>,
+) -> Option<MerkleTree<F, PoseidonHash>> {
+    let bytes = fs::read(cache_path(log_size)).ok()?;
+    let (cached_hash, digests, cap): CachedTree = bincode::deserialize(&bytes).ok()?;
+    (cached_hash == leaves_hash).then(|| MerkleTree {
+        leaves: leaves.to_vec(),
+        digests,
+        cap,
+    })
+}
+
+/// Builds the tree and caches it, leaving it uncached if the target directory isn't
+/// writable
+fn build_tree(
+    log_size: usize,
+    leaves: Vec<Vec<F>>,
+    leaves_hash: HashOut<F>,
+) -> MerkleTree<F, PoseidonHash> {
+    let tree = MerkleTree::new(leaves, 0);
+
+    let path = cache_path(log_size);
+    let cached = (leaves_hash, &tree.digests, &tree.cap);
+    if let (Some(dir), Ok(bytes)) = (path.parent(), bincode::serialize(&cached)) {
+        let _ = fs::create_dir_all(dir).and_then(|()| fs::write(&path, bytes));
+    }
+    tree
+}
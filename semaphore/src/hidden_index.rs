@@ -0,0 +1,192 @@
+//! Membership in an access set with the position of the member strictly private: the
+//! circuit takes the bits of the leaf index as boolean witnesses, which its targets don't
+//! expose, so that no caller can register them, or an index they are split from, as public
+//! inputs and reveal the path of the member. The only public inputs are the cap, so the
+//! proofs of different members of a set prove the same statement
+
+use plonky2::hash::hash_types::MerkleCapTarget;
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use proof_error::ProofError;
+use prover_utils::witness::{Named, WitnessBuilder};
+
+use crate::access_set::AccessSet;
+use crate::domain::DomainTag;
+use crate::signal::{Digest, PrivateKey, F};
+
+#[derive(Clone)]
+pub struct HiddenIndexTargets {
+    merkle_cap: Named<MerkleCapTarget>,
+    merkle_proof: Named<MerkleProofTarget>,
+    private_key: Named<[Target; 4]>,
+    index_bits: Vec<Named<BoolTarget>>,
+}
+
+/// The circuit proving that the holder of a private key is a member of an access set of
+/// height `tree_height`, committed to by a cap of height `cap_height` as public inputs
+pub fn hidden_index_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+    domain: &DomainTag,
+) -> HiddenIndexTargets {
+    let merkle_cap = builder.add_virtual_cap(cap_height);
+    for hash in &merkle_cap.0 {
+        builder.register_public_inputs(&hash.elements);
+    }
+    let merkle_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(tree_height - cap_height),
+    };
+
+    let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let index_bits: Vec<BoolTarget> = (0..tree_height)
+        .map(|_| builder.add_virtual_bool_target_safe())
+        .collect();
+    let commitment = domain.commitment_circuit(builder, private_key);
+    builder.verify_merkle_proof_to_cap::<PoseidonHash>(
+        commitment.elements.to_vec(),
+        &index_bits,
+        &merkle_cap,
+        &merkle_proof,
+    );
+
+    HiddenIndexTargets {
+        merkle_cap: Named::new("merkle cap", merkle_cap),
+        merkle_proof: Named::new("merkle proof", merkle_proof),
+        private_key: Named::new("private key", private_key),
+        index_bits: index_bits
+            .into_iter()
+            .enumerate()
+            .map(|(i, bit)| Named::new(format!("index bit {i}"), bit))
+            .collect(),
+    }
+}
+
+/// Fills the targets of `hidden_index_circuit` from the Merkle path of the commitment of
+/// `private_key`, at `index`, to `cap`
+pub fn fill_hidden_index_targets(
+    witness: &mut WitnessBuilder<F>,
+    cap: &MerkleCap<F, PoseidonHash>,
+    merkle_proof: &MerkleProof<F, PoseidonHash>,
+    private_key: PrivateKey,
+    index: usize,
+    targets: HiddenIndexTargets,
+) -> Result<(), ProofError> {
+    let num_leaves = 1 << targets.index_bits.len();
+    if index >= num_leaves {
+        return Err(ProofError::MemberIndexOutOfRange {
+            index,
+            size: num_leaves,
+        });
+    }
+
+    witness.set_cap(&targets.merkle_cap, cap)?;
+    witness.set_elements(&targets.private_key, &private_key)?;
+    for (i, bit) in targets.index_bits.iter().enumerate() {
+        witness.set_bool(bit, (index >> i) & 1 == 1)?;
+    }
+    witness.set_merkle_proof(&targets.merkle_proof, merkle_proof)
+}
+
+impl AccessSet {
+    /// The circuit of `hidden_index_circuit` for this access set
+    pub fn hidden_index_circuit(&self, builder: &mut CircuitBuilder<F, 2>) -> HiddenIndexTargets {
+        hidden_index_circuit(
+            builder,
+            self.tree_height(),
+            self.cap_height(),
+            &DomainTag::default(),
+        )
+    }
+
+    pub fn fill_hidden_index_targets(
+        &self,
+        witness: &mut WitnessBuilder<F>,
+        private_key: Digest,
+        index: usize,
+        targets: HiddenIndexTargets,
+    ) -> Result<(), ProofError> {
+        if index >= self.0.leaves.len() {
+            return Err(ProofError::MemberIndexOutOfRange {
+                index,
+                size: self.0.leaves.len(),
+            });
+        }
+        fill_hidden_index_targets(
+            witness,
+            &self.0.cap,
+            &self.0.prove(index),
+            private_key,
+            index,
+            targets,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::iop::witness::PartialWitness;
+    use prover_utils::leakage::leakage_report;
+    use prover_utils::options::ProverOptions;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::signal::C;
+
+    #[test]
+    fn test_hidden_index_is_indistinguishable() -> Result<()> {
+        let access_set = fixtures::access_set(3);
+
+        // members at either end of the tree, whose paths share no node
+        for zero_knowledge in [true, false] {
+            let mut builder = CircuitBuilder::new(prover_utils::standard_config(zero_knowledge));
+            let targets = access_set.hidden_index_circuit(&mut builder);
+            let data = builder.build::<C>();
+            let witnesses = [0, 7].map(|index| -> Result<PartialWitness<F>> {
+                let mut witness = WitnessBuilder::new();
+                access_set.fill_hidden_index_targets(
+                    &mut witness,
+                    fixtures::private_key(index),
+                    index,
+                    targets.clone(),
+                )?;
+                Ok(witness.build())
+            });
+            let [first, last] = witnesses;
+            let (first, last) = (first?, last?);
+
+            // the statement is the cap alone
+            let proof = data.prove(first.clone())?;
+            assert_eq!(proof.public_inputs, data.prove(last.clone())?.public_inputs);
+            data.verify(proof)?;
+
+            let report = leakage_report(&data, [&first, &last], 4)?;
+            if zero_knowledge {
+                assert_eq!(report.equal_openings, 0);
+                assert!(report.score < 0.5, "{report:?}");
+            } else {
+                // the harness tells members apart when the circuit isn't blinded
+                assert_eq!(report.score, 1.0);
+            }
+        }
+
+        // the index should be that of the member
+        let mut builder = CircuitBuilder::new(prover_utils::standard_config(true));
+        let targets = access_set.hidden_index_circuit(&mut builder);
+        let data = builder.build::<C>();
+        let mut witness = WitnessBuilder::new();
+        access_set.fill_hidden_index_targets(&mut witness, fixtures::private_key(2), 3, targets)?;
+        assert!(prover_utils::prove_with_options(
+            &data,
+            witness.build(),
+            &ProverOptions::default()
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
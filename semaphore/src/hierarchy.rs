@@ -0,0 +1,326 @@
+//! Access sets of access sets, e.g. of the teams of an organization: the leaves of the
+//! parent are the roots of the children, and a signal proves in a single proof that its
+//! member is in a child and that the child is in the parent, revealing neither. The public
+//! inputs are those of a signal of the parent, so it's verified like one
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use plonky2::hash::hash_types::{HashOutTarget, MerkleCapTarget};
+use plonky2::hash::merkle_proofs::MerkleProofTarget;
+use plonky2::hash::merkle_tree::MerkleTree;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::domain::DomainTag;
+use crate::signal::{Digest, PrivateKey, Signal, C, F, NULLIFIER_VERSION};
+use crate::verify;
+
+/// A parent access set whose members are child access sets of the same size, each
+/// committed to by its root
+pub struct HierarchicalAccessSet {
+    pub parent: AccessSet,
+    pub children: Vec<AccessSet>,
+}
+
+pub struct HierarchicalTargets {
+    parent_root: Named<HashOutTarget>,
+    topic: Named<[Target; 4]>,
+    private_key: Named<[Target; 4]>,
+    member_index: Named<Target>,
+    member_proof: Named<MerkleProofTarget>,
+    child_root: Named<HashOutTarget>,
+    child_index: Named<Target>,
+    child_proof: Named<MerkleProofTarget>,
+    parent_height: usize,
+    child_height: usize,
+}
+
+impl HierarchicalAccessSet {
+    /// The parent of `children`, which should have a power of two members of the same
+    /// number and a cap of a single hash
+    pub fn new(children: Vec<AccessSet>) -> Result<Self, ProofError> {
+        if !children.len().is_power_of_two() {
+            return Err(ProofError::AccessSetSize(children.len()));
+        }
+        for (index, child) in children.iter().enumerate() {
+            if child.tree_height() != children[0].tree_height() || child.cap_height() != 0 {
+                return Err(ProofError::ChildAccessSet {
+                    index,
+                    expected: 1 << children[0].tree_height(),
+                    actual: child.0.leaves.len(),
+                    cap_height: child.cap_height(),
+                });
+            }
+        }
+
+        let roots = children
+            .iter()
+            .map(|child| child.0.cap.0[0].elements.to_vec())
+            .collect();
+        Ok(Self {
+            parent: AccessSet(MerkleTree::new(roots, 0)),
+            children,
+        })
+    }
+
+    /// The hierarchy of a child access set per group of members
+    pub fn from_private_keys(groups: &[Vec<PrivateKey>]) -> Result<Self, ProofError> {
+        Self::new(
+            groups
+                .iter()
+                .map(|private_keys| AccessSet::from_private_keys(private_keys))
+                .collect(),
+        )
+    }
+
+    /// The root of the parent, the public input identifying the hierarchy
+    pub fn root(&self) -> Digest {
+        self.parent.0.cap.0[0].elements
+    }
+
+    pub fn circuit(&self, builder: &mut CircuitBuilder<F, 2>) -> HierarchicalTargets {
+        hierarchical_circuit(
+            builder,
+            self.parent.tree_height(),
+            self.children[0].tree_height(),
+            &DomainTag::default(),
+        )
+    }
+
+    pub fn fill_targets(
+        &self,
+        witness: &mut WitnessBuilder<F>,
+        private_key: Digest,
+        topic: Digest,
+        child_index: usize,
+        member_index: usize,
+        targets: HierarchicalTargets,
+    ) -> Result<(), ProofError> {
+        let child = self
+            .children
+            .get(child_index)
+            .ok_or(ProofError::MemberIndexOutOfRange {
+                index: child_index,
+                size: self.children.len(),
+            })?;
+        if member_index >= child.0.leaves.len() {
+            return Err(ProofError::MemberIndexOutOfRange {
+                index: member_index,
+                size: child.0.leaves.len(),
+            });
+        }
+
+        witness.set_hash(&targets.parent_root, self.parent.0.cap.0[0])?;
+        witness.set_elements(&targets.topic, &topic)?;
+        witness.set_elements(&targets.private_key, &private_key)?;
+        witness.set_bounded(
+            &targets.member_index,
+            member_index as u64,
+            targets.child_height,
+        )?;
+        witness.set_merkle_proof(&targets.member_proof, &child.0.prove(member_index))?;
+        witness.set_hash(&targets.child_root, child.0.cap.0[0])?;
+        witness.set_bounded(
+            &targets.child_index,
+            child_index as u64,
+            targets.parent_height,
+        )?;
+        witness.set_merkle_proof(&targets.child_proof, &self.parent.0.prove(child_index))
+    }
+
+    /// A signal of the member at `member_index` of the child at `child_index`. Its nullifier
+    /// is the one of the member in a flat access set, so that a member signals once per
+    /// topic whichever set it signals in
+    pub fn make_signal(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        child_index: usize,
+        member_index: usize,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        self.make_signal_with_profile(
+            private_key,
+            topic,
+            child_index,
+            member_index,
+            &FriProfile::default(),
+        )
+    }
+
+    /// Like `make_signal`, proven with the FRI parameters of `fri`
+    pub fn make_signal_with_profile(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        child_index: usize,
+        member_index: usize,
+        fri: &FriProfile,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        let targets = self.circuit(&mut builder);
+        let mut witness = WitnessBuilder::new();
+        self.fill_targets(
+            &mut witness,
+            private_key,
+            topic,
+            child_index,
+            member_index,
+            targets,
+        )?;
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+
+        Ok((
+            Signal {
+                version: NULLIFIER_VERSION,
+                nullifier: DomainTag::default().nullifier(private_key, topic),
+                nonce: 0,
+                proof: proof_with_pis.proof,
+                payload: None,
+                disclosed: Vec::new(),
+            },
+            circuit_data.verifier_data(),
+        ))
+    }
+
+    /// The verifier data of the signals of this hierarchy, built without proving one
+    pub fn signal_verifier_data(&self) -> VerifierCircuitData<F, C, 2> {
+        let fri = FriProfile::default();
+        let mut builder = CircuitBuilder::new(
+            fri.circuit_config(true)
+                .expect("the standard FRI profile is sound"),
+        );
+        self.circuit(&mut builder);
+        builder.build::<C>().verifier_data()
+    }
+
+    pub fn verify_signal(
+        &self,
+        topic: Digest,
+        signal: Signal,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        verify::verify_signal(&self.parent.0.cap, topic, signal, verifier_data)
+            .map_err(ProofError::Verification)
+    }
+}
+
+/// The circuit of a signal of a member of a child of height `child_height` in a parent of
+/// height `parent_height`. Its public inputs are the root of the parent, the nullifier and
+/// the topic, as for a signal of an access set with a cap of a single hash
+pub fn hierarchical_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    parent_height: usize,
+    child_height: usize,
+    domain: &DomainTag,
+) -> HierarchicalTargets {
+    let parent_root = builder.add_virtual_hash();
+    builder.register_public_inputs(&parent_root.elements);
+    let nullifier = builder.add_virtual_hash();
+    builder.register_public_inputs(&nullifier.elements);
+    let topic: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    builder.register_public_inputs(&topic);
+
+    // the member is in the child
+    let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let member_index = builder.add_virtual_target();
+    let member_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(child_height),
+    };
+    let child_root = builder.add_virtual_hash();
+    let commitment = domain.commitment_circuit(builder, private_key);
+    builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+        commitment.elements.to_vec(),
+        member_index,
+        &MerkleCapTarget(vec![child_root]),
+        &member_proof,
+    );
+
+    // the child is in the parent
+    let child_index = builder.add_virtual_target();
+    let child_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(parent_height),
+    };
+    builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+        child_root.elements.to_vec(),
+        child_index,
+        &MerkleCapTarget(vec![parent_root]),
+        &child_proof,
+    );
+
+    let should_be_nullifier = domain.nullifier_circuit(builder, private_key, topic);
+    builder.connect_hashes(nullifier, should_be_nullifier);
+
+    HierarchicalTargets {
+        parent_root: Named::new("parent root", parent_root),
+        topic: Named::new("topic", topic),
+        private_key: Named::new("private key", private_key),
+        member_index: Named::new("member index", member_index),
+        member_proof: Named::new("member proof", member_proof),
+        child_root: Named::new("child root", child_root),
+        child_index: Named::new("child index", child_index),
+        child_proof: Named::new("child proof", child_proof),
+        parent_height,
+        child_height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_hierarchical_signal() -> Result<()> {
+        let groups: Vec<Vec<PrivateKey>> = (0..4)
+            .map(|group| {
+                (0..8)
+                    .map(|i| fixtures::private_key(8 * group + i))
+                    .collect()
+            })
+            .collect();
+        let hierarchy = HierarchicalAccessSet::from_private_keys(&groups)?;
+        let topic = F::rand_array();
+
+        let (signal, verifier_data) = hierarchy.make_signal(groups[2][5], topic, 2, 5)?;
+        assert_eq!(
+            signal.nullifier,
+            DomainTag::default().nullifier(groups[2][5], topic)
+        );
+        hierarchy.verify_signal(topic, signal.clone(), &verifier_data)?;
+        assert!(hierarchy
+            .verify_signal(F::rand_array(), signal, &verifier_data)
+            .is_err());
+
+        // a member of another child
+        let other = panic::catch_unwind(AssertUnwindSafe(|| {
+            hierarchy.make_signal(groups[1][5], topic, 2, 5)
+        }));
+        assert!(!matches!(other, Ok(Ok(_))));
+        assert!(matches!(
+            hierarchy.make_signal(groups[2][5], topic, 4, 5),
+            Err(ProofError::MemberIndexOutOfRange { index: 4, size: 4 })
+        ));
+        assert!(matches!(
+            HierarchicalAccessSet::from_private_keys(&[groups[0].clone(), groups[1][..4].to_vec()]),
+            Err(ProofError::ChildAccessSet { index: 1, .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+//! Explicit layouts of the public inputs of the signal and aggregation circuits, so that
+//! verifiers build, reorder and validate public inputs from a descriptor shipped with the
+//! verifier data rather than from an ordering implied by the circuits
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use anyhow::{anyhow, ensure, Result};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use serde::{Deserialize, Serialize};
+
+use crate::signal::{Digest, F};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputKind {
+    /// The cap of the access set, of `4 * 2^cap_height` elements
+    Cap,
+    Nullifier,
+    Topic,
+    /// The digest of the verifier data of the signal circuit, see
+    /// `verify::verifier_data_digest`
+    VerifierDataDigest,
+    /// The digest of the aggregate a chained aggregation follows, see `chain`
+    PreviousAggregate,
+}
+
+/// The fields of the public inputs of a circuit, in order. The fields of a kind are
+/// numbered in the order they appear, e.g. the nullifier of each signal of an aggregation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicInputLayout {
+    pub cap_height: usize,
+    pub fields: Vec<InputKind>,
+}
+
+impl PublicInputLayout {
+    /// The layout of a signal: cap ‖ nullifier ‖ topic
+    pub fn signal(cap_height: usize) -> Self {
+        Self {
+            cap_height,
+            fields: [InputKind::Cap, InputKind::Nullifier, InputKind::Topic].to_vec(),
+        }
+    }
+
+    /// The layout of an aggregation: the layout of each signal in turn, followed by the
+    /// digest of the verifier data of the signal circuit if it's embedded
+    pub fn aggregate(cap_height: usize, num_signals: usize, embed_verifier_data: bool) -> Self {
+        let signal = Self::signal(cap_height);
+        let fields = (0..num_signals)
+            .flat_map(|_| signal.fields.iter().copied())
+            .chain(embed_verifier_data.then_some(InputKind::VerifierDataDigest))
+            .collect();
+        Self { cap_height, fields }
+    }
+
+    /// The layout of an aggregation chained to the previous aggregate, see
+    /// `AggregationOptions::chain`: this layout followed by the digest of the previous one
+    pub fn chained(mut self) -> Self {
+        self.fields.push(InputKind::PreviousAggregate);
+        self
+    }
+
+    fn field_len(&self, kind: InputKind) -> usize {
+        match kind {
+            InputKind::Cap => 4 << self.cap_height,
+            _ => 4,
+        }
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.fields.iter().map(|&kind| self.field_len(kind)).sum()
+    }
+
+    /// The public inputs holding field `index` of `kind`, if the layout has it
+    pub fn range_of(&self, kind: InputKind, index: usize) -> Option<Range<usize>> {
+        let mut start = 0;
+        let mut seen = 0;
+        for &field in &self.fields {
+            let len = self.field_len(field);
+            if field == kind {
+                if seen == index {
+                    return Some(start..start + len);
+                }
+                seen += 1;
+            }
+            start += len;
+        }
+
+        None
+    }
+
+    fn count(&self, kind: InputKind) -> usize {
+        self.fields.iter().filter(|&&field| field == kind).count()
+    }
+
+    /// The public inputs of this layout for signals on `topics_and_nullifiers`, the `i`-th
+    /// nullifier and topic fields being those of the `i`-th signal
+    pub fn arrange(
+        &self,
+        cap: &MerkleCap<F, PoseidonHash>,
+        topics_and_nullifiers: &[(Digest, Digest)],
+        verifier_data_digest: Option<Digest>,
+    ) -> Result<Vec<F>> {
+        self.arrange_fields(cap, topics_and_nullifiers, verifier_data_digest, None)
+    }
+
+    /// Like `arrange`, for a chained layout following the aggregate of digest `previous`
+    pub fn arrange_chained(
+        &self,
+        cap: &MerkleCap<F, PoseidonHash>,
+        topics_and_nullifiers: &[(Digest, Digest)],
+        verifier_data_digest: Option<Digest>,
+        previous: Digest,
+    ) -> Result<Vec<F>> {
+        self.arrange_fields(
+            cap,
+            topics_and_nullifiers,
+            verifier_data_digest,
+            Some(previous),
+        )
+    }
+
+    fn arrange_fields(
+        &self,
+        cap: &MerkleCap<F, PoseidonHash>,
+        topics_and_nullifiers: &[(Digest, Digest)],
+        verifier_data_digest: Option<Digest>,
+        previous: Option<Digest>,
+    ) -> Result<Vec<F>> {
+        ensure!(
+            cap.0.len() == 1 << self.cap_height,
+            "the cap has {} hashes, the layout expects a cap of height {}",
+            cap.0.len(),
+            self.cap_height
+        );
+        for kind in [InputKind::Nullifier, InputKind::Topic] {
+            ensure!(
+                self.count(kind) == topics_and_nullifiers.len(),
+                "the layout has {} {:?} fields for {} signals",
+                self.count(kind),
+                kind,
+                topics_and_nullifiers.len()
+            );
+        }
+
+        let (mut nullifiers, mut topics) = (0, 0);
+        let mut public_inputs = Vec::with_capacity(self.num_inputs());
+        for &field in &self.fields {
+            match field {
+                InputKind::Cap => public_inputs.extend(cap.0.iter().flat_map(|h| h.elements)),
+                InputKind::Nullifier => {
+                    public_inputs.extend(topics_and_nullifiers[nullifiers].1);
+                    nullifiers += 1;
+                }
+                InputKind::Topic => {
+                    public_inputs.extend(topics_and_nullifiers[topics].0);
+                    topics += 1;
+                }
+                InputKind::VerifierDataDigest => public_inputs.extend(
+                    verifier_data_digest
+                        .ok_or_else(|| anyhow!("the layout expects a verifier data digest"))?,
+                ),
+                InputKind::PreviousAggregate => public_inputs.extend(
+                    previous.ok_or_else(|| anyhow!("the layout expects a previous aggregate"))?,
+                ),
+            }
+        }
+
+        Ok(public_inputs)
+    }
+
+    /// Moves public inputs laid out as `from` to this layout, each field to the position of
+    /// the field of the same kind and number. Fails if the layouts don't have the same
+    /// fields, e.g. for an aggregation of another number of signals
+    pub fn reorder(&self, from: &PublicInputLayout, public_inputs: &[F]) -> Result<Vec<F>> {
+        ensure!(
+            self.cap_height == from.cap_height && self.fields.len() == from.fields.len(),
+            "the layouts don't have the same fields"
+        );
+        ensure!(
+            public_inputs.len() == from.num_inputs(),
+            "expected {} public inputs, got {}",
+            from.num_inputs(),
+            public_inputs.len()
+        );
+
+        let mut seen = Vec::new();
+        let mut reordered = Vec::with_capacity(public_inputs.len());
+        for &field in &self.fields {
+            let index = seen.iter().filter(|&&kind| kind == field).count();
+            seen.push(field);
+            let range = from
+                .range_of(field, index)
+                .ok_or_else(|| anyhow!("{:?} field {} is missing from the layout", field, index))?;
+            reordered.extend_from_slice(&public_inputs[range]);
+        }
+
+        Ok(reordered)
+    }
+
+    /// Checks that `public_inputs` have the length of the layout and that each cap field
+    /// is `cap`, the access set the verifier expects
+    pub fn validate(&self, public_inputs: &[F], cap: &MerkleCap<F, PoseidonHash>) -> Result<()> {
+        ensure!(
+            public_inputs.len() == self.num_inputs(),
+            "expected {} public inputs, got {}",
+            self.num_inputs(),
+            public_inputs.len()
+        );
+        let expected: Vec<F> = cap.0.iter().flat_map(|h| h.elements).collect();
+        for index in 0..self.count(InputKind::Cap) {
+            let range = self.range_of(InputKind::Cap, index).unwrap();
+            ensure!(
+                public_inputs[range] == expected[..],
+                "cap {} isn't the cap of the access set",
+                index
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use plonky2::field::types::Sample;
+    use plonky2::hash::hash_types::HashOut;
+
+    use super::*;
+    use crate::verify;
+
+    #[test]
+    fn test_public_input_layout() -> Result<()> {
+        let cap = MerkleCap(
+            (0..2)
+                .map(|_| HashOut {
+                    elements: F::rand_array(),
+                })
+                .collect(),
+        );
+        let signals: Vec<(Digest, Digest)> =
+            (0..3).map(|_| (F::rand_array(), F::rand_array())).collect();
+
+        // the layouts describe the public inputs of the circuits
+        let layout = PublicInputLayout::aggregate(1, 3, false);
+        let public_inputs = layout.arrange(&cap, &signals, None)?;
+        assert_eq!(
+            public_inputs,
+            verify::aggregate_public_inputs(&cap, &signals)
+        );
+        assert_eq!(
+            PublicInputLayout::signal(1).arrange(&cap, &signals[..1], None)?,
+            verify::signal_public_inputs(&cap, signals[0].0, signals[0].1)
+        );
+        assert_eq!(
+            public_inputs[layout.range_of(InputKind::Nullifier, 2).unwrap()],
+            signals[2].1
+        );
+        layout.validate(&public_inputs, &cap)?;
+        assert!(layout.validate(&public_inputs[1..], &cap).is_err());
+        assert!(layout.arrange(&cap, &signals[..2], None).is_err());
+        assert!(PublicInputLayout::aggregate(1, 3, true)
+            .arrange(&cap, &signals, None)
+            .is_err());
+
+        // a layout putting topics before nullifiers
+        let swapped = PublicInputLayout {
+            cap_height: 1,
+            fields: (0..3)
+                .flat_map(|_| [InputKind::Cap, InputKind::Topic, InputKind::Nullifier])
+                .collect(),
+        };
+        let swapped_inputs = swapped.arrange(&cap, &signals, None)?;
+        assert_ne!(swapped_inputs, public_inputs);
+        assert_eq!(layout.reorder(&swapped, &swapped_inputs)?, public_inputs);
+        assert!(layout
+            .reorder(&PublicInputLayout::aggregate(1, 2, true), &public_inputs)
+            .is_err());
+
+        // a chained layout ends with the digest of the previous aggregate
+        let chained = layout.clone().chained();
+        let previous = F::rand_array();
+        let chained_inputs = chained.arrange_chained(&cap, &signals, None, previous)?;
+        assert_eq!(chained_inputs[..public_inputs.len()], public_inputs);
+        assert_eq!(
+            chained_inputs[chained.range_of(InputKind::PreviousAggregate, 0).unwrap()],
+            previous
+        );
+        assert!(chained.arrange(&cap, &signals, None).is_err());
+
+        Ok(())
+    }
+}
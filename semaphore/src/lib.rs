@@ -1,3 +1,43 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod access_set;
+#[cfg(feature = "addresses")]
+pub mod address_set;
+#[cfg(feature = "std")]
+pub mod accumulator;
+#[cfg(feature = "attestation")]
+pub mod attestation;
+pub mod bytes32;
+#[cfg(feature = "std")]
+pub mod chain;
+#[cfg(feature = "std")]
 pub mod circuit;
+#[cfg(feature = "credential")]
+pub mod credential;
+#[cfg(feature = "std")]
+pub mod disclosure;
+pub mod domain;
+#[cfg(all(any(test, feature = "fixtures"), feature = "std"))]
+pub mod fixtures;
+#[cfg(feature = "std")]
+pub mod hidden_index;
+#[cfg(feature = "std")]
+pub mod hierarchy;
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod mmr;
+#[cfg(feature = "std")]
+pub mod multi_leaf;
+#[cfg(feature = "payload")]
+pub mod payload;
+pub mod pow;
+#[cfg(feature = "std")]
+pub mod protocol;
+#[cfg(feature = "std")]
+pub mod recursion;
 pub mod signal;
+pub mod topic;
+pub mod verify;
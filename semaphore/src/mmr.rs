@@ -0,0 +1,327 @@
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, MerkleCapTarget};
+use plonky2::hash::merkle_proofs::{verify_merkle_proof_to_cap, MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+use proof_error::ProofError;
+use prover_utils::witness::{Named, WitnessBuilder};
+
+use crate::signal::{Digest, F};
+
+/// A Merkle mountain range of every accepted nullifier or aggregate root, together with
+/// the epoch it was accepted in. The leaves are hashed like those of plonky2's
+/// `MerkleTree`, so that each mountain is a Merkle tree with a single-hash cap
+#[derive(Clone, Debug, Default)]
+pub struct Mmr {
+    /// The nodes of the perfect subtrees by height, the leaf hashes first
+    nodes: Vec<Vec<HashOut<F>>>,
+}
+
+/// Proof that a leaf is in an MMR with `num_leaves` leaves: the Merkle path to the peak
+/// of its mountain, and every peak
+#[derive(Clone, Debug)]
+pub struct MmrProof {
+    pub leaf_index: usize,
+    pub num_leaves: usize,
+    pub siblings: Vec<HashOut<F>>,
+    pub peaks: Vec<HashOut<F>>,
+}
+
+/// The mountain containing `leaf_index` among `num_leaves` leaves, as its position among
+/// the peaks, its height and its first leaf
+fn mountain_of(num_leaves: usize, leaf_index: usize) -> Option<(usize, usize, usize)> {
+    let mut start = 0;
+    let mut peak_index = 0;
+    for height in (0..usize::BITS as usize).rev() {
+        if (num_leaves >> height) & 1 == 0 {
+            continue;
+        }
+        if leaf_index < start + (1 << height) {
+            return Some((peak_index, height, start));
+        }
+        start += 1 << height;
+        peak_index += 1;
+    }
+
+    None
+}
+
+fn leaf_data(epoch: u64, digest: Digest) -> Vec<F> {
+    [&[F::from_canonical_u64(epoch)], &digest[..]].concat()
+}
+
+/// Commits to the peaks together with the number of leaves, which fixes their heights
+fn bag_peaks(num_leaves: usize, peaks: &[HashOut<F>]) -> HashOut<F> {
+    let mut inputs = vec![F::from_canonical_usize(num_leaves)];
+    for peak in peaks {
+        inputs.extend(peak.elements);
+    }
+    PoseidonHash::hash_no_pad(&inputs)
+}
+
+impl Mmr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.first().map_or(0, Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `digest` as accepted in `epoch`, returning the index of its leaf
+    pub fn push(&mut self, epoch: u64, digest: Digest) -> usize {
+        let leaf_index = self.len();
+        let mut node = PoseidonHash::hash_or_noop(&leaf_data(epoch, digest));
+        let mut height = 0;
+        loop {
+            if self.nodes.len() == height {
+                self.nodes.push(Vec::new());
+            }
+            let level = &mut self.nodes[height];
+            level.push(node);
+            if level.len() % 2 == 1 {
+                break;
+            }
+            node = PoseidonHash::two_to_one(level[level.len() - 2], node);
+            height += 1;
+        }
+
+        leaf_index
+    }
+
+    /// The roots of the mountains, the highest first
+    pub fn peaks(&self) -> Vec<HashOut<F>> {
+        self.nodes
+            .iter()
+            .rev()
+            .filter(|level| level.len() % 2 == 1)
+            .map(|level| level[level.len() - 1])
+            .collect()
+    }
+
+    pub fn root(&self) -> HashOut<F> {
+        bag_peaks(self.len(), &self.peaks())
+    }
+
+    pub fn prove(&self, leaf_index: usize) -> Result<MmrProof, ProofError> {
+        let num_leaves = self.len();
+        let (_, height, _) =
+            mountain_of(num_leaves, leaf_index).ok_or(ProofError::LeafIndexOutOfRange {
+                index: leaf_index,
+                num_leaves,
+            })?;
+
+        Ok(MmrProof {
+            leaf_index,
+            num_leaves,
+            siblings: (0..height)
+                .map(|h| self.nodes[h][(leaf_index >> h) ^ 1])
+                .collect(),
+            peaks: self.peaks(),
+        })
+    }
+}
+
+impl MmrProof {
+    /// Checks that `digest` was accepted in `epoch` in the MMR with root `root`
+    pub fn verify(&self, epoch: u64, digest: Digest, root: HashOut<F>) -> Result<(), ProofError> {
+        let (peak_index, _, start) = mountain_of(self.num_leaves, self.leaf_index).ok_or(
+            ProofError::LeafIndexOutOfRange {
+                index: self.leaf_index,
+                num_leaves: self.num_leaves,
+            },
+        )?;
+        if bag_peaks(self.num_leaves, &self.peaks) != root {
+            return Err(ProofError::Verification(anyhow::anyhow!(
+                "the peaks don't match the root"
+            )));
+        }
+
+        verify_merkle_proof_to_cap::<F, PoseidonHash>(
+            leaf_data(epoch, digest),
+            self.leaf_index - start,
+            &MerkleCap(vec![self.peaks[peak_index]]),
+            &MerkleProof {
+                siblings: self.siblings.clone(),
+            },
+        )
+        .map_err(ProofError::Verification)
+    }
+}
+
+/// Targets of an MMR inclusion proof. The shape of the MMR and the mountain of the leaf
+/// are fixed when building the circuit, the position in the mountain is part of the witness
+pub struct MmrProofTargets {
+    pub epoch: Target,
+    pub digest: [Target; 4],
+    pub leaf_index: Target,
+    pub root: HashOutTarget,
+    siblings: MerkleProofTarget,
+    peaks: Vec<HashOutTarget>,
+}
+
+/// Checks in the circuit that a digest was accepted in an epoch, for a leaf of the mountain
+/// containing `leaf_index` in an MMR of `num_leaves` leaves
+pub fn mmr_inclusion_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    num_leaves: usize,
+    leaf_index: usize,
+) -> Result<MmrProofTargets, ProofError> {
+    let (peak_index, height, start) =
+        mountain_of(num_leaves, leaf_index).ok_or(ProofError::LeafIndexOutOfRange {
+            index: leaf_index,
+            num_leaves,
+        })?;
+
+    let epoch = builder.add_virtual_target();
+    let digest: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    let leaf_index = builder.add_virtual_target();
+    let root = builder.add_virtual_hash();
+    let siblings = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(height),
+    };
+    let peaks = builder.add_virtual_hashes(num_leaves.count_ones() as usize);
+
+    // the position in the mountain, which `split_le` bounds by its number of leaves
+    let start_target = builder.constant(F::from_canonical_usize(start));
+    let offset = builder.sub(leaf_index, start_target);
+    let offset_bits = builder.split_le(offset, height);
+    builder.verify_merkle_proof_to_cap::<PoseidonHash>(
+        [&[epoch], &digest[..]].concat(),
+        &offset_bits,
+        &MerkleCapTarget(vec![peaks[peak_index]]),
+        &siblings,
+    );
+
+    let mut bag_inputs = vec![builder.constant(F::from_canonical_usize(num_leaves))];
+    for peak in &peaks {
+        bag_inputs.extend(peak.elements);
+    }
+    let bagged = builder.hash_n_to_hash_no_pad::<PoseidonHash>(bag_inputs);
+    builder.connect_hashes(bagged, root);
+
+    Ok(MmrProofTargets {
+        epoch,
+        digest,
+        leaf_index,
+        root,
+        siblings,
+        peaks,
+    })
+}
+
+/// Fills the targets of an inclusion proof, failing if the proof isn't of an MMR of the
+/// shape of the circuit
+pub fn fill_mmr_proof_targets(
+    witness: &mut WitnessBuilder<F>,
+    targets: &MmrProofTargets,
+    epoch: u64,
+    digest: Digest,
+    proof: &MmrProof,
+    root: HashOut<F>,
+) -> Result<(), ProofError> {
+    witness.set(
+        &Named::new("epoch", targets.epoch),
+        F::from_canonical_u64(epoch),
+    )?;
+    witness.set_elements(&Named::new("digest", targets.digest), &digest)?;
+    witness.set(
+        &Named::new("leaf index", targets.leaf_index),
+        F::from_canonical_usize(proof.leaf_index),
+    )?;
+    witness.set_hash(&Named::new("root", targets.root), root)?;
+    witness.set_merkle_proof(
+        &Named::new("siblings", targets.siblings.clone()),
+        &MerkleProof::<F, PoseidonHash> {
+            siblings: proof.siblings.clone(),
+        },
+    )?;
+    witness.set_cap(
+        &Named::new("peaks", MerkleCapTarget(targets.peaks.clone())),
+        &MerkleCap::<F, PoseidonHash>(proof.peaks.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+    use crate::signal::C;
+
+    fn make_mmr(num_leaves: usize) -> (Mmr, Vec<(u64, Digest)>) {
+        let mut mmr = Mmr::new();
+        let entries: Vec<(u64, Digest)> = (0..num_leaves)
+            .map(|i| (i as u64 / 3, F::rand_array()))
+            .collect();
+        for (i, (epoch, digest)) in entries.iter().enumerate() {
+            assert_eq!(mmr.push(*epoch, *digest), i);
+        }
+        (mmr, entries)
+    }
+
+    #[test]
+    fn test_mmr_proofs() -> Result<()> {
+        // mountains of 4, 2 and 1 leaves
+        let (mmr, entries) = make_mmr(7);
+        assert_eq!(mmr.peaks().len(), 3);
+
+        let root = mmr.root();
+        for (i, (epoch, digest)) in entries.iter().enumerate() {
+            let proof = mmr.prove(i)?;
+            proof.verify(*epoch, *digest, root)?;
+            assert!(proof.verify(epoch + 1, *digest, root).is_err());
+        }
+        assert!(matches!(
+            mmr.prove(7),
+            Err(ProofError::LeafIndexOutOfRange {
+                index: 7,
+                num_leaves: 7
+            })
+        ));
+
+        // proofs are against the root at the time they were made
+        let (mut mmr, entries) = make_mmr(5);
+        let proof = mmr.prove(2)?;
+        let (epoch, digest) = entries[2];
+        mmr.push(2, F::rand_array());
+        assert!(proof.verify(epoch, digest, mmr.root()).is_err());
+        mmr.prove(2)?.verify(epoch, digest, mmr.root())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mmr_inclusion_circuit() -> Result<()> {
+        let (mmr, entries) = make_mmr(7);
+        let root = mmr.root();
+
+        for i in [1, 5, 6] {
+            let mut builder =
+                CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+            let targets = mmr_inclusion_circuit(&mut builder, mmr.len(), i)?;
+            builder.register_public_input(targets.epoch);
+            builder.register_public_inputs(&targets.root.elements);
+
+            let (epoch, digest) = entries[i];
+            let mut witness = WitnessBuilder::new();
+            fill_mmr_proof_targets(&mut witness, &targets, epoch, digest, &mmr.prove(i)?, root)?;
+
+            let data = builder.build::<C>();
+            let proof = data.prove(witness.build())?;
+            assert_eq!(proof.public_inputs[0], F::from_canonical_u64(epoch));
+            data.verify(proof)?;
+        }
+
+        Ok(())
+    }
+}
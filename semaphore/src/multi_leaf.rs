@@ -0,0 +1,281 @@
+//! Signals proving membership of several leaves at once, e.g. a member key and the keys of
+//! its devices. The leaves of a member fill an aligned block of the access set, so the
+//! circuit hashes the block into the root of its subtree and verifies a single Merkle path
+//! from it to the cap, shared by every leaf, instead of a path per leaf. The nullifier is
+//! that of the first key, so the signal is verified like any other against the verifier
+//! data of the circuit
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use plonky2::hash::hash_types::{HashOutTarget, MerkleCapTarget};
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::domain::DomainTag;
+use crate::signal::{Digest, PrivateKey, Signal, C, F, NULLIFIER_VERSION};
+
+pub struct MultiLeafTargets {
+    merkle_cap: Named<MerkleCapTarget>,
+    topic: Named<[Target; 4]>,
+    merkle_proof: Named<MerkleProofTarget>,
+    private_keys: Vec<Named<[Target; 4]>>,
+    block_index: Named<Target>,
+    /// The height of the tree of the blocks, above the subtrees of the leaves of a member
+    block_height: usize,
+}
+
+/// The circuit of a signal of `num_leaves` keys, whose commitments are the leaves of an
+/// aligned block of an access set of height `tree_height` committed to by a cap of height
+/// `cap_height`. Its public inputs are those of `circuit::semaphore_circuit`
+pub fn multi_leaf_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    tree_height: usize,
+    cap_height: usize,
+    domain: &DomainTag,
+    num_leaves: usize,
+) -> Result<MultiLeafTargets, ProofError> {
+    let subtree_height = num_leaves.trailing_zeros() as usize;
+    if !num_leaves.is_power_of_two() || subtree_height > tree_height - cap_height {
+        return Err(ProofError::LeafCount(num_leaves));
+    }
+    let block_height = tree_height - subtree_height;
+
+    let merkle_cap = builder.add_virtual_cap(cap_height);
+    for hash in &merkle_cap.0 {
+        builder.register_public_inputs(&hash.elements);
+    }
+    let nullifier = builder.add_virtual_hash();
+    builder.register_public_inputs(&nullifier.elements);
+    let topic: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+    builder.register_public_inputs(&topic);
+
+    // the root of the subtree of the block, from the commitments of its keys
+    let private_keys: Vec<[Target; 4]> = (0..num_leaves)
+        .map(|_| builder.add_virtual_targets(4).try_into().unwrap())
+        .collect();
+    let mut nodes: Vec<HashOutTarget> = private_keys
+        .iter()
+        .map(|&private_key| domain.commitment_circuit(builder, private_key))
+        .collect();
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| {
+                builder.hash_n_to_hash_no_pad::<PoseidonHash>(
+                    [pair[0].elements, pair[1].elements].concat(),
+                )
+            })
+            .collect();
+    }
+
+    // a single path from the block to the cap
+    let merkle_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(block_height - cap_height),
+    };
+    let block_index = builder.add_virtual_target();
+    builder.verify_merkle_membership_to_cap::<PoseidonHash>(
+        nodes[0].elements.to_vec(),
+        block_index,
+        &merkle_cap,
+        &merkle_proof,
+    );
+
+    let should_be_nullifier = domain.nullifier_circuit(builder, private_keys[0], topic);
+    builder.connect_hashes(nullifier, should_be_nullifier);
+
+    Ok(MultiLeafTargets {
+        merkle_cap: Named::new("merkle cap", merkle_cap),
+        topic: Named::new("topic", topic),
+        merkle_proof: Named::new("merkle proof", merkle_proof),
+        private_keys: private_keys
+            .into_iter()
+            .enumerate()
+            .map(|(i, private_key)| Named::new(format!("private key {i}"), private_key))
+            .collect(),
+        block_index: Named::new("block index", block_index),
+        block_height,
+    })
+}
+
+impl AccessSet {
+    /// Fills the targets of `multi_leaf_circuit` for the keys of the leaves from
+    /// `first_index`, which should start a block of as many leaves
+    pub fn fill_multi_leaf_targets(
+        &self,
+        witness: &mut WitnessBuilder<F>,
+        private_keys: &[PrivateKey],
+        topic: Digest,
+        first_index: usize,
+        targets: MultiLeafTargets,
+    ) -> Result<(), ProofError> {
+        let num_leaves = targets.private_keys.len();
+        if private_keys.len() != num_leaves {
+            return Err(ProofError::WitnessCount {
+                expected: num_leaves,
+                actual: private_keys.len(),
+            });
+        }
+        if first_index + num_leaves > self.0.leaves.len() {
+            return Err(ProofError::MemberIndexOutOfRange {
+                index: first_index + num_leaves - 1,
+                size: self.0.leaves.len(),
+            });
+        }
+        if first_index % num_leaves != 0 {
+            return Err(ProofError::UnalignedLeaves {
+                index: first_index,
+                num_leaves,
+            });
+        }
+
+        // the path of the first leaf, above the subtree of the block
+        let subtree_height = num_leaves.trailing_zeros() as usize;
+        let merkle_proof = MerkleProof::<F, PoseidonHash> {
+            siblings: self.0.prove(first_index).siblings[subtree_height..].to_vec(),
+        };
+
+        witness.set_cap(&targets.merkle_cap, &self.0.cap)?;
+        witness.set_elements(&targets.topic, &topic)?;
+        for (target, private_key) in targets.private_keys.iter().zip(private_keys) {
+            witness.set_elements(target, private_key)?;
+        }
+        witness.set_bounded(
+            &targets.block_index,
+            (first_index >> subtree_height) as u64,
+            targets.block_height,
+        )?;
+        witness.set_merkle_proof(&targets.merkle_proof, &merkle_proof)
+    }
+
+    /// Like `make_signal`, proving that the member holds the keys of the leaves from
+    /// `first_index`, e.g. its own and those of its devices. The nullifier is that of the
+    /// first key
+    pub fn make_signal_multi_leaf(
+        &self,
+        private_keys: &[PrivateKey],
+        topic: Digest,
+        first_index: usize,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        self.make_signal_multi_leaf_with_profile(
+            private_keys,
+            topic,
+            first_index,
+            &FriProfile::default(),
+        )
+    }
+
+    /// Like `make_signal_multi_leaf`, proven with the FRI parameters of `fri`
+    pub fn make_signal_multi_leaf_with_profile(
+        &self,
+        private_keys: &[PrivateKey],
+        topic: Digest,
+        first_index: usize,
+        fri: &FriProfile,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        let domain = DomainTag::default();
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        let mut witness = WitnessBuilder::new();
+        let targets = multi_leaf_circuit(
+            &mut builder,
+            self.tree_height(),
+            self.cap_height(),
+            &domain,
+            private_keys.len(),
+        )?;
+        self.fill_multi_leaf_targets(&mut witness, private_keys, topic, first_index, targets)?;
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+
+        Ok((
+            Signal {
+                version: NULLIFIER_VERSION,
+                nullifier: domain.nullifier(private_keys[0], topic),
+                nonce: 0,
+                proof: proof_with_pis.proof,
+                payload: None,
+                disclosed: Vec::new(),
+            },
+            circuit_data.verifier_data(),
+        ))
+    }
+
+    /// The verifier data of the signals of `make_signal_multi_leaf` with `num_leaves` keys
+    pub fn signal_verifier_data_multi_leaf(
+        &self,
+        num_leaves: usize,
+        fri: &FriProfile,
+    ) -> Result<VerifierCircuitData<F, C, 2>, ProofError> {
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        multi_leaf_circuit(
+            &mut builder,
+            self.tree_height(),
+            self.cap_height(),
+            &DomainTag::default(),
+            num_leaves,
+        )?;
+        Ok(builder.build::<C>().verifier_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::verify;
+
+    #[test]
+    fn test_multi_leaf_signal() -> Result<()> {
+        // a member key followed by a device key, for each of four members
+        let private_keys: Vec<PrivateKey> = (0..8).map(fixtures::private_key).collect();
+        let access_set = fixtures::access_set(3);
+        let topic = F::rand_array();
+        let fri = FriProfile::default();
+
+        let (signal, verifier_data) =
+            access_set.make_signal_multi_leaf(&private_keys[4..6], topic, 4)?;
+        assert_eq!(
+            signal.nullifier,
+            DomainTag::default().nullifier(private_keys[4], topic)
+        );
+        assert_eq!(
+            verifier_data.verifier_only.circuit_digest,
+            access_set
+                .signal_verifier_data_multi_leaf(2, &fri)?
+                .verifier_only
+                .circuit_digest
+        );
+        verify::verify_signal(&access_set.0.cap, topic, signal, &verifier_data)?;
+
+        // the keys should be those of the block
+        assert!(access_set
+            .make_signal_multi_leaf(&[private_keys[4], private_keys[7]], topic, 4)
+            .is_err());
+        assert!(matches!(
+            access_set.make_signal_multi_leaf(&private_keys[5..7], topic, 5),
+            Err(ProofError::UnalignedLeaves {
+                index: 5,
+                num_leaves: 2
+            })
+        ));
+        assert!(matches!(
+            access_set.make_signal_multi_leaf(&private_keys[..3], topic, 0),
+            Err(ProofError::LeafCount(3))
+        ));
+
+        Ok(())
+    }
+}
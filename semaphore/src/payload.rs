@@ -0,0 +1,279 @@
+//! Encrypted payloads: a signal can carry a message encrypted to a relayer, together with a
+//! proof that the ciphertext encrypts the message whose hash is a public input, so that the
+//! relayer reads the message while anyone can still verify the signal. The encryption is
+//! ECIES-like: an ephemeral secp256k1 key agrees on a point with the key of the relayer,
+//! whose x coordinate keys a Poseidon keystream added to the message, which is cheaper to
+//! prove than a block cipher
+
+use gadgets::point_compression::{CircuitBuilderCompression, CompressedPoint};
+use num::BigUint;
+use plonky2::field::secp256k1_base::Secp256K1Base;
+use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+use plonky2::field::types::{Field, PrimeField64, Sample};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::Hasher;
+use plonky2_ecdsa::curve::curve_types::{AffinePoint, Curve, CurveScalar};
+use plonky2_ecdsa::curve::secp256k1::Secp256K1;
+use plonky2_ecdsa::gadgets::curve_fixed_base::fixed_base_curve_mul_circuit;
+use plonky2_ecdsa::gadgets::nonnative::CircuitBuilderNonNative;
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::witness::{Named, WitnessBuilder};
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::credential::{add_nonnative_limbs, limb_targets, nonnative_limbs};
+use crate::domain::DomainTag;
+use crate::signal::{Digest, EncryptedPayload, Signal, C, F, NULLIFIER_VERSION};
+
+/// The number of elements of a message, a multiple of 4 as the keystream is made of hashes
+pub const PAYLOAD_LEN: usize = 8;
+
+/// Prefixes the hashes of the keystream, so that they never coincide with attestations,
+/// credentials or the hashes of `DomainTag`
+const PAYLOAD_TAG: u64 = 6;
+
+/// The number of 32-bit limbs of a coordinate or a scalar
+const NUM_LIMBS: usize = 8;
+
+pub type Payload = [F; PAYLOAD_LEN];
+
+/// The keystream keyed by the x coordinate of the shared point
+fn keystream(shared: &AffinePoint<Secp256K1>) -> Vec<F> {
+    let limbs = nonnative_limbs(shared.x, NUM_LIMBS);
+    (0..PAYLOAD_LEN / 4)
+        .flat_map(|block| {
+            let inputs: Vec<F> = [F::from_canonical_u64(PAYLOAD_TAG)]
+                .into_iter()
+                .chain(limbs.iter().copied())
+                .chain([F::from_canonical_usize(block)])
+                .collect();
+            PoseidonHash::hash_no_pad(&inputs).elements
+        })
+        .collect()
+}
+
+fn mul(scalar: Secp256K1Scalar, point: &AffinePoint<Secp256K1>) -> AffinePoint<Secp256K1> {
+    (CurveScalar(scalar) * point.to_projective()).to_affine()
+}
+
+/// Encrypts `message` to `relayer` with the ephemeral secret key `ephemeral`
+pub fn encrypt(
+    relayer: &AffinePoint<Secp256K1>,
+    message: &Payload,
+    ephemeral: Secp256K1Scalar,
+) -> EncryptedPayload {
+    let ephemeral_point = mul(ephemeral, &Secp256K1::GENERATOR_AFFINE);
+    EncryptedPayload {
+        message_hash: PoseidonHash::hash_no_pad(message).elements,
+        ephemeral: CompressedPoint::compress(&ephemeral_point).public_inputs(),
+        ciphertext: message
+            .iter()
+            .zip(keystream(&mul(ephemeral, relayer)))
+            .map(|(m, k)| *m + k)
+            .collect(),
+    }
+}
+
+/// A relayer reading the payloads encrypted to it
+pub struct RelayerKey {
+    secret_key: Secp256K1Scalar,
+}
+
+impl RelayerKey {
+    pub fn new(secret_key: Secp256K1Scalar) -> Self {
+        Self { secret_key }
+    }
+
+    pub fn rand() -> Self {
+        Self::new(Secp256K1Scalar::rand())
+    }
+
+    pub fn public_key(&self) -> AffinePoint<Secp256K1> {
+        mul(self.secret_key, &Secp256K1::GENERATOR_AFFINE)
+    }
+
+    /// The message of `payload`, checked against its hash
+    pub fn decrypt(&self, payload: &EncryptedPayload) -> Result<Payload, ProofError> {
+        if payload.ephemeral.len() != NUM_LIMBS + 1 || payload.ciphertext.len() != PAYLOAD_LEN {
+            return Err(ProofError::InvalidPayload);
+        }
+        let limbs: Vec<u32> = payload.ephemeral[..NUM_LIMBS]
+            .iter()
+            .map(|limb| limb.to_canonical_u64() as u32)
+            .collect();
+        let ephemeral = CompressedPoint {
+            x: Secp256K1Base::from_noncanonical_biguint(BigUint::from_slice(&limbs)),
+            odd: payload.ephemeral[NUM_LIMBS].is_one(),
+        }
+        .decompress()
+        .ok_or(ProofError::InvalidPayload)?;
+
+        let message: Vec<F> = payload
+            .ciphertext
+            .iter()
+            .zip(keystream(&mul(self.secret_key, &ephemeral)))
+            .map(|(c, k)| *c - k)
+            .collect();
+        if PoseidonHash::hash_no_pad(&message).elements != payload.message_hash {
+            return Err(ProofError::InvalidPayload);
+        }
+
+        Ok(message.try_into().unwrap())
+    }
+}
+
+pub struct PayloadTargets {
+    message: Named<[Target; PAYLOAD_LEN]>,
+    ephemeral: Named<Vec<Target>>,
+}
+
+/// Extends a signal circuit with the encryption of a message to `relayer`, registering the
+/// public inputs of `EncryptedPayload::public_inputs` after those of the signal
+pub fn payload_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    relayer: &AffinePoint<Secp256K1>,
+) -> PayloadTargets {
+    let message: [Target; PAYLOAD_LEN] =
+        builder.add_virtual_targets(PAYLOAD_LEN).try_into().unwrap();
+    let message_hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(message.to_vec());
+    builder.register_public_inputs(&message_hash.elements);
+
+    // both points are multiples of fixed bases, the generator and the key of the relayer
+    let ephemeral = add_nonnative_limbs::<Secp256K1Scalar>(builder);
+    let scalar = builder.biguint_to_nonnative(&ephemeral);
+    let ephemeral_point =
+        fixed_base_curve_mul_circuit(builder, Secp256K1::GENERATOR_AFFINE, &scalar);
+    let compressed = builder.compress_point(&ephemeral_point);
+    builder.register_public_inputs(&limb_targets(&compressed.x));
+    builder.register_public_input(compressed.odd.target);
+
+    let shared = fixed_base_curve_mul_circuit(builder, *relayer, &scalar);
+    let shared_x = builder.nonnative_to_canonical_biguint(&shared.x);
+    let tag = builder.constant(F::from_canonical_u64(PAYLOAD_TAG));
+    for (block, chunk) in message.chunks(4).enumerate() {
+        let block = builder.constant(F::from_canonical_usize(block));
+        let inputs = [tag]
+            .into_iter()
+            .chain(limb_targets(&shared_x))
+            .chain([block])
+            .collect();
+        let key = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        for (&m, k) in chunk.iter().zip(key.elements) {
+            let c = builder.add(m, k);
+            builder.register_public_input(c);
+        }
+    }
+
+    PayloadTargets {
+        message: Named::new("message", message),
+        ephemeral: Named::new("ephemeral key", limb_targets(&ephemeral)),
+    }
+}
+
+pub fn fill_payload_targets(
+    witness: &mut WitnessBuilder<F>,
+    message: &Payload,
+    ephemeral: Secp256K1Scalar,
+    targets: PayloadTargets,
+) -> Result<(), ProofError> {
+    witness.set_elements(&targets.message, message)?;
+    let limbs = nonnative_limbs(ephemeral, targets.ephemeral.targets.len());
+    witness.set_elements(&targets.ephemeral, &limbs)
+}
+
+impl AccessSet {
+    /// Like `make_signal_with_profile`, carrying `message` encrypted to `relayer`
+    pub fn make_signal_with_payload(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        public_key_index: usize,
+        relayer: &AffinePoint<Secp256K1>,
+        message: &Payload,
+        fri: &FriProfile,
+    ) -> Result<(Signal, VerifierCircuitData<F, C, 2>), ProofError> {
+        let ephemeral = Secp256K1Scalar::rand();
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        let mut witness = WitnessBuilder::new();
+        let targets = self.semaphore_circuit(&mut builder);
+        self.fill_semaphore_targets(&mut witness, private_key, topic, public_key_index, targets)?;
+        let targets = payload_circuit(&mut builder, relayer);
+        fill_payload_targets(&mut witness, message, ephemeral, targets)?;
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::timed_prove::<F, C, 2>(builder, witness.build())?;
+
+        Ok((
+            Signal {
+                version: NULLIFIER_VERSION,
+                nullifier: DomainTag::default().nullifier(private_key, topic),
+                nonce: 0,
+                proof: proof_with_pis.proof,
+                payload: Some(encrypt(relayer, message, ephemeral)),
+                disclosed: Vec::new(),
+            },
+            circuit_data.verifier_data(),
+        ))
+    }
+
+    /// The verifier data of the signals of this access set carrying payloads to `relayer`
+    pub fn signal_verifier_data_with_payload(
+        &self,
+        relayer: &AffinePoint<Secp256K1>,
+        fri: &FriProfile,
+    ) -> Result<VerifierCircuitData<F, C, 2>, ProofError> {
+        let mut builder = CircuitBuilder::new(fri.circuit_config(true)?);
+        self.semaphore_circuit(&mut builder);
+        payload_circuit(&mut builder, relayer);
+        Ok(builder.build::<C>().verifier_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::verify;
+
+    #[test]
+    fn test_signal_with_payload() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let topic = F::rand_array();
+        let relayer = RelayerKey::rand();
+        let message: Payload = F::rand_array();
+
+        let fri = FriProfile::default();
+        let (signal, verifier_data) = access_set.make_signal_with_payload(
+            fixtures::private_key(1),
+            topic,
+            1,
+            &relayer.public_key(),
+            &message,
+            &fri,
+        )?;
+        let cap = &access_set.0.cap;
+        verify::verify_signal_with_payload(cap, topic, signal.clone(), &verifier_data)?;
+        let payload = signal.payload.clone().unwrap();
+        assert_eq!(relayer.decrypt(&payload)?, message);
+        assert!(matches!(
+            RelayerKey::rand().decrypt(&payload),
+            Err(ProofError::InvalidPayload)
+        ));
+
+        // the ciphertext is bound to the proof
+        let mut tampered = signal;
+        tampered.payload.as_mut().unwrap().ciphertext[0] += F::ONE;
+        assert!(verify::verify_signal_with_payload(cap, topic, tampered, &verifier_data).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Proof of work gating signals: a signal of difficulty `n` has a nonce such that the first
+//! element of `Poseidon(nullifier, nonce)` has `n` leading zero bits, which makes flooding
+//! an open relayer with signals costly while checking one costs a single hash
+
+use alloc::vec::Vec;
+
+use plonky2::field::types::{Field, PrimeField64};
+#[cfg(feature = "std")]
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::hash::poseidon::PoseidonHash;
+#[cfg(feature = "std")]
+use plonky2::iop::target::Target;
+#[cfg(feature = "std")]
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+#[cfg(feature = "std")]
+use proof_error::ProofError;
+
+use crate::signal::{Digest, F};
+
+/// The element of `Poseidon(nullifier, nonce)` whose leading zero bits are the work
+pub fn work(nullifier: Digest, nonce: u64) -> F {
+    let inputs: Vec<F> = nullifier
+        .into_iter()
+        .chain([F::from_canonical_u64(nonce)])
+        .collect();
+    PoseidonHash::hash_no_pad(&inputs).elements[0]
+}
+
+/// Whether `nonce` proves `difficulty` leading zero bits of work on `nullifier`
+pub fn meets_difficulty(nullifier: Digest, nonce: u64, difficulty: usize) -> bool {
+    work(nullifier, nonce).to_canonical_u64().leading_zeros() as usize >= difficulty
+}
+
+/// Fails unless `difficulty` is below the 64 bits of the work, the most a nonce can prove
+#[cfg(feature = "std")]
+pub fn check_range(difficulty: usize) -> Result<(), ProofError> {
+    if difficulty >= 64 {
+        return Err(ProofError::Difficulty(difficulty));
+    }
+    Ok(())
+}
+
+/// The first nonce proving `difficulty` bits of work on `nullifier`, after about
+/// `2^difficulty` hashes
+#[cfg(feature = "std")]
+pub fn grind(nullifier: Digest, difficulty: usize) -> Result<u64, ProofError> {
+    check_range(difficulty)?;
+    Ok((0..)
+        .find(|&nonce| meets_difficulty(nullifier, nonce, difficulty))
+        .expect("a difficulty below 64 bits is met by some nonce"))
+}
+
+/// Constrains `nonce` to prove `difficulty` bits of work on `nullifier`. The work has
+/// `difficulty` leading zero bits when it fits in the `64 - difficulty` low bits
+#[cfg(feature = "std")]
+pub fn pow_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    nullifier: HashOutTarget,
+    nonce: Target,
+    difficulty: usize,
+) -> Result<(), ProofError> {
+    check_range(difficulty)?;
+    let inputs = nullifier.elements.into_iter().chain([nonce]).collect();
+    let work = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+    builder.range_check(work.elements[0], 64 - difficulty);
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::hash_types::HashOut;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+    use crate::signal::C;
+
+    #[test]
+    fn test_pow() -> Result<()> {
+        let difficulty = 8;
+        let nullifier = F::rand_array();
+        let nonce = grind(nullifier, difficulty)?;
+        assert!(meets_difficulty(nullifier, nonce, difficulty));
+        assert!(work(nullifier, nonce).to_canonical_u64() < 1 << (64 - difficulty));
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let nullifier_target = builder.add_virtual_hash();
+        let nonce_target = builder.add_virtual_target();
+        pow_circuit(&mut builder, nullifier_target, nonce_target, difficulty)?;
+        let data = builder.build::<C>();
+
+        let prove = |nonce: u64| {
+            let mut pw = PartialWitness::new();
+            pw.set_hash_target(
+                nullifier_target,
+                HashOut {
+                    elements: nullifier,
+                },
+            );
+            pw.set_target(nonce_target, F::from_canonical_u64(nonce));
+            data.prove(pw)
+        };
+        data.verify(prove(nonce)?)?;
+
+        // a nonce short of the difficulty
+        let weak = (0..)
+            .find(|&nonce| !meets_difficulty(nullifier, nonce, difficulty))
+            .unwrap();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| prove(weak)));
+        assert!(!matches!(result, Ok(Ok(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_difficulty_out_of_range() {
+        let nullifier = F::rand_array();
+        assert!(matches!(
+            grind(nullifier, 64),
+            Err(ProofError::Difficulty(64))
+        ));
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let nullifier_target = builder.add_virtual_hash();
+        let nonce_target = builder.add_virtual_target();
+        assert!(matches!(
+            pow_circuit(&mut builder, nullifier_target, nonce_target, 70),
+            Err(ProofError::Difficulty(70))
+        ));
+    }
+}
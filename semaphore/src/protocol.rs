@@ -0,0 +1,247 @@
+//! The messages exchanged by the clients, relayers and servers built on this crate. Each
+//! message carries the version of the protocol, and the topic a member signals on is bound
+//! to the session of the request, so that a signal can't be replayed into another session
+
+use std::collections::HashSet;
+
+use plonky2::field::types::Field;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::Hasher;
+use proof_error::{ProofError, ProtocolError};
+use serde::{Deserialize, Serialize};
+
+use crate::access_set::AccessSet;
+use crate::signal::{Digest, PlonkyProof, Signal, C, F};
+
+pub const PROTOCOL_VERSION: u16 = 1;
+
+fn check_version(version: u16) -> Result<(), ProtocolError> {
+    if version == PROTOCOL_VERSION {
+        Ok(())
+    } else {
+        Err(ProtocolError::UnsupportedVersion {
+            supported: PROTOCOL_VERSION,
+            actual: version,
+        })
+    }
+}
+
+/// A server asking the members of an access set to signal on a question
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SignalRequest {
+    pub version: u16,
+    /// Chosen at random by the server for each request
+    pub session: [u8; 16],
+    pub question: Digest,
+    pub epoch: u64,
+    /// Unix time in seconds after which responses are rejected
+    pub expires_at: u64,
+}
+
+/// A member answering a `SignalRequest` with a signal on its topic
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignalResponse {
+    pub version: u16,
+    pub session: [u8; 16],
+    pub epoch: u64,
+    pub signal: Signal,
+}
+
+/// A relayer publishing the aggregate of the signals of an epoch, with the count of each
+/// topic as proven by `AccessSet::aggregate_signals_by_topic`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AggregateAnnouncement {
+    pub version: u16,
+    pub epoch: u64,
+    pub nullifiers: Vec<Digest>,
+    pub topic_counts: Vec<(Digest, usize)>,
+    pub proof: PlonkyProof,
+}
+
+impl SignalRequest {
+    pub fn new(session: [u8; 16], question: Digest, epoch: u64, expires_at: u64) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            session,
+            question,
+            epoch,
+            expires_at,
+        }
+    }
+
+    /// The topic to signal on, committing to both the question and the session
+    pub fn topic(&self) -> Digest {
+        let inputs: Vec<F> = self
+            .question
+            .into_iter()
+            .chain(self.session.iter().map(|b| F::from_canonical_u8(*b)))
+            .collect();
+        PoseidonHash::hash_no_pad(&inputs).elements
+    }
+
+    /// Checks that the request can still be answered at unix time `now`
+    pub fn validate(&self, now: u64) -> Result<(), ProtocolError> {
+        check_version(self.version)?;
+        if now > self.expires_at {
+            return Err(ProtocolError::Expired {
+                expires_at: self.expires_at,
+                now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Answers the request with a signal on `self.topic()`
+    pub fn respond(&self, signal: Signal) -> SignalResponse {
+        SignalResponse {
+            version: PROTOCOL_VERSION,
+            session: self.session,
+            epoch: self.epoch,
+            signal,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+}
+
+impl SignalResponse {
+    /// Checks that the response answers `request` at unix time `now`
+    pub fn validate(&self, request: &SignalRequest, now: u64) -> Result<(), ProtocolError> {
+        check_version(self.version)?;
+        request.validate(now)?;
+        if self.session != request.session {
+            return Err(ProtocolError::SessionMismatch);
+        }
+        if self.epoch != request.epoch {
+            return Err(ProtocolError::EpochMismatch {
+                expected: request.epoch,
+                actual: self.epoch,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Validates the response and verifies its signal on the topic of `request`
+    pub fn verify(
+        &self,
+        request: &SignalRequest,
+        now: u64,
+        access_set: &AccessSet,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        self.validate(request, now)?;
+        access_set.verify_signal(request.topic(), self.signal.clone(), verifier_data)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+}
+
+impl AggregateAnnouncement {
+    /// Checks that the counts account for every nullifier, each announced once
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        check_version(self.version)?;
+        let counted = self.topic_counts.iter().map(|(_, count)| count).sum();
+        if counted != self.nullifiers.len() {
+            return Err(ProtocolError::CountMismatch {
+                counted,
+                nullifiers: self.nullifiers.len(),
+            });
+        }
+
+        let mut seen = HashSet::new();
+        for (index, nullifier) in self.nullifiers.iter().enumerate() {
+            if !seen.insert(nullifier) {
+                return Err(ProtocolError::DuplicateNullifier { index });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates the announcement and verifies its aggregate proof
+    pub fn verify(
+        &self,
+        access_set: &AccessSet,
+        aggregate_verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        self.validate()?;
+        access_set.verify_topic_counts(
+            &self.nullifiers,
+            &self.topic_counts,
+            self.proof.clone(),
+            aggregate_verifier_data,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_signal_exchange() -> Result<()> {
+        let access_set = fixtures::access_set(2);
+        let verifier_data = access_set.signal_verifier_data();
+        let request = SignalRequest::new([7; 16], F::rand_array(), 3, 1_000);
+        let request = SignalRequest::from_bytes(&request.to_bytes()?)?;
+        request.validate(900)?;
+
+        let (signal, _) = access_set.make_signal(fixtures::private_key(2), request.topic(), 2)?;
+        let response = SignalResponse::from_bytes(&request.respond(signal).to_bytes()?)?;
+        response.verify(&request, 900, access_set, &verifier_data)?;
+
+        assert_eq!(
+            response.validate(&request, 1_001),
+            Err(ProtocolError::Expired {
+                expires_at: 1_000,
+                now: 1_001
+            })
+        );
+
+        // replaying the signal into another session of the same question
+        let other_request = SignalRequest::new([8; 16], request.question, 3, 1_000);
+        let mut replayed = other_request.respond(response.signal.clone());
+        assert!(replayed
+            .verify(&other_request, 900, access_set, &verifier_data)
+            .is_err());
+        assert_eq!(
+            replayed.validate(&request, 900),
+            Err(ProtocolError::SessionMismatch)
+        );
+
+        replayed.version = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            replayed.validate(&other_request, 900),
+            Err(ProtocolError::UnsupportedVersion { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1234 @@
+use std::time::Duration;
+
+use gadgets::builder_ext::CircuitBuilderExt;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitData, CommonCircuitData, VerifierCircuitData, VerifierCircuitTarget,
+};
+use plonky2::plonk::config::GenericConfig;
+#[cfg(feature = "keccak")]
+use plonky2::plonk::config::KeccakGoldilocksConfig;
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use proof_error::ProofError;
+use prover_utils::fri::FriProfile;
+use prover_utils::options::ProverOptions;
+use prover_utils::pipeline;
+use prover_utils::recursion_utils::{self, ProofTargets};
+use prover_utils::TimedProof;
+
+use crate::access_set::AccessSet;
+use crate::chain;
+use crate::layout::{InputKind, PublicInputLayout};
+use crate::signal::{Digest, PlonkyProof, Signal, C, F};
+use crate::verify;
+
+/// A proof aggregating signals together with the verifier data of the aggregation circuit
+pub type Aggregation<OuterC> = (
+    ProofWithPublicInputs<F, OuterC, 2>,
+    VerifierCircuitData<F, OuterC, 2>,
+);
+
+/// Options of the aggregation circuits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AggregationOptions {
+    /// Appends the digest of the verifier data of the signal circuit to the public inputs,
+    /// see `verify::verifier_data_digest`. Otherwise the verifier data is a witness, and
+    /// consumers of the aggregate have to agree out of band on the circuit it verified
+    pub embed_verifier_data: bool,
+    /// Appends the digest of the previous aggregate to the public inputs, after the
+    /// verifier data digest, so that the aggregates form a hash chain, see `chain`. One-off
+    /// aggregations start a chain from `chain::GENESIS`
+    pub chain: bool,
+    /// The FRI parameters of the aggregation proof
+    pub fri: FriProfile,
+}
+
+impl AccessSet {
+    /// Proves both signals in a single recursive proof, returning their nullifiers and the
+    /// verifier data of the aggregation, whose public inputs are those of both signals
+    pub fn aggregate_signals(
+        &self,
+        topic0: Digest,
+        signal0: Signal,
+        topic1: Digest,
+        signal1: Signal,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(Digest, Digest, PlonkyProof, VerifierCircuitData<F, C, 2>), ProofError> {
+        let (recursive_proof, aggregate_verifier_data) = self.aggregate_signals_with::<C>(
+            &[(topic0, &signal0), (topic1, &signal1)],
+            verifier_data,
+            &AggregationOptions::default(),
+        )?;
+
+        Ok((
+            signal0.nullifier,
+            signal1.nullifier,
+            recursive_proof.proof,
+            aggregate_verifier_data,
+        ))
+    }
+
+    pub fn verify_aggregated_signals(
+        &self,
+        topic0: Digest,
+        nullifier0: Digest,
+        topic1: Digest,
+        nullifier1: Digest,
+        proof: PlonkyProof,
+        aggregate_verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        verify::verify_aggregated_signals(
+            &self.0.cap,
+            &[(topic0, nullifier0), (topic1, nullifier1)],
+            proof,
+            aggregate_verifier_data,
+        )
+        .map_err(ProofError::Verification)
+    }
+
+    /// Checks an aggregation of `aggregate_signals_by_topic` against the nullifiers of its
+    /// signals and the claimed count of each topic
+    pub fn verify_topic_counts(
+        &self,
+        nullifiers: &[Digest],
+        topic_counts: &[(Digest, usize)],
+        proof: PlonkyProof,
+        aggregate_verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        verify::verify_topic_counts(
+            &self.0.cap,
+            nullifiers,
+            topic_counts,
+            proof,
+            aggregate_verifier_data,
+        )
+        .map_err(ProofError::Verification)
+    }
+
+    /// Like `aggregate_signals`, proving the aggregation with a keccak transcript, for
+    /// verifiers which only have keccak256 such as the EVM
+    #[cfg(feature = "keccak")]
+    pub fn aggregate_signals_for_evm(
+        &self,
+        topic0: Digest,
+        signal0: Signal,
+        topic1: Digest,
+        signal1: Signal,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(Digest, Digest, Aggregation<KeccakGoldilocksConfig>), ProofError> {
+        let aggregation = self.aggregate_signals_with::<KeccakGoldilocksConfig>(
+            &[(topic0, &signal0), (topic1, &signal1)],
+            verifier_data,
+            &AggregationOptions::default(),
+        )?;
+
+        Ok((signal0.nullifier, signal1.nullifier, aggregation))
+    }
+
+    /// Proves any number of signals in a single recursive proof, whose public inputs are
+    /// those of each signal in turn, e.g. all the signals of an epoch
+    pub fn aggregate_signal_batch(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<Aggregation<C>, ProofError> {
+        self.aggregate_signal_batch_with_options(
+            signals,
+            verifier_data,
+            &AggregationOptions::default(),
+        )
+    }
+
+    /// Like `aggregate_signal_batch`, with the given options
+    pub fn aggregate_signal_batch_with_options(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+        options: &AggregationOptions,
+    ) -> Result<Aggregation<C>, ProofError> {
+        let signals: Vec<(Digest, &Signal)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal))
+            .collect();
+        self.aggregate_signals_with::<C>(&signals, verifier_data, options)
+    }
+
+    /// Proves any number of signals in a single recursive proof counting the signals on
+    /// each of their topics, e.g. the answers to each question of a poll. Returns the
+    /// topics in the order they first appear with their counts, which together with the
+    /// nullifiers make up the public inputs, see `verify::topic_counts_public_inputs`. As in
+    /// `aggregate_threshold`, the signal circuit is a constant of the circuit, and a signal
+    /// repeating the nullifier of another is rejected rather than counted twice
+    pub fn aggregate_signals_by_topic(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(Vec<(Digest, usize)>, Aggregation<C>), ProofError> {
+        for (index, (_, signal)) in signals.iter().enumerate() {
+            if signals[..index]
+                .iter()
+                .any(|(_, other)| other.nullifier == signal.nullifier)
+            {
+                return Err(ProofError::DuplicateSignal { index });
+            }
+        }
+
+        // the first signal on each topic, and the number of signals on it
+        let mut topic_counts: Vec<(Digest, usize)> = Vec::new();
+        let mut first_signals = Vec::new();
+        for (i, (topic, _)) in signals.iter().enumerate() {
+            match topic_counts.iter_mut().find(|(t, _)| t == topic) {
+                Some((_, count)) => *count += 1,
+                None => {
+                    topic_counts.push((*topic, 1));
+                    first_signals.push(i);
+                }
+            }
+        }
+
+        let mut builder = CircuitBuilder::new(prover_utils::standard_config(true));
+        let mut pw = PartialWitness::new();
+        let signals: Vec<(Digest, &Signal)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal))
+            .collect();
+        let (vd_target, proof_targets) =
+            self.add_signal_proofs(&mut builder, &mut pw, &signals, verifier_data)?;
+        connect_verifier_data(&mut builder, &vd_target, verifier_data);
+
+        let public_inputs = signal_input_targets(&proof_targets, self.cap_height());
+
+        let (cap, _, _) = public_inputs[0];
+        builder.register_public_inputs(cap);
+        for (i, &(other_cap, nullifier, _)) in public_inputs.iter().enumerate() {
+            for (x, y) in cap.iter().zip(other_cap) {
+                builder.connect(*x, *y);
+            }
+            for &(_, other_nullifier, _) in &public_inputs[..i] {
+                let reused = digests_equal(&mut builder, nullifier, other_nullifier);
+                builder.assert_zero(reused.target);
+            }
+            builder.register_public_inputs(nullifier);
+        }
+
+        // each signal is counted under exactly one topic
+        let topics: Vec<&[Target]> = first_signals.iter().map(|&i| public_inputs[i].2).collect();
+        let mut counts = vec![builder.zero(); topics.len()];
+        let one = builder.one();
+        for &(_, _, signal_topic) in &public_inputs {
+            let mut num_matches = builder.zero();
+            for (topic, count) in topics.iter().zip(&mut counts) {
+                let is_match = digests_equal(&mut builder, signal_topic, topic);
+                *count = builder.add(*count, is_match.target);
+                num_matches = builder.add(num_matches, is_match.target);
+            }
+            builder.connect(num_matches, one);
+        }
+        for (topic, count) in topics.iter().zip(counts) {
+            builder.register_public_inputs(topic);
+            builder.register_public_input(count);
+        }
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::prove_and_verify::<F, C, 2>(builder, pw)?;
+
+        Ok((topic_counts, (proof_with_pis, circuit_data.verifier_data())))
+    }
+
+    /// Proves that the signals come from `signals.len()` distinct members of the access
+    /// set signaling on the same topic, without revealing the members or their nullifiers,
+    /// e.g. to attest that a quorum approved a proposal. A nullifier is derived from the
+    /// private key and the topic, so on a single topic distinct nullifiers are distinct
+    /// members. The public inputs are the cap, the topic and the number of signals, see
+    /// `verify::threshold_public_inputs`. The signal circuit is a constant of the circuit,
+    /// so that the proofs it verifies can't be of another circuit
+    pub fn aggregate_threshold(
+        &self,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<Aggregation<C>, ProofError> {
+        let mut builder = CircuitBuilder::new(prover_utils::standard_config(true));
+        let mut pw = PartialWitness::new();
+        let signals: Vec<(Digest, &Signal)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal))
+            .collect();
+        let (vd_target, proof_targets) =
+            self.add_signal_proofs(&mut builder, &mut pw, &signals, verifier_data)?;
+        connect_verifier_data(&mut builder, &vd_target, verifier_data);
+
+        let public_inputs = signal_input_targets(&proof_targets, self.cap_height());
+
+        let (cap, _, topic) = public_inputs[0];
+        for (i, &(other_cap, nullifier, other_topic)) in public_inputs.iter().enumerate() {
+            for (x, y) in cap
+                .iter()
+                .chain(topic)
+                .zip(other_cap.iter().chain(other_topic))
+            {
+                builder.connect(*x, *y);
+            }
+            for &(_, other_nullifier, _) in &public_inputs[..i] {
+                let reused = digests_equal(&mut builder, nullifier, other_nullifier);
+                builder.assert_zero(reused.target);
+            }
+        }
+        builder.register_public_inputs(cap);
+        builder.register_public_inputs(topic);
+        let count = builder.constant(F::from_canonical_usize(signals.len()));
+        builder.register_public_input(count);
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::prove_and_verify::<F, C, 2>(builder, pw)?;
+
+        Ok((proof_with_pis, circuit_data.verifier_data()))
+    }
+
+    /// Checks an aggregation of `aggregate_threshold` against the topic and the number of
+    /// members claimed to have signaled on it
+    pub fn verify_threshold(
+        &self,
+        topic: Digest,
+        threshold: usize,
+        proof: PlonkyProof,
+        aggregate_verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        verify::verify_threshold(
+            &self.0.cap,
+            topic,
+            threshold,
+            proof,
+            aggregate_verifier_data,
+        )
+        .map_err(ProofError::Verification)
+    }
+
+    fn aggregate_signals_with<OuterC: GenericConfig<2, F = F>>(
+        &self,
+        signals: &[(Digest, &Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+        options: &AggregationOptions,
+    ) -> Result<Aggregation<OuterC>, ProofError> {
+        let mut builder = CircuitBuilder::new(options.fri.circuit_config(true)?);
+        let mut pw = PartialWitness::new();
+
+        let (vd_target, proof_targets) =
+            self.add_signal_proofs(&mut builder, &mut pw, signals, verifier_data)?;
+        let previous =
+            register_aggregate_public_inputs(&mut builder, &vd_target, &proof_targets, options);
+        if let Some(previous) = previous {
+            pw.set_hash_target(
+                previous,
+                HashOut {
+                    elements: chain::GENESIS,
+                },
+            );
+        }
+
+        let TimedProof {
+            circuit_data,
+            proof_with_pis,
+            ..
+        } = prover_utils::prove_and_verify::<F, OuterC, 2>(builder, pw)?;
+
+        Ok((proof_with_pis, circuit_data.verifier_data()))
+    }
+
+    /// Verifies each signal in the circuit, returning the targets of the verifier data and
+    /// of the proofs
+    pub(crate) fn add_signal_proofs(
+        &self,
+        builder: &mut CircuitBuilder<F, 2>,
+        pw: &mut PartialWitness<F>,
+        signals: &[(Digest, &Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<ProofTargets<2>, ProofError> {
+        let proofs = self.signal_proofs(signals)?;
+        recursion_utils::verify_proofs_in_circuit(builder, pw, &proofs, verifier_data)
+    }
+
+    /// Sets the targets of `recursion_utils::add_proof_targets` to the signals and their
+    /// verifier data
+    fn set_signal_proofs(
+        &self,
+        pw: &mut PartialWitness<F>,
+        targets: &ProofTargets<2>,
+        signals: &[(Digest, &Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<(), ProofError> {
+        let proofs = self.signal_proofs(signals)?;
+        recursion_utils::set_proof_targets(pw, targets, &proofs, verifier_data)
+    }
+
+    /// The proofs of the signals together with their public inputs
+    fn signal_proofs(
+        &self,
+        signals: &[(Digest, &Signal)],
+    ) -> Result<Vec<ProofWithPublicInputs<F, C, 2>>, ProofError> {
+        signals
+            .iter()
+            .map(|&(topic, signal)| {
+                verify::check_version(signal).map_err(ProofError::Verification)?;
+                Ok(ProofWithPublicInputs {
+                    proof: signal.proof.clone(),
+                    public_inputs: verify::signal_public_inputs(
+                        &self.0.cap,
+                        topic,
+                        signal.nullifier,
+                    ),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Connects the verifier data target to the constant verifier data of the signal circuit, so
+/// that the proofs verified against it can't be of another circuit
+fn connect_verifier_data(
+    builder: &mut CircuitBuilder<F, 2>,
+    vd_target: &VerifierCircuitTarget,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) {
+    let expected = builder.constant_verifier_data(&verifier_data.verifier_only);
+    for (x, y) in vd_target
+        .constants_sigmas_cap
+        .0
+        .iter()
+        .chain([&vd_target.circuit_digest])
+        .zip(
+            expected
+                .constants_sigmas_cap
+                .0
+                .iter()
+                .chain([&expected.circuit_digest]),
+        )
+    {
+        builder.connect_hashes(*x, *y);
+    }
+}
+
+/// Registers the public inputs of each signal in turn, followed by the digest of the
+/// verifier data if the options embed it, and by the digest of the previous aggregate,
+/// whose target is returned, if they chain aggregates
+pub(crate) fn register_aggregate_public_inputs(
+    builder: &mut CircuitBuilder<F, 2>,
+    vd_target: &VerifierCircuitTarget,
+    proof_targets: &[ProofWithPublicInputsTarget<2>],
+    options: &AggregationOptions,
+) -> Option<HashOutTarget> {
+    for proof_target in proof_targets {
+        builder.register_public_inputs(&proof_target.public_inputs);
+    }
+    if options.embed_verifier_data {
+        let inputs = vd_target
+            .constants_sigmas_cap
+            .0
+            .iter()
+            .chain([&vd_target.circuit_digest])
+            .flat_map(|hash| hash.elements)
+            .collect();
+        let digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        builder.register_public_inputs(&digest.elements);
+    }
+
+    options.chain.then(|| {
+        let previous = builder.add_virtual_hash();
+        builder.register_public_inputs(&previous.elements);
+        previous
+    })
+}
+
+/// How an `Aggregator` proves the signals it aggregates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AggregationMode {
+    /// Verifies the proof of each signal in the aggregation circuit
+    #[default]
+    Recursive,
+    /// Verifies the signals natively and only proves the update of a Poseidon accumulator
+    /// over their nullifiers, see `verify::verify_accumulator`. The circuit is a few hashes
+    /// per signal instead of a verifier per signal, but consumers of the aggregates trust
+    /// the aggregator to have verified the signals
+    Accumulator,
+}
+
+/// An aggregation circuit built for a number of signals of a signal circuit shape
+struct AggregationCircuit {
+    inner_common: CommonCircuitData<F, 2>,
+    circuit_data: CircuitData<F, C, 2>,
+    targets: ProofTargets<2>,
+    /// The digest of the previous aggregate, if the aggregates are chained
+    previous: Option<HashOutTarget>,
+    /// A copy of the circuit without generators, proving from the witnesses generated by
+    /// `Aggregator::aggregate_pipelined`, built on its first use
+    proving_data: Option<CircuitData<F, C, 2>>,
+}
+
+/// An accumulator circuit built for a number of signals
+struct AccumulatorCircuit {
+    circuit_data: CircuitData<F, C, 2>,
+    accumulator: HashOutTarget,
+    nullifiers: Vec<HashOutTarget>,
+}
+
+/// Aggregates signals like `AccessSet::aggregate_signal_batch_with_options`, building the
+/// aggregation circuit of each number of signals and shape of signal circuit once: later
+/// aggregations of the same shape only fill the witness and prove. The circuits don't
+/// depend on the access set or on the verifier-only data of the signal circuit, which are
+/// witnesses, so one aggregator serves every access set. In `AggregationMode::Accumulator`,
+/// the aggregations instead chain an accumulator from one to the next. With
+/// `AggregationOptions::chain`, each aggregation of `AggregationMode::Recursive` follows the
+/// previous one in a hash chain, see `chain`
+pub struct Aggregator {
+    options: AggregationOptions,
+    mode: AggregationMode,
+    circuits: Vec<AggregationCircuit>,
+    accumulator_circuits: Vec<AccumulatorCircuit>,
+    accumulator: Digest,
+    chain_head: Digest,
+}
+
+impl Default for Aggregator {
+    fn default() -> Self {
+        Self::new(AggregationOptions::default())
+    }
+}
+
+impl Aggregator {
+    pub fn new(options: AggregationOptions) -> Self {
+        Self {
+            options,
+            mode: AggregationMode::default(),
+            circuits: Vec::new(),
+            accumulator_circuits: Vec::new(),
+            accumulator: [F::ZERO; 4],
+            chain_head: chain::GENESIS,
+        }
+    }
+
+    /// Continues the chain of aggregates from the aggregate of digest `chain_head`, e.g. the
+    /// last one published before a restart
+    pub fn with_chain_head(mut self, chain_head: Digest) -> Self {
+        self.chain_head = chain_head;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: AggregationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The number of aggregation circuits built so far
+    pub fn num_circuits(&self) -> usize {
+        self.circuits.len() + self.accumulator_circuits.len()
+    }
+
+    /// The accumulator after the last aggregation in `AggregationMode::Accumulator`, zero
+    /// before the first
+    pub fn accumulator(&self) -> Digest {
+        self.accumulator
+    }
+
+    /// The digest of the last chained aggregate, `chain::GENESIS` before the first, which
+    /// the next aggregation follows
+    pub fn chain_head(&self) -> Digest {
+        self.chain_head
+    }
+
+    /// Proves the signals in a single recursive proof, whose public inputs are those of
+    /// each signal in turn. Unlike `aggregate_signal_batch`, the proof isn't verified. In
+    /// `AggregationMode::Accumulator`, the public inputs are those of
+    /// `verify::accumulator_public_inputs` instead, and if the aggregates are chained, they
+    /// are followed by the chain head before the aggregation
+    pub fn aggregate(
+        &mut self,
+        access_set: &AccessSet,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<Aggregation<C>, ProofError> {
+        if self.mode == AggregationMode::Accumulator {
+            return self.accumulate(access_set, signals, verifier_data);
+        }
+
+        let chain_head = self.chain_head;
+        let circuit = self.circuit(signals.len(), &verifier_data.common)?;
+        let signals: Vec<(Digest, &Signal)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal))
+            .collect();
+        let mut pw = PartialWitness::new();
+        access_set.set_signal_proofs(&mut pw, &circuit.targets, &signals, verifier_data)?;
+        if let Some(previous) = circuit.previous {
+            pw.set_hash_target(
+                previous,
+                HashOut {
+                    elements: chain_head,
+                },
+            );
+        }
+
+        let proof_with_pis =
+            prover_utils::prove_with_options(&circuit.circuit_data, pw, &ProverOptions::default())?;
+        let aggregate_verifier_data = circuit.circuit_data.verifier_data();
+        if self.options.chain {
+            self.chain_head = chain::aggregate_digest(
+                &proof_with_pis.public_inputs,
+                &aggregate_verifier_data.verifier_only,
+            );
+        }
+
+        Ok((proof_with_pis, aggregate_verifier_data))
+    }
+
+    /// Like `aggregate` for each of `batches` in turn, generating the witness of an
+    /// aggregation while the previous one is proven, see `prover_utils::pipeline`, at the
+    /// cost of building each aggregation circuit a second time. The aggregations come with
+    /// the time each took, and stop at the first failure
+    pub fn aggregate_pipelined(
+        &mut self,
+        access_set: &AccessSet,
+        batches: &[Vec<(Digest, Signal)>],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Vec<(Result<Aggregation<C>, ProofError>, Duration)> {
+        if self.mode == AggregationMode::Accumulator {
+            // the accumulator circuits are a few hashes, not worth a pipeline
+            let mut results = Vec::new();
+            for signals in batches {
+                let (result, duration) =
+                    prover_utils::timed(|| self.aggregate(access_set, signals, verifier_data));
+                let failed = result.is_err();
+                results.push((result, duration));
+                if failed {
+                    break;
+                }
+            }
+            return results;
+        }
+
+        let mut jobs = Vec::new();
+        let mut unbuilt = None;
+        for signals in batches {
+            match self.pipelined_circuit_index(signals.len(), &verifier_data.common) {
+                Ok(index) => jobs.push((index, signals)),
+                Err(e) => {
+                    unbuilt = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let circuits = &self.circuits;
+        let chained = self.options.chain;
+        let mut chain_head = self.chain_head;
+        let results = pipeline::pipelined(
+            jobs,
+            |(index, signals)| {
+                let circuit = &circuits[index];
+                let signals: Vec<(Digest, &Signal)> = signals
+                    .iter()
+                    .map(|(topic, signal)| (*topic, signal))
+                    .collect();
+                let mut pw = PartialWitness::new();
+                access_set.set_signal_proofs(&mut pw, &circuit.targets, &signals, verifier_data)?;
+                if let Some(previous) = circuit.previous {
+                    pw.set_hash_target(
+                        previous,
+                        HashOut {
+                            elements: chain_head,
+                        },
+                    );
+                }
+                let witness = pipeline::generate_witness(&circuit.circuit_data, pw)?;
+                // the next witness follows this aggregate before it is proven
+                if chained {
+                    chain_head = chain::aggregate_digest(
+                        witness.public_inputs(),
+                        &circuit.circuit_data.verifier_only,
+                    );
+                }
+                Ok((index, witness, chain_head))
+            },
+            |(index, witness, chain_head)| {
+                let circuit = &circuits[index];
+                let proving_data = circuit
+                    .proving_data
+                    .as_ref()
+                    .expect("the pipelined circuits have a proving copy");
+                let proof_with_pis =
+                    pipeline::prove_with_witness(proving_data, witness, &ProverOptions::default())?;
+                Ok((
+                    (proof_with_pis, circuit.circuit_data.verifier_data()),
+                    chain_head,
+                ))
+            },
+        );
+
+        let mut results: Vec<(Result<Aggregation<C>, ProofError>, Duration)> = results
+            .into_iter()
+            .map(|(result, duration)| {
+                let aggregation = result.map(|(aggregation, chain_head)| {
+                    self.chain_head = chain_head;
+                    aggregation
+                });
+                (aggregation, duration)
+            })
+            .collect();
+        if let Some(e) = unbuilt {
+            if results.iter().all(|(result, _)| result.is_ok()) {
+                results.push((Err(e), Duration::ZERO));
+            }
+        }
+
+        results
+    }
+
+    fn accumulate(
+        &mut self,
+        access_set: &AccessSet,
+        signals: &[(Digest, Signal)],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<Aggregation<C>, ProofError> {
+        for (index, (topic, signal)) in signals.iter().enumerate() {
+            verify::verify_signal(&access_set.0.cap, *topic, signal.clone(), verifier_data)
+                .map_err(|e| ProofError::InvalidBatchProof {
+                    index,
+                    source: Box::new(ProofError::Verification(e)),
+                })?;
+        }
+
+        let accumulator = self.accumulator;
+        let circuit = self.accumulator_circuit(signals.len())?;
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(
+            circuit.accumulator,
+            HashOut {
+                elements: accumulator,
+            },
+        );
+        for (target, (_, signal)) in circuit.nullifiers.iter().zip(signals) {
+            pw.set_hash_target(
+                *target,
+                HashOut {
+                    elements: signal.nullifier,
+                },
+            );
+        }
+
+        let proof_with_pis =
+            prover_utils::prove_with_options(&circuit.circuit_data, pw, &ProverOptions::default())?;
+        let verifier_data = circuit.circuit_data.verifier_data();
+        let nullifiers: Vec<Digest> = signals.iter().map(|(_, signal)| signal.nullifier).collect();
+        self.accumulator = verify::accumulate(accumulator, &nullifiers);
+
+        Ok((proof_with_pis, verifier_data))
+    }
+
+    /// The circuit updating the accumulator with `num_signals` nullifiers, built on first
+    /// use
+    fn accumulator_circuit(
+        &mut self,
+        num_signals: usize,
+    ) -> Result<&AccumulatorCircuit, ProofError> {
+        if num_signals == 0 {
+            return Err(ProofError::EmptyCircuit);
+        }
+
+        let cached = self
+            .accumulator_circuits
+            .iter()
+            .position(|circuit| circuit.nullifiers.len() == num_signals);
+        let index = match cached {
+            Some(index) => index,
+            None => {
+                let mut builder = CircuitBuilder::new(self.options.fri.circuit_config(true)?);
+                let accumulator = builder.add_virtual_hash();
+                builder.register_public_inputs(&accumulator.elements);
+                let nullifiers = builder.add_virtual_hashes(num_signals);
+                let mut updated = accumulator;
+                for nullifier in &nullifiers {
+                    builder.register_public_inputs(&nullifier.elements);
+                    let inputs = [updated.elements, nullifier.elements].concat();
+                    updated = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+                }
+                builder.register_public_inputs(&updated.elements);
+
+                self.accumulator_circuits.push(AccumulatorCircuit {
+                    circuit_data: builder.build::<C>(),
+                    accumulator,
+                    nullifiers,
+                });
+                self.accumulator_circuits.len() - 1
+            }
+        };
+
+        Ok(&self.accumulator_circuits[index])
+    }
+
+    /// The circuit aggregating `num_signals` signals of shape `common`, built on first use
+    fn circuit(
+        &mut self,
+        num_signals: usize,
+        common: &CommonCircuitData<F, 2>,
+    ) -> Result<&AggregationCircuit, ProofError> {
+        let index = self.circuit_index(num_signals, common)?;
+        Ok(&self.circuits[index])
+    }
+
+    /// The index of the aggregation circuit of `num_signals` signals, built on first use
+    fn circuit_index(
+        &mut self,
+        num_signals: usize,
+        common: &CommonCircuitData<F, 2>,
+    ) -> Result<usize, ProofError> {
+        let cached = self.circuits.iter().position(|circuit| {
+            circuit.targets.1.len() == num_signals && circuit.inner_common == *common
+        });
+        if let Some(index) = cached {
+            return Ok(index);
+        }
+
+        let (circuit_data, targets, previous) = self.build_circuit(num_signals, common)?;
+        self.circuits.push(AggregationCircuit {
+            inner_common: common.clone(),
+            circuit_data,
+            targets,
+            previous,
+            proving_data: None,
+        });
+        Ok(self.circuits.len() - 1)
+    }
+
+    /// Like `circuit_index`, also building the copy of the circuit without generators
+    fn pipelined_circuit_index(
+        &mut self,
+        num_signals: usize,
+        common: &CommonCircuitData<F, 2>,
+    ) -> Result<usize, ProofError> {
+        let index = self.circuit_index(num_signals, common)?;
+        if self.circuits[index].proving_data.is_none() {
+            let (circuit_data, ..) = self.build_circuit(num_signals, common)?;
+            self.circuits[index].proving_data = Some(pipeline::without_generators(circuit_data));
+        }
+
+        Ok(index)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn build_circuit(
+        &self,
+        num_signals: usize,
+        common: &CommonCircuitData<F, 2>,
+    ) -> Result<(CircuitData<F, C, 2>, ProofTargets<2>, Option<HashOutTarget>), ProofError> {
+        let mut builder = CircuitBuilder::new(self.options.fri.circuit_config(true)?);
+        let targets =
+            recursion_utils::add_proof_targets::<F, C, 2>(&mut builder, num_signals, common)?;
+        let previous =
+            register_aggregate_public_inputs(&mut builder, &targets.0, &targets.1, &self.options);
+
+        Ok((builder.build::<C>(), targets, previous))
+    }
+}
+
+/// The cap, nullifier and topic targets of each signal, as laid out by
+/// `PublicInputLayout::signal`
+pub(crate) fn signal_input_targets(
+    proof_targets: &[ProofWithPublicInputsTarget<2>],
+    cap_height: usize,
+) -> Vec<(&[Target], &[Target], &[Target])> {
+    let layout = PublicInputLayout::signal(cap_height);
+    let field = |kind| {
+        layout
+            .range_of(kind, 0)
+            .expect("a signal has a field of each kind")
+    };
+    let (cap, nullifier, topic) = (
+        field(InputKind::Cap),
+        field(InputKind::Nullifier),
+        field(InputKind::Topic),
+    );
+    proof_targets
+        .iter()
+        .map(|proof_target| {
+            let public_inputs = &proof_target.public_inputs;
+            (
+                &public_inputs[cap.clone()],
+                &public_inputs[nullifier.clone()],
+                &public_inputs[topic.clone()],
+            )
+        })
+        .collect()
+}
+
+/// Whether two digests are equal, element by element
+fn digests_equal(builder: &mut CircuitBuilder<F, 2>, x: &[Target], y: &[Target]) -> BoolTarget {
+    let mut equal = builder._true();
+    for (x, y) in x.iter().zip(y) {
+        let is_equal = builder.is_equal(*x, *y);
+        equal = builder.and(equal, is_equal);
+    }
+    equal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::plonk::circuit_data::VerifierCircuitData;
+    use proof_error::{ProofError, ProverDiagnostic};
+
+    use super::{AggregationMode, AggregationOptions, Aggregator};
+    use crate::access_set::AccessSet;
+    use crate::fixtures;
+    use crate::signal::{Digest, Signal, C, F};
+    use crate::verify;
+
+    fn make_signals() -> Result<(
+        &'static AccessSet,
+        [(Digest, Signal); 2],
+        VerifierCircuitData<F, C, 2>,
+    )> {
+        let access_set = fixtures::access_set(fixtures::log_size());
+
+        let (i0, i1) = (12, 345);
+        let (topic0, topic1) = (F::rand_array(), F::rand_array());
+        let (signal0, verifier_data) =
+            access_set.make_signal(fixtures::private_key(i0), topic0, i0)?;
+        let (signal1, _) = access_set.make_signal(fixtures::private_key(i1), topic1, i1)?;
+
+        Ok((
+            access_set,
+            [(topic0, signal0), (topic1, signal1)],
+            verifier_data,
+        ))
+    }
+
+    #[test]
+    fn test_aggregate_signals() -> Result<()> {
+        let (access_set, [(topic0, signal0), (topic1, signal1)], verifier_data) = make_signals()?;
+        let (nullifier0, nullifier1) = (signal0.nullifier, signal1.nullifier);
+
+        // regression: the circuit digest of the verifier data used to be left unset,
+        // so that the recursive prover failed with unrun generators
+        let (aggregated0, aggregated1, proof, aggregate_verifier_data) =
+            access_set.aggregate_signals(topic0, signal0, topic1, signal1, &verifier_data)?;
+        assert_eq!((aggregated0, aggregated1), (nullifier0, nullifier1));
+
+        access_set.verify_aggregated_signals(
+            topic0,
+            nullifier0,
+            topic1,
+            nullifier1,
+            proof.clone(),
+            &aggregate_verifier_data,
+        )?;
+        // the nullifiers are bound to the topics they were signaled on
+        assert!(access_set
+            .verify_aggregated_signals(
+                topic1,
+                nullifier0,
+                topic0,
+                nullifier1,
+                proof,
+                &aggregate_verifier_data
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed_verifier_data() -> Result<()> {
+        let (access_set, signals, verifier_data) = make_signals()?;
+        let topics_and_nullifiers: Vec<(Digest, Digest)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal.nullifier))
+            .collect();
+
+        let options = AggregationOptions {
+            embed_verifier_data: true,
+            ..Default::default()
+        };
+        let (proof_with_pis, aggregate_verifier_data) =
+            access_set.aggregate_signal_batch_with_options(&signals, &verifier_data, &options)?;
+        assert_eq!(
+            proof_with_pis.public_inputs[proof_with_pis.public_inputs.len() - 4..],
+            verify::verifier_data_digest(&verifier_data.verifier_only)
+        );
+        verify::verify_aggregated_signals_with_verifier_data(
+            &access_set.0.cap,
+            &topics_and_nullifiers,
+            &verifier_data.verifier_only,
+            proof_with_pis.proof.clone(),
+            &aggregate_verifier_data,
+        )?;
+
+        // the aggregate doesn't pass for one of another signal circuit
+        let other_verifier_data =
+            AccessSet::from_private_keys(&[F::rand_array(); 2]).signal_verifier_data();
+        assert!(verify::verify_aggregated_signals_with_verifier_data(
+            &access_set.0.cap,
+            &topics_and_nullifiers,
+            &other_verifier_data.verifier_only,
+            proof_with_pis.proof,
+            &aggregate_verifier_data,
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregation_diagnostics() -> Result<()> {
+        let (access_set, signals, verifier_data) = make_signals()?;
+
+        // against the cap of an access set of another cap height, the signals have more
+        // public inputs than the signal circuit
+        let other = AccessSet(MerkleTree::new(access_set.0.leaves.clone(), 1));
+        assert!(matches!(
+            other.aggregate_signal_batch(&signals, &verifier_data),
+            Err(ProofError::Diagnosed(ProverDiagnostic::PublicInputCount {
+                index: 0,
+                expected: 12,
+                actual: 16
+            }))
+        ));
+
+        // verifier data missing an element of its cap would leave targets unfilled
+        let mut missing = access_set.signal_verifier_data();
+        missing.verifier_only.constants_sigmas_cap.0.pop();
+        assert!(matches!(
+            Aggregator::default().aggregate(&access_set, &signals, &missing),
+            Err(ProofError::Diagnosed(
+                ProverDiagnostic::MissingVerifierData { .. }
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_signals_by_topic() -> Result<()> {
+        let access_set = fixtures::access_set(4);
+        let (yes, no) = (F::rand_array(), F::rand_array());
+
+        let mut signals = Vec::new();
+        for (i, topic) in [(3, yes), (5, no), (8, yes)] {
+            let (signal, _) = access_set.make_signal(fixtures::private_key(i), topic, i)?;
+            signals.push((topic, signal));
+        }
+        let verifier_data = access_set.signal_verifier_data();
+        let nullifiers: Vec<Digest> = signals.iter().map(|(_, s)| s.nullifier).collect();
+
+        let (topic_counts, (proof_with_pis, aggregate_verifier_data)) =
+            access_set.aggregate_signals_by_topic(&signals, &verifier_data)?;
+        assert_eq!(topic_counts, [(yes, 2), (no, 1)]);
+        access_set.verify_topic_counts(
+            &nullifiers,
+            &topic_counts,
+            proof_with_pis.proof.clone(),
+            &aggregate_verifier_data,
+        )?;
+        assert!(access_set
+            .verify_topic_counts(
+                &nullifiers,
+                &[(yes, 1), (no, 2)],
+                proof_with_pis.proof,
+                &aggregate_verifier_data
+            )
+            .is_err());
+
+        // the same signal twice isn't counted twice
+        let repeated = vec![signals[0].clone(), signals[1].clone(), signals[0].clone()];
+        assert!(matches!(
+            access_set.aggregate_signals_by_topic(&repeated, &verifier_data),
+            Err(ProofError::DuplicateSignal { index: 2 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregator() -> Result<()> {
+        let (access_set, signals, verifier_data) = make_signals()?;
+        let topics_and_nullifiers: Vec<(Digest, Digest)> = signals
+            .iter()
+            .map(|(topic, signal)| (*topic, signal.nullifier))
+            .collect();
+
+        let mut aggregator = Aggregator::default();
+        for _ in 0..2 {
+            let (proof_with_pis, aggregate_verifier_data) =
+                aggregator.aggregate(&access_set, &signals, &verifier_data)?;
+            verify::verify_aggregated_signals(
+                &access_set.0.cap,
+                &topics_and_nullifiers,
+                proof_with_pis.proof,
+                &aggregate_verifier_data,
+            )?;
+        }
+        assert_eq!(aggregator.num_circuits(), 1);
+
+        // a single signal needs a circuit of its own
+        aggregator.aggregate(&access_set, &signals[..1], &verifier_data)?;
+        assert_eq!(aggregator.num_circuits(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipelined_aggregation() -> Result<()> {
+        let (access_set, signals, verifier_data) = make_signals()?;
+        let options = AggregationOptions {
+            chain: true,
+            ..Default::default()
+        };
+        let batches = vec![signals.clone(), signals[..1].to_vec(), signals];
+
+        // the same aggregates and chain as one aggregation after the other
+        let mut sequential = Aggregator::new(options);
+        let mut pipelined = Aggregator::new(options);
+        let results = pipelined.aggregate_pipelined(&access_set, &batches, &verifier_data);
+        assert_eq!(results.len(), batches.len());
+        for ((result, _), signals) in results.into_iter().zip(&batches) {
+            let (proof_with_pis, aggregate_verifier_data) = result?;
+            let expected = sequential.aggregate(&access_set, signals, &verifier_data)?;
+            assert_eq!(proof_with_pis.public_inputs, expected.0.public_inputs);
+            aggregate_verifier_data.verify(proof_with_pis)?;
+        }
+        assert_eq!(pipelined.chain_head(), sequential.chain_head());
+        assert_eq!(pipelined.num_circuits(), 2);
+
+        // the aggregations before a batch without signals are kept
+        let results = pipelined.aggregate_pipelined(
+            &access_set,
+            &[batches[1].clone(), vec![]],
+            &verifier_data,
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].0.is_ok());
+        assert!(results[1].0.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accumulator_mode() -> Result<()> {
+        let (access_set, signals, verifier_data) = make_signals()?;
+        let nullifiers: Vec<Digest> = signals.iter().map(|(_, signal)| signal.nullifier).collect();
+
+        let mut aggregator = Aggregator::default().with_mode(AggregationMode::Accumulator);
+        let (proof_with_pis, aggregate_verifier_data) =
+            aggregator.aggregate(&access_set, &signals[..1], &verifier_data)?;
+        let accumulator = verify::verify_accumulator(
+            [F::ZERO; 4],
+            &nullifiers[..1],
+            proof_with_pis.proof,
+            &aggregate_verifier_data,
+        )?;
+        assert_eq!(aggregator.accumulator(), accumulator);
+
+        // the next aggregation continues the chain
+        let (proof_with_pis, aggregate_verifier_data) =
+            aggregator.aggregate(&access_set, &signals[1..], &verifier_data)?;
+        assert!(verify::verify_accumulator(
+            [F::ZERO; 4],
+            &nullifiers[1..],
+            proof_with_pis.proof.clone(),
+            &aggregate_verifier_data,
+        )
+        .is_err());
+        let accumulator = verify::verify_accumulator(
+            accumulator,
+            &nullifiers[1..],
+            proof_with_pis.proof,
+            &aggregate_verifier_data,
+        )?;
+        assert_eq!(accumulator, verify::accumulate([F::ZERO; 4], &nullifiers));
+        assert_eq!(aggregator.num_circuits(), 1);
+
+        // the signals are still verified, natively
+        let (_, signal) = &signals[0];
+        let mismatched = [(F::rand_array(), signal.clone())];
+        assert!(matches!(
+            aggregator.aggregate(&access_set, &mismatched, &verifier_data),
+            Err(ProofError::InvalidBatchProof { index: 0, .. })
+        ));
+        assert_eq!(aggregator.accumulator(), accumulator);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_threshold() -> Result<()> {
+        let access_set = fixtures::access_set(4);
+        let topic = F::rand_array();
+        let signal = |i: usize| -> Result<(Digest, Signal)> {
+            Ok((
+                topic,
+                access_set
+                    .make_signal(fixtures::private_key(i), topic, i)?
+                    .0,
+            ))
+        };
+        let verifier_data = access_set.signal_verifier_data();
+
+        let signals = vec![signal(2)?, signal(7)?, signal(11)?];
+        let (proof_with_pis, aggregate_verifier_data) =
+            access_set.aggregate_threshold(&signals, &verifier_data)?;
+        access_set.verify_threshold(
+            topic,
+            3,
+            proof_with_pis.proof.clone(),
+            &aggregate_verifier_data,
+        )?;
+        assert!(access_set
+            .verify_threshold(topic, 4, proof_with_pis.proof, &aggregate_verifier_data)
+            .is_err());
+
+        // a member signaling twice isn't counted twice
+        let reused = vec![signal(2)?, signal(7)?, signal(2)?];
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            access_set.aggregate_threshold(&reused, &verifier_data)
+        }));
+        assert!(!matches!(result, Ok(Ok(_))));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_aggregate_signals_for_evm() -> Result<()> {
+        let (access_set, [(topic0, signal0), (topic1, signal1)], verifier_data) = make_signals()?;
+        let (nullifier0, nullifier1) = (signal0.nullifier, signal1.nullifier);
+
+        let (_, _, (proof_with_pis, _)) = access_set.aggregate_signals_for_evm(
+            topic0,
+            signal0,
+            topic1,
+            signal1,
+            &verifier_data,
+        )?;
+        assert_eq!(
+            proof_with_pis.public_inputs,
+            verify::aggregate_public_inputs(
+                &access_set.0.cap,
+                &[(topic0, nullifier0), (topic1, nullifier1)]
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -1,50 +1,194 @@
+use alloc::vec::Vec;
+
+use plonky2::field::extension::Extendable;
 use plonky2::field::goldilocks_field::GoldilocksField;
-use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::field::types::Field64;
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use plonky2::plonk::proof::Proof;
+#[cfg(feature = "std")]
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+/// A 64-bit field and the Poseidon configuration proving over it, which signals are
+/// built on. Plonky2 only implements Poseidon over Goldilocks, but forks with other 64-bit
+/// fields can implement this for them
+pub trait FieldSpec: 'static {
+    type F: RichField + Extendable<2>;
+    type C: GenericConfig<2, F = Self::F, Hasher = PoseidonHash, InnerHasher = PoseidonHash>;
+}
 
-pub type F = GoldilocksField;
+#[derive(Clone, Copy, Debug)]
+pub struct Goldilocks;
+
+impl FieldSpec for Goldilocks {
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+}
+
+/// The spec the circuits of this crate are built for
+pub type Spec = Goldilocks;
+
+pub type F = <Spec as FieldSpec>::F;
 pub type Digest = [F; 4];
-pub type C = PoseidonGoldilocksConfig;
-pub type PlonkyProof = Proof<F, PoseidonGoldilocksConfig, 2>;
+pub type PrivateKey = Digest;
+pub type C = <Spec as FieldSpec>::C;
+pub type PlonkyProof = Proof<F, C, 2>;
+
+// digests and nullifiers are sized for elements of about 64 bits
+const _: () = assert!(
+    <F as Field64>::ORDER > 1 << 62,
+    "the field should be of 64 bits"
+);
+
+/// The version of the nullifier scheme, hashed into every nullifier as a constant of the
+/// signal circuit: a new scheme bumps it, so that its nullifiers never collide with those
+/// of earlier schemes
+pub const NULLIFIER_VERSION: u32 = 1;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Signal {
+    /// The `NULLIFIER_VERSION` the nullifier was derived with
+    pub version: u32,
     pub nullifier: Digest,
+    /// The nonce of the proof of work on the nullifier, 0 for signals without one, see
+    /// `pow::meets_difficulty`
+    pub nonce: u64,
     pub proof: PlonkyProof,
+    /// A message encrypted to a relayer, which the proof shows to be the message of its
+    /// hash, see `payload`
+    pub payload: Option<EncryptedPayload>,
+    /// The attributes of the member revealed by the disclosure policy of the signal circuit,
+    /// public inputs after those of the signal, see `disclosure`. Empty for plain signals
+    pub disclosed: Vec<F>,
+}
+
+/// A message encrypted to a relayer, whose hash, ephemeral public key and ciphertext are
+/// public inputs of the signal after its own
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    pub message_hash: Digest,
+    /// The compressed ephemeral public key: the limbs of x and the parity of y
+    pub ephemeral: Vec<F>,
+    pub ciphertext: Vec<F>,
+}
+
+impl EncryptedPayload {
+    pub fn public_inputs(&self) -> Vec<F> {
+        self.message_hash
+            .iter()
+            .chain(&self.ephemeral)
+            .chain(&self.ciphertext)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Signal {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        bincode::serialize(self).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        bincode::deserialize(bytes).map_err(|e| ProofError::Serialization(e.to_string()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
-    use plonky2::field::types::{Field, Sample};
-    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::field::types::{Field, Field64, Sample};
     use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::Hasher;
+    use proptest::prelude::*;
 
     use crate::access_set::AccessSet;
-    use crate::signal::{Digest, F};
+    use crate::fixtures;
+    use crate::signal::{Digest, FieldSpec, Goldilocks, Signal, F};
+
+    /// Checks that the Poseidon hashes of the circuits match the native ones over the
+    /// field of `S`
+    fn check_field_spec<S: FieldSpec>() -> Result<()> {
+        let inputs = S::F::rand_vec(8);
+        let expected = PoseidonHash::hash_no_pad(&inputs);
+
+        let mut builder =
+            CircuitBuilder::<S::F, 2>::new(CircuitConfig::standard_recursion_config());
+        let targets = builder.add_virtual_targets(inputs.len());
+        let hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(targets.clone());
+        builder.register_public_inputs(&hash.elements);
+        let mut pw = PartialWitness::new();
+        for (target, value) in targets.into_iter().zip(inputs) {
+            pw.set_target(target, value);
+        }
+
+        let proof = prover_utils::prove_and_verify::<S::F, S::C, 2>(builder, pw)?;
+        assert_eq!(proof.proof_with_pis.public_inputs, expected.elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_specs() -> Result<()> {
+        check_field_spec::<Goldilocks>()
+    }
 
     #[test]
     fn test_semaphore() -> Result<()> {
-        let n = 1 << 20;
-        let private_keys: Vec<Digest> = (0..n).map(|_| [F::rand(); 4]).collect();
-        let public_keys: Vec<Vec<F>> = private_keys
-            .iter()
-            .map(|&sk| {
-                PoseidonHash::hash_no_pad(&[sk, [F::ZERO; 4]].concat())
-                    .elements
-                    .to_vec()
-            })
-            .collect();
-        let access_set = AccessSet(MerkleTree::new(public_keys, 0));
+        let access_set = fixtures::access_set(fixtures::log_size());
 
         let i = 12;
         let topic = [F::rand(); 4];
 
-        let now = std::time::Instant::now();
-        let (signal, verifier_circuit_data) = access_set.make_signal(private_keys[i], topic, i)?;
-        println!("done proving, elapsed: {:.2?}", now.elapsed());
+        let (signal, elapsed) =
+            prover_utils::timed(|| access_set.make_signal(fixtures::private_key(i), topic, i));
+        let (signal, verifier_circuit_data) = signal?;
+        println!("done proving, elapsed: {elapsed:.2?}");
+
+        let signal = Signal::from_bytes(&signal.to_bytes()?)?;
+        access_set.verify_signal(topic, signal, &verifier_circuit_data)?;
+
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(4))]
+
+        #[test]
+        fn signals_are_sound(
+            seed in prop::array::uniform4(any::<u64>()),
+            topic in prop::array::uniform4(any::<u64>()),
+            i in 0..16usize,
+            corrupted in 0..8usize,
+            delta in 1..F::ORDER,
+        ) {
+            let private_keys: Vec<Digest> = (0..16u64)
+                .map(|j| seed.map(|s| F::from_noncanonical_u64(s ^ j)))
+                .collect();
+            let access_set = AccessSet::from_private_keys(&private_keys);
+            let topic = topic.map(F::from_noncanonical_u64);
+
+            let (signal, verifier_data) =
+                access_set.make_signal(private_keys[i], topic, i).unwrap();
+            prop_assert!(access_set
+                .verify_signal(topic, signal.clone(), &verifier_data)
+                .is_ok());
 
-        access_set.verify_signal(topic, signal, &verifier_circuit_data)
+            // corrupt a single element of either the nullifier or the topic
+            let delta = F::from_canonical_u64(delta);
+            let (mut signal, mut topic) = (signal, topic);
+            if corrupted < 4 {
+                signal.nullifier[corrupted] += delta;
+            } else {
+                topic[corrupted - 4] += delta;
+            }
+            prop_assert!(access_set
+                .verify_signal(topic, signal, &verifier_data)
+                .is_err());
+        }
     }
 }
@@ -0,0 +1,136 @@
+//! Topics derived from an application, an epoch and a salt, so that applications sharing
+//! an access set signal on disjoint topics and never reuse each other's nullifiers
+
+#[cfg(feature = "std")]
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use plonky2::field::types::Field;
+#[cfg(feature = "std")]
+use plonky2::hash::hash_types::HashOutTarget;
+use plonky2::hash::poseidon::PoseidonHash;
+#[cfg(feature = "std")]
+use plonky2::iop::target::Target;
+#[cfg(feature = "std")]
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+#[cfg(feature = "std")]
+use proof_error::ProofError;
+use serde::{Deserialize, Serialize};
+
+use crate::signal::{Digest, F};
+
+/// The id of an application signaling on an access set
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppId(pub Digest);
+
+impl AppId {
+    /// The id of the application named `name`
+    pub fn from_name(name: &str) -> Self {
+        let bytes: Vec<F> = name.bytes().map(F::from_canonical_u8).collect();
+        Self(PoseidonHash::hash_no_pad(&bytes).elements)
+    }
+
+    /// The topic of the application in `epoch`, e.g. a poll or a round of a vote, with a
+    /// salt which keeps topics unlinkable to their epoch unless it is shared
+    pub fn topic(&self, epoch: u64, salt: Digest) -> Digest {
+        let inputs: Vec<F> = self
+            .0
+            .into_iter()
+            .chain([F::from_canonical_u64(epoch)])
+            .chain(salt)
+            .collect();
+        PoseidonHash::hash_no_pad(&inputs).elements
+    }
+}
+
+/// The topic of `AppId::topic` computed in the circuit
+#[cfg(feature = "std")]
+pub fn topic_circuit(
+    builder: &mut CircuitBuilder<F, 2>,
+    app_id: [Target; 4],
+    epoch: Target,
+    salt: [Target; 4],
+) -> HashOutTarget {
+    let inputs = app_id.into_iter().chain([epoch]).chain(salt).collect();
+    builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs)
+}
+
+/// The applications sharing an access set, by name. Names are registered once, and the
+/// registry rejects a name whose id is already taken
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct AppRegistry {
+    apps: BTreeMap<String, AppId>,
+}
+
+#[cfg(feature = "std")]
+impl AppRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, name: &str) -> Result<AppId, ProofError> {
+        let app_id = AppId::from_name(name);
+        if self.apps.contains_key(name) || self.apps.values().any(|id| *id == app_id) {
+            return Err(ProofError::DuplicateApp(name.into()));
+        }
+
+        self.apps.insert(name.into(), app_id);
+        Ok(app_id)
+    }
+
+    pub fn get(&self, name: &str) -> Option<AppId> {
+        self.apps.get(name).copied()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use anyhow::Result;
+    use plonky2::field::types::Sample;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+    use crate::signal::C;
+
+    #[test]
+    fn test_app_topics() -> Result<()> {
+        let mut registry = AppRegistry::new();
+        let polls = registry.register("polls")?;
+        let votes = registry.register("votes")?;
+        assert!(matches!(
+            registry.register("polls"),
+            Err(ProofError::DuplicateApp(_))
+        ));
+        assert_eq!(registry.get("votes"), Some(votes));
+
+        let salt = F::rand_array();
+        assert_ne!(polls.topic(3, salt), votes.topic(3, salt));
+        assert_ne!(polls.topic(3, salt), polls.topic(4, salt));
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let app_id: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+        let epoch = builder.add_virtual_target();
+        let salt_target: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+        let topic = topic_circuit(&mut builder, app_id, epoch, salt_target);
+        builder.register_public_inputs(&topic.elements);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        for (target, value) in app_id.into_iter().zip(polls.0) {
+            pw.set_target(target, value);
+        }
+        pw.set_target(epoch, F::from_canonical_u64(3));
+        for (target, value) in salt_target.into_iter().zip(salt) {
+            pw.set_target(target, value);
+        }
+        let proof = data.prove(pw)?;
+        assert_eq!(proof.public_inputs, polls.topic(3, salt));
+
+        data.verify(proof)
+    }
+}
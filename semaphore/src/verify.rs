@@ -0,0 +1,281 @@
+//! Verification of signals and of aggregated signals, which only needs `alloc` so that
+//! verifiers can run without `std`, e.g. in embedded or zkVM guest environments
+
+use alloc::vec::Vec;
+
+use anyhow::{anyhow, ensure, Result};
+use plonky2::field::types::Field;
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::circuit_data::{VerifierCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::config::Hasher;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+
+use crate::layout::PublicInputLayout;
+use crate::pow;
+use crate::signal::{Digest, PlonkyProof, Signal, C, F, NULLIFIER_VERSION};
+
+/// The public inputs of a signal: the access set cap, the nullifier and the topic
+pub fn signal_public_inputs(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    nullifier: Digest,
+) -> Vec<F> {
+    cap.0
+        .iter()
+        .flat_map(|h| h.elements)
+        .chain(nullifier)
+        .chain(topic)
+        .collect()
+}
+
+/// The public inputs of an aggregation of signals, i.e. those of each signal in turn
+pub fn aggregate_public_inputs(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+) -> Vec<F> {
+    topics_and_nullifiers
+        .iter()
+        .flat_map(|&(topic, nullifier)| signal_public_inputs(cap, topic, nullifier))
+        .collect()
+}
+
+/// Hashes each nullifier in turn into `accumulator`, as the aggregations of an
+/// `Aggregator` in `AggregationMode::Accumulator` do
+pub fn accumulate(accumulator: Digest, nullifiers: &[Digest]) -> Digest {
+    nullifiers
+        .iter()
+        .fold(accumulator, |accumulator, nullifier| {
+            PoseidonHash::hash_no_pad(&[accumulator, *nullifier].concat()).elements
+        })
+}
+
+/// The public inputs of an accumulator aggregation: the accumulator before it, the
+/// nullifier of each signal and the accumulator after it
+pub fn accumulator_public_inputs(accumulator: Digest, nullifiers: &[Digest]) -> Vec<F> {
+    accumulator
+        .into_iter()
+        .chain(nullifiers.iter().flatten().copied())
+        .chain(accumulate(accumulator, nullifiers))
+        .collect()
+}
+
+/// The digest an aggregation embedding the verifier data of the signal circuit appends to
+/// its public inputs, committing to the constants-sigmas cap and the circuit digest
+pub fn verifier_data_digest(verifier_only: &VerifierOnlyCircuitData<C, 2>) -> Digest {
+    let inputs: Vec<F> = verifier_only
+        .constants_sigmas_cap
+        .0
+        .iter()
+        .chain([&verifier_only.circuit_digest])
+        .flat_map(|hash| hash.elements)
+        .collect();
+    PoseidonHash::hash_no_pad(&inputs).elements
+}
+
+/// The public inputs of an aggregation counting signals by topic: the access set cap, the
+/// nullifier of each signal, then each topic followed by its count
+pub fn topic_counts_public_inputs(
+    cap: &MerkleCap<F, PoseidonHash>,
+    nullifiers: &[Digest],
+    topic_counts: &[(Digest, usize)],
+) -> Vec<F> {
+    cap.0
+        .iter()
+        .flat_map(|h| h.elements)
+        .chain(nullifiers.iter().flatten().copied())
+        .chain(
+            topic_counts.iter().flat_map(|&(topic, count)| {
+                topic.into_iter().chain([F::from_canonical_usize(count)])
+            }),
+        )
+        .collect()
+}
+
+/// The public inputs of a threshold aggregation: the access set cap, the topic and the
+/// number of distinct members who signaled on it
+pub fn threshold_public_inputs(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    threshold: usize,
+) -> Vec<F> {
+    cap.0
+        .iter()
+        .flat_map(|h| h.elements)
+        .chain(topic)
+        .chain([F::from_canonical_usize(threshold)])
+        .collect()
+}
+
+/// Like `verify_signal`, for a signal carrying an encrypted payload, against verifier data
+/// built for the relayer the payload is encrypted to
+pub fn verify_signal_with_payload(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    signal: Signal,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    check_version(&signal)?;
+    let payload = signal
+        .payload
+        .ok_or_else(|| anyhow!("the signal carries no payload"))?;
+    let mut public_inputs = signal_public_inputs(cap, topic, signal.nullifier);
+    public_inputs.extend(payload.public_inputs());
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs,
+        proof: signal.proof,
+    })
+}
+
+/// Like `verify_signal`, for a signal revealing attributes of its member, against verifier
+/// data built for the disclosure policy the verifier expects
+pub fn verify_signal_with_disclosure(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    signal: Signal,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    check_version(&signal)?;
+    let mut public_inputs = signal_public_inputs(cap, topic, signal.nullifier);
+    public_inputs.extend(signal.disclosed);
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs,
+        proof: signal.proof,
+    })
+}
+
+/// Checks that the nullifier of `signal` was derived under the current `NULLIFIER_VERSION`,
+/// which the signal circuit hashes into it
+pub fn check_version(signal: &Signal) -> Result<()> {
+    ensure!(
+        signal.version == NULLIFIER_VERSION,
+        "the signal has nullifier version {}, expected {}",
+        signal.version,
+        NULLIFIER_VERSION
+    );
+    Ok(())
+}
+
+/// Checks that the nonce of `signal` proves `difficulty` bits of work on its nullifier,
+/// which costs a hash rather than the verification of the proof
+pub fn check_difficulty(signal: &Signal, difficulty: usize) -> Result<()> {
+    ensure!(
+        pow::meets_difficulty(signal.nullifier, signal.nonce, difficulty),
+        "the signal doesn't prove {} bits of work",
+        difficulty
+    );
+    Ok(())
+}
+
+pub fn verify_signal(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    signal: Signal,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    check_version(&signal)?;
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: signal_public_inputs(cap, topic, signal.nullifier),
+        proof: signal.proof,
+    })
+}
+
+/// Like `verify_signal`, for a signal circuit gated by a proof of work of `difficulty`
+/// bits, rejecting signals short of it before verifying their proof
+pub fn verify_signal_with_difficulty(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    signal: Signal,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+    difficulty: usize,
+) -> Result<()> {
+    check_difficulty(&signal, difficulty)?;
+    verify_signal(cap, topic, signal, verifier_data)
+}
+
+pub fn verify_aggregated_signals(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: aggregate_public_inputs(cap, topics_and_nullifiers),
+        proof,
+    })
+}
+
+pub fn verify_topic_counts(
+    cap: &MerkleCap<F, PoseidonHash>,
+    nullifiers: &[Digest],
+    topic_counts: &[(Digest, usize)],
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: topic_counts_public_inputs(cap, nullifiers, topic_counts),
+        proof,
+    })
+}
+
+pub fn verify_threshold(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topic: Digest,
+    threshold: usize,
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: threshold_public_inputs(cap, topic, threshold),
+        proof,
+    })
+}
+
+/// Checks an accumulator aggregation of `nullifiers` from `accumulator`, returning the
+/// accumulator after it, which the next aggregation of the chain starts from. The proof only
+/// attests the update of the accumulator: the signals were verified by the aggregator
+pub fn verify_accumulator(
+    accumulator: Digest,
+    nullifiers: &[Digest],
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<Digest> {
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: accumulator_public_inputs(accumulator, nullifiers),
+        proof,
+    })?;
+    Ok(accumulate(accumulator, nullifiers))
+}
+
+/// Verifies a proof whose public inputs follow `layout`, e.g. the layout shipped with the
+/// verifier data of an aggregation, rather than the ordering of the current circuits
+pub fn verify_with_layout(
+    layout: &PublicInputLayout,
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+    verifier_data_digest: Option<Digest>,
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs: layout.arrange(cap, topics_and_nullifiers, verifier_data_digest)?,
+        proof,
+    })
+}
+
+/// Like `verify_aggregated_signals`, for an aggregation embedding the verifier data of the
+/// signal circuit, checking that it verified the signal circuit of `inner_verifier_only`
+pub fn verify_aggregated_signals_with_verifier_data(
+    cap: &MerkleCap<F, PoseidonHash>,
+    topics_and_nullifiers: &[(Digest, Digest)],
+    inner_verifier_only: &VerifierOnlyCircuitData<C, 2>,
+    proof: PlonkyProof,
+    verifier_data: &VerifierCircuitData<F, C, 2>,
+) -> Result<()> {
+    let mut public_inputs = aggregate_public_inputs(cap, topics_and_nullifiers);
+    public_inputs.extend(verifier_data_digest(inner_verifier_only));
+    verifier_data.verify(ProofWithPublicInputs {
+        public_inputs,
+        proof,
+    })
+}